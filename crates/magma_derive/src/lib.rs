@@ -31,17 +31,21 @@ pub fn derive_uniform_buffer(input: TokenStream) -> TokenStream {
         _ => panic!("Unsupported shader stage, must be one of ['vertex', 'fragment', 'compute', 'all_graphics']")
     };
 
-    let descriptions = generate_field_descriptions(&ast.data);
+    let fields = match &ast.data {
+        syn::Data::Struct(data) => &data.fields,
+        _ => panic!("Only a struct derive UniformBuffer"),
+    };
+    let layouts: Vec<FieldLayout> = fields.iter().map(get_field_layout).collect();
+    let descriptions = generate_field_descriptions(&layouts, BlockLayout::Std140);
+    let std430_descriptions = generate_field_descriptions(&layouts, BlockLayout::Std430);
+    let writes = generate_writes(fields, &layouts, BlockLayout::Std140);
+    let std430_writes = generate_writes(fields, &layouts, BlockLayout::Std430);
 
     quote! {
         impl UniformBuffer for #ident {
-            /// TODO: Correct alignment automatically
-            ///
-            /// Scalars need to be aligned by N (= 4 bytes)
-            /// Vec2 needs to be aligned by 2N (= 8 bytes)
-            /// Vec3 and vec4 need to be aligned by 4N (= 16 bytes)
-            /// A nested struct needs the base alignment of each member rounded to a multiple of 16
-            /// A mat4 needs to have the same alignment as a vec4
+            /// Reinterprets the struct's memory, which only matches the shader's layout if the
+            /// fields happen to be aligned following std140 rules, see
+            /// [UniformBuffer::as_std140_bytes]
             fn as_bytes(&self) -> &[u8] {
                 unsafe {
                     let size_in_bytes = ::std::mem::size_of::<Self>();
@@ -50,6 +54,22 @@ pub fn derive_uniform_buffer(input: TokenStream) -> TokenStream {
                 }
             }
 
+            fn as_std140_bytes(&self) -> Vec<u8> {
+                let fields = Self::get_field_descriptions();
+                let offsets = std140_offsets(&fields);
+                let mut bytes = vec![0_u8; std140_size(&fields)];
+                #(#writes)*
+                bytes
+            }
+
+            fn as_std430_bytes(&self) -> Vec<u8> {
+                let fields = Self::get_std430_field_descriptions();
+                let offsets = std430_offsets(&fields);
+                let mut bytes = vec![0_u8; std430_size(&fields)];
+                #(#std430_writes)*
+                bytes
+            }
+
             fn stage() -> ShaderStageFlags {
                 #stage
             }
@@ -57,83 +77,171 @@ pub fn derive_uniform_buffer(input: TokenStream) -> TokenStream {
             fn get_field_descriptions() -> Vec<UboFieldDescription> {
                 #descriptions
             }
+
+            fn get_std430_field_descriptions() -> Vec<UboFieldDescription> {
+                #std430_descriptions
+            }
         }
     }
     .into()
 }
 
-fn generate_field_descriptions(data: &syn::Data) -> proc_macro2::TokenStream {
-    let mut sizes: Vec<usize> = Vec::new();
+/// Layout rules a uniform buffer's fields are laid out with
+#[derive(Clone, Copy, PartialEq)]
+enum BlockLayout {
+    /// Used by uniform blocks
+    Std140,
+    /// Used by push constant blocks, where arrays and matrix columns aren't padded to a vec4 and
+    /// structs are only aligned to their largest field
+    Std430,
+}
 
-    match data {
-        syn::Data::Struct(data) => {
-            for field in data.fields.iter() {
-                sizes.push(get_field_size(field));
-            }
+/// How a field of a uniform buffer is laid out following std140 rules
+enum FieldLayout {
+    /// Field is copied as is, with its size and base alignment in bytes
+    Plain { size: usize, alignment: usize },
+    /// Matrix whose columns are each padded to a vec4, with its padded size in bytes
+    Matrix { size: usize, columns: usize },
+    /// Array of more than 4 floats, whose elements are each padded to a vec4
+    Array { len: usize },
+    /// Boolean, which is 4 bytes in GLSL and written as a `u32`
+    Bool,
+    /// Struct that also derives UniformBuffer and is marked with #[ubo(nested)], aligned to 16
+    /// bytes with its size rounded up to a multiple of 16
+    Nested(Box<syn::Type>),
+}
 
-            let mut descriptions: Vec<proc_macro2::TokenStream> = Vec::new();
-            for &size in sizes.iter() {
-                descriptions.push(quote! {
-                    UboFieldDescription {
-                        size: #size
-                    }
-                });
-            }
+impl FieldLayout {
+    fn plain(size: usize, alignment: usize) -> FieldLayout {
+        FieldLayout::Plain { size, alignment }
+    }
 
-            quote! {
-                vec![
-                    #(#descriptions),*
-                ]
+    fn matrix(size: usize, columns: usize) -> FieldLayout {
+        FieldLayout::Matrix { size, columns }
+    }
+}
+
+fn generate_field_descriptions(
+    layouts: &[FieldLayout],
+    block_layout: BlockLayout,
+) -> proc_macro2::TokenStream {
+    let std430 = block_layout == BlockLayout::Std430;
+    let descriptions = layouts.iter().map(|layout| {
+        let (size, alignment) = match layout {
+            FieldLayout::Plain { size, alignment } => (quote! { #size }, quote! { #alignment }),
+            // Columns of a mat2 are only 8 bytes apart in std430
+            FieldLayout::Matrix { columns: 2, .. } if std430 => (quote! { 16 }, quote! { 8 }),
+            FieldLayout::Matrix { size, .. } => (quote! { #size }, quote! { 16 }),
+            FieldLayout::Array { len } if std430 => {
+                let size = 4 * len;
+                (quote! { #size }, quote! { 4 })
+            }
+            FieldLayout::Array { len } => {
+                let size = 16 * len;
+                (quote! { #size }, quote! { 16 })
+            }
+            FieldLayout::Bool => (quote! { 4 }, quote! { 4 }),
+            FieldLayout::Nested(ty) if std430 => (
+                quote! { std430_size(&<#ty as UniformBuffer>::get_std430_field_descriptions()) },
+                quote! { std430_alignment(&<#ty as UniformBuffer>::get_std430_field_descriptions()) },
+            ),
+            FieldLayout::Nested(ty) => (
+                quote! { std140_size(&<#ty as UniformBuffer>::get_field_descriptions()) },
+                quote! { 16 },
+            ),
+        };
+        quote! {
+            UboFieldDescription {
+                size: #size,
+                alignment: #alignment,
             }
         }
-        _ => panic!("Only a struct derive UniformBuffer"),
+    });
+
+    quote! {
+        vec![
+            #(#descriptions),*
+        ]
     }
 }
 
-fn get_field_size(field: &syn::Field) -> usize {
+/// Generates the statements writing every field into `bytes` at its offset following
+/// `block_layout`
+fn generate_writes(
+    fields: &syn::Fields,
+    layouts: &[FieldLayout],
+    block_layout: BlockLayout,
+) -> Vec<proc_macro2::TokenStream> {
+    let std430 = block_layout == BlockLayout::Std430;
+    fields
+        .iter()
+        .zip(layouts.iter())
+        .enumerate()
+        .map(|(index, (field, layout))| {
+            let field_name = field.ident.as_ref().unwrap();
+            match layout {
+                FieldLayout::Plain { .. } => quote! {
+                    unsafe { std140_write(&mut bytes, offsets[#index], &self.#field_name) };
+                },
+                FieldLayout::Matrix { columns: 2, .. } | FieldLayout::Array { .. } if std430 => {
+                    quote! {
+                        unsafe { std140_write(&mut bytes, offsets[#index], &self.#field_name) };
+                    }
+                }
+                FieldLayout::Matrix { columns, .. } => quote! {
+                    unsafe { std140_write_columns(&mut bytes, offsets[#index], &self.#field_name, #columns) };
+                },
+                FieldLayout::Array { len } => quote! {
+                    unsafe { std140_write_columns(&mut bytes, offsets[#index], &self.#field_name, #len) };
+                },
+                FieldLayout::Bool => quote! {
+                    unsafe { std140_write(&mut bytes, offsets[#index], &(self.#field_name as u32)) };
+                },
+                FieldLayout::Nested(_) if std430 => quote! {
+                    let nested = self.#field_name.as_std430_bytes();
+                    bytes[offsets[#index]..offsets[#index] + nested.len()].copy_from_slice(&nested);
+                },
+                FieldLayout::Nested(_) => quote! {
+                    let nested = self.#field_name.as_std140_bytes();
+                    bytes[offsets[#index]..offsets[#index] + nested.len()].copy_from_slice(&nested);
+                },
+            }
+        })
+        .collect()
+}
+
+/// Returns how a field is laid out following std140 rules
+fn get_field_layout(field: &syn::Field) -> FieldLayout {
     let field_name = &field.ident.as_ref().unwrap();
     match &field.ty {
         syn::Type::Path(ref path) => {
-            if path.path.segments.len() == 2 {
-                if path
-                    .path
-                    .segments
-                    .first()
-                    .unwrap()
-                    .ident
-                    .to_string()
-                    .as_str()
-                    == "glam"
-                {
-                    let ty = path.path.segments[1].ident.to_string();
-                    let ty = ty.as_str();
-                    match ty {
-                        "Vec2" => 8,
-                        "Vec3" => 12,
-                        "Vec4" => 16,
-                        "Mat2" => 16,
-                        "Mat3" => 36,
-                        "Mat4" => 64,
-                        _ => panic!("Field `{}` has unsupported glam type `{}`", field_name, ty)
-                    }
-                } else {
-                    panic!(
-                        "Field `{}` has a type with 2 path segments but only types from glam are supported (e.g. glam::Vec3)",
-                        field_name
-                    );
-                }
-            } else if path.path.segments.len() == 1 {
-                let ty = format!("{}", path.path.get_ident().unwrap());
+            let is_glam = path.path.segments.len() == 2
+                && path.path.segments.first().unwrap().ident == "glam";
+            if is_glam {
+                let ty = path.path.segments[1].ident.to_string();
                 let ty = ty.as_str();
                 match ty {
-                    "u32" => ::std::mem::size_of::<u32>(),
-                    _ => panic!("Type `{}` on field `{}` is not supported", ty, field_name),
+                    "Vec2" => FieldLayout::plain(8, 8),
+                    "Vec3" => FieldLayout::plain(12, 16),
+                    "Vec4" => FieldLayout::plain(16, 16),
+                    "Quat" => FieldLayout::plain(16, 16),
+                    "Mat2" => FieldLayout::matrix(32, 2),
+                    "Mat3" => FieldLayout::matrix(48, 3),
+                    "Mat4" => FieldLayout::matrix(64, 4),
+                    _ => panic!("Field `{}` has unsupported glam type `{}`", field_name, ty)
                 }
             } else {
-                panic!(
-                    "Field `{}` has a type with more than 2 path segments, only built-in types like `[f32; 3]` or glam types like `glam::Vec3` are supported",
-                    field_name
-                );
+                let ty = path.path.get_ident().map(|ident| ident.to_string());
+                match ty.as_deref() {
+                    Some("u32") | Some("i32") | Some("f32") => FieldLayout::plain(4, 4),
+                    Some("bool") => FieldLayout::Bool,
+                    _ if is_nested(field) => FieldLayout::Nested(Box::new(field.ty.clone())),
+                    _ => panic!(
+                        "Field `{}` has unsupported type `{}`, mark it with #[ubo(nested)] if it is a struct that derives UniformBuffer",
+                        field_name,
+                        quote! { #path }
+                    ),
+                }
             }
         }
         syn::Type::Array(array) => {
@@ -164,13 +272,49 @@ fn get_field_size(field: &syn::Field) -> usize {
                 panic!("Field `{}` must be an f32 array", field_name);
             }
 
-            (4 * array_len) as usize
+            // Arrays of up to 4 floats are treated as vectors, longer arrays have a stride of 16
+            // bytes like any std140 array
+            match array_len {
+                1 => FieldLayout::plain(4, 4),
+                2 => FieldLayout::plain(8, 8),
+                3 | 4 => FieldLayout::plain((4 * array_len) as usize, 16),
+                _ => FieldLayout::Array {
+                    len: array_len as usize,
+                },
+            }
         }
         _ => panic!("Field `{}` has unsupported type", field_name),
     }
 }
 
-#[proc_macro_derive(Vertex, attributes(location))]
+/// Returns whether a field is marked with #[ubo(nested)], as a struct that also derives
+/// UniformBuffer
+fn is_nested(field: &syn::Field) -> bool {
+    field
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("ubo"))
+        .any(|a| match a.parse_meta() {
+            Ok(syn::Meta::List(list)) => list.nested.iter().any(|nested| {
+                matches!(nested, syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("nested"))
+            }),
+            _ => panic!(
+                "Field `{}` ubo attribute must be in the form #[ubo(nested)]",
+                field.ident.as_ref().unwrap()
+            ),
+        })
+}
+
+#[derive(FromDeriveInput, Default)]
+#[darling(default, attributes(vertex))]
+struct VertexOpts {
+    /// Overrides the stride of binding 0, for when the GPU stride differs from the struct size
+    stride: Option<u32>,
+    /// Input rate of binding 0, either "vertex" or "instance"
+    rate: Option<String>,
+}
+
+#[proc_macro_derive(Vertex, attributes(vertex, location, binding, instance_binding))]
 pub fn vertex_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
     let gen = generate_vertex_impl(&ast);
@@ -179,7 +323,118 @@ pub fn vertex_derive(input: TokenStream) -> TokenStream {
 
 fn generate_vertex_impl(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
     let ident = &ast.ident;
-    let attribute_descriptions = generate_attribute_descriptions(&ast.data);
+    let fields = match &ast.data {
+        syn::Data::Enum(_) => panic!("Cannot implement Vertex on an enum"),
+        syn::Data::Union(_) => panic!("Cannot implement Vertex on a union"),
+        syn::Data::Struct(ref data) => &data.fields,
+    };
+
+    let opts = VertexOpts::from_derive_input(ast)
+        .expect("Provided invalid options, only expected `stride` and `rate`");
+    let mut instance_bindings: Vec<u32> = ast
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("instance_binding"))
+        .map(|a| parse_u32_attribute(a, "instance_binding", &ident.to_string()))
+        .collect();
+    match opts.rate.as_ref().map(|rate| rate.to_lowercase()).as_deref() {
+        Some("instance") => instance_bindings.push(0),
+        Some("vertex") | None => {}
+        Some(rate) => panic!(
+            "`{}` has unknown vertex input rate `{}`, expected `vertex` or `instance`",
+            ident, rate
+        ),
+    }
+
+    let mut bindings: Vec<u32> = Vec::with_capacity(fields.len());
+    for field in fields.iter() {
+        let (binding, rate) = get_field_binding(field);
+        match rate.map(|rate| rate.to_lowercase()).as_deref() {
+            Some("instance") => instance_bindings.push(binding),
+            Some("vertex") | None => {}
+            Some(rate) => panic!(
+                "Field `{}` has unknown vertex input rate `{}`, expected `vertex` or `instance`",
+                field.ident.as_ref().unwrap(),
+                rate
+            ),
+        }
+        bindings.push(binding);
+    }
+    let (attribute_descriptions, binding_descriptions) = if bindings.iter().all(|&b| b == 0) {
+        // Every field is in the same binding so the struct is laid out exactly like the buffer
+        let attribute_descriptions: Vec<proc_macro2::TokenStream> = fields
+            .iter()
+            .map(|field| {
+                let field_name = field.ident.as_ref().unwrap();
+                generate_attribute_description(
+                    field,
+                    0,
+                    quote! { offset_of!(Self, #field_name) as u32 },
+                )
+            })
+            .collect();
+        let input_rate = get_input_rate(0, &instance_bindings);
+        let stride = match opts.stride {
+            Some(stride) => quote! { #stride },
+            None => quote! { ::std::mem::size_of::<Self>() as u32 },
+        };
+
+        (
+            attribute_descriptions,
+            vec![quote! {
+                VertexBindingDescription {
+                    binding: 0,
+                    stride: #stride,
+                    input_rate: #input_rate,
+                }
+            }],
+        )
+    } else {
+        // Fields are split between several buffers, each of which holds its fields tightly
+        // packed in the order they are declared
+        let mut strides: Vec<(u32, u32)> = Vec::new();
+        let attribute_descriptions: Vec<proc_macro2::TokenStream> = fields
+            .iter()
+            .zip(bindings.iter())
+            .map(|(field, &binding)| {
+                let offset = match strides.iter_mut().find(|(b, _)| *b == binding) {
+                    Some((_, stride)) => {
+                        let offset = *stride;
+                        *stride += get_field_size(field);
+                        offset
+                    }
+                    None => {
+                        strides.push((binding, get_field_size(field)));
+                        0
+                    }
+                };
+
+                generate_attribute_description(field, binding, quote! { #offset })
+            })
+            .collect();
+
+        bindings.sort_unstable();
+        bindings.dedup();
+        let binding_descriptions = bindings
+            .iter()
+            .map(|binding| {
+                let stride = match opts.stride {
+                    Some(stride) if *binding == 0 => stride,
+                    _ => strides.iter().find(|(b, _)| b == binding).unwrap().1,
+                };
+                let input_rate = get_input_rate(*binding, &instance_bindings);
+                quote! {
+                    VertexBindingDescription {
+                        binding: #binding,
+                        stride: #stride,
+                        input_rate: #input_rate,
+                    }
+                }
+            })
+            .collect();
+
+        (attribute_descriptions, binding_descriptions)
+    };
 
     quote! {
         impl Vertex for #ident {
@@ -190,67 +445,120 @@ fn generate_vertex_impl(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
             }
 
             fn get_binding_descriptions() -> Vec<VertexBindingDescription> {
-                vec![VertexBindingDescription {
-                    binding: 0,
-                    stride: ::std::mem::size_of::<Self>() as u32,
-                    input_rate: VertexInputRate::Vertex,
-                }]
+                vec![
+                    #(#binding_descriptions),*
+                ]
             }
         }
     }
 }
 
-fn generate_attribute_descriptions(body: &syn::Data) -> Vec<proc_macro2::TokenStream> {
-    match body {
-        syn::Data::Enum(_) => panic!("Cannot implement Vertex on an enum"),
-        syn::Data::Union(_) => panic!("Cannot implement Vertex on a union"),
-        syn::Data::Struct(ref data) => data
-            .fields
-            .iter()
-            .map(generate_attribute_description)
-            .collect(),
+/// Returns whether vertices in `binding` advance per instance or per vertex
+fn get_input_rate(binding: u32, instance_bindings: &[u32]) -> proc_macro2::TokenStream {
+    if instance_bindings.contains(&binding) {
+        quote! { VertexInputRate::Instance }
+    } else {
+        quote! { VertexInputRate::Vertex }
     }
 }
 
-fn generate_attribute_description(field: &syn::Field) -> proc_macro2::TokenStream {
+/// Parses an attribute in the form #[name = N]
+fn parse_u32_attribute(attr: &syn::Attribute, name: &str, owner: &str) -> u32 {
+    match attr.parse_meta().unwrap() {
+        syn::Meta::NameValue(nv) => match nv.lit {
+            syn::Lit::Int(i) => i.base10_parse::<u32>().unwrap(),
+            _ => panic!("`{}` {} attribute must be an integer", owner, name),
+        },
+        _ => panic!(
+            "`{}` {} attribute must be in the form #[{} = ?]",
+            owner, name, name
+        ),
+    }
+}
+
+/// Returns the vertex buffer binding of a field, set with #[binding = ?] and defaulting to 0,
+/// along with the input rate of the binding if it is set with #[binding(?, rate = "instance")]
+fn get_field_binding(field: &syn::Field) -> (u32, Option<String>) {
+    let field_name = field.ident.as_ref().unwrap().to_string();
+    let attr = match field.attrs.iter().find(|a| a.path.is_ident("binding")) {
+        Some(attr) => attr,
+        None => return (0, None),
+    };
+
+    let parser = |input: syn::parse::ParseStream| {
+        if input.parse::<Option<syn::Token![=]>>()?.is_some() {
+            let binding = input.parse::<syn::LitInt>()?.base10_parse::<u32>()?;
+            return Ok((binding, None));
+        }
+
+        let content;
+        syn::parenthesized!(content in input);
+        let binding = content.parse::<syn::LitInt>()?.base10_parse::<u32>()?;
+        let mut rate = None;
+        if content.parse::<Option<syn::Token![,]>>()?.is_some() {
+            let name = content.parse::<syn::Ident>()?;
+            if name != "rate" {
+                return Err(syn::Error::new(name.span(), "expected `rate`"));
+            }
+            content.parse::<syn::Token![=]>()?;
+            rate = Some(content.parse::<syn::LitStr>()?.value());
+        }
+
+        Ok((binding, rate))
+    };
+    syn::parse::Parser::parse2(parser, attr.tokens.clone()).unwrap_or_else(|_| {
+        panic!(
+            "`{}` binding attribute must be in the form #[binding = ?] or #[binding(?, rate = \"instance\")]",
+            field_name
+        )
+    })
+}
+
+fn generate_attribute_description(
+    field: &syn::Field,
+    binding: u32,
+    offset: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
     let field_name = field.ident.as_ref().unwrap();
     let field_type = get_field_type(field);
 
     let location_attr = field
         .attrs
         .iter()
-        .filter(|a| a.path.is_ident("location"))
-        .next()
+        .find(|a| a.path.is_ident("location"))
         .unwrap_or_else(|| {
             panic!(
                 "Field `{:?}` is missing #[location = ?] attribute",
                 field_name
             )
         });
-    let location_lit = match location_attr.parse_meta().unwrap() {
-        syn::Meta::NameValue(nv) => match nv.lit {
-            syn::Lit::Int(i) => i.base10_parse::<u32>().unwrap(),
-            _ => panic!(
-                "Field `{:?}` location attribute must be an integer",
-                field_name
-            ),
-        },
-        _ => panic!(
-            "Field `{:?}` location attribute must be in the form #[location = ?]",
-            field_name
-        ),
-    };
+    let location_lit = parse_u32_attribute(location_attr, "location", &field_name.to_string());
 
     quote! {
         VertexAttributeDescription {
-            binding: 0,
+            binding: #binding,
             location: #location_lit,
             format: #field_type,
-            offset: offset_of!(Self, #field_name) as u32,
+            offset: #offset,
         }
     }
 }
 
+/// Returns the size, in bytes, of a vertex field
+fn get_field_size(field: &syn::Field) -> u32 {
+    let field_name = &field.ident.as_ref().unwrap();
+    match &field.ty {
+        syn::Type::Array(array) => match &array.len {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(i),
+                ..
+            }) => 4 * i.base10_parse::<u32>().unwrap(),
+            _ => panic!("Field `{}` has unexpected literal in array", field_name),
+        },
+        _ => panic!("Field `{}` should be an array type", field_name),
+    }
+}
+
 fn get_field_type(field: &syn::Field) -> proc_macro2::TokenStream {
     let field_name = &field.ident.as_ref().unwrap();
     match &field.ty {
@@ -271,7 +579,7 @@ fn get_field_type(field: &syn::Field) -> proc_macro2::TokenStream {
                 _ => panic!("Field `{}` has unexpected literal in array", field_name),
             };
 
-            if array_type.eq("f32".into()) {
+            if array_type.eq("f32") {
                 match array_len {
                     1 => quote! { VkFormat::R32_SFLOAT },
                     2 => quote! { VkFormat::R32G32_SFLOAT },
@@ -282,7 +590,7 @@ fn get_field_type(field: &syn::Field) -> proc_macro2::TokenStream {
                         field_name
                     ),
                 }
-            } else if array_type.eq("i32".into()) {
+            } else if array_type.eq("i32") {
                 match array_len {
                     1 => quote! { VkFormat::R32_SINT },
                     2 => quote! { VkFormat::R32G32_SINT },
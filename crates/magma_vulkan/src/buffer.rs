@@ -1,10 +1,16 @@
 use ash::vk;
 use bitflags::bitflags;
-use std::{rc::Rc, usize};
-
-use crate::core::{
-    commands::pool::CommandPool,
-    device::{LogicalDevice, LogicalDeviceError, QueueFlags},
+use std::rc::Rc;
+
+use crate::{
+    core::{
+        commands::pool::{CommandPool, CommandPoolError},
+        device::{AllocationKind, DeviceExtension, LogicalDevice, LogicalDeviceError},
+    },
+    pipeline::{
+        ubo::{std140_size, UniformBuffer},
+        vertex::{self, Vertex},
+    },
 };
 
 /// Errors that can be returned by a `Buffer"
@@ -12,6 +18,26 @@ use crate::core::{
 pub enum BufferError {
     #[error("Can't copy from buffer: {0}")]
     InvalidCopy(&'static str),
+    #[error("The buffer's usage requires the '{0}' device extension to be enabled")]
+    MissingExtension(DeviceExtension),
+    #[error("Can't write {len} instances at offset {offset} into a buffer with a capacity of {capacity}")]
+    OutOfRange {
+        offset: usize,
+        len: usize,
+        capacity: usize,
+    },
+    #[error("The buffer's memory is already mapped")]
+    AlreadyMapped,
+    #[error("Uniform buffers take up {size} bytes when laid out using std140 rules, but the buffer only has {instance_size} bytes for each, create it with Buffer::new_uniform")]
+    UniformTooLarge { size: usize, instance_size: usize },
+    #[error("Can't allocate {requested} bytes from the frame arena, only {remaining} bytes remain")]
+    ArenaFull { requested: u64, remaining: u64 },
+    #[error("The frame arena must have been created with the {0:?} usage")]
+    MissingUsage(BufferUsageFlags),
+    #[error("Can't get the device address of a buffer created without SHADER_DEVICE_ADDRESS usage")]
+    NoDeviceAddress,
+    #[error(transparent)]
+    CommandPoolError(#[from] CommandPoolError),
     #[error(transparent)]
     DeviceError(#[from] LogicalDeviceError),
 }
@@ -25,10 +51,15 @@ bitflags! {
         const TRANSFER_DST = 0x2;
         /// Buffer can be used to create a descriptor buffer info
         const UNIFORM_BUFFER = 0x10;
+        /// Buffer can be used to create a descriptor buffer info for a storage buffer
+        const STORAGE_BUFFER = 0x20;
         /// Buffer is able to be passed to `bind_index_buffer`
         const INDEX_BUFFER = 0x40;
         /// Buffer is able to be passed to `bind_vertex_buffer`
         const VERTEX_BUFFER = 0x80;
+        /// Buffer's device address can be queried with `device_address` and used in shaders,
+        /// requires [DeviceExtension::BufferDeviceAddress]
+        const SHADER_DEVICE_ADDRESS = 0x20000;
     }
 }
 
@@ -60,8 +91,14 @@ impl Into<vk::MemoryPropertyFlags> for MemoryPropertyFlags {
 pub struct Buffer<T, const CAPACITY: usize> {
     /// Mapped memory address where writes can be seen by both the device and host
     mapped: Option<*mut T>,
+    /// Number of instances of `T` covered by the mapped memory range
+    mapped_len: usize,
     /// Usage flags set on the buffer
     usage: BufferUsageFlags,
+    /// Properties of the device memory backing the buffer
+    memory_properties: MemoryPropertyFlags,
+    /// Size, in bytes, of a single instance of `T` after being aligned
+    instance_size: usize,
     /// Size, in bytes, of the buffer assuming the whole capacity is used up
     size: usize,
 
@@ -80,13 +117,37 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
         usage: BufferUsageFlags,
         memory_properties: MemoryPropertyFlags,
     ) -> Result<Buffer<T, CAPACITY>, BufferError> {
+        Buffer::with_instance_size(device, usage, memory_properties, std::mem::size_of::<T>())
+    }
+
+    /// Creates a new [`Buffer`] where every instance takes up at least `instance_size` bytes
+    fn with_instance_size(
+        device: Rc<LogicalDevice>,
+        usage: BufferUsageFlags,
+        memory_properties: MemoryPropertyFlags,
+        instance_size: usize,
+    ) -> Result<Buffer<T, CAPACITY>, BufferError> {
+        let has_device_address = usage.contains(BufferUsageFlags::SHADER_DEVICE_ADDRESS);
+        if has_device_address
+            && !device
+                .physical_device()
+                .enabled_extensions()
+                .contains(&DeviceExtension::BufferDeviceAddress)
+        {
+            return Err(BufferError::MissingExtension(
+                DeviceExtension::BufferDeviceAddress,
+            ));
+        }
+
+        let limits = &device.physical_device().properties().limits;
         let min_offset_alignment = if usage.contains(BufferUsageFlags::UNIFORM_BUFFER) {
-            device.physical_device().properties().limits.min_uniform_buffer_offset_alignment
+            limits.min_uniform_buffer_offset_alignment
+        } else if usage.contains(BufferUsageFlags::STORAGE_BUFFER) {
+            limits.min_storage_buffer_offset_alignment
         } else {
             1
         };
 
-        let instance_size = std::mem::size_of::<T>();
         let alignment_size = (instance_size + min_offset_alignment as usize - 1)
             & !(min_offset_alignment as usize - 1);
         let buffer_size = alignment_size * CAPACITY;
@@ -108,16 +169,16 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
         let memory_type =
             device.find_memory_type(memory_requirements.memory_type_bits, memory_properties)?;
 
-        let allocate_info = vk::MemoryAllocateInfo::builder()
+        let mut allocate_flags_info = vk::MemoryAllocateFlagsInfo::builder()
+            .flags(vk::MemoryAllocateFlags::DEVICE_ADDRESS);
+        let mut allocate_info = vk::MemoryAllocateInfo::builder()
             .allocation_size(memory_requirements.size)
             .memory_type_index(memory_type);
+        if has_device_address {
+            allocate_info = allocate_info.push_next(&mut allocate_flags_info);
+        }
 
-        let memory = unsafe {
-            device
-                .vk_handle()
-                .allocate_memory(&allocate_info, None)
-                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?
-        };
+        let memory = device.allocate_memory(&allocate_info, AllocationKind::Buffer)?;
 
         unsafe {
             device
@@ -128,7 +189,10 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
 
         Ok(Buffer {
             mapped: None,
+            mapped_len: 0,
             usage,
+            memory_properties,
+            instance_size: alignment_size,
             size: buffer_size,
 
             handle,
@@ -147,12 +211,16 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
     /// Returns a corresponding descriptor buffer info if the buffer has been marked with a
     /// `BufferUsageFlag` that can be used in a descriptor set.
     pub fn descriptor(&self) -> Option<vk::DescriptorBufferInfo> {
-        if self.usage.contains(BufferUsageFlags::UNIFORM_BUFFER) {
-        Some(vk::DescriptorBufferInfo {
-            buffer: self.handle,
-            offset: 0,
-            range: vk::WHOLE_SIZE,
-        }) } else {
+        if self
+            .usage
+            .intersects(BufferUsageFlags::UNIFORM_BUFFER | BufferUsageFlags::STORAGE_BUFFER)
+        {
+            Some(vk::DescriptorBufferInfo {
+                buffer: self.handle,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            })
+        } else {
             None
         }
     }
@@ -161,6 +229,26 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
         self.usage
     }
 
+    /// Returns the address of the buffer in device memory, that can be passed to shaders to
+    /// access the buffer through a pointer.
+    ///
+    /// # Errors
+    /// - [BufferError::NoDeviceAddress] - The buffer wasn't created with
+    ///   [BufferUsageFlags::SHADER_DEVICE_ADDRESS]
+    pub fn device_address(&self) -> Result<u64, BufferError> {
+        if !self.usage.contains(BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+            return Err(BufferError::NoDeviceAddress);
+        }
+
+        let buffer_device_address = ash::extensions::khr::BufferDeviceAddress::new(
+            self.device.instance().vk_handle(),
+            self.device.vk_handle(),
+        );
+        let info = vk::BufferDeviceAddressInfo::builder().buffer(self.handle);
+
+        Ok(unsafe { buffer_device_address.get_buffer_device_address(&info) })
+    }
+
     /// Returns a Vulkan handle to the Vulkan buffer
     pub(crate) fn vk_handle(&self) -> vk::Buffer {
         self.handle
@@ -178,17 +266,53 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
                 as *mut T
         });
 
+        let mapped_size = if size == vk::WHOLE_SIZE {
+            (self.size as u64).saturating_sub(offset)
+        } else {
+            size.min((self.size as u64).saturating_sub(offset))
+        };
+        self.mapped_len = mapped_size as usize / self.instance_size;
+
         Ok(())
     }
 
+    /// Returns a slice over the mapped device memory, or `None` if the buffer isn't mapped.
+    ///
+    /// The slice only covers the instances of `T` that lie within the range passed to
+    /// [`Buffer::map`]. `None` is also returned if the instances in the buffer are padded for
+    /// alignment (e.g. uniform buffers that hold a `T` smaller than the device's minimum uniform
+    /// buffer offset alignment), as they can't be laid out contiguously as a slice.
+    pub fn mapped_slice(&self) -> Option<&[T]> {
+        let mapped = self.contiguous_mapping()?;
+        Some(unsafe { std::slice::from_raw_parts(mapped, self.mapped_len) })
+    }
+
+    /// Returns a mutable slice over the mapped device memory, or `None` if the buffer isn't
+    /// mapped.
+    ///
+    /// Writes through the slice are visible to the device once the memory is unmapped, or
+    /// immediately if the memory is [`MemoryPropertyFlags::HOST_COHERENT`]. See
+    /// [`Buffer::mapped_slice`] for the range the slice covers.
+    pub fn mapped_slice_mut(&mut self) -> Option<&mut [T]> {
+        let mapped = self.contiguous_mapping()?;
+        Some(unsafe { std::slice::from_raw_parts_mut(mapped, self.mapped_len) })
+    }
+
+    /// Returns the mapped memory address if the instances of `T` in it can be viewed as a slice
+    fn contiguous_mapping(&self) -> Option<*mut T> {
+        let mapped = self.mapped?;
+        is_contiguous(self.instance_size, mapped).then_some(mapped)
+    }
+
     /// Unmaps the device memory associated with the buffer meaning write operations will have no
     /// effect.
     pub fn unmap(&mut self) {
-        if let Some(_) = self.mapped {
+        if self.mapped.is_some() {
             unsafe {
                 self.device.vk_handle().unmap_memory(self.memory);
             };
             self.mapped = None;
+            self.mapped_len = 0;
         }
     }
 
@@ -205,6 +329,97 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
         }
     }
 
+    /// Writes `data` into the buffer starting at the instance at index `offset`, only mapping and
+    /// synchronizing the bytes being written.
+    ///
+    /// If the memory isn't [`MemoryPropertyFlags::HOST_COHERENT`] the written range is flushed,
+    /// expanded to the device's non-coherent atom size, so only the dirty bytes are made visible
+    /// to the device instead of the whole buffer. The memory is unmapped after the write.
+    ///
+    /// # Errors
+    /// - [BufferError::OutOfRange] - `data` doesn't fit in the buffer at `offset`
+    /// - [BufferError::AlreadyMapped] - The buffer was already mapped with [`Buffer::map`]
+    pub fn write_range(&mut self, offset: usize, data: &[T]) -> Result<(), BufferError> {
+        let instance_size = self.instance_size;
+        self.write_instances(offset, data.len(), |base| unsafe {
+            if instance_size == std::mem::size_of::<T>() {
+                (base as *mut T).copy_from_nonoverlapping(data.as_ptr(), data.len());
+            } else {
+                for (i, instance) in data.iter().enumerate() {
+                    (base.add(i * instance_size) as *mut T).copy_from_nonoverlapping(instance, 1);
+                }
+            }
+        })
+    }
+
+    /// Maps the `len` instances starting at the instance at index `offset`, calls `write` with
+    /// the address of the first one and then flushes and unmaps them, see [`Buffer::write_range`]
+    fn write_instances(
+        &mut self,
+        offset: usize,
+        len: usize,
+        write: impl FnOnce(*mut u8),
+    ) -> Result<(), BufferError> {
+        if offset + len > CAPACITY {
+            return Err(BufferError::OutOfRange {
+                offset,
+                len,
+                capacity: CAPACITY,
+            });
+        }
+
+        if self.mapped.is_some() {
+            return Err(BufferError::AlreadyMapped);
+        }
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        let atom_size = self
+            .device
+            .physical_device()
+            .properties()
+            .limits
+            .non_coherent_atom_size;
+        let start = (offset * self.instance_size) as u64;
+        let end = ((offset + len) * self.instance_size) as u64;
+        let (map_offset, map_size) = flush_range(start, end, atom_size, self.size as u64);
+
+        let mapped = unsafe {
+            self.device
+                .vk_handle()
+                .map_memory(self.memory, map_offset, map_size, vk::MemoryMapFlags::empty())
+                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?
+                as *mut u8
+        };
+
+        write(unsafe { mapped.add((start - map_offset) as usize) });
+
+        let flushed = if self
+            .memory_properties
+            .contains(MemoryPropertyFlags::HOST_COHERENT)
+        {
+            Ok(())
+        } else {
+            let range = vk::MappedMemoryRange::builder()
+                .memory(self.memory)
+                .offset(map_offset)
+                .size(map_size);
+            unsafe {
+                self.device
+                    .vk_handle()
+                    .flush_mapped_memory_ranges(std::slice::from_ref(&range))
+            }
+        };
+
+        unsafe {
+            self.device.vk_handle().unmap_memory(self.memory);
+        };
+
+        flushed.map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))
+    }
+
     /// Copies data from a buffer with the same data type and capacity to this buffer's device
     /// memory through the use of a transfer command.
     ///
@@ -227,84 +442,386 @@ impl<T, const CAPACITY: usize> Buffer<T, CAPACITY> {
             ));
         }
 
-        let allocate_info = vk::CommandBufferAllocateInfo::builder()
-            .command_buffer_count(1)
-            .command_pool(command_pool.vk_handle())
-            .level(vk::CommandBufferLevel::PRIMARY);
-
-        let command_buffers = unsafe {
-            self.device
-                .vk_handle()
-                .allocate_command_buffers(&allocate_info)
-                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?
-        };
-        let command_buffer = command_buffers[0];
-
-        let begin_info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-
-        unsafe {
-            self.device
-                .vk_handle()
-                .begin_command_buffer(command_buffer, &begin_info)
-                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?;
-
+        command_pool.immediate_submit(|command_buffer| {
             let copy_regions = [vk::BufferCopy {
                 src_offset: 0,
                 dst_offset: 0,
                 size: self.size as u64,
             }];
 
-            self.device.vk_handle().cmd_copy_buffer(
-                command_buffer,
-                buffer.handle,
-                self.handle,
-                &copy_regions,
-            );
+            unsafe {
+                self.device.vk_handle().cmd_copy_buffer(
+                    command_buffer.vk_handle(),
+                    buffer.handle,
+                    self.handle,
+                    &copy_regions,
+                );
+            };
+        })?;
 
-            self.device
-                .vk_handle()
-                .end_command_buffer(command_buffer)
-                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?;
-        };
+        Ok(())
+    }
+}
+
+impl<T, const CAPACITY: usize> Buffer<T, CAPACITY>
+where
+    T: UniformBuffer,
+{
+    /// Creates a new [`Buffer`] of uniform buffers, where every instance has room for the fields
+    /// of `T` laid out using std140 rules, which can take up more space than `T` itself
+    pub fn new_uniform(
+        device: Rc<LogicalDevice>,
+        usage: BufferUsageFlags,
+        memory_properties: MemoryPropertyFlags,
+    ) -> Result<Buffer<T, CAPACITY>, BufferError> {
+        let size = std140_size(&T::get_field_descriptions()).max(std::mem::size_of::<T>());
+        Buffer::with_instance_size(device, usage, memory_properties, size)
+    }
 
-        let submit_infos = [vk::SubmitInfo::builder()
-            .command_buffers(&command_buffers)
-            .build()];
+    /// Writes `data` into the buffer starting at the instance at index `offset`, with the fields
+    /// of every instance at their std140 offsets, see [UniformBuffer::as_std140_bytes]. Only the
+    /// bytes being written are mapped and synchronized, like [`Buffer::write_range`].
+    ///
+    /// # Errors
+    /// - [BufferError::OutOfRange] - `data` doesn't fit in the buffer at `offset`
+    /// - [BufferError::AlreadyMapped] - The buffer was already mapped with [`Buffer::map`]
+    /// - [BufferError::UniformTooLarge] - The instances of the buffer are smaller than `T` laid
+    ///   out using std140 rules
+    pub fn write_uniform(&mut self, offset: usize, data: &[T]) -> Result<(), BufferError> {
+        let size = std140_size(&T::get_field_descriptions());
+        let instance_size = self.instance_size;
+        if size > instance_size {
+            return Err(BufferError::UniformTooLarge {
+                size,
+                instance_size,
+            });
+        }
 
+        self.write_instances(offset, data.len(), |base| {
+            for (i, instance) in data.iter().enumerate() {
+                let bytes = instance.as_std140_bytes();
+                unsafe {
+                    base.add(i * instance_size)
+                        .copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+                };
+            }
+        })
+    }
+}
+
+impl<T, const CAPACITY: usize> Drop for Buffer<T, CAPACITY> {
+    fn drop(&mut self) {
+        self.unmap();
         unsafe {
-            let transfer_queue = self.device.queue(QueueFlags::GRAPHICS).ok_or(BufferError::InvalidCopy("Missing transfer queue on logical devcie"))?;
+            self.device.vk_handle().destroy_buffer(self.handle, None);
+        };
+        self.device.free_memory(self.memory);
+    }
+}
 
-            self.device
+
+/// A sub-allocation of a [FrameArena], only valid until the arena is reset
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaAllocation {
+    /// Opaque handle to the Vulkan buffer of the arena the allocation is in
+    pub(crate) buffer: vk::Buffer,
+    /// Offset, in bytes, of the allocation in the arena's buffer
+    pub offset: u64,
+    /// Size, in bytes, of the allocation
+    pub size: u64,
+}
+
+/// Linear allocator over a persistently mapped, host visible buffer, for transient data that is
+/// generated every frame such as debug lines or UI vertices.
+///
+/// Allocations are handed out one after the other and are all reclaimed at once with
+/// [FrameArena::reset], instead of creating a [Buffer] per draw. Use one arena per frame in
+/// flight and only reset it once the frame that last used it has finished executing.
+pub struct FrameArena {
+    /// Address of the mapped buffer memory
+    mapped: *mut u8,
+    /// Offset, in bytes, of the next free byte in the arena
+    offset: u64,
+    /// Size, in bytes, of the arena
+    capacity: u64,
+    /// Alignment, in bytes, of every allocation's offset
+    alignment: u64,
+    /// Usage flags set on the arena's buffer
+    usage: BufferUsageFlags,
+
+    /// Opaque handle to Vulkan buffer
+    handle: vk::Buffer,
+    /// Opaque handle to Vulkan device memory belonging to the buffer
+    memory: vk::DeviceMemory,
+    /// [`LogicalDevice`] the buffer and memory belong to
+    device: Rc<LogicalDevice>,
+}
+
+impl FrameArena {
+    /// Creates a new [FrameArena] that can hold `capacity` bytes each frame
+    pub fn new(
+        device: Rc<LogicalDevice>,
+        capacity: u64,
+        usage: BufferUsageFlags,
+    ) -> Result<FrameArena, BufferError> {
+        let limits = &device.physical_device().properties().limits;
+        let mut alignment = 16;
+        if usage.contains(BufferUsageFlags::UNIFORM_BUFFER) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment);
+        }
+        if usage.contains(BufferUsageFlags::STORAGE_BUFFER) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment);
+        }
+
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(capacity)
+            .usage(usage.into())
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let handle = unsafe {
+            device
                 .vk_handle()
-                .queue_submit(
-                    transfer_queue.handle,
-                    &submit_infos,
-                    vk::Fence::null(),
-                )
-                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?;
+                .create_buffer(&create_info, None)
+                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?
+        };
 
-            self.device
+        let memory_requirements =
+            unsafe { device.vk_handle().get_buffer_memory_requirements(handle) };
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(device.find_memory_type(
+                memory_requirements.memory_type_bits,
+                MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+            )?);
+        let memory = device.allocate_memory(&allocate_info, AllocationKind::Buffer)?;
+
+        let mapped = unsafe {
+            device
                 .vk_handle()
-                .queue_wait_idle(transfer_queue.handle)
+                .bind_buffer_memory(handle, memory, 0)
                 .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?;
-
-            self.device
+            device
                 .vk_handle()
-                .free_command_buffers(command_pool.vk_handle(), &command_buffers);
+                .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                .map_err(|err| BufferError::DeviceError(LogicalDeviceError::Other(err.into())))?
+                as *mut u8
         };
 
-        Ok(())
+        Ok(FrameArena {
+            mapped,
+            offset: 0,
+            capacity,
+            alignment,
+            usage,
+
+            handle,
+            memory,
+            device,
+        })
     }
 }
 
-impl<T, const CAPACITY: usize> Drop for Buffer<T, CAPACITY> {
+impl FrameArena {
+    /// Returns the size, in bytes, of the arena
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the number of bytes allocated since the arena was last reset, including padding
+    pub fn used(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the usage flags set on the arena's buffer
+    pub fn usage(&self) -> BufferUsageFlags {
+        self.usage
+    }
+}
+
+impl FrameArena {
+    /// Copies `data` into the next free, aligned, region of the arena
+    ///
+    /// # Errors
+    /// - [BufferError::ArenaFull] - There isn't enough space left in the arena for `data`
+    pub fn alloc<T>(&mut self, data: &[T]) -> Result<ArenaAllocation, BufferError> {
+        let size = std::mem::size_of_val(data) as u64;
+        let alignment = self.alignment.max(std::mem::align_of::<T>() as u64);
+        let offset = arena_offset(self.offset, self.capacity, size, alignment)?;
+
+        unsafe {
+            (self.mapped.add(offset as usize) as *mut T)
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+        };
+        self.offset = offset + size;
+
+        Ok(ArenaAllocation {
+            buffer: self.handle,
+            offset,
+            size,
+        })
+    }
+
+    /// Copies `vertices` into the arena, so they can be bound with
+    /// [CommandBuffer::bind_vertex_allocation]
+    ///
+    /// [CommandBuffer::bind_vertex_allocation]:
+    /// crate::core::commands::buffer::CommandBuffer::bind_vertex_allocation
+    ///
+    /// # Errors
+    /// - [BufferError::MissingUsage] - The arena wasn't created with
+    ///   [BufferUsageFlags::VERTEX_BUFFER]
+    /// - [BufferError::ArenaFull] - There isn't enough space left in the arena for `vertices`
+    pub fn alloc_vertices<V: Vertex>(
+        &mut self,
+        vertices: &[V],
+    ) -> Result<ArenaAllocation, BufferError> {
+        if !self.usage.contains(BufferUsageFlags::VERTEX_BUFFER) {
+            return Err(BufferError::MissingUsage(BufferUsageFlags::VERTEX_BUFFER));
+        }
+
+        vertex::debug_assert_layout::<V>();
+        self.alloc(vertices)
+    }
+
+    /// Reclaims every allocation made since the last reset, the device must have finished using
+    /// them
+    pub fn reset(&mut self) {
+        self.offset = 0;
+    }
+}
+
+/// Returns whether instances of `T` that are `instance_size` bytes apart starting at `mapped` can
+/// be viewed as a slice, i.e. they aren't padded and the address is aligned for `T`
+fn is_contiguous<T>(instance_size: usize, mapped: *mut T) -> bool {
+    instance_size == std::mem::size_of::<T>() && mapped.is_aligned()
+}
+
+/// Returns the offset and size of the range to map and flush so that the bytes from `start` to
+/// `end` of memory that is `memory_size` bytes large are made visible to the device
+///
+/// Flushed ranges have to start and end on multiples of the non-coherent `atom_size`, unless they
+/// end at the end of the memory, in which case the size is [vk::WHOLE_SIZE].
+fn flush_range(start: u64, end: u64, atom_size: u64, memory_size: u64) -> (u64, u64) {
+    let offset = start - start % atom_size;
+    let aligned_end = end.div_ceil(atom_size) * atom_size;
+    if aligned_end >= memory_size {
+        (offset, vk::WHOLE_SIZE)
+    } else {
+        (offset, aligned_end - offset)
+    }
+}
+
+/// Returns the offset, in bytes, of an allocation of `size` bytes aligned to `alignment` in an
+/// arena of `capacity` bytes whose next free byte is at `offset`
+///
+/// # Errors
+/// - [BufferError::ArenaFull] - The allocation doesn't fit in the rest of the arena
+fn arena_offset(offset: u64, capacity: u64, size: u64, alignment: u64) -> Result<u64, BufferError> {
+    let offset = offset.div_ceil(alignment) * alignment;
+    if offset + size > capacity {
+        return Err(BufferError::ArenaFull {
+            requested: size,
+            remaining: capacity.saturating_sub(offset),
+        });
+    }
+
+    Ok(offset)
+}
+
+impl Drop for FrameArena {
     fn drop(&mut self) {
-        self.unmap();
         unsafe {
+            self.device.vk_handle().unmap_memory(self.memory);
             self.device.vk_handle().destroy_buffer(self.handle, None);
-            self.device.vk_handle().free_memory(self.memory, None);
         };
+        self.device.free_memory(self.memory);
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allocates `sizes` one after the other in an arena of `capacity` bytes starting at
+    /// `offset`, returning the range of every allocation and the arena's new offset
+    fn allocate(
+        mut offset: u64,
+        capacity: u64,
+        sizes: &[u64],
+        alignment: u64,
+    ) -> Result<(Vec<std::ops::Range<u64>>, u64), BufferError> {
+        let mut ranges = Vec::with_capacity(sizes.len());
+        for &size in sizes {
+            let start = arena_offset(offset, capacity, size, alignment)?;
+            ranges.push(start..start + size);
+            offset = start + size;
+        }
+
+        Ok((ranges, offset))
+    }
+
+    #[test]
+    fn allocations_are_aligned_and_dont_overlap() {
+        let (ranges, used) = allocate(0, 256, &[12, 1, 40, 16], 16).unwrap();
+
+        assert_eq!(ranges, vec![0..12, 16..17, 32..72, 80..96]);
+        assert_eq!(used, 96);
+        for (range, next) in ranges.iter().zip(ranges.iter().skip(1)) {
+            assert_eq!(next.start % 16, 0);
+            assert!(range.end <= next.start);
+        }
+    }
+
+    #[test]
+    fn full_arena_reports_the_remaining_space() {
+        let (_, used) = allocate(0, 64, &[40], 16).unwrap();
+
+        match arena_offset(used, 64, 32, 16) {
+            Err(BufferError::ArenaFull {
+                requested,
+                remaining,
+            }) => {
+                assert_eq!(requested, 32);
+                assert_eq!(remaining, 16);
+            }
+            result => panic!("Expected the arena to be full, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reset_reclaims_space() {
+        let (_, used) = allocate(0, 64, &[40], 16).unwrap();
+        assert!(arena_offset(used, 64, 32, 16).is_err());
+
+        // A reset arena starts allocating from its first byte again
+        assert_eq!(arena_offset(0, 64, 32, 16).unwrap(), 0);
+    }
+
+    #[test]
+    fn flush_range_is_expanded_to_the_atom_size() {
+        assert_eq!(flush_range(0, 64, 64, 1024), (0, 64));
+        assert_eq!(flush_range(72, 100, 64, 1024), (64, 64));
+        assert_eq!(flush_range(120, 136, 64, 1024), (64, 128));
+    }
+
+    #[test]
+    fn flush_range_reaching_the_end_of_memory_is_whole_size() {
+        assert_eq!(flush_range(72, 1000, 64, 1024), (64, vk::WHOLE_SIZE));
+        assert_eq!(flush_range(960, 1024, 64, 1024), (960, vk::WHOLE_SIZE));
+    }
+
+    #[test]
+    fn unpadded_instances_are_contiguous() {
+        let mut instances = [[0.0f32; 4]; 2];
+
+        assert!(is_contiguous(16, instances.as_mut_ptr()));
+    }
+
+    #[test]
+    fn padded_instances_are_not_contiguous() {
+        let mut instances = [[0.0f32; 4]; 16];
+
+        // A 16 byte instance padded to a 256 byte uniform buffer offset alignment
+        assert!(!is_contiguous(256, instances.as_mut_ptr()));
+    }
+}
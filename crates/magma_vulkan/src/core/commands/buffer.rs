@@ -1,10 +1,13 @@
 use ash::vk;
+use bitflags::bitflags;
 use std::rc::Rc;
 
 use crate::{
-    buffer::{Buffer, BufferUsageFlags},
+    buffer::{ArenaAllocation, Buffer, BufferUsageFlags},
     core::device::LogicalDevice,
-    pipeline::{ubo::UniformBuffer, vertex::Vertex, Pipeline},
+    descriptors::DescriptorSet,
+    pipeline::{shader::ShaderStageFlags, ubo::UniformBuffer, vertex::Vertex, Pipeline},
+    render_pass::RenderPass,
     VulkanError,
 };
 
@@ -19,6 +22,40 @@ pub enum CommandBufferError {
         "A {0} command is already started, end that one before starting another of the same type"
     )]
     CommandAlreadyStarted(&'static str),
+    #[error("A {0} command can only be recorded inside a render pass")]
+    OutsideRenderPass(&'static str),
+    #[error("A {0} command can't be recorded inside a render pass")]
+    InsideRenderPass(&'static str),
+    #[error("The render pass clears attachments up to index {}, but only {count} clear values were given", expected - 1)]
+    ClearValueCount { expected: usize, count: usize },
+    #[error("{count} clear values were given, but the render pass only has {attachments} attachments")]
+    TooManyClearValues { attachments: usize, count: usize },
+    #[error("A {0} command can only be recorded once a pipeline is bound")]
+    NoPipelineBound(&'static str),
+    #[error("A different pipeline is bound than the one the {0} command was recorded for")]
+    PipelineMismatch(&'static str),
+    #[error("Push constant of {size} bytes at offset {offset} for stages {stages:?} doesn't fit in the pipeline's push constant range of {range_size} bytes for stages {range_stages:?}")]
+    PushConstantOutOfRange {
+        stages: ShaderStageFlags,
+        offset: u32,
+        size: u32,
+        range_stages: ShaderStageFlags,
+        range_size: u32,
+    },
+    #[error("The descriptor set bound at set {0} isn't compatible with the pipeline's layout for that set")]
+    IncompatibleDescriptorSet(u32),
+    #[error("The bound pipeline wasn't created with the {0} dynamic state enabled")]
+    DynamicStateDisabled(&'static str),
+    #[error("The buffer must have been created with the {0:?} usage")]
+    MissingBufferUsage(BufferUsageFlags),
+    #[error("The elements of the index buffer don't match the size of {0:?} indices")]
+    IndexTypeMismatch(IndexType),
+    #[error("Binding {binding} of the pipeline has a stride of {stride} bytes, but the buffer bound to it holds elements of {size} bytes")]
+    VertexStrideMismatch { binding: u32, stride: u32, size: usize },
+    #[error("The pipeline's vertex type has no binding with an instance input rate")]
+    NoInstanceBinding,
+    #[error("Can't draw {count} instances from an instance buffer with a capacity of {capacity}")]
+    InstanceCountOutOfRange { count: u32, capacity: usize },
     #[error(transparent)]
     DeviceError(VulkanError),
 }
@@ -69,18 +106,300 @@ impl Into<vk::CommandBufferLevel> for CommandBufferLevel {
     }
 }
 
-/// Wraps a Vulkan command buffer
-#[derive(Clone)]
-pub struct CommandBuffer {
+/// Possible types of the indices in an index buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexType {
+    U16,
+    U32,
+}
+
+impl IndexType {
+    /// Returns the size of a single index in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            IndexType::U16 => std::mem::size_of::<u16>(),
+            IndexType::U32 => std::mem::size_of::<u32>(),
+        }
+    }
+
+    /// Returns the index value that restarts primitive assembly when primitive restart is
+    /// enabled on the pipeline
+    pub fn restart_index(&self) -> u32 {
+        match self {
+            IndexType::U16 => u16::MAX as u32,
+            IndexType::U32 => u32::MAX,
+        }
+    }
+}
+
+impl Into<vk::IndexType> for IndexType {
+    fn into(self) -> vk::IndexType {
+        match self {
+            IndexType::U16 => vk::IndexType::UINT16,
+            IndexType::U32 => vk::IndexType::UINT32,
+        }
+    }
+}
+
+/// Value to clear an attachment to when a render pass begins
+#[derive(Clone, Copy, Debug)]
+pub enum ClearValue {
+    /// Clears a color attachment to an RGBA color
+    Color(f32, f32, f32, f32),
+    /// Clears a depth stencil attachment
+    DepthStencil { depth: f32, stencil: u32 },
+}
+
+impl Into<vk::ClearValue> for ClearValue {
+    fn into(self) -> vk::ClearValue {
+        match self {
+            ClearValue::Color(r, g, b, a) => vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [r, g, b, a],
+                },
+            },
+            ClearValue::DepthStencil { depth, stencil } => vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+            },
+        }
+    }
+}
+
+/// Describes an attachment of the current subpass to clear with
+/// [CommandBuffer::clear_attachments]
+#[derive(Clone, Copy, Debug)]
+pub enum ClearAttachment {
+    /// Clears the color attachment at index `attachment` of the subpass to an RGBA `color`
+    Color {
+        attachment: u32,
+        color: (f32, f32, f32, f32),
+    },
+    /// Clears the depth aspect of the depth stencil attachment
+    Depth(f32),
+    /// Clears the stencil aspect of the depth stencil attachment
+    Stencil(u32),
+    /// Clears both the depth and stencil aspects of the depth stencil attachment
+    DepthStencil { depth: f32, stencil: u32 },
+}
+
+impl Into<vk::ClearAttachment> for ClearAttachment {
+    fn into(self) -> vk::ClearAttachment {
+        match self {
+            ClearAttachment::Color { attachment, color } => vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                color_attachment: attachment,
+                clear_value: vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [color.0, color.1, color.2, color.3],
+                    },
+                },
+            },
+            ClearAttachment::Depth(depth) => vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                color_attachment: 0,
+                clear_value: vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue { depth, stencil: 0 },
+                },
+            },
+            ClearAttachment::Stencil(stencil) => vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::STENCIL,
+                color_attachment: 0,
+                clear_value: vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 0.0,
+                        stencil,
+                    },
+                },
+            },
+            ClearAttachment::DepthStencil { depth, stencil } => vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+                color_attachment: 0,
+                clear_value: vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+                },
+            },
+        }
+    }
+}
+
+/// Region of the attachments to clear with [CommandBuffer::clear_attachments]
+#[derive(Clone, Copy, Debug)]
+pub struct ClearRect {
+    /// Offset of the top left corner of the region
+    pub offset: (i32, i32),
+    /// Width and height of the region
+    pub extent: (u32, u32),
+    /// First layer of the attachments to clear
+    pub base_array_layer: u32,
+    /// Number of layers of the attachments to clear
+    pub layer_count: u32,
+}
+
+impl ClearRect {
+    /// Creates a [ClearRect] covering the first layer of an attachment with size `extent`
+    pub fn new(extent: (u32, u32)) -> ClearRect {
+        ClearRect {
+            offset: (0, 0),
+            extent,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+}
+
+impl Into<vk::ClearRect> for ClearRect {
+    fn into(self) -> vk::ClearRect {
+        vk::ClearRect {
+            rect: vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: self.offset.0,
+                    y: self.offset.1,
+                },
+                extent: vk::Extent2D {
+                    width: self.extent.0,
+                    height: self.extent.1,
+                },
+            },
+            base_array_layer: self.base_array_layer,
+            layer_count: self.layer_count,
+        }
+    }
+}
+
+bitflags! {
+    /// Wraps VkCommandBufferUsageFlagBits
+    pub struct CommandBufferUsageFlags: u32 {
+        /// Command buffer will be submitted once and then re-recorded, suits command buffers that
+        /// are recorded every frame
+        const ONE_TIME_SUBMIT = 0x1;
+        /// Secondary command buffer is entirely inside a render pass
+        const RENDER_PASS_CONTINUE = 0x2;
+        /// Command buffer can be resubmitted while it is already pending execution
+        const SIMULTANEOUS_USE = 0x4;
+    }
+}
+
+impl Into<vk::CommandBufferUsageFlags> for CommandBufferUsageFlags {
+    fn into(self) -> vk::CommandBufferUsageFlags {
+        vk::CommandBufferUsageFlags::from_raw(self.bits())
+    }
+}
+
+/// What has been recorded on a [CommandBuffer] so far, which determines the commands that can be
+/// recorded next
+#[derive(Clone, Debug)]
+struct Recording {
     /// Which state the command buffer is in currently, determines what the command buffer can be
     /// used for and what methods can be used
     state: CommandBufferState,
     /// Whether a render pass was started on the command buffer
     started_render_pass: bool,
-    /// The color to clear to
-    clear_color: (f32, f32, f32),
     /// Vulkan handle of the currently bound graphics pipeline
     current_pipeline: Option<vk::Pipeline>,
+    /// Whether the currently bound graphics pipeline has dynamic depth bias enabled
+    dynamic_depth_bias: bool,
+}
+
+impl Recording {
+    /// Creates the [Recording] of a command buffer that was just allocated
+    fn new() -> Recording {
+        Recording {
+            state: CommandBufferState::Initial,
+            started_render_pass: false,
+            current_pipeline: None,
+            dynamic_depth_bias: false,
+        }
+    }
+
+    /// Checks that recording can begin, which requires the command buffer to not be recording
+    /// already, re-recording an executable or invalid command buffer is allowed
+    fn check_can_begin(&self) -> Result<(), CommandBufferError> {
+        if !(self.state == CommandBufferState::Initial
+            || self.state == CommandBufferState::Invalid
+            || self.state == CommandBufferState::Executable)
+        {
+            return Err(CommandBufferError::IncorrectState(
+                CommandBufferState::Initial,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Starts a new recording, forgetting the pipeline bound in the previous one
+    fn begin(&mut self) {
+        self.state = CommandBufferState::Recording;
+        self.current_pipeline = None;
+        self.dynamic_depth_bias = false;
+    }
+
+    /// Checks that the command buffer is recording
+    fn check_recording(&self) -> Result<(), CommandBufferError> {
+        if self.state != CommandBufferState::Recording {
+            return Err(CommandBufferError::IncorrectState(
+                CommandBufferState::Recording,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the command buffer is recording with `pipeline` bound, for a `command` that
+    /// is recorded for that pipeline
+    fn check_pipeline_bound(
+        &self,
+        pipeline: vk::Pipeline,
+        command: &'static str,
+    ) -> Result<(), CommandBufferError> {
+        self.check_recording()?;
+
+        match self.current_pipeline {
+            Some(current_pipeline) if current_pipeline != pipeline => {
+                Err(CommandBufferError::PipelineMismatch(command))
+            }
+            Some(_) => Ok(()),
+            None => Err(CommandBufferError::NoPipelineBound(command)),
+        }
+    }
+
+    /// Checks that the depth bias can be set, which requires the command buffer to be recording
+    /// with a pipeline bound that has dynamic depth bias enabled
+    fn check_can_set_depth_bias(&self) -> Result<(), CommandBufferError> {
+        self.check_recording()?;
+
+        if self.current_pipeline.is_none() {
+            return Err(CommandBufferError::NoPipelineBound("set depth bias"));
+        }
+
+        if !self.dynamic_depth_bias {
+            return Err(CommandBufferError::DynamicStateDisabled("depth bias"));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a draw command can be recorded, which requires the command buffer to be
+    /// recording inside a render pass with a pipeline bound
+    fn check_can_draw(&self, command: &'static str) -> Result<(), CommandBufferError> {
+        self.check_recording()?;
+
+        if !self.started_render_pass {
+            return Err(CommandBufferError::OutsideRenderPass(command));
+        }
+
+        if self.current_pipeline.is_none() {
+            return Err(CommandBufferError::NoPipelineBound(command));
+        }
+
+        Ok(())
+    }
+}
+
+/// Wraps a Vulkan command buffer
+#[derive(Clone)]
+pub struct CommandBuffer {
+    /// What has been recorded on the command buffer so far
+    recording: Recording,
 
     /// Opaque handle to Vulkan command buffer
     handle: vk::CommandBuffer,
@@ -98,10 +417,7 @@ impl CommandBuffer {
     /// Creates a new [CommandBuffer]
     pub fn new(handle: vk::CommandBuffer, device: Rc<LogicalDevice>) -> CommandBuffer {
         CommandBuffer {
-            state: CommandBufferState::Initial,
-            started_render_pass: false,
-            clear_color: (1.0, 0.0, 1.0),
-            current_pipeline: None,
+            recording: Recording::new(),
 
             handle,
             device,
@@ -117,25 +433,21 @@ impl CommandBuffer {
 
     /// Returns the Vulkan handle to the graphics pipeline that was most previously bound
     pub fn currently_bound_pipeline(&self) -> Option<vk::Pipeline> {
-        self.current_pipeline
+        self.recording.current_pipeline
     }
 }
 
 impl CommandBuffer {
-    /// Begins recording a command buffer, transitioning it into the
+    /// Begins recording a command buffer with the usage `flags`, transitioning it into the
     /// [CommandBufferState::Recording] state.
-    pub fn begin(&mut self) -> Result<(), CommandBufferError> {
-        if !(self.state == CommandBufferState::Initial
-            || self.state == CommandBufferState::Invalid
-            || self.state == CommandBufferState::Executable)
-        {
-            return Err(CommandBufferError::IncorrectState(
-                CommandBufferState::Initial,
-            ));
-        }
+    ///
+    /// Use [CommandBufferUsageFlags::ONE_TIME_SUBMIT] for command buffers that are re-recorded
+    /// every time they are submitted, and [CommandBufferUsageFlags::SIMULTANEOUS_USE] for ones
+    /// that are recorded once and submitted again while still pending.
+    pub fn begin(&mut self, flags: CommandBufferUsageFlags) -> Result<(), CommandBufferError> {
+        self.recording.check_can_begin()?;
 
-        let begin_info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
+        let begin_info = vk::CommandBufferBeginInfo::builder().flags(flags.into());
 
         unsafe {
             self.device
@@ -143,7 +455,7 @@ impl CommandBuffer {
                 .begin_command_buffer(self.handle, &begin_info)
                 .map_err(|err| CommandBufferError::DeviceError(err.into()))?;
         };
-        self.state = CommandBufferState::Recording;
+        self.recording.begin();
 
         Ok(())
     }
@@ -151,13 +463,9 @@ impl CommandBuffer {
     /// Finishes recording the command buffer, transitioning the command buffer to the
     /// [CommandBufferState::Executable] state.
     pub fn end(&mut self) -> Result<(), CommandBufferError> {
-        if self.state != CommandBufferState::Recording {
-            return Err(CommandBufferError::IncorrectState(
-                CommandBufferState::Recording,
-            ));
-        }
+        self.recording.check_recording()?;
 
-        if self.started_render_pass {
+        if self.recording.started_render_pass {
             return Err(CommandBufferError::UnfinishedCommand("render pass"));
         }
 
@@ -167,31 +475,20 @@ impl CommandBuffer {
                 .end_command_buffer(self.handle)
                 .map_err(|err| CommandBufferError::DeviceError(err.into()))?;
         };
-        self.state = CommandBufferState::Executable;
+        self.recording.state = CommandBufferState::Executable;
 
         Ok(())
     }
 
-    /// Sets the clear color to use in the next render pass that is begun
-    pub fn set_clear_color(&mut self, color: (f32, f32, f32)) {
-        self.clear_color.0 = color.0.clamp(0.0, 1.0);
-        self.clear_color.1 = color.1.clamp(0.0, 1.0);
-        self.clear_color.2 = color.2.clamp(0.0, 1.0);
-    }
-
     /// Sets the Vulkan viewport, will have an depth of 0-1 and be positioned at (0,0)
     pub fn set_viewport(&mut self, width: f32, height: f32) -> Result<(), CommandBufferError> {
-        if self.state != CommandBufferState::Recording {
-            return Err(CommandBufferError::IncorrectState(
-                CommandBufferState::Recording,
-            ));
-        }
+        self.recording.check_recording()?;
 
         let viewports = [vk::Viewport {
             x: 0.0,
             y: 0.0,
-            width: width as f32,
-            height: height as f32,
+            width,
+            height,
             min_depth: 0.0,
             max_depth: 1.0,
         }];
@@ -207,11 +504,7 @@ impl CommandBuffer {
 
     /// Sets the Vulkan scissor, will have an offset of (0, 0)
     pub fn set_scissor(&mut self, extent: (u32, u32)) -> Result<(), CommandBufferError> {
-        if self.state != CommandBufferState::Recording {
-            return Err(CommandBufferError::IncorrectState(
-                CommandBufferState::Recording,
-            ));
-        }
+        self.recording.check_recording()?;
 
         let scissors = [vk::Rect2D {
             offset: vk::Offset2D { x: 0, y: 0 },
@@ -232,39 +525,28 @@ impl CommandBuffer {
 
     /// Begins a render pass on the command buffer.
     ///
-    /// The render pass will clear the framebuffer to the clear color of the [CommandBuffer]. The
-    /// depth stencil attachment will also be cleared
+    /// `clear_values` holds a value for each attachment of the render pass in the order of the
+    /// attachments, up to the last attachment that is cleared when loaded. Values for
+    /// attachments that aren't cleared are ignored, so trailing ones can be left out, but there
+    /// can't be more clear values than attachments.
     pub fn begin_render_pass(
         &mut self,
-        render_pass: vk::RenderPass,
+        render_pass: &RenderPass,
         framebuffer: vk::Framebuffer,
         extent: (u32, u32),
+        clear_values: &[ClearValue],
     ) -> Result<(), CommandBufferError> {
-        if self.started_render_pass {
+        if self.recording.started_render_pass {
             return Err(CommandBufferError::CommandAlreadyStarted("render pass"));
         }
 
-        let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [
-                        self.clear_color.0,
-                        self.clear_color.1,
-                        self.clear_color.2,
-                        1.0,
-                    ],
-                },
-            },
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 1.0,
-                    stencil: 0,
-                },
-            },
-        ];
+        check_clear_value_count(&render_pass.description().attachments, clear_values.len())?;
+
+        let clear_values: Vec<vk::ClearValue> =
+            clear_values.iter().map(|&value| value.into()).collect();
 
         let begin_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(render_pass)
+            .render_pass(render_pass.vk_handle())
             .framebuffer(framebuffer)
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D { x: 0, y: 0 },
@@ -283,20 +565,74 @@ impl CommandBuffer {
             );
         };
 
-        self.started_render_pass = true;
+        self.recording.started_render_pass = true;
         Ok(())
     }
 
     /// Ends a render pass on the [CommandBuffer]
     pub fn end_render_pass(&mut self) {
-        if self.started_render_pass {
+        if self.recording.started_render_pass {
             unsafe {
                 self.device.vk_handle().cmd_end_render_pass(self.handle);
             };
-            self.started_render_pass = false;
+            self.recording.started_render_pass = false;
         }
     }
 
+    /// Clears regions of attachments in the current subpass, e.g. to clear only the depth buffer
+    /// midway through a render pass
+    pub fn clear_attachments(
+        &mut self,
+        attachments: &[ClearAttachment],
+        rects: &[ClearRect],
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_recording()?;
+
+        if !self.recording.started_render_pass {
+            return Err(CommandBufferError::OutsideRenderPass("clear attachments"));
+        }
+
+        let attachments: Vec<vk::ClearAttachment> =
+            attachments.iter().map(|&attachment| attachment.into()).collect();
+        let rects: Vec<vk::ClearRect> = rects.iter().map(|&rect| rect.into()).collect();
+
+        unsafe {
+            self.device
+                .vk_handle()
+                .cmd_clear_attachments(self.handle, &attachments, &rects);
+        };
+
+        Ok(())
+    }
+
+    /// Records a pipeline barrier made of image memory barriers, e.g. to transition images between
+    /// layouts
+    pub(crate) fn image_barriers(
+        &mut self,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        barriers: &[vk::ImageMemoryBarrier],
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_recording()?;
+        if self.recording.started_render_pass {
+            return Err(CommandBufferError::InsideRenderPass("pipeline barrier"));
+        }
+
+        unsafe {
+            self.device.vk_handle().cmd_pipeline_barrier(
+                self.handle,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                barriers,
+            );
+        };
+
+        Ok(())
+    }
+
     /// Binds a graphics pipeline
     pub fn bind_pipeline<V, P>(&mut self, pipeline: &Pipeline<V, P>)
     where
@@ -310,19 +646,146 @@ impl CommandBuffer {
                 pipeline.vk_handle(),
             )
         };
-        self.current_pipeline = Some(pipeline.vk_handle());
+        self.recording.current_pipeline = Some(pipeline.vk_handle());
+        self.recording.dynamic_depth_bias = pipeline.has_dynamic_depth_bias();
+    }
+
+    /// Sets the depth bias used by the following draws, so that coplanar geometry such as
+    /// decals can be drawn over other geometry without z-fighting and without needing a separate
+    /// pipeline per bias.
+    ///
+    /// The bound pipeline must have been created with
+    /// [PipelineBuilder::dynamic_depth_bias][crate::pipeline::PipelineBuilder::dynamic_depth_bias],
+    /// and a non-zero `clamp` requires the `depth_bias_clamp` device feature.
+    pub fn set_depth_bias(
+        &mut self,
+        constant_factor: f32,
+        clamp: f32,
+        slope_factor: f32,
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_can_set_depth_bias()?;
+
+        unsafe {
+            self.device.vk_handle().cmd_set_depth_bias(
+                self.handle,
+                constant_factor,
+                clamp,
+                slope_factor,
+            );
+        };
+
+        Ok(())
+    }
+
+    /// Binds descriptor sets to the graphics bind point, starting at set number `first_set` of
+    /// `pipeline`'s layout. Must be called after `pipeline` has been bound.
+    ///
+    /// `dynamic_offsets` must contain an offset for every dynamic uniform or storage buffer in
+    /// `sets`, in the order of their sets and bindings.
+    ///
+    /// # Safety
+    /// The descriptor sets must be compatible with the pipeline's layout and outlive the
+    /// execution of the command buffer
+    pub unsafe fn bind_descriptor_sets<V, P>(
+        &mut self,
+        pipeline: &Pipeline<V, P>,
+        first_set: u32,
+        sets: &[&DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) -> Result<(), CommandBufferError>
+    where
+        V: Vertex,
+        P: UniformBuffer,
+    {
+        self.recording
+            .check_pipeline_bound(pipeline.vk_handle(), "bind descriptor sets")?;
+
+        let sets: Vec<vk::DescriptorSet> = sets.iter().map(|set| set.vk_handle()).collect();
+        self.device.vk_handle().cmd_bind_descriptor_sets(
+            self.handle,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.layout(),
+            first_set,
+            &sets,
+            dynamic_offsets,
+        );
+
+        Ok(())
+    }
+
+    /// Updates the push constants of `pipeline` for the shader `stages`, starting at `offset`
+    /// bytes into the pipeline's push constant range. Must be called after `pipeline` has been
+    /// bound.
+    ///
+    /// The fields of `data` are uploaded at their std430 offsets, which push constant blocks use
+    /// by default, see [UniformBuffer::as_std430_bytes].
+    ///
+    /// # Errors
+    /// - [CommandBufferError::PushConstantOutOfRange] - The `stages` aren't all part of the
+    ///   pipeline's push constant range, or `data` doesn't fit in the range at `offset`
+    pub fn push_constants<V, P, T>(
+        &mut self,
+        pipeline: &Pipeline<V, P>,
+        stages: ShaderStageFlags,
+        offset: u32,
+        data: &T,
+    ) -> Result<(), CommandBufferError>
+    where
+        V: Vertex,
+        P: UniformBuffer,
+        T: UniformBuffer,
+    {
+        self.recording
+            .check_pipeline_bound(pipeline.vk_handle(), "push constants")?;
+
+        let bytes = data.as_std430_bytes();
+        check_push_constant_range(
+            stages,
+            offset,
+            bytes.len() as u32,
+            pipeline.push_constant_range(),
+        )?;
+
+        unsafe {
+            self.device.vk_handle().cmd_push_constants(
+                self.handle,
+                pipeline.layout(),
+                stages.into(),
+                offset,
+                &bytes,
+            );
+        };
+
+        Ok(())
     }
 
     /// Binds a vertex buffer
+    ///
+    /// # Safety
+    /// The buffer must outlive the execution of the command buffer
     pub unsafe fn bind_vertex_buffer<T, const CAPACITY: usize>(
         &mut self,
         buffer: &Buffer<T, CAPACITY>,
-    ) {
+    ) -> Result<(), CommandBufferError> {
+        self.bind_vertex_buffer_at(0, buffer)
+    }
+
+    /// Binds a vertex buffer to a specific binding, allowing vertex attributes to be split across
+    /// multiple vertex buffers
+    ///
+    /// # Safety
+    /// The buffer must outlive the execution of the command buffer
+    pub unsafe fn bind_vertex_buffer_at<T, const CAPACITY: usize>(
+        &mut self,
+        binding: u32,
+        buffer: &Buffer<T, CAPACITY>,
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_recording()?;
+
         if !buffer.usage().contains(BufferUsageFlags::VERTEX_BUFFER) {
-            log::warn!(
-                "Can't bind a buffer as vertex buffer if it's usage hasn't been marked as such"
-            );
-            return;
+            return Err(CommandBufferError::MissingBufferUsage(
+                BufferUsageFlags::VERTEX_BUFFER,
+            ));
         }
 
         let buffers = [buffer.vk_handle()];
@@ -330,62 +793,521 @@ impl CommandBuffer {
 
         self.device
             .vk_handle()
-            .cmd_bind_vertex_buffers(self.handle, 0, &buffers, &offsets);
+            .cmd_bind_vertex_buffers(self.handle, binding, &buffers, &offsets);
+
+        Ok(())
     }
 
-    /// Binds an index buffer
-    pub unsafe fn bind_index_buffer<const CAPACITY: usize>(
+    /// Binds vertices allocated from a [FrameArena][crate::buffer::FrameArena] to a binding
+    ///
+    /// # Safety
+    /// The arena must not be reset or dropped until the command buffer has finished executing
+    pub unsafe fn bind_vertex_allocation(
         &mut self,
-        buffer: &Buffer<u32, CAPACITY>,
-    ) {
+        binding: u32,
+        allocation: &ArenaAllocation,
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_recording()?;
+
+        self.device.vk_handle().cmd_bind_vertex_buffers(
+            self.handle,
+            binding,
+            &[allocation.buffer],
+            &[allocation.offset],
+        );
+
+        Ok(())
+    }
+
+    /// Binds an index buffer, whose elements must match the size of `index_type`
+    ///
+    /// # Safety
+    /// The buffer must outlive the execution of the command buffer
+    pub unsafe fn bind_index_buffer<T, const CAPACITY: usize>(
+        &mut self,
+        buffer: &Buffer<T, CAPACITY>,
+        index_type: IndexType,
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_recording()?;
+
         if !buffer.usage().contains(BufferUsageFlags::INDEX_BUFFER) {
-            log::warn!(
-                "Can't bind a buffer as an index buffer it it's usage hasn't been marked as such"
-            );
-            return;
+            return Err(CommandBufferError::MissingBufferUsage(
+                BufferUsageFlags::INDEX_BUFFER,
+            ));
+        }
+
+        if std::mem::size_of::<T>() != index_type.size() {
+            return Err(CommandBufferError::IndexTypeMismatch(index_type));
         }
 
         self.device.vk_handle().cmd_bind_index_buffer(
             self.handle,
             buffer.vk_handle(),
             0,
-            vk::IndexType::UINT32,
+            index_type.into(),
         );
+
+        Ok(())
     }
 
     /// Draws from the vertices in the previoulsy bound vertex buffer
+    ///
+    /// # Safety
+    /// A vertex buffer matching the bound pipeline's vertex input must be bound
     pub unsafe fn draw(
         &self,
         vertex_count: u32,
         instance_count: u32,
         first_vertex: u32,
         first_instance: u32,
-    ) {
-            self.device.vk_handle().cmd_draw(
-                self.handle,
-                vertex_count,
-                instance_count,
-                first_vertex,
-                first_instance,
-            );
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_can_draw("draw")?;
+
+        self.device.vk_handle().cmd_draw(
+            self.handle,
+            vertex_count,
+            instance_count,
+            first_vertex,
+            first_instance,
+        );
+
+        Ok(())
+    }
+
+    /// Draws a single triangle covering the whole viewport without any vertex buffer, for
+    /// post-processing and sky passes. The bound pipeline should use
+    /// [EmptyVertex][crate::pipeline::vertex::EmptyVertex] and a vertex shader that derives the
+    /// positions from the vertex index:
+    ///
+    /// ```glsl
+    /// layout(location = 0) out vec2 uv;
+    ///
+    /// void main() {
+    ///     uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    ///     gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+    /// }
+    /// ```
+    pub fn draw_fullscreen_triangle(&self) -> Result<(), CommandBufferError> {
+        self.recording.check_can_draw("draw fullscreen triangle")?;
+
+        unsafe {
+            self.device.vk_handle().cmd_draw(self.handle, 3, 1, 0, 0);
+        };
+
+        Ok(())
     }
 
     /// Draws from a vertex buffer using an index buffer as well
+    ///
+    /// # Safety
+    /// A vertex buffer matching the bound pipeline's vertex input and an index buffer must be
+    /// bound
     pub unsafe fn draw_indexed(
         &self,
         index_count: u32,
         instance_count: u32,
         first_index: u32,
-        vertex_offset: u32,
+        vertex_offset: i32,
         first_instance: u32,
-    ) {
-            self.device.vk_handle().cmd_draw_indexed(
-                self.handle,
-                index_count,
-                instance_count,
-                first_index,
-                vertex_offset as i32,
-                first_instance,
-            );
+    ) -> Result<(), CommandBufferError> {
+        self.recording.check_can_draw("draw indexed")?;
+
+        self.device.vk_handle().cmd_draw_indexed(
+            self.handle,
+            index_count,
+            instance_count,
+            first_index,
+            vertex_offset,
+            first_instance,
+        );
+
+        Ok(())
+    }
+}
+
+/// Checks that `count` clear values reach the last of `attachments` that is cleared when loaded,
+/// without going past the last attachment
+fn check_clear_value_count(
+    attachments: &[vk::AttachmentDescription],
+    count: usize,
+) -> Result<(), CommandBufferError> {
+    let expected = attachments
+        .iter()
+        .rposition(|attachment| {
+            attachment.load_op == vk::AttachmentLoadOp::CLEAR
+                || attachment.stencil_load_op == vk::AttachmentLoadOp::CLEAR
+        })
+        .map_or(0, |index| index + 1);
+    if count < expected {
+        return Err(CommandBufferError::ClearValueCount { expected, count });
+    }
+    if count > attachments.len() {
+        return Err(CommandBufferError::TooManyClearValues {
+            attachments: attachments.len(),
+            count,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that `size` bytes pushed at `offset` for `stages` fit in a pipeline's push constant
+/// `range`, given as its stages and size in bytes
+fn check_push_constant_range(
+    stages: ShaderStageFlags,
+    offset: u32,
+    size: u32,
+    range: (ShaderStageFlags, u32),
+) -> Result<(), CommandBufferError> {
+    let (range_stages, range_size) = range;
+    if stages.is_empty()
+        || !range_stages.contains(stages)
+        || offset as u64 + size as u64 > range_size as u64
+    {
+        return Err(CommandBufferError::PushConstantOutOfRange {
+            stages,
+            offset,
+            size,
+            range_stages,
+            range_size,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    /// Returns the [Recording] of a command buffer that has begun recording
+    fn recording() -> Recording {
+        Recording {
+            state: CommandBufferState::Recording,
+            ..Recording::new()
+        }
+    }
+
+    #[test]
+    fn usage_flags_convert_to_vulkan() {
+        assert_eq!(
+            Into::<vk::CommandBufferUsageFlags>::into(CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+        );
+        assert_eq!(
+            Into::<vk::CommandBufferUsageFlags>::into(
+                CommandBufferUsageFlags::SIMULTANEOUS_USE
+                    | CommandBufferUsageFlags::RENDER_PASS_CONTINUE
+            ),
+            vk::CommandBufferUsageFlags::SIMULTANEOUS_USE
+                | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE
+        );
+    }
+
+    #[test]
+    fn begin_while_recording_errors() {
+        assert!(matches!(
+            recording().check_can_begin(),
+            Err(CommandBufferError::IncorrectState(
+                CommandBufferState::Initial
+            ))
+        ));
+    }
+
+    #[test]
+    fn re_begin_after_end_forgets_the_bound_pipeline() {
+        let mut recording = Recording {
+            state: CommandBufferState::Executable,
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            dynamic_depth_bias: true,
+            ..Recording::new()
+        };
+
+        assert!(recording.check_can_begin().is_ok());
+        recording.begin();
+        assert_eq!(recording.state, CommandBufferState::Recording);
+        assert!(recording.current_pipeline.is_none());
+        assert!(!recording.dynamic_depth_bias);
+    }
+
+    #[test]
+    fn draw_before_begin_errors() {
+        let recording = Recording {
+            started_render_pass: true,
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            ..Recording::new()
+        };
+
+        assert!(matches!(
+            recording.check_can_draw("draw"),
+            Err(CommandBufferError::IncorrectState(
+                CommandBufferState::Recording
+            ))
+        ));
+    }
+
+    #[test]
+    fn draw_before_begin_render_pass_errors() {
+        let recording = Recording {
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            ..recording()
+        };
+
+        assert!(matches!(
+            recording.check_can_draw("draw indexed"),
+            Err(CommandBufferError::OutsideRenderPass("draw indexed"))
+        ));
+    }
+
+    #[test]
+    fn draw_without_pipeline_errors() {
+        let recording = Recording {
+            started_render_pass: true,
+            ..recording()
+        };
+
+        assert!(matches!(
+            recording.check_can_draw("draw"),
+            Err(CommandBufferError::NoPipelineBound("draw"))
+        ));
+    }
+
+    #[test]
+    fn draw_inside_render_pass_with_pipeline_is_allowed() {
+        let recording = Recording {
+            started_render_pass: true,
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            ..recording()
+        };
+
+        assert!(recording.check_can_draw("draw").is_ok());
+    }
+
+    #[test]
+    fn fullscreen_triangle_outside_render_pass_errors() {
+        let recording = Recording {
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            ..recording()
+        };
+
+        assert!(matches!(
+            recording.check_can_draw("draw fullscreen triangle"),
+            Err(CommandBufferError::OutsideRenderPass(
+                "draw fullscreen triangle"
+            ))
+        ));
+    }
+
+    #[test]
+    fn fullscreen_triangle_without_pipeline_errors() {
+        let recording = Recording {
+            started_render_pass: true,
+            ..recording()
+        };
+
+        assert!(matches!(
+            recording.check_can_draw("draw fullscreen triangle"),
+            Err(CommandBufferError::NoPipelineBound(
+                "draw fullscreen triangle"
+            ))
+        ));
+    }
+
+    #[test]
+    fn bind_descriptor_sets_before_pipeline_errors() {
+        assert!(matches!(
+            recording().check_pipeline_bound(vk::Pipeline::from_raw(1), "bind descriptor sets"),
+            Err(CommandBufferError::NoPipelineBound("bind descriptor sets"))
+        ));
+    }
+
+    #[test]
+    fn bind_descriptor_sets_for_another_pipeline_errors() {
+        let recording = Recording {
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            ..recording()
+        };
+
+        assert!(matches!(
+            recording.check_pipeline_bound(vk::Pipeline::from_raw(2), "bind descriptor sets"),
+            Err(CommandBufferError::PipelineMismatch("bind descriptor sets"))
+        ));
+    }
+
+    #[test]
+    fn set_depth_bias_without_dynamic_depth_bias_errors() {
+        let recording = Recording {
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            ..recording()
+        };
+
+        assert!(matches!(
+            recording.check_can_set_depth_bias(),
+            Err(CommandBufferError::DynamicStateDisabled("depth bias"))
+        ));
+    }
+
+    #[test]
+    fn set_depth_bias_with_dynamic_depth_bias_is_allowed() {
+        let recording = Recording {
+            current_pipeline: Some(vk::Pipeline::from_raw(1)),
+            dynamic_depth_bias: true,
+            ..recording()
+        };
+
+        assert!(recording.check_can_set_depth_bias().is_ok());
+    }
+
+    #[test]
+    fn set_depth_bias_without_pipeline_errors() {
+        assert!(matches!(
+            recording().check_can_set_depth_bias(),
+            Err(CommandBufferError::NoPipelineBound("set depth bias"))
+        ));
+    }
+
+    #[test]
+    fn push_constant_within_range_is_allowed() {
+        let range = (ShaderStageFlags::ALL_GRAPHICS, 64);
+
+        assert!(check_push_constant_range(ShaderStageFlags::VERTEX, 0, 64, range).is_ok());
+        assert!(check_push_constant_range(ShaderStageFlags::FRAGMENT, 48, 16, range).is_ok());
+    }
+
+    #[test]
+    fn push_constant_too_large_errors() {
+        let range = (ShaderStageFlags::VERTEX, 64);
+
+        assert!(matches!(
+            check_push_constant_range(ShaderStageFlags::VERTEX, 16, 64, range),
+            Err(CommandBufferError::PushConstantOutOfRange {
+                offset: 16,
+                size: 64,
+                range_size: 64,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn push_constant_for_other_stages_errors() {
+        let range = (ShaderStageFlags::VERTEX, 64);
+
+        assert!(matches!(
+            check_push_constant_range(ShaderStageFlags::FRAGMENT, 0, 16, range),
+            Err(CommandBufferError::PushConstantOutOfRange { .. })
+        ));
+        assert!(matches!(
+            check_push_constant_range(ShaderStageFlags::empty(), 0, 16, range),
+            Err(CommandBufferError::PushConstantOutOfRange { .. })
+        ));
+    }
+
+    /// Returns the description of an attachment loaded with `load_op`
+    fn attachment(load_op: vk::AttachmentLoadOp) -> vk::AttachmentDescription {
+        vk::AttachmentDescription::builder()
+            .load_op(load_op)
+            .build()
+    }
+
+    #[test]
+    fn clear_value_for_every_attachment_is_allowed() {
+        let attachments = [
+            attachment(vk::AttachmentLoadOp::CLEAR),
+            attachment(vk::AttachmentLoadOp::CLEAR),
+        ];
+
+        assert!(check_clear_value_count(&attachments, 2).is_ok());
+    }
+
+    #[test]
+    fn clear_values_for_trailing_loaded_attachments_can_be_left_out() {
+        let attachments = [
+            attachment(vk::AttachmentLoadOp::CLEAR),
+            attachment(vk::AttachmentLoadOp::CLEAR),
+            attachment(vk::AttachmentLoadOp::DONT_CARE),
+        ];
+
+        assert!(check_clear_value_count(&attachments, 2).is_ok());
+        assert!(check_clear_value_count(&attachments, 3).is_ok());
+        assert!(check_clear_value_count(&[attachment(vk::AttachmentLoadOp::LOAD)], 0).is_ok());
+    }
+
+    #[test]
+    fn missing_clear_values_error() {
+        let attachments = [
+            attachment(vk::AttachmentLoadOp::CLEAR),
+            attachment(vk::AttachmentLoadOp::CLEAR),
+        ];
+
+        assert!(matches!(
+            check_clear_value_count(&attachments, 1),
+            Err(CommandBufferError::ClearValueCount {
+                expected: 2,
+                count: 1
+            })
+        ));
+        assert!(matches!(
+            check_clear_value_count(&attachments, 3),
+            Err(CommandBufferError::TooManyClearValues {
+                attachments: 2,
+                count: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn clear_attachments_convert_to_vulkan() {
+        let color: vk::ClearAttachment = ClearAttachment::Color {
+            attachment: 1,
+            color: (0.25, 0.5, 0.75, 1.0),
+        }
+        .into();
+        assert_eq!(color.aspect_mask, vk::ImageAspectFlags::COLOR);
+        assert_eq!(color.color_attachment, 1);
+        assert_eq!(
+            unsafe { color.clear_value.color.float32 },
+            [0.25, 0.5, 0.75, 1.0]
+        );
+
+        let depth: vk::ClearAttachment = ClearAttachment::Depth(0.5).into();
+        assert_eq!(depth.aspect_mask, vk::ImageAspectFlags::DEPTH);
+        assert_eq!(unsafe { depth.clear_value.depth_stencil.depth }, 0.5);
+
+        let depth_stencil: vk::ClearAttachment = ClearAttachment::DepthStencil {
+            depth: 1.0,
+            stencil: 7,
+        }
+        .into();
+        let value = unsafe { depth_stencil.clear_value.depth_stencil };
+        assert_eq!(
+            depth_stencil.aspect_mask,
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        );
+        assert_eq!((value.depth, value.stencil), (1.0, 7));
+
+        let stencil: vk::ClearAttachment = ClearAttachment::Stencil(3).into();
+        assert_eq!(stencil.aspect_mask, vk::ImageAspectFlags::STENCIL);
+        assert_eq!(unsafe { stencil.clear_value.depth_stencil.stencil }, 3);
+    }
+
+    #[test]
+    fn clear_rect_converts_to_vulkan() {
+        let rect: vk::ClearRect = ClearRect {
+            offset: (4, 8),
+            ..ClearRect::new((640, 480))
+        }
+        .into();
+
+        assert_eq!(rect.rect.offset, vk::Offset2D { x: 4, y: 8 });
+        assert_eq!(
+            rect.rect.extent,
+            vk::Extent2D {
+                width: 640,
+                height: 480
+            }
+        );
+        assert_eq!((rect.base_array_layer, rect.layer_count), (0, 1));
     }
 }
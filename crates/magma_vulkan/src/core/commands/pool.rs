@@ -1,15 +1,24 @@
 use ash::vk;
 use std::rc::Rc;
 
-use super::buffer::{CommandBuffer, CommandBufferLevel};
+use super::buffer::{
+    CommandBuffer, CommandBufferError, CommandBufferLevel, CommandBufferUsageFlags,
+};
 use crate::{
-    core::device::{LogicalDevice, QueueFamily},
+    core::{
+        device::{LogicalDevice, QueueFamily, QueueFlags},
+        swapchain::{Swapchain, SwapchainDependent, SwapchainError},
+    },
     VulkanError,
 };
 
 /// Errors that can be returned by the [CommandPool]
 #[derive(thiserror::Error, Debug)]
 pub enum CommandPoolError {
+    #[error("The device has no '{0}' queue to submit the command pool's command buffers to")]
+    MissingQueue(QueueFlags),
+    #[error(transparent)]
+    CommandBufferError(#[from] CommandBufferError),
     #[error(transparent)]
     DeviceError(#[from] VulkanError),
 }
@@ -18,6 +27,8 @@ pub enum CommandPoolError {
 pub struct CommandPool {
     /// [CommandBuffers][CommandBuffer] that are allocated to this [CommandPool]
     buffers: Vec<CommandBuffer>,
+    /// Queue family the command buffers can be submitted to
+    queue_family: QueueFamily,
     /// Opaque handle to Vulkan command pool
     handle: vk::CommandPool,
     /// [LogicalDevice] this command pool belongs to
@@ -49,6 +60,7 @@ impl CommandPool {
 
         Ok(CommandPool {
             buffers: Vec::new(),
+            queue_family: *queue_family,
             handle,
             device,
         })
@@ -56,9 +68,9 @@ impl CommandPool {
 }
 
 impl CommandPool {
-    /// Return Vulkan handle to Vulkan command pool
-    pub(crate) fn vk_handle(&self) -> vk::CommandPool {
-        self.handle
+    /// Returns the [QueueFamily] the command buffers of this [CommandPool] can be submitted to
+    pub fn queue_family(&self) -> &QueueFamily {
+        &self.queue_family
     }
 
     /// Returns [CommandBuffers][CommandBuffer] that have been allocated from this [CommandPool]
@@ -119,6 +131,96 @@ impl CommandPool {
     }
 }
 
+impl CommandPool {
+    /// Records a one-off command buffer with `record`, submits it to a queue of this pool's
+    /// [QueueFamily], and waits for it to finish executing.
+    ///
+    /// The command buffer is begun before `record` is called and ended after, and is freed once
+    /// the work has completed. Useful for uploads, copies, and layout transitions that need to
+    /// finish before continuing.
+    pub fn immediate_submit<F>(&self, record: F) -> Result<(), CommandPoolError>
+    where
+        F: FnOnce(&mut CommandBuffer),
+    {
+        let allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_buffer_count(1)
+            .command_pool(self.handle)
+            .level(CommandBufferLevel::Primary.into());
+
+        let command_buffers = unsafe {
+            self.device
+                .vk_handle()
+                .allocate_command_buffers(&allocate_info)
+                .map_err(|err| CommandPoolError::DeviceError(err.into()))?
+        };
+        let mut command_buffer = CommandBuffer::new(command_buffers[0], self.device.clone());
+
+        let result = self.submit_and_wait(&mut command_buffer, record);
+
+        unsafe {
+            self.device
+                .vk_handle()
+                .free_command_buffers(self.handle, &command_buffers);
+        };
+
+        result
+    }
+
+    /// Records `command_buffer` with `record`, submits it, and waits for the queue to idle
+    fn submit_and_wait<F>(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        record: F,
+    ) -> Result<(), CommandPoolError>
+    where
+        F: FnOnce(&mut CommandBuffer),
+    {
+        let queue = self
+            .device
+            .queue(self.queue_family.ty)
+            .ok_or(CommandPoolError::MissingQueue(self.queue_family.ty))?;
+
+        command_buffer.begin(CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        record(command_buffer);
+        command_buffer.end()?;
+
+        let command_buffers = [command_buffer.vk_handle()];
+        let submit_infos = [vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .build()];
+
+        unsafe {
+            self.device
+                .vk_handle()
+                .queue_submit(queue.handle, &submit_infos, vk::Fence::null())
+                .map_err(|err| CommandPoolError::DeviceError(err.into()))?;
+
+            self.device
+                .vk_handle()
+                .queue_wait_idle(queue.handle)
+                .map_err(|err| CommandPoolError::DeviceError(err.into()))?;
+        };
+
+        Ok(())
+    }
+}
+
+/// Keeps one primary command buffer per framebuffer of the swapchain, reallocating the command
+/// buffers when the number of framebuffers changes
+impl SwapchainDependent for CommandPool {
+    fn swapchain_recreated(&mut self, swapchain: &Swapchain) -> Result<(), SwapchainError> {
+        if swapchain.framebuffers().len() != self.buffers().len() {
+            self.free_buffers();
+            self.allocate_buffers(
+                swapchain.framebuffers().len() as u32,
+                CommandBufferLevel::Primary,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
 impl Drop for CommandPool {
     fn drop(&mut self) {
         self.free_buffers();
@@ -1,6 +1,8 @@
 use ash::{extensions::ext::DebugUtils, vk};
+use bitflags::bitflags;
 use std::ffi::CString;
 use std::ffi::{c_void, CStr};
+use std::sync::Mutex;
 
 use crate::VulkanError;
 
@@ -42,8 +44,144 @@ impl Into<String> for DebugLayer {
     }
 }
 
+bitflags! {
+    /// Wraps VkDebugUtilsMessageSeverityFlagBitsEXT
+    pub struct DebugMessageSeverity: u32 {
+        /// Diagnostic messages from the Vulkan loader, layers, and drivers
+        const VERBOSE = 0x1;
+        /// Informational messages such as resource details
+        const INFO = 0x10;
+        /// Messages about behaviour that is likely, but not always, a bug
+        const WARNING = 0x100;
+        /// Messages about behaviour that is invalid and may cause undefined results
+        const ERROR = 0x1000;
+    }
+}
+
+impl Into<vk::DebugUtilsMessageSeverityFlagsEXT> for DebugMessageSeverity {
+    fn into(self) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+        vk::DebugUtilsMessageSeverityFlagsEXT::from_raw(self.bits())
+    }
+}
+
+bitflags! {
+    /// Wraps VkDebugUtilsMessageTypeFlagBitsEXT
+    pub struct DebugMessageType: u32 {
+        /// Events that are unrelated to the specification or performance
+        const GENERAL = 0x1;
+        /// Events that violate the specification or indicate a possible mistake
+        const VALIDATION = 0x2;
+        /// Events that indicate a potentially non-optimal use of Vulkan
+        const PERFORMANCE = 0x4;
+    }
+}
+
+impl Into<vk::DebugUtilsMessageTypeFlagsEXT> for DebugMessageType {
+    fn into(self) -> vk::DebugUtilsMessageTypeFlagsEXT {
+        vk::DebugUtilsMessageTypeFlagsEXT::from_raw(self.bits())
+    }
+}
+
+/// Describes which messages the [Debugger] logs
+#[derive(Clone, Debug)]
+pub struct DebugMessageFilter {
+    /// Severities of the messages to log
+    pub severity: DebugMessageSeverity,
+    /// Types of the messages to log
+    pub message_type: DebugMessageType,
+    /// Messages whose id contains any of these substrings are dropped, useful for silencing
+    /// known-benign validation messages
+    pub ignored_message_ids: Vec<String>,
+}
+
+impl Default for DebugMessageFilter {
+    fn default() -> DebugMessageFilter {
+        DebugMessageFilter {
+            severity: DebugMessageSeverity::INFO
+                | DebugMessageSeverity::WARNING
+                | DebugMessageSeverity::ERROR,
+            message_type: DebugMessageType::PERFORMANCE | DebugMessageType::VALIDATION,
+            ignored_message_ids: Vec::new(),
+        }
+    }
+}
+
+impl DebugMessageFilter {
+    /// Returns whether a message with the id `message_id` should be dropped
+    pub fn is_ignored(&self, message_id: &str) -> bool {
+        self.ignored_message_ids
+            .iter()
+            .any(|ignored| message_id.contains(ignored.as_str()))
+    }
+
+    /// Returns whether a message should be logged, i.e. its severity and type are enabled and its
+    /// id, if it has one, is not ignored
+    pub fn allows(
+        &self,
+        severity: DebugMessageSeverity,
+        message_type: DebugMessageType,
+        message_id: Option<&str>,
+    ) -> bool {
+        self.severity.intersects(severity)
+            && self.message_type.intersects(message_type)
+            && !message_id.is_some_and(|message_id| self.is_ignored(message_id))
+    }
+}
+
+/// Callback invoked with every debug message that passes the [DebugMessageFilter], alongside
+/// logging it
+///
+/// Vulkan can report messages from any thread making Vulkan calls, so the callback must be [Send].
+pub type ValidationCallback = Box<dyn FnMut(DebugMessageSeverity, &str) + Send>;
+
+/// Data passed to the messenger callback as user data
+struct DebugCallbackData {
+    /// Filter for the messages that are logged
+    filter: DebugMessageFilter,
+    /// Optional callback that receives every logged message
+    validation_callback: Option<Mutex<ValidationCallback>>,
+    /// Validation errors reported since they were last taken, only recorded if the [Debugger]
+    /// was created with `record_errors`
+    validation_errors: Option<Mutex<Vec<String>>>,
+}
+
+impl DebugCallbackData {
+    /// Forwards `message` to the [ValidationCallback] if there is one
+    fn forward(&self, severity: DebugMessageSeverity, message: &str) {
+        if let Some(Ok(mut validation_callback)) = self
+            .validation_callback
+            .as_ref()
+            .map(|callback| callback.lock())
+        {
+            validation_callback(severity, message);
+        }
+    }
+
+    /// Records `message` if it is a validation error and errors are being recorded
+    fn record_error(
+        &self,
+        severity: DebugMessageSeverity,
+        message_type: DebugMessageType,
+        message: &str,
+    ) {
+        if !severity.contains(DebugMessageSeverity::ERROR)
+            || !message_type.contains(DebugMessageType::VALIDATION)
+        {
+            return;
+        }
+
+        if let Some(Ok(mut validation_errors)) =
+            self.validation_errors.as_ref().map(|errors| errors.lock())
+        {
+            validation_errors.push(message.to_string());
+        }
+    }
+}
+
 /// Wraps Vulkan debug utils
 pub struct Debugger {
+    /// Data used by the messenger callback, boxed so its address stays valid as user data
+    _callback_data: Box<DebugCallbackData>,
     /// Vulkan debug utils extension used to create the messenger
     debug_utils: DebugUtils,
     /// Opaque handle to Vulkan debug utils messenger
@@ -51,26 +189,33 @@ pub struct Debugger {
 }
 
 impl Debugger {
-    /// Creates a new Vulkan debug messenger that logs performance and validation messages
+    /// Creates a new Vulkan debug messenger that logs the messages allowed through `filter`
+    ///
+    /// If a `validation_callback` is given it will also receive every logged message.
+    ///
+    /// If `record_errors` is set, every validation error is also recorded until it is taken with
+    /// [Debugger::take_validation_errors].
     pub fn new(
         entry: &ash::Entry,
         instance: &ash::Instance,
         layers: &[DebugLayer],
+        filter: DebugMessageFilter,
+        validation_callback: Option<ValidationCallback>,
+        record_errors: bool,
     ) -> Result<Debugger, DebuggerError> {
         Debugger::check_validation_layers(entry, layers)?;
 
+        let callback_data = Box::new(DebugCallbackData {
+            filter,
+            validation_callback: validation_callback.map(Mutex::new),
+            validation_errors: record_errors.then(|| Mutex::new(Vec::new())),
+        });
         let debug_utils = DebugUtils::new(entry, instance);
         let create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-            )
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
-            )
-            .pfn_user_callback(Some(vulkan_debug_utils_callback));
+            .message_severity(callback_data.filter.severity.into())
+            .message_type(callback_data.filter.message_type.into())
+            .pfn_user_callback(Some(vulkan_debug_utils_callback))
+            .user_data(callback_data.as_ref() as *const DebugCallbackData as *mut c_void);
 
         let handle = unsafe {
             debug_utils
@@ -79,6 +224,7 @@ impl Debugger {
         };
 
         Ok(Debugger {
+            _callback_data: callback_data,
             debug_utils,
             handle,
         })
@@ -86,6 +232,17 @@ impl Debugger {
 }
 
 impl Debugger {
+    /// Takes the validation errors reported since the last call, which is always empty unless the
+    /// debugger was created with `record_errors`
+    pub fn take_validation_errors(&self) -> Vec<String> {
+        self._callback_data
+            .validation_errors
+            .as_ref()
+            .and_then(|errors| errors.lock().ok())
+            .map(|mut errors| std::mem::take(&mut *errors))
+            .unwrap_or_default()
+    }
+
     /// Checks wether the loaded Vulkan library supports the required validation layers
     pub fn check_validation_layers(
         entry: &ash::Entry,
@@ -127,20 +284,36 @@ impl Drop for Debugger {
     }
 }
 
-/// Vulkan callback to print vulkan debug messages using the `log` crate
+/// Vulkan callback to print vulkan debug messages using the `log` crate, and forward them to the
+/// [ValidationCallback] if there is one
 unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
+    let callback_data = &*p_callback_data;
+    let user_data = (p_user_data as *const DebugCallbackData).as_ref();
+    let severity = DebugMessageSeverity::from_bits_truncate(message_severity.as_raw());
+    let debug_message_type = DebugMessageType::from_bits_truncate(message_type.as_raw());
+    if let Some(user_data) = user_data {
+        let message_id = (!callback_data.p_message_id_name.is_null())
+            .then(|| CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy());
+        if !user_data
+            .filter
+            .allows(severity, debug_message_type, message_id.as_deref())
+        {
+            return vk::FALSE;
+        }
+    }
+
     let type_ = match message_type {
         vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
         vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[Performance]",
         vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
         _ => "[Unknown]",
     };
-    let message = CStr::from_ptr((*p_callback_data).p_message);
+    let message = CStr::from_ptr(callback_data.p_message);
 
     match message_severity {
         vk::DebugUtilsMessageSeverityFlagsEXT::INFO => {
@@ -158,5 +331,129 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
         _ => {}
     };
 
+    if let Some(user_data) = user_data {
+        user_data.forward(severity, &message.to_string_lossy());
+        user_data.record_error(severity, debug_message_type, &message.to_string_lossy());
+    }
+
     vk::FALSE
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn callback_data(record_errors: bool) -> DebugCallbackData {
+        DebugCallbackData {
+            filter: DebugMessageFilter::default(),
+            validation_callback: None,
+            validation_errors: record_errors.then(|| Mutex::new(Vec::new())),
+        }
+    }
+
+    fn recorded(data: &DebugCallbackData) -> Vec<String> {
+        data.validation_errors
+            .as_ref()
+            .map(|errors| errors.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn validation_errors_are_recorded() {
+        let data = callback_data(true);
+        data.record_error(
+            DebugMessageSeverity::ERROR,
+            DebugMessageType::VALIDATION,
+            "VUID-vkCmdDraw-None-02700",
+        );
+
+        assert_eq!(recorded(&data), vec!["VUID-vkCmdDraw-None-02700"]);
+    }
+
+    #[test]
+    fn warnings_and_non_validation_errors_are_not_recorded() {
+        let data = callback_data(true);
+        data.record_error(
+            DebugMessageSeverity::WARNING,
+            DebugMessageType::VALIDATION,
+            "warning",
+        );
+        data.record_error(
+            DebugMessageSeverity::ERROR,
+            DebugMessageType::PERFORMANCE,
+            "performance",
+        );
+
+        assert!(recorded(&data).is_empty());
+    }
+
+    #[test]
+    fn filter_drops_disabled_severities_and_types() {
+        let filter = DebugMessageFilter::default();
+
+        assert!(filter.allows(
+            DebugMessageSeverity::WARNING,
+            DebugMessageType::VALIDATION,
+            None
+        ));
+        assert!(!filter.allows(
+            DebugMessageSeverity::VERBOSE,
+            DebugMessageType::VALIDATION,
+            None
+        ));
+        assert!(!filter.allows(DebugMessageSeverity::ERROR, DebugMessageType::GENERAL, None));
+    }
+
+    #[test]
+    fn filter_drops_ignored_message_ids() {
+        let filter = DebugMessageFilter {
+            ignored_message_ids: vec![String::from("BestPractices")],
+            ..DebugMessageFilter::default()
+        };
+
+        assert!(!filter.allows(
+            DebugMessageSeverity::WARNING,
+            DebugMessageType::PERFORMANCE,
+            Some("UNASSIGNED-BestPractices-vkAllocateMemory-small-allocation")
+        ));
+        assert!(filter.allows(
+            DebugMessageSeverity::ERROR,
+            DebugMessageType::VALIDATION,
+            Some("VUID-vkCmdDraw-None-02700")
+        ));
+    }
+
+    #[test]
+    fn messages_are_forwarded_to_the_validation_callback() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let data = DebugCallbackData {
+            validation_callback: Some(Mutex::new(Box::new(move |severity, message: &str| {
+                sink.lock().unwrap().push((severity, message.to_string()));
+            }))),
+            ..callback_data(false)
+        };
+        data.forward(DebugMessageSeverity::ERROR, "VUID-vkCmdDraw-None-02700");
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(
+                DebugMessageSeverity::ERROR,
+                String::from("VUID-vkCmdDraw-None-02700")
+            )]
+        );
+    }
+
+    #[test]
+    fn errors_are_not_recorded_unless_enabled() {
+        let data = callback_data(false);
+        data.record_error(
+            DebugMessageSeverity::ERROR,
+            DebugMessageType::VALIDATION,
+            "error",
+        );
+
+        assert!(data.validation_errors.is_none());
+    }
+}
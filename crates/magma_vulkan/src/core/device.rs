@@ -7,23 +7,47 @@ use std::fmt::Display;
 mod logical;
 mod physical;
 
-pub use logical::{LogicalDevice, LogicalDeviceError};
+pub(crate) use logical::AllocationKind;
+pub use logical::{CalibratedTimestamps, LogicalDevice, LogicalDeviceError, MemoryStats};
 pub use physical::{
-    PhysicalDevice, PhysicalDeviceBuilder, PhysicalDeviceError, PhysicalDeviceType,
+    PhysicalDevice, PhysicalDeviceBuilder, PhysicalDeviceError, PhysicalDeviceInfo,
+    PhysicalDeviceType,
 };
 
 /// Vulkan device extensions that are supported my [`magma_vulkan`]
 ///
 /// https://www.khronos.org/registry/vulkan/specs/1.2-extensions/html/vkspec.html#extension-appendices-list
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DeviceExtension {
     Swapchain,
+    BufferDeviceAddress,
+    DescriptorIndexing,
+    ExternalSemaphoreFd,
+    ExternalSemaphoreWin32,
+    LineRasterization,
+    CalibratedTimestamps,
+    CreateRenderPass2,
+    DepthStencilResolve,
+    PresentId,
+    PresentWait,
 }
 
 impl Display for DeviceExtension {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DeviceExtension::Swapchain => write!(f, "VK_KHR_swapchain"),
+            DeviceExtension::BufferDeviceAddress => write!(f, "VK_KHR_buffer_device_address"),
+            DeviceExtension::DescriptorIndexing => write!(f, "VK_EXT_descriptor_indexing"),
+            DeviceExtension::ExternalSemaphoreFd => write!(f, "VK_KHR_external_semaphore_fd"),
+            DeviceExtension::ExternalSemaphoreWin32 => {
+                write!(f, "VK_KHR_external_semaphore_win32")
+            }
+            DeviceExtension::LineRasterization => write!(f, "VK_EXT_line_rasterization"),
+            DeviceExtension::CalibratedTimestamps => write!(f, "VK_EXT_calibrated_timestamps"),
+            DeviceExtension::CreateRenderPass2 => write!(f, "VK_KHR_create_renderpass2"),
+            DeviceExtension::DepthStencilResolve => write!(f, "VK_KHR_depth_stencil_resolve"),
+            DeviceExtension::PresentId => write!(f, "VK_KHR_present_id"),
+            DeviceExtension::PresentWait => write!(f, "VK_KHR_present_wait"),
         }
     }
 }
@@ -52,9 +76,24 @@ impl Into<vk::QueueFlags> for QueueFlags {
 /// Wraps a Vulkan Queue
 pub struct QueueHandle {
     /// Opaque handle to Vulkan Queue
-    pub(crate) handle: vk::Queue,
+    pub handle: vk::Queue,
     /// Type of the Queue
     pub ty: QueueFlags,
+    /// Index of the Queue within its queue family
+    pub index: u32,
+    /// Number of meaningful bits in timestamps written on the queue
+    timestamp_valid_bits: u32,
+}
+
+impl QueueHandle {
+    /// Returns the number of meaningful bits in timestamps written on the queue, 0 if the queue
+    /// doesn't support timestamps
+    ///
+    /// Timestamps from queues of different families aren't directly comparable, use
+    /// [LogicalDevice::calibrated_timestamps] to align them with the host's clock.
+    pub fn timestamp_valid_bits(&self) -> u32 {
+        self.timestamp_valid_bits
+    }
 }
 
 /// Wraps the index of a given Queue type
@@ -64,11 +103,36 @@ pub struct QueueFamily {
     pub ty: QueueFlags,
     /// Index on the device where the queue is
     pub index: Option<u32>,
+    /// Number of queues requested from the queue family
+    pub count: u32,
+    /// Number of queues created from the queue family, the requested count clamped to the
+    /// number of queues the chosen device's queue family has, 0 until it has been found on a
+    /// device
+    pub queue_count: u32,
+    /// Number of meaningful bits in timestamps written on the queue family's queues, set once
+    /// the queue family has been found on a device
+    pub timestamp_valid_bits: u32,
 }
 
 impl QueueFamily {
-    /// Creates a new [QueueFamily] of the type setting the index to `None`
+    /// Creates a new [QueueFamily] of the type setting the index to `None`, requesting a single
+    /// queue
     pub fn new(ty: QueueFlags) -> QueueFamily {
-        QueueFamily { ty, index: None }
+        QueueFamily {
+            ty,
+            index: None,
+            count: 1,
+            queue_count: 0,
+            timestamp_valid_bits: 0,
+        }
+    }
+
+    /// Sets the number of queues to create from the queue family
+    ///
+    /// If the chosen device's queue family has fewer queues, only that many are created, see
+    /// [QueueFamily::queue_count].
+    pub fn with_count(mut self, count: u32) -> QueueFamily {
+        self.count = count.max(1);
+        self
     }
 }
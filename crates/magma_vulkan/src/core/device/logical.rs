@@ -1,13 +1,14 @@
-use std::ffi::CString;
+use std::{cell::RefCell, collections::HashMap, ffi::CString};
 use ash::vk;
 
-use super::{PhysicalDevice, QueueFlags};
+use super::{DeviceExtension, PhysicalDevice, QueueFamily, QueueFlags};
 use crate::{
     core::{
         device::QueueHandle,
         instance::Instance,
     },
     buffer::MemoryPropertyFlags,
+    sampler::{SamplerCache, SamplerInfo},
     sync::Fence,
     VulkanError,
 };
@@ -23,14 +24,92 @@ pub enum LogicalDeviceError {
         "Failed to find a memory type that the device supports that matches caller's requirements"
     )]
     NoSupportedMemoryType,
+    #[error("The '{0}' device extension is required but wasn't enabled")]
+    MissingExtension(DeviceExtension),
+    #[error("The physical device wasn't created with a '{0}' queue family")]
+    MissingQueueFamily(QueueFlags),
+    #[error("The device doesn't support the '{0}' feature")]
+    MissingFeature(&'static str),
+    #[error("The device can't calibrate its timestamps against the host's clock")]
+    UnsupportedTimeDomain,
     #[error(transparent)]
     Other(#[from] VulkanError),
 }
 
+/// What a device memory allocation is backing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AllocationKind {
+    Buffer,
+    Image,
+}
+
+/// Host clock that device timestamps are calibrated against
+#[cfg(windows)]
+const HOST_TIME_DOMAIN: vk::TimeDomainEXT = vk::TimeDomainEXT::QUERY_PERFORMANCE_COUNTER;
+/// Host clock that device timestamps are calibrated against
+#[cfg(not(windows))]
+const HOST_TIME_DOMAIN: vk::TimeDomainEXT = vk::TimeDomainEXT::CLOCK_MONOTONIC_RAW;
+
+/// A device and host timestamp sampled at the same moment, to align GPU timings with the CPU's
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CalibratedTimestamps {
+    /// Device timestamp, in ticks of `timestamp_period` nanoseconds
+    pub device: u64,
+    /// Host timestamp, from `CLOCK_MONOTONIC_RAW` or `QueryPerformanceCounter` on Windows
+    pub host: u64,
+    /// Maximum deviation, in nanoseconds, between when the two timestamps were sampled
+    pub max_deviation: u64,
+}
+
+/// Totals of the device memory allocated through a [LogicalDevice] that hasn't been freed yet
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes of device memory backing buffers
+    pub buffer_bytes: u64,
+    /// Bytes of device memory backing images
+    pub image_bytes: u64,
+    /// Number of live device memory allocations
+    pub allocation_count: u32,
+}
+
+/// Live device memory allocations, with what they back and their size in bytes
+#[derive(Default)]
+struct Allocations(HashMap<vk::DeviceMemory, (AllocationKind, u64)>);
+
+impl Allocations {
+    /// Starts tracking `memory`, which is `size` bytes backing a resource of `kind`
+    fn insert(&mut self, memory: vk::DeviceMemory, kind: AllocationKind, size: u64) {
+        self.0.insert(memory, (kind, size));
+    }
+
+    /// Stops tracking `memory` once it has been freed
+    fn remove(&mut self, memory: vk::DeviceMemory) {
+        self.0.remove(&memory);
+    }
+
+    /// Returns the totals of the tracked allocations
+    fn stats(&self) -> MemoryStats {
+        self.0
+            .values()
+            .fold(MemoryStats::default(), |mut stats, &(kind, size)| {
+                match kind {
+                    AllocationKind::Buffer => stats.buffer_bytes += size,
+                    AllocationKind::Image => stats.image_bytes += size,
+                }
+                stats.allocation_count += 1;
+                stats
+            })
+    }
+}
+
 /// Wraps a Vulkan logical devcie, allowing you to interface with a [PhysicalDevice]
 pub struct LogicalDevice {
     /// Vulkan handles to all of the queues of the [PhysicalDevice]
     queues: Vec<QueueHandle>,
+    /// Samplers shared between everything created with this device
+    sampler_cache: SamplerCache,
+    /// Live device memory allocations, with what they back and their size in bytes
+    allocations: RefCell<Allocations>,
 
     /// [PhysicalDevice] this logical device interfaces with
     physical_device: PhysicalDevice,
@@ -46,24 +125,67 @@ impl LogicalDevice {
         instance: Instance,
         physical_device: PhysicalDevice,
     ) -> Result<LogicalDevice, LogicalDeviceError> {
-        use std::collections::HashSet;
+        let queue_families = physical_device.queue_families().to_vec();
+        LogicalDevice::with_queue_families(instance, physical_device, queue_families)
+    }
 
-        let mut unique_queue_indices = HashSet::new();
-        for queue_family in physical_device.queue_families().iter() {
-            unique_queue_indices.insert(queue_family.index.unwrap());
+    /// Creates a new [LogicalDevice] for compute and transfer work, such as on headless machines
+    /// without a display, which only creates queues from the compute and transfer queue families
+    /// of `physical_device`
+    ///
+    /// # Errors
+    /// - [LogicalDeviceError::MissingQueueFamily] - `physical_device` has no compute queue family
+    pub fn new_compute_only(
+        instance: Instance,
+        physical_device: PhysicalDevice,
+    ) -> Result<LogicalDevice, LogicalDeviceError> {
+        if physical_device.queue_family(QueueFlags::COMPUTE).is_none() {
+            return Err(LogicalDeviceError::MissingQueueFamily(QueueFlags::COMPUTE));
         }
 
-        let queue_priorities = [1.0_f32];
-        let mut queue_infos: Vec<vk::DeviceQueueCreateInfo> = Vec::new();
-        for &queue_index in unique_queue_indices.iter() {
-            queue_infos.push(
-                vk::DeviceQueueCreateInfo::builder()
-                    .queue_family_index(queue_index)
-                    .queue_priorities(&queue_priorities)
-                    .build(),
-            );
+        let queue_families = physical_device
+            .queue_families()
+            .iter()
+            .filter(|family| {
+                family
+                    .ty
+                    .intersects(QueueFlags::COMPUTE | QueueFlags::TRANSFER)
+            })
+            .copied()
+            .collect();
+        LogicalDevice::with_queue_families(instance, physical_device, queue_families)
+    }
+
+    /// Creates a new [LogicalDevice] with queues from only `queue_families`
+    fn with_queue_families(
+        instance: Instance,
+        physical_device: PhysicalDevice,
+        queue_families: Vec<QueueFamily>,
+    ) -> Result<LogicalDevice, LogicalDeviceError> {
+        // Several queue families may resolve to the same index on the device, so request the
+        // largest number of queues any of them asked for
+        let mut unique_queue_counts: HashMap<u32, u32> = HashMap::new();
+        for queue_family in queue_families.iter() {
+            let count = unique_queue_counts
+                .entry(queue_family.index.unwrap())
+                .or_insert(0);
+            *count = (*count).max(queue_family.queue_count);
         }
 
+        let queue_priorities: Vec<(u32, Vec<f32>)> = unique_queue_counts
+            .iter()
+            .map(|(&queue_index, &count)| (queue_index, vec![1.0_f32; count as usize]))
+            .collect();
+        let queue_infos: Vec<vk::DeviceQueueCreateInfo> = queue_priorities
+            .iter()
+            .map(|(queue_index, priorities)| {
+                vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(*queue_index)
+                    .queue_priorities(priorities)
+                    .build()
+            })
+            .collect();
+
         let required_validation_layers_raw: Vec<CString> = instance.debug_layers().iter().map(|&layer| Into::<CString>::into(layer)).collect();
         let required_validation_layers: Vec<*const i8> = required_validation_layers_raw.iter().map(|layer| layer.as_ptr()).collect();
 
@@ -78,11 +200,55 @@ impl LogicalDevice {
             .map(|extension| extension.as_ptr())
             .collect();
 
-        let create_info = vk::DeviceCreateInfo::builder()
+        let mut buffer_device_address_features =
+            vk::PhysicalDeviceBufferDeviceAddressFeatures::builder().buffer_device_address(true);
+        let mut descriptor_indexing_features = *physical_device.descriptor_indexing_features();
+        let mut line_rasterization_features = *physical_device.line_rasterization_features();
+        let mut present_id_features = *physical_device.present_id_features();
+        let mut present_wait_features = *physical_device.present_wait_features();
+
+        let mut create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_infos)
             .enabled_features(physical_device.features())
             .enabled_layer_names(&required_validation_layers)
             .enabled_extension_names(&device_extensions_ptr);
+        if physical_device
+            .enabled_extensions()
+            .contains(&DeviceExtension::BufferDeviceAddress)
+        {
+            if physical_device
+                .buffer_device_address_features()
+                .buffer_device_address
+                == vk::FALSE
+            {
+                return Err(LogicalDeviceError::MissingFeature("bufferDeviceAddress"));
+            }
+            create_info = create_info.push_next(&mut buffer_device_address_features);
+        }
+        if physical_device
+            .enabled_extensions()
+            .contains(&DeviceExtension::DescriptorIndexing)
+        {
+            create_info = create_info.push_next(&mut descriptor_indexing_features);
+        }
+        if physical_device
+            .enabled_extensions()
+            .contains(&DeviceExtension::LineRasterization)
+        {
+            create_info = create_info.push_next(&mut line_rasterization_features);
+        }
+        if physical_device
+            .enabled_extensions()
+            .contains(&DeviceExtension::PresentId)
+        {
+            create_info = create_info.push_next(&mut present_id_features);
+        }
+        if physical_device
+            .enabled_extensions()
+            .contains(&DeviceExtension::PresentWait)
+        {
+            create_info = create_info.push_next(&mut present_wait_features);
+        }
 
         let handle = unsafe {
             instance
@@ -95,15 +261,21 @@ impl LogicalDevice {
         };
 
         let mut queues: Vec<QueueHandle> = Vec::new();
-        for queue_family in physical_device.queue_families().iter() {
-            queues.push(QueueHandle {
-                handle: unsafe { handle.get_device_queue(queue_family.index.unwrap(), 0) },
-                ty: queue_family.ty,
-            });
+        for queue_family in queue_families.iter() {
+            for index in 0..queue_family.queue_count {
+                queues.push(QueueHandle {
+                    handle: unsafe { handle.get_device_queue(queue_family.index.unwrap(), index) },
+                    ty: queue_family.ty,
+                    index,
+                    timestamp_valid_bits: queue_family.timestamp_valid_bits,
+                });
+            }
         }
 
         Ok(LogicalDevice {
             queues,
+            sampler_cache: SamplerCache::new(),
+            allocations: RefCell::new(Allocations::default()),
 
             physical_device,
             handle,
@@ -129,6 +301,18 @@ impl LogicalDevice {
         self.queues.iter().find(|queue| queue.ty.contains(ty))
     }
 
+    /// Returns a [QueueHandle] to the `index`th queue created from the queue family with the type
+    /// `ty`
+    ///
+    /// See [QueueFamily::with_count] for creating more than one queue from a family.
+    ///
+    /// [QueueFamily::with_count]: crate::core::device::QueueFamily::with_count
+    pub fn queue_at(&self, ty: QueueFlags, index: u32) -> Option<&QueueHandle> {
+        self.queues
+            .iter()
+            .find(|queue| queue.ty.contains(ty) && queue.index == index)
+    }
+
     /// Returns the [PhysicalDevcie] this logical device is interfacing with
     pub fn physical_device(&self) -> &PhysicalDevice {
         &self.physical_device
@@ -138,16 +322,86 @@ impl LogicalDevice {
     pub fn instance(&self) -> &Instance {
         &self.instance
     }
+
+    /// Returns the [SamplerCache] holding the samplers created with this device
+    pub fn sampler_cache(&self) -> &SamplerCache {
+        &self.sampler_cache
+    }
+
+    /// Returns how much device memory is currently allocated for buffers and images created
+    /// with this device, useful for tracking down leaks and budgeting VRAM
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.allocations.borrow().stats()
+    }
+}
+
+impl LogicalDevice {
+    /// Samples the device's timestamp and the host's clock at the same moment, so that profilers
+    /// can place GPU timestamps on the CPU timeline
+    ///
+    /// # Errors
+    /// - [LogicalDeviceError::MissingExtension] - [DeviceExtension::CalibratedTimestamps] isn't
+    ///   enabled
+    /// - [LogicalDeviceError::UnsupportedTimeDomain] - The device can't calibrate against either
+    ///   its own or the host's clock
+    pub fn calibrated_timestamps(&self) -> Result<CalibratedTimestamps, LogicalDeviceError> {
+        if !self
+            .physical_device
+            .enabled_extensions()
+            .contains(&DeviceExtension::CalibratedTimestamps)
+        {
+            return Err(LogicalDeviceError::MissingExtension(
+                DeviceExtension::CalibratedTimestamps,
+            ));
+        }
+
+        let calibrated_timestamps = ash::extensions::ext::CalibratedTimestamps::new(
+            self.instance.entry(),
+            self.instance.vk_handle(),
+        );
+        let time_domains = unsafe {
+            calibrated_timestamps
+                .get_physical_device_calibrateable_time_domains(self.physical_device.vk_handle())
+                .map_err(|err| LogicalDeviceError::Other(err.into()))?
+        };
+        if !time_domains.contains(&vk::TimeDomainEXT::DEVICE)
+            || !time_domains.contains(&HOST_TIME_DOMAIN)
+        {
+            return Err(LogicalDeviceError::UnsupportedTimeDomain);
+        }
+
+        let infos = [
+            vk::CalibratedTimestampInfoEXT::builder()
+                .time_domain(vk::TimeDomainEXT::DEVICE)
+                .build(),
+            vk::CalibratedTimestampInfoEXT::builder()
+                .time_domain(HOST_TIME_DOMAIN)
+                .build(),
+        ];
+        let (timestamps, max_deviations) = unsafe {
+            calibrated_timestamps
+                .get_calibrated_timestamps(self.handle.handle(), &infos)
+                .map_err(|err| LogicalDeviceError::Other(err.into()))?
+        };
+
+        Ok(CalibratedTimestamps {
+            device: timestamps[0],
+            host: timestamps[1],
+            max_deviation: max_deviations[0],
+        })
+    }
 }
 
 impl LogicalDevice {
     /// Waits for the [PhysicalDevice] to idle/stop using resources
     pub fn wait_for_idle(&self) -> Result<(), LogicalDeviceError> {
-        Ok(unsafe {
+        unsafe {
             self.handle
                 .device_wait_idle()
                 .map_err(|err| LogicalDeviceError::Other(err.into()))?
-        })
+        };
+
+        Ok(())
     }
 
     /// Waits the logical device until `wait_all` fences are signaled by the host
@@ -157,7 +411,7 @@ impl LogicalDevice {
         wait_all: bool,
         timeout: u64,
     ) -> Result<(), LogicalDeviceError> {
-        if fences.len() == 0 {
+        if fences.is_empty() {
             return Ok(());
         }
 
@@ -202,11 +456,7 @@ impl LogicalDevice {
                 self.find_memory_type(memory_requirements.memory_type_bits, memory_properties)?,
             );
 
-        let device_memory = unsafe {
-            self.handle
-                .allocate_memory(&allocate_info, None)
-                .map_err(|err| LogicalDeviceError::Other(err.into()))?
-        };
+        let device_memory = self.allocate_memory(&allocate_info, AllocationKind::Image)?;
 
         unsafe {
             self.handle
@@ -217,6 +467,45 @@ impl LogicalDevice {
         Ok((image, device_memory))
     }
 
+    /// Allocates device memory, keeping track of it in the device's [MemoryStats]
+    pub(crate) fn allocate_memory(
+        &self,
+        allocate_info: &vk::MemoryAllocateInfo,
+        kind: AllocationKind,
+    ) -> Result<vk::DeviceMemory, LogicalDeviceError> {
+        let memory = unsafe {
+            self.handle
+                .allocate_memory(allocate_info, None)
+                .map_err(|err| LogicalDeviceError::Other(err.into()))?
+        };
+        self.allocations
+            .borrow_mut()
+            .insert(memory, kind, allocate_info.allocation_size);
+
+        Ok(memory)
+    }
+
+    /// Frees device memory allocated with [LogicalDevice::allocate_memory]
+    pub(crate) fn free_memory(&self, memory: vk::DeviceMemory) {
+        self.allocations.borrow_mut().remove(memory);
+        unsafe {
+            self.handle.free_memory(memory, None);
+        };
+    }
+
+    /// Returns a Vulkan sampler created with `info`, shared with every other caller that
+    /// requested the same parameters.
+    ///
+    /// The sampler is owned by the device and destroyed when the device is dropped.
+    pub fn sampler(&self, info: &SamplerInfo) -> Result<vk::Sampler, LogicalDeviceError> {
+        self.sampler_cache.get(
+            &self.handle,
+            &self.physical_device.properties().limits,
+            self.physical_device.features().sampler_anisotropy == vk::TRUE,
+            info,
+        )
+    }
+
     /// Finds a memory type on the [PhysicalDevice] that matches the `type_filter` and
     /// `required_properties`.
     pub fn find_memory_type(
@@ -255,12 +544,10 @@ impl LogicalDevice {
                     .get_physical_device_format_properties(self.physical_device.vk_handle(), format)
             };
 
-            if tiling == vk::ImageTiling::LINEAR
-                && properties.linear_tiling_features.contains(features)
-            {
-                return Ok(format);
-            } else if tiling == vk::ImageTiling::OPTIMAL
-                && properties.optimal_tiling_features.contains(features)
+            if (tiling == vk::ImageTiling::LINEAR
+                && properties.linear_tiling_features.contains(features))
+                || (tiling == vk::ImageTiling::OPTIMAL
+                    && properties.optimal_tiling_features.contains(features))
             {
                 return Ok(format);
             }
@@ -272,8 +559,43 @@ impl LogicalDevice {
 
 impl Drop for LogicalDevice {
     fn drop(&mut self) {
+        self.sampler_cache.destroy(&self.handle);
         unsafe {
             self.handle.destroy_device(None);
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn allocations_are_totalled_by_kind() {
+        let mut allocations = Allocations::default();
+        allocations.insert(vk::DeviceMemory::from_raw(1), AllocationKind::Buffer, 256);
+        allocations.insert(vk::DeviceMemory::from_raw(2), AllocationKind::Image, 1024);
+        allocations.insert(vk::DeviceMemory::from_raw(3), AllocationKind::Buffer, 64);
+
+        assert_eq!(
+            allocations.stats(),
+            MemoryStats {
+                buffer_bytes: 320,
+                image_bytes: 1024,
+                allocation_count: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn allocate_and_free_round_trips_to_zero() {
+        let mut allocations = Allocations::default();
+        allocations.insert(vk::DeviceMemory::from_raw(1), AllocationKind::Buffer, 256);
+        allocations.insert(vk::DeviceMemory::from_raw(2), AllocationKind::Image, 1024);
+        allocations.remove(vk::DeviceMemory::from_raw(1));
+        allocations.remove(vk::DeviceMemory::from_raw(2));
+
+        assert_eq!(allocations.stats(), MemoryStats::default());
+    }
+}
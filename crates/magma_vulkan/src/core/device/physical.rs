@@ -14,6 +14,8 @@ pub enum PhysicalDeviceError {
     NoPhysicalDevices,
     #[error("Failed to find a physical device that matched the requirements")]
     NoSuitableDevice,
+    #[error("Found a suitable physical device but none of the preferred type {0:?}, and falling back to other types is disabled")]
+    NoPreferredDevice(PhysicalDeviceType),
     #[error("Not all queue families have an index")]
     IncompleteQueueFamilies,
     #[error("The physical device doesn't support some (or all) of the required extensions")]
@@ -23,6 +25,7 @@ pub enum PhysicalDeviceError {
 }
 
 /// Possible physical device types
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PhysicalDeviceType {
     CPU,
     IntegratedGPU,
@@ -31,15 +34,78 @@ pub enum PhysicalDeviceType {
     Other,
 }
 
+impl From<vk::PhysicalDeviceType> for PhysicalDeviceType {
+    fn from(ty: vk::PhysicalDeviceType) -> PhysicalDeviceType {
+        match ty {
+            vk::PhysicalDeviceType::CPU => PhysicalDeviceType::CPU,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => PhysicalDeviceType::IntegratedGPU,
+            vk::PhysicalDeviceType::DISCRETE_GPU => PhysicalDeviceType::DiscreteGPU,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => PhysicalDeviceType::VirtualGPU,
+            _ => PhysicalDeviceType::Other,
+        }
+    }
+}
+
+/// Describes a physical device available on the machine, before any [PhysicalDevice] is created
+/// from it
+#[derive(Clone, Debug)]
+pub struct PhysicalDeviceInfo {
+    /// Index of the device in the order the Vulkan driver enumerates them
+    pub index: usize,
+    /// Name of the device reported by the driver
+    pub name: String,
+    /// Type of the device
+    pub ty: PhysicalDeviceType,
+    /// Vendor ID of the device
+    pub vendor_id: u32,
+    /// Device ID of the device, unique for the vendor
+    pub device_id: u32,
+}
+
+impl PhysicalDeviceInfo {
+    /// Creates a [PhysicalDeviceInfo] from the properties of a Vulkan physical device
+    pub(crate) fn new(index: usize, properties: &vk::PhysicalDeviceProperties) -> PhysicalDeviceInfo {
+        PhysicalDeviceInfo {
+            index,
+            name: utils::char_array_to_string(&properties.device_name),
+            ty: properties.device_type.into(),
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+        }
+    }
+}
+
+/// Forces the [PhysicalDeviceBuilder] to pick a specific device
+enum DeviceSelection {
+    /// Picks the first device whose name contains the string, ignoring case
+    Name(String),
+    /// Picks the device at an index in the enumeration order
+    Index(usize),
+}
+
+impl DeviceSelection {
+    /// Returns whether the device at `index` in the enumeration order is selected, `device_name`
+    /// is only called when selecting by name
+    fn matches(&self, index: usize, device_name: impl FnOnce() -> String) -> bool {
+        match self {
+            DeviceSelection::Index(selected) => *selected == index,
+            DeviceSelection::Name(name) => device_name().to_lowercase().contains(name.as_str()),
+        }
+    }
+}
+
 /// Wraps the steps required to create a [PhysicalDevice]
 pub struct PhysicalDeviceBuilder {
     /// Queue families to create the physical device with
     queue_families: Vec<QueueFamily>,
     /// Type of device to use, if found
-    // FIXME: Not being taken into account
     preferred_type: PhysicalDeviceType,
+    /// Whether a device of another type can be used when none of the preferred type is suitable
+    fallback: bool,
     /// Device extensions to enable on the physical device
     device_extensions: Vec<DeviceExtension>,
+    /// Specific device to pick, if any
+    selection: Option<DeviceSelection>,
 }
 
 impl PhysicalDeviceBuilder {
@@ -48,7 +114,9 @@ impl PhysicalDeviceBuilder {
         PhysicalDeviceBuilder {
             queue_families: Vec::new(),
             preferred_type: PhysicalDeviceType::DiscreteGPU,
+            fallback: true,
             device_extensions: Vec::new(),
+            selection: None,
         }
     }
 
@@ -64,18 +132,154 @@ impl PhysicalDeviceBuilder {
         self
     }
 
+    /// Sets whether a suitable device of another type can be used when there is no suitable
+    /// device of the preferred type, defaults to `true`
+    ///
+    /// When disabled, building fails with [PhysicalDeviceError::NoPreferredDevice] instead, e.g.
+    /// to not silently run on an integrated GPU when a discrete one is required.
+    pub fn fallback(mut self, fallback: bool) -> PhysicalDeviceBuilder {
+        self.fallback = fallback;
+        self
+    }
+
     /// Sets the device extensions to create the physical device with
     pub fn device_extensions(mut self, extensions: &[DeviceExtension]) -> PhysicalDeviceBuilder {
         self.device_extensions = extensions.to_vec();
         self
     }
 
+    /// Only considers devices whose name contains `name`, ignoring case, e.g. "llvmpipe" to
+    /// always render on the software rasterizer on CI
+    ///
+    /// See [Instance::enumerate_physical_devices] for the names of available devices.
+    pub fn by_name(mut self, name: &str) -> PhysicalDeviceBuilder {
+        self.selection = Some(DeviceSelection::Name(name.to_lowercase()));
+        self
+    }
+
+    /// Only considers the device at `index` in the order devices are enumerated by the driver
+    ///
+    /// See [Instance::enumerate_physical_devices] for the indices of available devices.
+    pub fn by_index(mut self, index: usize) -> PhysicalDeviceBuilder {
+        self.selection = Some(DeviceSelection::Index(index));
+        self
+    }
+
     /// Creates a [PhysicalDevice]
     pub fn build(mut self, instance: &Instance) -> Result<PhysicalDevice, PhysicalDeviceError> {
         let handle = self.pick_physical_device(instance)?;
 
         let properties = unsafe { instance.vk_handle().get_physical_device_properties(handle) };
         let features = unsafe { instance.vk_handle().get_physical_device_features(handle) };
+        let buffer_device_address_features = if self
+            .device_extensions
+            .contains(&DeviceExtension::BufferDeviceAddress)
+        {
+            let mut buffer_device_address_features =
+                vk::PhysicalDeviceBufferDeviceAddressFeatures::default();
+            let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+                .push_next(&mut buffer_device_address_features);
+            unsafe {
+                instance
+                    .vk_handle()
+                    .get_physical_device_features2(handle, &mut features2)
+            };
+            buffer_device_address_features.p_next = std::ptr::null_mut();
+
+            buffer_device_address_features
+        } else {
+            vk::PhysicalDeviceBufferDeviceAddressFeatures::default()
+        };
+        let descriptor_indexing_features = if self
+            .device_extensions
+            .contains(&DeviceExtension::DescriptorIndexing)
+        {
+            let mut descriptor_indexing_features =
+                vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut descriptor_indexing_features);
+            unsafe {
+                instance
+                    .vk_handle()
+                    .get_physical_device_features2(handle, &mut features2)
+            };
+            descriptor_indexing_features.p_next = std::ptr::null_mut();
+
+            descriptor_indexing_features
+        } else {
+            vk::PhysicalDeviceDescriptorIndexingFeatures::default()
+        };
+        let line_rasterization_features = if self
+            .device_extensions
+            .contains(&DeviceExtension::LineRasterization)
+        {
+            let mut line_rasterization_features =
+                vk::PhysicalDeviceLineRasterizationFeaturesEXT::default();
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut line_rasterization_features);
+            unsafe {
+                instance
+                    .vk_handle()
+                    .get_physical_device_features2(handle, &mut features2)
+            };
+            line_rasterization_features.p_next = std::ptr::null_mut();
+
+            line_rasterization_features
+        } else {
+            vk::PhysicalDeviceLineRasterizationFeaturesEXT::default()
+        };
+        let present_id_features = if self.device_extensions.contains(&DeviceExtension::PresentId) {
+            let mut present_id_features = vk::PhysicalDevicePresentIdFeaturesKHR::default();
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut present_id_features);
+            unsafe {
+                instance
+                    .vk_handle()
+                    .get_physical_device_features2(handle, &mut features2)
+            };
+            present_id_features.p_next = std::ptr::null_mut();
+
+            present_id_features
+        } else {
+            vk::PhysicalDevicePresentIdFeaturesKHR::default()
+        };
+        let present_wait_features = if self
+            .device_extensions
+            .contains(&DeviceExtension::PresentWait)
+        {
+            let mut present_wait_features = vk::PhysicalDevicePresentWaitFeaturesKHR::default();
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut present_wait_features);
+            unsafe {
+                instance
+                    .vk_handle()
+                    .get_physical_device_features2(handle, &mut features2)
+            };
+            present_wait_features.p_next = std::ptr::null_mut();
+
+            present_wait_features
+        } else {
+            vk::PhysicalDevicePresentWaitFeaturesKHR::default()
+        };
+        let depth_stencil_resolve_properties = if self
+            .device_extensions
+            .contains(&DeviceExtension::DepthStencilResolve)
+        {
+            let mut depth_stencil_resolve_properties =
+                vk::PhysicalDeviceDepthStencilResolveProperties::default();
+            let mut properties2 = vk::PhysicalDeviceProperties2::builder()
+                .push_next(&mut depth_stencil_resolve_properties);
+            unsafe {
+                instance
+                    .vk_handle()
+                    .get_physical_device_properties2(handle, &mut properties2)
+            };
+            depth_stencil_resolve_properties.p_next = std::ptr::null_mut();
+
+            depth_stencil_resolve_properties
+        } else {
+            vk::PhysicalDeviceDepthStencilResolveProperties::default()
+        };
         let memory_properties = unsafe {
             instance
                 .vk_handle()
@@ -100,6 +304,12 @@ impl PhysicalDeviceBuilder {
 
             properties,
             features,
+            buffer_device_address_features,
+            descriptor_indexing_features,
+            line_rasterization_features,
+            present_id_features,
+            present_wait_features,
+            depth_stencil_resolve_properties,
             memory_properties,
 
             handle,
@@ -124,50 +334,102 @@ impl PhysicalDeviceBuilder {
                 .map_err(|_| PhysicalDeviceError::NoPhysicalDevices)?
         };
 
-        let mut chosen_device: Option<vk::PhysicalDevice> = None;
-        for &physical_device in physical_devices.iter() {
-            if self.is_device_suitable(instance, physical_device)? {
-                chosen_device = Some(physical_device);
-                break;
+        let mut suitable_devices: Vec<(vk::PhysicalDevice, Vec<QueueFamily>)> = Vec::new();
+        let mut device_types: Vec<PhysicalDeviceType> = Vec::new();
+        let mut last_error: Option<PhysicalDeviceError> = None;
+        for (index, &physical_device) in physical_devices.iter().enumerate() {
+            if !self.is_device_selected(instance, index, physical_device) {
+                continue;
+            }
+
+            match self.suitable_queue_families(instance, physical_device) {
+                Ok(queue_families) => {
+                    let properties = unsafe {
+                        instance
+                            .vk_handle()
+                            .get_physical_device_properties(physical_device)
+                    };
+                    suitable_devices.push((physical_device, queue_families));
+                    device_types.push(properties.device_type.into());
+                }
+                Err(err) => last_error = Some(err),
             }
         }
 
-        match chosen_device {
-            Some(device) => Ok(device),
-            None => Err(PhysicalDeviceError::NoSuitableDevice),
+        if suitable_devices.is_empty() {
+            return Err(last_error.unwrap_or(PhysicalDeviceError::NoSuitableDevice));
         }
+
+        let chosen = choose_device(&device_types, self.preferred_type, self.fallback)?;
+        let (chosen_device, queue_families) = suitable_devices.swap_remove(chosen);
+        for queue_family in queue_families
+            .iter()
+            .filter(|family| family.queue_count < family.count)
+        {
+            log::warn!(
+                "Requested {} {:?} queues, but the device's queue family only has {}",
+                queue_family.count,
+                queue_family.ty,
+                queue_family.queue_count
+            );
+        }
+        self.queue_families = queue_families;
+
+        Ok(chosen_device)
+    }
+
+    /// Checks whether the Vulkan physical device at `index` matches the device forced by
+    /// [PhysicalDeviceBuilder::by_name] or [PhysicalDeviceBuilder::by_index], if any
+    fn is_device_selected(
+        &self,
+        instance: &Instance,
+        index: usize,
+        device: vk::PhysicalDevice,
+    ) -> bool {
+        self.selection.as_ref().is_none_or(|selection| {
+            selection.matches(index, || {
+                let properties =
+                    unsafe { instance.vk_handle().get_physical_device_properties(device) };
+                utils::char_array_to_string(&properties.device_name)
+            })
+        })
     }
 
     /// Checks wether the Vulkan physical device contains the requried queue families and supports
-    /// the required device extensions.
-    fn is_device_suitable(
-        &mut self,
+    /// the required device extensions, returning the queue families found on the device
+    fn suitable_queue_families(
+        &self,
         instance: &Instance,
         device: vk::PhysicalDevice,
-    ) -> Result<bool, PhysicalDeviceError> {
-        self.find_queue_families(instance, device)?;
+    ) -> Result<Vec<QueueFamily>, PhysicalDeviceError> {
+        let queue_families = self.find_queue_families(instance, device)?;
         self.check_device_extension_support(instance, device)?;
 
-        Ok(true)
+        Ok(queue_families)
     }
 
     /// Goes through all the queue families of the physical device, finding the ones required by
     /// the [PhysicalDeviceBuilder].
     ///
+    /// The requested queue families are left untouched, so every device is checked against the
+    /// same requirements, and the families found on this device are returned instead.
+    ///
     /// If not all the required queue families were found
     /// [PhysicalDeviceError::IncompleteQueueFamilies] is returned.
     fn find_queue_families(
-        &mut self,
+        &self,
         instance: &Instance,
         device: vk::PhysicalDevice,
-    ) -> Result<(), PhysicalDeviceError> {
+    ) -> Result<Vec<QueueFamily>, PhysicalDeviceError> {
         let device_queue_families = unsafe {
             instance
                 .vk_handle()
                 .get_physical_device_queue_family_properties(device)
         };
 
-        for queue_family in self.queue_families.iter_mut() {
+        let mut queue_families = self.queue_families.clone();
+        for queue_family in queue_families.iter_mut() {
+            queue_family.index = None;
             for (index, device_queue_family) in device_queue_families.iter().enumerate() {
                 if device_queue_family.queue_count > 0
                     && device_queue_family
@@ -175,19 +437,18 @@ impl PhysicalDeviceBuilder {
                         .contains(queue_family.ty.into())
                 {
                     queue_family.index = Some(index as u32);
+                    queue_family.queue_count =
+                        queue_family.count.min(device_queue_family.queue_count);
+                    queue_family.timestamp_valid_bits = device_queue_family.timestamp_valid_bits;
                     break;
                 }
             }
         }
 
-        if let Some(_) = self
-            .queue_families
-            .iter()
-            .find(|family| family.index.is_none())
-        {
+        if queue_families.iter().any(|family| family.index.is_none()) {
             Err(PhysicalDeviceError::IncompleteQueueFamilies)
         } else {
-            Ok(())
+            Ok(queue_families)
         }
     }
 
@@ -231,6 +492,42 @@ impl PhysicalDeviceBuilder {
     }
 }
 
+/// Returns the index of the device to use out of suitable devices of `types`, the first one of
+/// the `preferred` type, or the first one of any type if `fallback` is set
+///
+/// # Errors
+/// - [PhysicalDeviceError::NoSuitableDevice] - There are no suitable devices
+/// - [PhysicalDeviceError::NoPreferredDevice] - No device is of the `preferred` type and
+///   `fallback` isn't set
+fn choose_device(
+    types: &[PhysicalDeviceType],
+    preferred: PhysicalDeviceType,
+    fallback: bool,
+) -> Result<usize, PhysicalDeviceError> {
+    match types.iter().position(|&ty| ty == preferred) {
+        Some(index) => Ok(index),
+        None if types.is_empty() => Err(PhysicalDeviceError::NoSuitableDevice),
+        None if fallback => Ok(0),
+        None => Err(PhysicalDeviceError::NoPreferredDevice(preferred)),
+    }
+}
+
+/// Returns the highest sample count in `sample_counts`, 1 if none are set
+fn max_sample_count(sample_counts: vk::SampleCountFlags) -> u32 {
+    [64, 32, 16, 8, 4, 2]
+        .into_iter()
+        .find(|&samples| sample_counts.contains(vk::SampleCountFlags::from_raw(samples)))
+        .unwrap_or(1)
+}
+
+/// Returns the highest sample count that both color and depth framebuffer attachments support
+/// with `limits`
+fn max_usable_sample_count(limits: &vk::PhysicalDeviceLimits) -> u32 {
+    max_sample_count(
+        limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts,
+    )
+}
+
 /// Wraps a Vulkan physical device and its capabilities
 pub struct PhysicalDevice {
     /// List of all enabled device extensions
@@ -242,6 +539,22 @@ pub struct PhysicalDevice {
     properties: vk::PhysicalDeviceProperties,
     /// Vulkan physical device features
     features: vk::PhysicalDeviceFeatures,
+    /// Vulkan buffer device address features, all unsupported unless
+    /// [DeviceExtension::BufferDeviceAddress] is enabled
+    buffer_device_address_features: vk::PhysicalDeviceBufferDeviceAddressFeatures,
+    /// Vulkan descriptor indexing features, all unsupported unless
+    /// [DeviceExtension::DescriptorIndexing] is enabled
+    descriptor_indexing_features: vk::PhysicalDeviceDescriptorIndexingFeatures,
+    /// Vulkan line rasterization features, all unsupported unless
+    /// [DeviceExtension::LineRasterization] is enabled
+    line_rasterization_features: vk::PhysicalDeviceLineRasterizationFeaturesEXT,
+    /// Vulkan present id features, unsupported unless [DeviceExtension::PresentId] is enabled
+    present_id_features: vk::PhysicalDevicePresentIdFeaturesKHR,
+    /// Vulkan present wait features, unsupported unless [DeviceExtension::PresentWait] is enabled
+    present_wait_features: vk::PhysicalDevicePresentWaitFeaturesKHR,
+    /// Vulkan depth stencil resolve properties, no resolve modes are supported unless
+    /// [DeviceExtension::DepthStencilResolve] is enabled
+    depth_stencil_resolve_properties: vk::PhysicalDeviceDepthStencilResolveProperties,
     /// Vulkan physical device memory properties
     memory_properties: vk::PhysicalDeviceMemoryProperties,
 
@@ -285,13 +598,163 @@ impl PhysicalDevice {
         &self.properties
     }
 
+    /// Returns the highest number of samples that both color and depth framebuffer attachments
+    /// support, to use for multisample anti-aliasing
+    pub fn max_usable_sample_count(&self) -> u32 {
+        max_usable_sample_count(&self.properties.limits)
+    }
+
     /// Returns the Vulkan physical device features
     pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
         &self.features
     }
 
+    /// Returns the Vulkan buffer device address features the device supports
+    ///
+    /// Every feature is unsupported unless [DeviceExtension::BufferDeviceAddress] is enabled.
+    pub fn buffer_device_address_features(&self) -> &vk::PhysicalDeviceBufferDeviceAddressFeatures {
+        &self.buffer_device_address_features
+    }
+
+    /// Returns the Vulkan descriptor indexing features the device supports
+    ///
+    /// Every feature is unsupported unless [DeviceExtension::DescriptorIndexing] is enabled.
+    pub fn descriptor_indexing_features(&self) -> &vk::PhysicalDeviceDescriptorIndexingFeatures {
+        &self.descriptor_indexing_features
+    }
+
+    /// Returns the Vulkan line rasterization features the device supports
+    ///
+    /// Every feature is unsupported unless [DeviceExtension::LineRasterization] is enabled.
+    pub fn line_rasterization_features(&self) -> &vk::PhysicalDeviceLineRasterizationFeaturesEXT {
+        &self.line_rasterization_features
+    }
+
+    /// Returns the Vulkan present id features the device supports
+    ///
+    /// Every feature is unsupported unless [DeviceExtension::PresentId] is enabled.
+    pub fn present_id_features(&self) -> &vk::PhysicalDevicePresentIdFeaturesKHR {
+        &self.present_id_features
+    }
+
+    /// Returns the Vulkan present wait features the device supports
+    ///
+    /// Every feature is unsupported unless [DeviceExtension::PresentWait] is enabled.
+    pub fn present_wait_features(&self) -> &vk::PhysicalDevicePresentWaitFeaturesKHR {
+        &self.present_wait_features
+    }
+
+    /// Returns the Vulkan depth stencil resolve properties of the device
+    ///
+    /// No resolve modes are supported unless [DeviceExtension::DepthStencilResolve] is enabled.
+    pub fn depth_stencil_resolve_properties(
+        &self,
+    ) -> &vk::PhysicalDeviceDepthStencilResolveProperties {
+        &self.depth_stencil_resolve_properties
+    }
+
     /// Returns the Vulkan physical device memory properties
     pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
         &self.memory_properties
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_usable_sample_count_is_supported_by_color_and_depth() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_2
+                | vk::SampleCountFlags::TYPE_4
+                | vk::SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_2
+                | vk::SampleCountFlags::TYPE_4,
+            ..Default::default()
+        };
+
+        assert_eq!(max_usable_sample_count(&limits), 4);
+    }
+
+    #[test]
+    fn max_usable_sample_count_without_multisampling_is_1() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1,
+            ..Default::default()
+        };
+
+        assert_eq!(max_usable_sample_count(&limits), 1);
+    }
+
+    #[test]
+    fn preferred_device_type_is_chosen() {
+        let types = [
+            PhysicalDeviceType::IntegratedGPU,
+            PhysicalDeviceType::DiscreteGPU,
+        ];
+
+        assert_eq!(
+            choose_device(&types, PhysicalDeviceType::DiscreteGPU, false).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn first_device_is_chosen_as_a_fallback() {
+        let types = [PhysicalDeviceType::CPU, PhysicalDeviceType::IntegratedGPU];
+
+        assert_eq!(
+            choose_device(&types, PhysicalDeviceType::DiscreteGPU, true).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn integrated_gpu_without_fallback_errors() {
+        let types = [PhysicalDeviceType::IntegratedGPU];
+
+        assert!(matches!(
+            choose_device(&types, PhysicalDeviceType::DiscreteGPU, false),
+            Err(PhysicalDeviceError::NoPreferredDevice(
+                PhysicalDeviceType::DiscreteGPU
+            ))
+        ));
+    }
+
+    /// Names of the devices of a machine with a discrete GPU and the software rasterizer, in
+    /// enumeration order
+    const DEVICE_NAMES: [&str; 2] = [
+        "NVIDIA GeForce RTX 3070",
+        "llvmpipe (LLVM 15.0.7, 256 bits)",
+    ];
+
+    /// Returns the indices of the [DEVICE_NAMES] the `builder` selects
+    fn selected_devices(builder: PhysicalDeviceBuilder) -> Vec<usize> {
+        let selection = builder.selection.unwrap();
+        (0..DEVICE_NAMES.len())
+            .filter(|&index| selection.matches(index, || DEVICE_NAMES[index].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn by_name_selects_devices_containing_the_name_ignoring_case() {
+        assert_eq!(
+            selected_devices(PhysicalDeviceBuilder::new().by_name("LLVMpipe")),
+            vec![1]
+        );
+        assert!(selected_devices(PhysicalDeviceBuilder::new().by_name("radeon")).is_empty());
+    }
+
+    #[test]
+    fn by_index_selects_only_the_device_at_the_index() {
+        assert_eq!(
+            selected_devices(PhysicalDeviceBuilder::new().by_index(0)),
+            vec![0]
+        );
+        assert!(selected_devices(PhysicalDeviceBuilder::new().by_index(2)).is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use std::ffi::{CStr, CString};
 use std::mem::ManuallyDrop;
 
 #[cfg(target_os = "windows")]
@@ -9,7 +10,11 @@ use ash::extensions::ext::DebugUtils;
 use ash::extensions::khr::Surface;
 use ash::vk;
 
-use super::debugger::{DebugLayer, Debugger, DebuggerError};
+use super::device::PhysicalDeviceInfo;
+use super::debugger::{
+    DebugLayer, DebugMessageFilter, DebugMessageSeverity, DebugMessageType, Debugger, DebuggerError,
+    ValidationCallback,
+};
 use crate::{
     utils, VulkanError,
 };
@@ -21,48 +26,158 @@ pub enum InstanceError {
     LoadLibraryError(#[from] ash::LoadingError),
     #[error("Creating instance failed: {0}")]
     CantCreate(VulkanError),
+    #[error("The Vulkan loader only supports Vulkan {0}.{1}, but Vulkan 1.1 is required")]
+    UnsupportedApiVersion(u32, u32),
     #[error("Missing required extensions")]
     MissingExtensions(Vec<String>),
     #[error(transparent)]
     CantCreateDebugger(#[from] DebuggerError),
+    #[error("Vulkan reported {} validation errors: {0:?}", .0.len())]
+    ValidationErrors(Vec<String>),
+    #[error("The application name can't contain a nul byte")]
+    InvalidApplicationName,
     #[error(transparent)]
     Other(VulkanError),
 }
 
-/// Wraps a Vulkan instance and loaded library
-pub struct Instance {
-    /// List of Vulkan validation layers used by the [Debugger]
+/// Configures the creation of an [Instance]
+pub struct InstanceBuilder {
+    /// List of Vulkan validation layers to enable
     debug_layers: Vec<DebugLayer>,
-    /// Handle to the created debugger
-    debugger: ManuallyDrop<Option<Debugger>>,
-    /// Opaque handle to Vulkan instance
-    handle: ash::Instance,
-    /// Opaque handle to loaded Vulkan library
-    entry: ash::Entry,
+    /// Filter for the messages logged by the [Debugger]
+    debug_message_filter: DebugMessageFilter,
+    /// Callback that receives every message logged by the [Debugger]
+    validation_callback: Option<ValidationCallback>,
+    /// Whether validation errors are recorded for [Instance::check_validation_errors]
+    record_validation_errors: bool,
+    /// Name of the application, reported to drivers and tools
+    application_name: String,
+    /// Version of the application as (major, minor, patch), reported to drivers and tools
+    application_version: (u32, u32, u32),
+    /// Whether the surface extensions are skipped, for running without a window
+    headless: bool,
 }
 
-impl Instance {
-    /// Creates a new instance that loads the Vulkan library
+impl InstanceBuilder {
+    /// Creates a new [InstanceBuilder] with no debug layers
+    pub fn new() -> InstanceBuilder {
+        InstanceBuilder {
+            debug_layers: Vec::new(),
+            debug_message_filter: DebugMessageFilter::default(),
+            validation_callback: None,
+            record_validation_errors: false,
+            application_name: String::from("Magma App"),
+            application_version: (0, 1, 0),
+            headless: false,
+        }
+    }
+
+    /// Sets whether the instance is created without the surface extensions, so that it can be
+    /// used on machines without a display e.g. CI runners. A headless instance can't create a
+    /// [Surface][crate::core::surface::Surface].
+    pub fn headless(mut self, headless: bool) -> InstanceBuilder {
+        self.headless = headless;
+        self
+    }
+
+    /// Sets the name of the application, which drivers and tools like overlays may use to apply
+    /// per-application profiles
+    pub fn application_name(mut self, name: &str) -> InstanceBuilder {
+        self.application_name = name.to_string();
+        self
+    }
+
+    /// Sets the version of the application
+    pub fn application_version(mut self, major: u32, minor: u32, patch: u32) -> InstanceBuilder {
+        self.application_version = (major, minor, patch);
+        self
+    }
+
+    /// Enables the `debug_layers`, a [Debugger] is created if any layers are enabled
+    pub fn debug_layers(mut self, debug_layers: &[DebugLayer]) -> InstanceBuilder {
+        self.debug_layers = debug_layers.to_vec();
+        self
+    }
+
+    /// Sets the severities of the messages the [Debugger] logs
+    pub fn debug_message_severity(mut self, severity: DebugMessageSeverity) -> InstanceBuilder {
+        self.debug_message_filter.severity = severity;
+        self
+    }
+
+    /// Sets the types of the messages the [Debugger] logs
+    pub fn debug_message_type(mut self, message_type: DebugMessageType) -> InstanceBuilder {
+        self.debug_message_filter.message_type = message_type;
+        self
+    }
+
+    /// Drops any debug message whose id contains `message_id`
+    pub fn ignore_debug_message(mut self, message_id: &str) -> InstanceBuilder {
+        self.debug_message_filter
+            .ignored_message_ids
+            .push(message_id.to_string());
+        self
+    }
+
+    /// Sets a callback that receives every message logged by the [Debugger], for capturing
+    /// validation messages programmatically
     ///
-    /// Automatically creates a [Debugger] if `magma` is being built in debug mode
-    pub fn new(debug_layers: &[DebugLayer]) -> Result<Instance, InstanceError> {
+    /// Only invoked if any debug layers are enabled.
+    pub fn validation_callback(mut self, callback: ValidationCallback) -> InstanceBuilder {
+        self.validation_callback = Some(callback);
+        self
+    }
+
+    /// Sets whether validation errors reported by the [Debugger] are recorded, so tests and CI can
+    /// fail on misuse of Vulkan with [Instance::check_validation_errors]. Off by default.
+    pub fn record_validation_errors(mut self, record_validation_errors: bool) -> InstanceBuilder {
+        self.record_validation_errors = record_validation_errors;
+        self
+    }
+
+    /// Returns the names of the application and engine, as passed to Vulkan
+    fn names(&self) -> Result<(CString, CString), InstanceError> {
+        let app_name = CString::new(self.application_name.as_str())
+            .map_err(|_| InstanceError::InvalidApplicationName)?;
+        let engine_name = CString::new("Magma").unwrap();
+
+        Ok((app_name, engine_name))
+    }
+
+    /// Describes the application to Vulkan, using the `app_name` and `engine_name` returned by
+    /// [InstanceBuilder::names]
+    fn application_info<'a>(
+        &self,
+        app_name: &'a CStr,
+        engine_name: &'a CStr,
+    ) -> vk::ApplicationInfoBuilder<'a> {
+        let (major, minor, patch) = self.application_version;
+        vk::ApplicationInfo::builder()
+            .application_name(app_name)
+            .application_version(vk::make_api_version(0, major, minor, patch))
+            .engine_name(engine_name)
+            // Vulkan 1.1 is needed for vkGetPhysicalDeviceFeatures2, which queries the extension
+            // feature structs chained onto the device create info
+            .api_version(vk::API_VERSION_1_1)
+    }
+
+    /// Creates a new instance that loads the Vulkan library
+    pub fn build(self) -> Result<Instance, InstanceError> {
         let entry =
-            unsafe { ash::Entry::load().map_err(|err| InstanceError::LoadLibraryError(err))? };
+            unsafe { ash::Entry::load().map_err(InstanceError::LoadLibraryError)? };
 
-        Instance::check_required_extensions(&entry)?;
-        if !debug_layers.is_empty() {
-            Debugger::check_validation_layers(&entry, debug_layers)?;
+        Instance::check_api_version(&entry)?;
+        Instance::check_required_extensions(&entry, self.headless)?;
+        if !self.debug_layers.is_empty() {
+            Debugger::check_validation_layers(&entry, &self.debug_layers)?;
         }
 
-        use std::ffi::CString;
-        let app_name = CString::new("Magma").unwrap();
-        let engine_name = CString::new("Magma").unwrap();
-        let app_info = vk::ApplicationInfo::builder()
-            .application_name(&app_name)
-            .engine_name(&engine_name);
+        let (app_name, engine_name) = self.names()?;
+        let app_info = self.application_info(&app_name, &engine_name);
 
-        let enabled_extension_names = Instance::required_extension_names();
-        let enabled_layer_names_raw: Vec<CString> = debug_layers
+        let enabled_extension_names = Instance::required_extension_names(self.headless);
+        let enabled_layer_names_raw: Vec<CString> = self
+            .debug_layers
             .iter()
             .map(|&layer| Into::<CString>::into(layer))
             .collect();
@@ -82,25 +197,92 @@ impl Instance {
                 .map_err(|err| InstanceError::CantCreate(err.into()))?
         };
 
-        let debugger: Option<Debugger> = if !debug_layers.is_empty() {
+        let debugger: Option<Debugger> = if !self.debug_layers.is_empty() {
             log::debug!("Created Vulkan debugger");
-            Some(Debugger::new(&entry, &handle, debug_layers)?)
+            Some(Debugger::new(
+                &entry,
+                &handle,
+                &self.debug_layers,
+                self.debug_message_filter,
+                self.validation_callback,
+                self.record_validation_errors,
+            )?)
         } else {
             None
         };
 
         Ok(Instance {
-            debug_layers: debug_layers.to_vec(),
+            debug_layers: self.debug_layers,
+            headless: self.headless,
             debugger: ManuallyDrop::new(debugger),
             entry,
             handle,
         })
     }
+}
+
+/// Wraps a Vulkan instance and loaded library
+pub struct Instance {
+    /// List of Vulkan validation layers used by the [Debugger]
+    debug_layers: Vec<DebugLayer>,
+    /// Whether the instance was created without the surface extensions
+    headless: bool,
+    /// Handle to the created debugger
+    debugger: ManuallyDrop<Option<Debugger>>,
+    /// Opaque handle to Vulkan instance
+    handle: ash::Instance,
+    /// Opaque handle to loaded Vulkan library
+    entry: ash::Entry,
+}
+
+impl Instance {
+    /// Creates a new [InstanceBuilder]
+    pub fn builder() -> InstanceBuilder {
+        InstanceBuilder::new()
+    }
+
+    /// Creates a new instance that loads the Vulkan library
+    ///
+    /// Creates a [Debugger] with the default [DebugMessageFilter] if any `debug_layers` are given,
+    /// use [Instance::builder] to configure the debugger.
+    pub fn new(debug_layers: &[DebugLayer]) -> Result<Instance, InstanceError> {
+        Instance::builder().debug_layers(debug_layers).build()
+    }
+
+    /// Creates a new instance without the surface extensions, for using the device for compute
+    /// and transfer work on machines without a display
+    ///
+    /// See [InstanceBuilder::headless]
+    pub fn new_headless(debug_layers: &[DebugLayer]) -> Result<Instance, InstanceError> {
+        Instance::builder()
+            .debug_layers(debug_layers)
+            .headless(true)
+            .build()
+    }
+
+    /// Checks whether the loaded Vulkan library supports Vulkan 1.1, which the instance is created
+    /// with
+    fn check_api_version(entry: &ash::Entry) -> Result<(), InstanceError> {
+        // Loaders without vkEnumerateInstanceVersion only support Vulkan 1.0
+        let api_version = entry
+            .try_enumerate_instance_version()
+            .map_err(|err| InstanceError::Other(err.into()))?
+            .unwrap_or(vk::API_VERSION_1_0);
+
+        if api_version < vk::API_VERSION_1_1 {
+            Err(InstanceError::UnsupportedApiVersion(
+                vk::api_version_major(api_version),
+                vk::api_version_minor(api_version),
+            ))
+        } else {
+            Ok(())
+        }
+    }
 
     /// Checks whether the instance supports all the extensions needed
     ///
     /// See [`Instance::required_extension_names`]
-    fn check_required_extensions(entry: &ash::Entry) -> Result<(), InstanceError> {
+    fn check_required_extensions(entry: &ash::Entry, headless: bool) -> Result<(), InstanceError> {
         let supported_extension_names = entry
             .enumerate_instance_extension_properties(None)
             .map_err(|err| InstanceError::Other(err.into()))?;
@@ -110,9 +292,9 @@ impl Instance {
                 .iter()
                 .map(|extension| utils::char_array_to_string(&extension.extension_name))
                 .collect::<Vec<String>>(),
-            &Instance::required_extension_names()
+            &Instance::required_extension_names(headless)
                 .iter()
-                .map(|&extension| utils::char_ptr_to_string(extension))
+                .map(|&extension| unsafe { utils::char_ptr_to_string(extension) })
                 .collect::<Vec<String>>(),
         );
 
@@ -127,24 +309,29 @@ impl Instance {
         }
     }
 
-    /// Gets the names of all the required extensions on Windows
-    #[cfg(all(windows))]
-    fn required_extension_names() -> Vec<*const i8> {
-        vec![
-            Surface::name().as_ptr(),
-            Win32Surface::name().as_ptr(),
-            DebugUtils::name().as_ptr(),
-        ]
+    /// Gets the names of all the required extensions, which only include the surface
+    /// extensions if the instance isn't `headless`
+    fn required_extension_names(headless: bool) -> Vec<*const i8> {
+        let mut extension_names = if headless {
+            Vec::new()
+        } else {
+            Instance::surface_extension_names()
+        };
+        extension_names.push(DebugUtils::name().as_ptr());
+
+        extension_names
+    }
+
+    /// Gets the names of all the surface extensions on Windows
+    #[cfg(windows)]
+    fn surface_extension_names() -> Vec<*const i8> {
+        vec![Surface::name().as_ptr(), Win32Surface::name().as_ptr()]
     }
 
-    /// Gets the names of al the required exetensions on Linux
+    /// Gets the names of al the surface exetensions on Linux
     #[cfg(all(unix, not(target_os = "android"), not(target_os = "macos")))]
-    fn required_extension_names() -> Vec<*const i8> {
-        vec![
-            Surface::name().as_ptr(),
-            XlibSurface::name().as_ptr(),
-            DebugUtils::name().as_ptr(),
-        ]
+    fn surface_extension_names() -> Vec<*const i8> {
+        vec![Surface::name().as_ptr(), XlibSurface::name().as_ptr()]
     }
 }
 
@@ -163,6 +350,54 @@ impl Instance {
     pub fn debug_layers(&self) -> &[DebugLayer] {
         &self.debug_layers
     }
+
+    /// Returns whether the instance was created without the surface extensions
+    pub fn is_headless(&self) -> bool {
+        self.headless
+    }
+
+    /// Returns the validation errors reported since the last check
+    ///
+    /// Errors are only recorded if the instance was built with
+    /// [InstanceBuilder::record_validation_errors] and has debug layers enabled.
+    ///
+    /// # Errors
+    /// - [InstanceError::ValidationErrors] - Vulkan reported validation errors since the last check
+    pub fn check_validation_errors(&self) -> Result<(), InstanceError> {
+        let validation_errors = self
+            .debugger
+            .as_ref()
+            .map(Debugger::take_validation_errors)
+            .unwrap_or_default();
+
+        if validation_errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InstanceError::ValidationErrors(validation_errors))
+        }
+    }
+
+    /// Lists every physical device available to the instance, in the order the driver enumerates
+    /// them
+    pub fn enumerate_physical_devices(&self) -> Result<Vec<PhysicalDeviceInfo>, VulkanError> {
+        let physical_devices = unsafe {
+            self.handle
+                .enumerate_physical_devices()
+                .map_err(VulkanError::from)?
+        };
+
+        Ok(physical_devices
+            .iter()
+            .enumerate()
+            .map(|(index, &physical_device)| {
+                let properties = unsafe {
+                    self.handle
+                        .get_physical_device_properties(physical_device)
+                };
+                PhysicalDeviceInfo::new(index, &properties)
+            })
+            .collect())
+    }
 }
 
 impl Drop for Instance {
@@ -173,3 +408,35 @@ impl Drop for Instance {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn application_name_and_version_reach_the_application_info() {
+        let builder = Instance::builder()
+            .application_name("Sandbox")
+            .application_version(1, 2, 3);
+        let (app_name, engine_name) = builder.names().unwrap();
+        let app_info = builder.application_info(&app_name, &engine_name);
+
+        assert_eq!(
+            unsafe { CStr::from_ptr(app_info.p_application_name) },
+            c"Sandbox"
+        );
+        assert_eq!(unsafe { CStr::from_ptr(app_info.p_engine_name) }, c"Magma");
+        assert_eq!(
+            app_info.application_version,
+            vk::make_api_version(0, 1, 2, 3)
+        );
+    }
+
+    #[test]
+    fn application_name_with_nul_byte_errors() {
+        assert!(matches!(
+            Instance::builder().application_name("Sand\0box").names(),
+            Err(InstanceError::InvalidApplicationName)
+        ));
+    }
+}
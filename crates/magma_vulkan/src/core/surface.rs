@@ -11,6 +11,8 @@ use crate::{
 pub enum SurfaceError {
     #[error("Can't create a surface on the device provided as it doesn't have the DeviceExtension::Swapchain")]
     DeviceNotCapable,
+    #[error("Can't create a surface with a headless instance")]
+    HeadlessInstance,
     #[error("Can't create a surface on a device that wasn't created with a '{0}' queue family")]
     MissingQueueFamily(QueueFlags),
     #[error("Failed to create a surface for Windows")]
@@ -52,6 +54,10 @@ impl Surface {
         physical_device: &PhysicalDevice,
         window: &winit::window::Window,
     ) -> Result<Surface, SurfaceError> {
+        if instance.is_headless() {
+            return Err(SurfaceError::HeadlessInstance);
+        }
+
         if !physical_device
             .enabled_extensions()
             .contains(&DeviceExtension::Swapchain)
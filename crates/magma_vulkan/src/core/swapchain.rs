@@ -1,13 +1,20 @@
 use ash::vk;
-use std::rc::Rc;
+use rayon::prelude::*;
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use crate::{
     core::{
-        commands::buffer::CommandBuffer,
+        commands::{buffer::CommandBuffer, pool::CommandPoolError},
         device::{DeviceExtension, LogicalDevice, LogicalDeviceError, QueueFlags},
-        surface::Surface,
+        surface::{Surface, SurfaceError},
     },
     buffer::MemoryPropertyFlags,
+    image::ImageUsageFlags,
+    pipeline::PipelineError,
+    render_pass::{RenderPass, RenderPassDescription, RenderPassError, Subpass},
     sync::{Fence, Semaphore},
     VulkanError,
 };
@@ -15,6 +22,12 @@ use crate::{
 /// Maximum number of frames to keep in flight
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
+/// How much of each new frame time sample is blended into the smoothed frame time
+const FRAME_TIME_SMOOTHING: f64 = 0.1;
+
+/// Images, device memory, and image views created for each color or depth stencil attachment
+type AttachmentResources = (Vec<vk::Image>, Vec<vk::DeviceMemory>, Vec<vk::ImageView>);
+
 /// Errors that could be returned by the [Swapchain]
 #[derive(thiserror::Error, Debug)]
 pub enum SwapchainError {
@@ -28,20 +41,51 @@ pub enum SwapchainError {
         "Can't create a swapchain because the device wasn't created with a '{0}' queue family"
     )]
     MissingQueueFamily(QueueFlags),
-    #[error("Failed to create a Vulkan render pass: {0}")]
-    CantCreateRenderPass(VulkanError),
+    #[error("Failed to create the swapchain's render pass: {0}")]
+    CantCreateRenderPass(#[from] RenderPassError),
+    #[error("Expected {expected} framebuffer attachments to match the render pass, but got {count}")]
+    AttachmentMismatch { expected: usize, count: usize },
     #[error("Failed to create a Vulkan framebuffer: {0}")]
     CantCreateFramebuffer(VulkanError),
     #[error("Failed to create a Vulkan image view: {0}")]
     CantCreateImageView(VulkanError),
-    #[error("The swapchain is suboptimal for the surface, can still draw but should be recreated")]
-    Suboptimal,
+    #[error("Expected to create {expected} handles, one for every swapchain image, but created {count}")]
+    CreatedCountMismatch { expected: usize, count: usize },
+    #[error("The surface doesn't support using swapchain images with the usage: {0}")]
+    UnsupportedImageUsage(ImageUsageFlags),
+    #[error("The surface doesn't report any supported formats, try again once it does")]
+    NoSurfaceFormats,
+    #[error("The surface doesn't report any supported present modes, try again once it does")]
+    NoPresentModes,
+    #[error("The swapchain is out of date with the surface and must be recreated before drawing")]
+    OutOfDate,
+    #[error("Waiting for presents requires the '{0}' device extension to be enabled")]
+    MissingExtension(DeviceExtension),
+    #[error("Waiting for presents requires the '{0}' device feature, which the device doesn't support")]
+    MissingFeature(&'static str),
     #[error("Can't perform an operation because the graphics queue is required but the device doesn't have one")]
     DeviceMissingGraphicsQueue,
+    #[error("{requested} isn't a supported sample count, it must be a power of two up to {max}")]
+    UnsupportedSampleCount { requested: u32, max: u32 },
+    #[error("Failed to reallocate command buffers for the recreated swapchain: {0}")]
+    CommandPoolError(#[from] CommandPoolError),
+    #[error("Failed to rebuild a pipeline for the recreated swapchain: {0}")]
+    PipelineError(#[from] PipelineError),
+    #[error("Failed to update the surface the swapchain presents to: {0}")]
+    SurfaceError(#[from] SurfaceError),
     #[error(transparent)]
     DeviceError(#[from] LogicalDeviceError),
 }
 
+/// An object created for a [Swapchain] that has to be updated when the swapchain is recreated,
+/// such as the command buffers recorded for each framebuffer or the pipelines drawing into its
+/// render pass, see [Swapchain::recreate_with]
+pub trait SwapchainDependent {
+    /// Updates the object for `swapchain` after it was recreated, the device is idle when this
+    /// is called
+    fn swapchain_recreated(&mut self, swapchain: &Swapchain) -> Result<(), SwapchainError>;
+}
+
 /// Possible color formats
 #[derive(Clone, Copy)]
 pub enum ColorFormat {
@@ -86,6 +130,15 @@ pub struct SwapchainBuilder {
     preferred_present_mode: PresentMode,
     /// Old [Swapchain] to create the new [Swapchain] from
     old_swapchain: vk::SwapchainKHR,
+    /// Description of the render pass of the swapchain being replaced, if any
+    old_render_pass: Option<RenderPassDescription>,
+    /// Size of the window being presented to, used when the surface leaves the extent up to the
+    /// swapchain
+    window_size: Option<(u32, u32)>,
+    /// How the swapchain images will be used
+    image_usage: ImageUsageFlags,
+    /// Number of samples each pixel of the color and depth attachments has
+    samples: u32,
 }
 
 impl SwapchainBuilder {
@@ -95,6 +148,10 @@ impl SwapchainBuilder {
             preferred_color_format: ColorFormat::Unorm,
             preferred_present_mode: PresentMode::Fifo,
             old_swapchain: vk::SwapchainKHR::null(),
+            old_render_pass: None,
+            window_size: None,
+            image_usage: ImageUsageFlags::COLOR_ATTACHMENT,
+            samples: 1,
         }
     }
 
@@ -113,6 +170,44 @@ impl SwapchainBuilder {
     /// Sets the old [Swapchain] to base the new [Swapchain] from
     pub fn old_swapchain(mut self, swapchain: &Swapchain) -> SwapchainBuilder {
         self.old_swapchain = swapchain.vk_handle();
+        self.old_render_pass = Some(swapchain.render_pass().description().clone());
+        self
+    }
+
+    /// Sets the inner size (width, height) of the window the [Swapchain] presents to
+    ///
+    /// Some platforms let the swapchain decide its own extent, in which case the window size is
+    /// used, clamped to the extents the [Surface] supports.
+    pub fn window_size(mut self, size: (u32, u32)) -> SwapchainBuilder {
+        self.window_size = Some(size);
+        self
+    }
+
+    /// Sets how the swapchain images will be used, defaults to
+    /// [ImageUsageFlags::COLOR_ATTACHMENT]
+    ///
+    /// e.g. [ImageUsageFlags::TRANSFER_SRC] is needed to copy the images for screenshots. The
+    /// usage must be supported by the [Surface].
+    pub fn image_usage(mut self, usage: ImageUsageFlags) -> SwapchainBuilder {
+        self.image_usage = usage;
+        self
+    }
+
+    /// Sets the number of samples per pixel to render with for multisample anti-aliasing,
+    /// defaults to 1 which disables it.
+    ///
+    /// With more than 1 sample, each framebuffer gets a multisampled color attachment that is
+    /// resolved into the swapchain image at the end of the render pass, and the depth attachment
+    /// is multisampled as well. The resolve attachment comes last and isn't cleared, so
+    /// [CommandBuffer::begin_render_pass] only needs clear values for the color and depth
+    /// attachments, and pipelines drawing to the render pass must use the same
+    /// [sample count][crate::pipeline::PipelineBuilder::sample_count]. The highest supported
+    /// count is given by [PhysicalDevice::max_usable_sample_count].
+    ///
+    /// [PhysicalDevice::max_usable_sample_count]:
+    /// crate::core::device::PhysicalDevice::max_usable_sample_count
+    pub fn sample_count(mut self, samples: u32) -> SwapchainBuilder {
+        self.samples = samples;
         self
     }
 
@@ -147,9 +242,25 @@ impl SwapchainBuilder {
             return Err(SwapchainError::MissingQueueFamily(QueueFlags::GRAPHICS));
         }
 
-        let surface_format = self.choose_format(&surface.formats());
-        let present_mode = self.choose_present_mode(&surface.present_modes());
-        let extent = self.choose_extent(surface.capabilities());
+        let max_samples = device.physical_device().max_usable_sample_count();
+        if !self.samples.is_power_of_two() || self.samples > max_samples {
+            return Err(SwapchainError::UnsupportedSampleCount {
+                requested: self.samples,
+                max: max_samples,
+            });
+        }
+        let samples = vk::SampleCountFlags::from_raw(self.samples);
+
+        check_image_usage(
+            ImageUsageFlags::from_bits_truncate(
+                surface.capabilities().supported_usage_flags.as_raw(),
+            ),
+            self.image_usage,
+        )?;
+
+        let surface_format = self.choose_format(surface.formats())?;
+        let present_mode = self.choose_present_mode(surface.present_modes())?;
+        let extent = choose_extent(self.window_size, surface.capabilities());
 
         let image_count = surface.capabilities().min_image_count + 1;
         let image_count = if surface.capabilities().max_image_count > 0
@@ -173,7 +284,7 @@ impl SwapchainBuilder {
             .image_color_space(surface_format.color_space)
             .image_format(surface_format.format)
             .image_extent(extent)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(self.image_usage.into())
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&queue_family_indices)
             .pre_transform(surface.capabilities().current_transform)
@@ -185,6 +296,14 @@ impl SwapchainBuilder {
 
         let swapchain =
             ash::extensions::khr::Swapchain::new(device.instance().vk_handle(), device.vk_handle());
+        let present_wait = if Swapchain::present_wait_supported(&device).is_ok() {
+            Some(ash::extensions::khr::PresentWait::new(
+                device.instance().vk_handle(),
+                device.vk_handle(),
+            ))
+        } else {
+            None
+        };
         let handle = unsafe {
             swapchain
                 .create_swapchain(&create_info, None)
@@ -200,7 +319,7 @@ impl SwapchainBuilder {
             device.vk_handle(),
             surface_format.format,
             &images,
-        );
+        )?;
 
         let depth_format = device.find_supported_format(
             &[
@@ -212,23 +331,42 @@ impl SwapchainBuilder {
             vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
         )?;
         let render_pass = SwapchainBuilder::create_render_pass(
-            device.vk_handle(),
+            device.clone(),
             surface_format.format,
             depth_format,
+            samples,
         )?;
+        let render_pass_changed = !self
+            .old_render_pass
+            .is_some_and(|old| old.is_compatible_with(render_pass.description()));
 
         let (depth_images, depth_image_memories, depth_image_views) =
-            SwapchainBuilder::create_depth_resources(
+            SwapchainBuilder::create_attachment_resources(
                 device.as_ref(),
-                &depth_format,
+                depth_format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                samples,
                 image_views.len(),
                 &extent,
             )?;
+        let (color_images, color_image_memories, color_image_views) = if self.samples > 1 {
+            SwapchainBuilder::create_attachment_resources(
+                device.as_ref(),
+                surface_format.format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                samples,
+                image_views.len(),
+                &extent,
+            )?
+        } else {
+            (Vec::new(), Vec::new(), Vec::new())
+        };
         let framebuffers = SwapchainBuilder::create_framebuffers(
             device.vk_handle(),
-            render_pass,
+            &render_pass,
             &image_views,
             &depth_image_views,
+            &color_image_views,
             &extent,
         )?;
 
@@ -247,18 +385,30 @@ impl SwapchainBuilder {
             images_in_flight.push(vk::Fence::null());
         }
 
+        let image_layouts = vec![vk::ImageLayout::UNDEFINED; images.len()];
+
         Ok(Swapchain {
-            _images: images,
+            preferred_color_format: self.preferred_color_format,
+            preferred_present_mode: self.preferred_present_mode,
+            image_usage: self.image_usage,
+            samples: self.samples,
+
+            images,
+            image_layouts,
             image_views,
             depth_images,
             depth_image_views,
             depth_image_memories,
+            color_images,
+            color_image_views,
+            color_image_memories,
 
             _format: surface_format.format,
             _depth_format: depth_format,
             extent,
 
             render_pass,
+            render_pass_changed,
             framebuffers,
 
             image_available_semaphores,
@@ -266,6 +416,14 @@ impl SwapchainBuilder {
             in_flight_fences,
             images_in_flight,
             current_frame: 0,
+            needs_recreation: false,
+
+            submitted_at: vec![None; MAX_FRAMES_IN_FLIGHT],
+            last_frame_gpu_time: None,
+            smoothed_frame_gpu_time: None,
+
+            present_wait,
+            last_present_id: 0,
 
             swapchain,
             handle,
@@ -278,171 +436,141 @@ impl SwapchainBuilder {
     /// Tries to find `preferred_color_format` in the [Surface] supported color formats, will
     /// return the first supported format on the surface if the preferred color format is not
     /// found.
-    fn choose_format(&self, available_formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+    ///
+    /// Returns [SwapchainError::NoSurfaceFormats] if the surface doesn't support any formats,
+    /// which can happen transiently e.g. while a display is being connected.
+    fn choose_format(
+        &self,
+        available_formats: &[vk::SurfaceFormatKHR],
+    ) -> Result<vk::SurfaceFormatKHR, SwapchainError> {
         for available_format in available_formats {
             if available_format.format == self.preferred_color_format.into()
                 && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
             {
-                return available_format.clone();
+                return Ok(*available_format);
             }
         }
 
+        let fallback_format = *available_formats
+            .first()
+            .ok_or(SwapchainError::NoSurfaceFormats)?;
         log::info!(
             "Preferred color format not supported by device, resorting to {:?}",
-            available_formats.first().unwrap().format
+            fallback_format.format
         );
-        available_formats.first().unwrap().clone()
+        Ok(fallback_format)
     }
 
     /// Returns the `preferred_present_mode` if supported by the [Surface], otherwise returns
     /// [PresentMode::Fifo].
+    ///
+    /// Returns [SwapchainError::NoPresentModes] if the surface doesn't support any present
+    /// modes.
     fn choose_present_mode(
         &self,
         available_present_modes: &[vk::PresentModeKHR],
-    ) -> vk::PresentModeKHR {
-        if available_present_modes.contains(&self.preferred_present_mode.into()) {
-            self.preferred_present_mode.into()
-        } else {
-            log::info!("Preferred present mode not supported by device, resorting to FIFO");
-            vk::PresentModeKHR::FIFO
+    ) -> Result<vk::PresentModeKHR, SwapchainError> {
+        if available_present_modes.is_empty() {
+            return Err(SwapchainError::NoPresentModes);
         }
-    }
 
-    /// Chooses the extent of the swapchain by clapming it to the [Surface] min and max image
-    /// extent.
-    fn choose_extent(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
-        if capabilities.current_extent.width != u32::MAX {
-            capabilities.current_extent
+        if available_present_modes.contains(&self.preferred_present_mode.into()) {
+            Ok(self.preferred_present_mode.into())
         } else {
-            vk::Extent2D {
-                width: 800.clamp(
-                    capabilities.min_image_extent.width,
-                    capabilities.max_image_extent.width,
-                ),
-                height: 600.clamp(
-                    capabilities.min_image_extent.height,
-                    capabilities.max_image_extent.height,
-                ),
-            }
+            log::info!("Preferred present mode not supported by device, resorting to FIFO");
+            Ok(vk::PresentModeKHR::FIFO)
         }
     }
 
     /// Creates a Vulkan image view for every image in the [Swapchain]
+    ///
+    /// The image views are created in parallel, since each one only depends on its own image.
     fn create_image_views(
         device: &ash::Device,
         surface_format: vk::Format,
         images: &[vk::Image],
-    ) -> Vec<vk::ImageView> {
-        let mut image_views: Vec<vk::ImageView> = Vec::new();
-        for &image in images.iter() {
-            let create_info = vk::ImageViewCreateInfo::builder()
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(surface_format)
-                .components(vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::IDENTITY,
-                    g: vk::ComponentSwizzle::IDENTITY,
-                    b: vk::ComponentSwizzle::IDENTITY,
-                    a: vk::ComponentSwizzle::IDENTITY,
-                })
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                })
-                .image(image);
-
-            image_views.push(unsafe {
-                device
-                    .create_image_view(&create_info, None)
-                    .expect("Failed to create image view")
-            });
-        }
+    ) -> Result<Vec<vk::ImageView>, SwapchainError> {
+        let image_views: Vec<Result<vk::ImageView, SwapchainError>> = images
+            .par_iter()
+            .map(|&image| {
+                let create_info = vk::ImageViewCreateInfo::builder()
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(surface_format)
+                    .components(vk::ComponentMapping {
+                        r: vk::ComponentSwizzle::IDENTITY,
+                        g: vk::ComponentSwizzle::IDENTITY,
+                        b: vk::ComponentSwizzle::IDENTITY,
+                        a: vk::ComponentSwizzle::IDENTITY,
+                    })
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image(image);
+
+                unsafe {
+                    device
+                        .create_image_view(&create_info, None)
+                        .map_err(|err| SwapchainError::CantCreateImageView(err.into()))
+                }
+            })
+            .collect();
 
-        image_views
+        all_created(image_views, images.len(), |image_view| unsafe {
+            device.destroy_image_view(image_view, None);
+        })
     }
 
-    /// Creates a render pass with a color and depth stencil attachment
+    /// Creates a render pass with a color and depth stencil attachment, and an attachment the
+    /// color attachment is resolved into if it is multisampled
     fn create_render_pass(
-        device: &ash::Device,
+        device: Rc<LogicalDevice>,
         surface_format: vk::Format,
         depth_format: vk::Format,
-    ) -> Result<vk::RenderPass, SwapchainError> {
-        let color_attachment = vk::AttachmentDescription::builder()
-            .format(surface_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .build();
+        samples: vk::SampleCountFlags,
+    ) -> Result<RenderPass, SwapchainError> {
+        let description = render_pass_description(surface_format, depth_format, samples);
 
-        let color_attachment_ref = [vk::AttachmentReference {
-            attachment: 0,
-            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        }];
-
-        let depth_attachment = vk::AttachmentDescription::builder()
-            .format(depth_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-            .build();
-
-        let depth_attachment_ref = vk::AttachmentReference {
-            attachment: 1,
-            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-        };
-
-        let subpasses = [vk::SubpassDescription::builder()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&color_attachment_ref)
-            .depth_stencil_attachment(&depth_attachment_ref)
-            .build()];
-
-        let subpass_dependencies = [vk::SubpassDependency {
-            src_subpass: vk::SUBPASS_EXTERNAL,
-            dst_subpass: 0,
-            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            src_access_mask: vk::AccessFlags::empty(),
-            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-            dependency_flags: vk::DependencyFlags::empty(),
-        }];
-
-        let render_pass_attachments = [color_attachment, depth_attachment];
-        let create_info = vk::RenderPassCreateInfo::builder()
-            .attachments(&render_pass_attachments)
-            .subpasses(&subpasses)
-            .dependencies(&subpass_dependencies);
-
-        unsafe {
-            device
-                .create_render_pass(&create_info, None)
-                .map_err(|err| SwapchainError::CantCreateRenderPass(err.into()))
+        let mut builder = RenderPass::builder();
+        for attachment in description.attachments {
+            builder = builder.add_attachment(attachment);
+        }
+        for subpass in description.subpasses {
+            builder = builder.add_subpass(subpass);
+        }
+        for dependency in description.dependencies {
+            builder = builder.add_dependency(dependency);
         }
+
+        Ok(builder.build(device)?)
     }
 
-    /// Creates images, image views, and device memory for the depth stencil attachment for evey color
-    /// image in the [Swapchain]
-    fn create_depth_resources(
+    /// Creates images, image views, and device memory for a color or depth stencil attachment
+    /// with `samples` samples per pixel for every color image in the [Swapchain]
+    ///
+    /// Unlike the image views and framebuffers these are created one after another, since the
+    /// memory is allocated through the [LogicalDevice], which keeps track of its allocations
+    /// without synchronization and so can't be shared between threads.
+    fn create_attachment_resources(
         device: &LogicalDevice,
-        depth_format: &vk::Format,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
         count: usize,
         extent: &vk::Extent2D,
-    ) -> Result<(Vec<vk::Image>, Vec<vk::DeviceMemory>, Vec<vk::ImageView>), SwapchainError> {
-        let mut depth_images: Vec<vk::Image> = Vec::new();
-        let mut depth_image_memories: Vec<vk::DeviceMemory> = Vec::new();
-        let mut depth_image_views: Vec<vk::ImageView> = Vec::new();
+    ) -> Result<AttachmentResources, SwapchainError> {
+        let aspect_mask = if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
+
+        let mut images: Vec<vk::Image> = Vec::new();
+        let mut image_memories: Vec<vk::DeviceMemory> = Vec::new();
+        let mut image_views: Vec<vk::ImageView> = Vec::new();
 
         for _ in 0..count {
             let image_info = vk::ImageCreateInfo::builder()
@@ -454,75 +582,106 @@ impl SwapchainBuilder {
                 })
                 .mip_levels(1)
                 .array_layers(1)
-                .format(*depth_format)
+                .format(format)
                 .tiling(vk::ImageTiling::OPTIMAL)
-                .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
-                .samples(vk::SampleCountFlags::TYPE_1)
+                .usage(usage)
+                .samples(samples)
                 .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
-            let (depth_image, depth_image_memory) =
+            let (image, image_memory) =
                 device.create_image(&image_info, MemoryPropertyFlags::DEVICE_LOCAL)?;
 
             let image_view_info = vk::ImageViewCreateInfo::builder()
-                .image(depth_image)
+                .image(image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(*depth_format)
+                .format(format)
                 .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::DEPTH,
+                    aspect_mask,
                     base_mip_level: 0,
                     level_count: 1,
                     base_array_layer: 0,
                     layer_count: 1,
                 });
 
-            depth_image_views.push(unsafe {
+            image_views.push(unsafe {
                 device
                     .vk_handle()
                     .create_image_view(&image_view_info, None)
                     .map_err(|err| SwapchainError::CantCreateImageView(err.into()))?
             });
-            depth_images.push(depth_image);
-            depth_image_memories.push(depth_image_memory);
+            images.push(image);
+            image_memories.push(image_memory);
         }
 
-        Ok((depth_images, depth_image_memories, depth_image_views))
+        Ok((images, image_memories, image_views))
     }
 
     /// Creates a Vulkan framebuffer with a color and depth stencil attachment for every image in
-    /// the [Swapchain]
+    /// the [Swapchain], rendering to a multisampled color attachment that is resolved into the
+    /// image if `color_image_views` isn't empty
+    ///
+    /// The framebuffers are created in parallel, since each one only depends on its own attachments.
+    ///
+    /// Returns [SwapchainError::AttachmentMismatch] if the render pass doesn't have exactly a
+    /// color and a depth attachment, and a resolve attachment if multisampled.
     fn create_framebuffers(
         device: &ash::Device,
-        render_pass: vk::RenderPass,
+        render_pass: &RenderPass,
         image_views: &[vk::ImageView],
         depth_image_views: &[vk::ImageView],
+        color_image_views: &[vk::ImageView],
         swapchain_extent: &vk::Extent2D,
     ) -> Result<Vec<vk::Framebuffer>, SwapchainError> {
-        let mut framebuffers: Vec<vk::Framebuffer> = Vec::new();
-        for (&image_view, &depth_image_view) in image_views.iter().zip(depth_image_views) {
-            let attachments = [image_view, depth_image_view];
-
-            let framebuffer_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(render_pass)
-                .attachments(&attachments)
-                .width(swapchain_extent.width)
-                .height(swapchain_extent.height)
-                .layers(1);
-
-            framebuffers.push(unsafe {
-                device
-                    .create_framebuffer(&framebuffer_info, None)
-                    .map_err(|err| SwapchainError::CantCreateFramebuffer(err.into()))?
-            });
-        }
+        let attachment_count = if color_image_views.is_empty() { 2 } else { 3 };
+        check_attachment_count(render_pass.description(), attachment_count)?;
+
+        let render_pass = render_pass.vk_handle();
+        let framebuffers: Vec<Result<vk::Framebuffer, SwapchainError>> = image_views
+            .par_iter()
+            .zip(depth_image_views)
+            .enumerate()
+            .map(|(index, (&image_view, &depth_image_view))| {
+                let attachments: Vec<vk::ImageView> = match color_image_views.get(index) {
+                    Some(&color_image_view) => vec![color_image_view, depth_image_view, image_view],
+                    None => vec![image_view, depth_image_view],
+                };
+
+                let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&attachments)
+                    .width(swapchain_extent.width)
+                    .height(swapchain_extent.height)
+                    .layers(1);
+
+                unsafe {
+                    device
+                        .create_framebuffer(&framebuffer_info, None)
+                        .map_err(|err| SwapchainError::CantCreateFramebuffer(err.into()))
+                }
+            })
+            .collect();
 
-        Ok(framebuffers)
+        all_created(framebuffers, image_views.len(), |framebuffer| unsafe {
+            device.destroy_framebuffer(framebuffer, None);
+        })
     }
 }
 
 /// Wraps a Vulkan swapchain and holds the images and framebuffers for drawing to
 pub struct Swapchain {
+    /// Preferred color format the [Swapchain] was created with
+    preferred_color_format: ColorFormat,
+    /// Preferred present mode the [Swapchain] was created with
+    preferred_present_mode: PresentMode,
+    /// How the swapchain images are used
+    image_usage: ImageUsageFlags,
+    /// Number of samples each pixel of the color and depth attachments has
+    samples: u32,
+
     /// List of all Vulkan images for the [Swapchain]
-    _images: Vec<vk::Image>,
+    images: Vec<vk::Image>,
+    /// Layout each image was left in by the last frame presented from it
+    image_layouts: Vec<vk::ImageLayout>,
     /// List of all Vulkan image views for every image
     image_views: Vec<vk::ImageView>,
     /// List of Vulkan images for use in the depth stencil attachment
@@ -531,6 +690,13 @@ pub struct Swapchain {
     depth_image_views: Vec<vk::ImageView>,
     /// List of Vulkan device memory for every depth image
     depth_image_memories: Vec<vk::DeviceMemory>,
+    /// List of multisampled Vulkan images that are resolved into every image, empty if the
+    /// [Swapchain] isn't multisampled
+    color_images: Vec<vk::Image>,
+    /// List of Vulkan image views for every multisampled color image
+    color_image_views: Vec<vk::ImageView>,
+    /// List of Vulkan device memory for every multisampled color image
+    color_image_memories: Vec<vk::DeviceMemory>,
 
     /// Color format of the [Swapchain]
     _format: vk::Format,
@@ -540,7 +706,9 @@ pub struct Swapchain {
     extent: vk::Extent2D,
 
     /// The main render pass of the [Swapchain]
-    render_pass: vk::RenderPass,
+    render_pass: RenderPass,
+    /// Whether the render pass isn't compatible with the one of the swapchain this replaced
+    render_pass_changed: bool,
     /// List of all the framebuffers that can be drawn to and presented
     framebuffers: Vec<vk::Framebuffer>,
 
@@ -554,6 +722,21 @@ pub struct Swapchain {
     images_in_flight: Vec<vk::Fence>,
     /// Index of framebuffer being presented
     current_frame: usize,
+    /// Whether acquiring or presenting reported that the swapchain no longer matches the surface
+    needs_recreation: bool,
+
+    /// When each frame in flight was last submitted, cleared once its fence is waited on
+    submitted_at: Vec<Option<Instant>>,
+    /// Time between the most recently finished frame being submitted and its fence signaling
+    last_frame_gpu_time: Option<Duration>,
+    /// Exponential moving average of the frame GPU times
+    smoothed_frame_gpu_time: Option<Duration>,
+
+    /// Vulkan present wait extension, if [DeviceExtension::PresentId] and
+    /// [DeviceExtension::PresentWait] are enabled and supported
+    present_wait: Option<ash::extensions::khr::PresentWait>,
+    /// Id given to the most recent present, 0 if no present was given an id yet
+    last_present_id: u64,
 
     /// Vulkan swapchain extension used to created the [Swapchain]
     swapchain: ash::extensions::khr::Swapchain,
@@ -568,6 +751,56 @@ impl Swapchain {
     pub fn builder() -> SwapchainBuilder {
         SwapchainBuilder::new()
     }
+
+    /// Recreates the swapchain in place for a window of `window_size`, with the same preferred
+    /// color format, present mode and image usage it was created with.
+    ///
+    /// Should be called when [Swapchain::acquire_next_image] returns [SwapchainError::OutOfDate],
+    /// when [Swapchain::needs_recreation] is set, or when the window is resized. Waits for the
+    /// device to be idle and updates the `surface` capabilities first. The number of
+    /// framebuffers may change, and pipelines only need to be rebuilt if
+    /// [Swapchain::render_pass_changed] is set, see
+    /// [Pipeline::rebuild][crate::pipeline::Pipeline::rebuild].
+    pub fn recreate(
+        &mut self,
+        surface: &mut Surface,
+        window_size: (u32, u32),
+    ) -> Result<(), SwapchainError> {
+        self.device.wait_for_idle()?;
+        surface.update(self.device.physical_device())?;
+
+        *self = Swapchain::builder()
+            .old_swapchain(self)
+            .preferred_color_format(self.preferred_color_format)
+            .preferred_present_mode(self.preferred_present_mode)
+            .image_usage(self.image_usage)
+            .sample_count(self.samples)
+            .window_size(window_size)
+            .build(self.device.clone(), surface)?;
+        Ok(())
+    }
+
+    /// Recreates the swapchain like [Swapchain::recreate], then updates every one of the
+    /// `dependents` for the recreated swapchain, e.g. reallocating a
+    /// [CommandPool][crate::core::commands::pool::CommandPool]'s command buffers when the number
+    /// of framebuffers changed and rebuilding [Pipelines][crate::pipeline::Pipeline] if the
+    /// render pass changed.
+    ///
+    /// # Errors
+    /// Any error returned by [Swapchain::recreate], or by one of the `dependents`
+    pub fn recreate_with(
+        &mut self,
+        surface: &mut Surface,
+        window_size: (u32, u32),
+        dependents: &mut [&mut dyn SwapchainDependent],
+    ) -> Result<(), SwapchainError> {
+        self.recreate(surface, window_size)?;
+        for dependent in dependents.iter_mut() {
+            dependent.swapchain_recreated(self)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Swapchain {
@@ -584,11 +817,47 @@ impl Swapchain {
         self.extent.width as f32 / self.extent.height as f32
     }
 
+    /// Returns the number of samples per pixel the swapchain renders with, see
+    /// [SwapchainBuilder::sample_count]
+    pub fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
     /// Returns the main render pass of the swapchain.
     ///
-    /// The render pass consists of a color and depth stencil attachment
-    pub fn render_pass(&self) -> vk::RenderPass {
-        self.render_pass
+    /// The render pass consists of a color and depth stencil attachment, followed by the
+    /// attachment the color is resolved into if the swapchain is multisampled
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    /// Returns whether the render pass isn't compatible with the render pass of the swapchain
+    /// passed to [SwapchainBuilder::old_swapchain], always `true` if there was none
+    ///
+    /// When the render pass is still compatible, e.g. after a resize that kept the surface
+    /// format, pipelines created against the old render pass can keep being used and only
+    /// need to be recreated when this returns `true`, see [Pipeline::is_compatible_with].
+    ///
+    /// [Pipeline::is_compatible_with]: crate::pipeline::Pipeline::is_compatible_with
+    pub fn render_pass_changed(&self) -> bool {
+        self.render_pass_changed
+    }
+
+    /// Returns all the color images in the [Swapchain], indexed the same as the framebuffers
+    ///
+    /// An image can be copied from, e.g. to record a video, once the frame drawn to it has
+    /// finished executing, which is the case once the fence of its frame in flight is waited on.
+    /// The copy must happen before the image is acquired again and has to transition the image
+    /// from, and back to, [Swapchain::image_layout]. The swapchain must have been created with
+    /// [ImageUsageFlags::TRANSFER_SRC] for the images to be copied.
+    pub fn images(&self) -> &[vk::Image] {
+        &self.images
+    }
+
+    /// Returns the layout the image at `index` is in, [vk::ImageLayout::UNDEFINED] if it was
+    /// never presented and [vk::ImageLayout::PRESENT_SRC_KHR] otherwise
+    pub fn image_layout(&self, index: usize) -> Option<vk::ImageLayout> {
+        self.image_layouts.get(index).copied()
     }
 
     /// Returns all the framebuffers in the [Swapchain]
@@ -600,40 +869,86 @@ impl Swapchain {
     pub fn current_frame(&self) -> usize {
         self.current_frame
     }
+
+    /// Returns the approximate time the GPU took to render the most recently finished frame
+    ///
+    /// Measured from when the frame was submitted until its fence was waited on in
+    /// [Swapchain::acquire_next_image], so will include any time the frame spent queued and will
+    /// be an overestimate when the CPU is the bottleneck.
+    pub fn last_frame_gpu_time(&self) -> Option<Duration> {
+        self.last_frame_gpu_time
+    }
+
+    /// Returns a smoothed version of [Swapchain::last_frame_gpu_time], suitable to drive
+    /// adaptive quality or dynamic resolution without reacting to single frame spikes
+    pub fn smoothed_frame_gpu_time(&self) -> Option<Duration> {
+        self.smoothed_frame_gpu_time
+    }
+
+    /// Returns whether the [Swapchain] no longer matches the [Surface] and should be recreated
+    /// once the current frame has been submitted.
+    ///
+    /// Set when acquiring returned a suboptimal image or presenting reported the swapchain as
+    /// suboptimal or out of date, in both cases the frame could still be drawn and presented.
+    pub fn needs_recreation(&self) -> bool {
+        self.needs_recreation
+    }
 }
 
 impl Swapchain {
     /// Returns the index of the next image that is ready to be drawn to.
     ///
-    /// May return [SwapchainError::Suboptimal] in which the [Swapchain] no longer perfectly
-    /// matches the [Surface] and should be recreated.
-    pub fn acquire_next_image(&self) -> Result<usize, SwapchainError> {
+    /// If the image is suboptimal for the [Surface] it is still returned so the frame can be
+    /// drawn, and [Swapchain::needs_recreation] is set.
+    ///
+    /// # Errors
+    /// - [SwapchainError::OutOfDate] - The [Swapchain] can't be drawn to anymore and must be
+    ///   recreated before drawing this frame
+    pub fn acquire_next_image(&mut self) -> Result<usize, SwapchainError> {
         self.device.wait_for_fences(
             &[&self.in_flight_fences[self.current_frame]],
             true,
             u64::MAX,
         )?;
+        if let Some(submitted_at) = self.submitted_at[self.current_frame].take() {
+            let frame_time = submitted_at.elapsed();
+            self.last_frame_gpu_time = Some(frame_time);
+            self.smoothed_frame_gpu_time = Some(smooth_frame_time(
+                self.smoothed_frame_gpu_time,
+                frame_time,
+            ));
+        }
 
         let result = unsafe {
-            self.swapchain
-                .acquire_next_image(
-                    self.handle,
-                    u64::MAX,
-                    self.image_available_semaphores[self.current_frame].vk_handle(),
-                    vk::Fence::null(),
-                )
-                .map_err(|err| SwapchainError::DeviceError(LogicalDeviceError::Other(err.into())))?
+            self.swapchain.acquire_next_image(
+                self.handle,
+                u64::MAX,
+                self.image_available_semaphores[self.current_frame].vk_handle(),
+                vk::Fence::null(),
+            )
         };
 
-        if result.1 {
-            Err(SwapchainError::Suboptimal)
-        } else {
-            Ok(result.0 as usize)
+        match result {
+            Ok((index, suboptimal)) => {
+                self.needs_recreation |= suboptimal;
+                Ok(index as usize)
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.needs_recreation = true;
+                Err(SwapchainError::OutOfDate)
+            }
+            Err(err) => Err(SwapchainError::DeviceError(LogicalDeviceError::Other(
+                err.into(),
+            ))),
         }
     }
 
     /// Submits a command buffer to the present queue using the framebuffer and images at `index`.
     ///
+    /// A suboptimal or out of date swapchain when presenting doesn't return an error since the
+    /// frame was already drawn, instead [Swapchain::needs_recreation] is set so the swapchain can
+    /// be recreated before the next frame.
+    ///
     /// FIXME: Safety is not garuanteed, checkthe command buffer belongs to a graphics queue so
     /// that it can be submitted
     pub fn submit_command_buffer(
@@ -654,6 +969,7 @@ impl Swapchain {
             };
         }
         self.images_in_flight[index] = self.in_flight_fences[self.current_frame].vk_handle();
+        self.image_layouts[index] = vk::ImageLayout::PRESENT_SRC_KHR;
 
         // Get GPU to start working on the next frame buffer
         let wait_semaphores = [self.image_available_semaphores[self.current_frame].vk_handle()];
@@ -687,27 +1003,302 @@ impl Swapchain {
                 )
                 .map_err(|err| SwapchainError::DeviceError(LogicalDeviceError::Other(err.into())))?
         };
+        self.submitted_at[self.current_frame] = Some(Instant::now());
 
         // Present the frame that just finished drawing
         let swapchains = [self.handle];
         let image_indices = [index as u32];
-        let present_info = vk::PresentInfoKHR::builder()
+        let present_ids = [self.last_present_id + 1];
+        let mut present_id_info = vk::PresentIdKHR::builder().present_ids(&present_ids);
+        let mut present_info = vk::PresentInfoKHR::builder()
             .wait_semaphores(&signal_semaphores)
             .swapchains(&swapchains)
             .image_indices(&image_indices);
+        if self.present_wait.is_some() {
+            present_info = present_info.push_next(&mut present_id_info);
+            self.last_present_id += 1;
+        }
 
-        unsafe {
+        let result = unsafe {
             self.swapchain
                 .queue_present(graphics_queue.handle, &present_info)
-                .map_err(|err| SwapchainError::DeviceError(LogicalDeviceError::Other(err.into())))?
         };
 
+        // The frame has already been submitted, so it is finished even if presenting it reports
+        // that the swapchain should be recreated
         self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
 
+        self.needs_recreation |= present_needs_recreation(result)?;
         Ok(())
     }
 }
 
+impl Swapchain {
+    /// Checks whether presents can be given ids and waited on with `device`
+    fn present_wait_supported(device: &LogicalDevice) -> Result<(), SwapchainError> {
+        let physical_device = device.physical_device();
+        for extension in [DeviceExtension::PresentId, DeviceExtension::PresentWait] {
+            if !physical_device.enabled_extensions().contains(&extension) {
+                return Err(SwapchainError::MissingExtension(extension));
+            }
+        }
+
+        if physical_device.present_id_features().present_id == vk::FALSE {
+            return Err(SwapchainError::MissingFeature("present_id"));
+        }
+        if physical_device.present_wait_features().present_wait == vk::FALSE {
+            return Err(SwapchainError::MissingFeature("present_wait"));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the id of the most recent present, which can be passed to
+    /// [Swapchain::wait_for_present]
+    ///
+    /// Is `None` until a frame is presented, or if presents can't be waited on because
+    /// [DeviceExtension::PresentId] and [DeviceExtension::PresentWait] aren't enabled.
+    pub fn last_present_id(&self) -> Option<u64> {
+        if self.present_wait.is_some() && self.last_present_id > 0 {
+            Some(self.last_present_id)
+        } else {
+            None
+        }
+    }
+
+    /// Waits until the present with `present_id` has been displayed, or `timeout` passes.
+    ///
+    /// Waiting on the previous present before sampling input for the next frame keeps the
+    /// latency between input and the frame being displayed low, instead of queuing frames up
+    /// blindly. Returns whether the present was displayed before the timeout.
+    ///
+    /// # Errors
+    /// - [SwapchainError::MissingExtension] and [SwapchainError::MissingFeature] - Present ids
+    ///   and present waits aren't enabled or supported on the device
+    /// - [SwapchainError::OutOfDate] - The swapchain must be recreated
+    pub fn wait_for_present(
+        &self,
+        present_id: u64,
+        timeout: Duration,
+    ) -> Result<bool, SwapchainError> {
+        Swapchain::present_wait_supported(&self.device)?;
+        let present_wait = self
+            .present_wait
+            .as_ref()
+            .ok_or(SwapchainError::MissingExtension(DeviceExtension::PresentWait))?;
+
+        let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        let result = unsafe { present_wait.wait_for_present(self.handle, present_id, timeout) };
+        match result {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(SwapchainError::OutOfDate),
+            Err(err) => Err(SwapchainError::DeviceError(LogicalDeviceError::Other(
+                err.into(),
+            ))),
+        }
+    }
+}
+
+/// Describes the render pass of a swapchain, with a color and depth stencil attachment and an
+/// attachment the color attachment is resolved into if it has more than one sample
+///
+/// Only depends on the formats and sample count, so recreating a swapchain for a new window size
+/// keeps its render pass compatible.
+fn render_pass_description(
+    surface_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> RenderPassDescription {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(surface_format)
+        .samples(samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(if multisampled {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        })
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .final_layout(if multisampled {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        })
+        .build();
+
+    let depth_attachment = vk::AttachmentDescription::builder()
+        .format(depth_format)
+        .samples(samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .build();
+
+    let subpass_dependency = vk::SubpassDependency {
+        src_subpass: vk::SUBPASS_EXTERNAL,
+        dst_subpass: 0,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        src_access_mask: vk::AccessFlags::empty(),
+        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dependency_flags: vk::DependencyFlags::empty(),
+    };
+
+    let mut attachments = vec![color_attachment, depth_attachment];
+    let mut subpass = Subpass {
+        color_attachments: vec![0],
+        depth_stencil_attachment: Some(1),
+        ..Default::default()
+    };
+
+    if multisampled {
+        let resolve_attachment = vk::AttachmentDescription::builder()
+            .format(surface_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .build();
+        attachments.push(resolve_attachment);
+        subpass.resolve_attachments = vec![2];
+    }
+
+    RenderPassDescription {
+        attachments,
+        subpasses: vec![subpass],
+        dependencies: vec![subpass_dependency],
+    }
+}
+
+/// Checks that framebuffers with `count` attachments can be used with a render pass created from
+/// `description`, returning [SwapchainError::AttachmentMismatch] if it has a different number of
+/// attachments
+fn check_attachment_count(
+    description: &RenderPassDescription,
+    count: usize,
+) -> Result<(), SwapchainError> {
+    let expected = description.attachments.len();
+    if expected != count {
+        return Err(SwapchainError::AttachmentMismatch { expected, count });
+    }
+
+    Ok(())
+}
+
+/// Checks that the `requested` usage of the swapchain images is within the usage the surface
+/// `supported`, returning [SwapchainError::UnsupportedImageUsage] with the flags that aren't
+fn check_image_usage(
+    supported: ImageUsageFlags,
+    requested: ImageUsageFlags,
+) -> Result<(), SwapchainError> {
+    if !supported.contains(requested) {
+        return Err(SwapchainError::UnsupportedImageUsage(requested - supported));
+    }
+
+    Ok(())
+}
+
+/// Chooses the extent of the swapchain, which is the [Surface]'s current extent unless the surface
+/// leaves it up to the swapchain. In that case `window_size`, or 800x600 if it isn't known, is
+/// clamped to the surface's min and max image extent.
+fn choose_extent(
+    window_size: Option<(u32, u32)>,
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+) -> vk::Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        capabilities.current_extent
+    } else {
+        let (width, height) = window_size.unwrap_or_else(|| {
+            log::warn!("The surface doesn't specify an extent and no window size was given to the swapchain, falling back to 800x600");
+            (800, 600)
+        });
+
+        vk::Extent2D {
+            width: width.clamp(
+                capabilities.min_image_extent.width,
+                capabilities.max_image_extent.width,
+            ),
+            height: height.clamp(
+                capabilities.min_image_extent.height,
+                capabilities.max_image_extent.height,
+            ),
+        }
+    }
+}
+
+/// Returns whether the swapchain must be recreated given the `result` of presenting a frame
+///
+/// A suboptimal or out of date swapchain isn't an error when presenting, since the frame was
+/// already drawn and submitted, the swapchain only has to be recreated before the next frame.
+fn present_needs_recreation(result: Result<bool, vk::Result>) -> Result<bool, SwapchainError> {
+    match result {
+        Ok(suboptimal) => Ok(suboptimal),
+        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+        Err(err) => Err(SwapchainError::DeviceError(LogicalDeviceError::Other(
+            err.into(),
+        ))),
+    }
+}
+
+/// Blends a new frame time `sample` into the `previous` exponential moving average, the first
+/// sample is used as is
+fn smooth_frame_time(previous: Option<Duration>, sample: Duration) -> Duration {
+    match previous {
+        Some(previous) => Duration::from_secs_f64(
+            previous.as_secs_f64()
+                + FRAME_TIME_SMOOTHING * (sample.as_secs_f64() - previous.as_secs_f64()),
+        ),
+        None => sample,
+    }
+}
+
+/// Returns every handle in `results` if they were all created and there are `expected` of them,
+/// otherwise destroys the handles that were created with `destroy` and returns the first error,
+/// so handles created in parallel aren't leaked when one of them fails
+///
+/// Returns [SwapchainError::CreatedCountMismatch] if every handle was created, but not as many
+/// as `expected`.
+fn all_created<T>(
+    results: Vec<Result<T, SwapchainError>>,
+    expected: usize,
+    destroy: impl Fn(T),
+) -> Result<Vec<T>, SwapchainError> {
+    let mut handles: Vec<T> = Vec::with_capacity(results.len());
+    let mut error = None;
+    let count = results.len();
+    for result in results {
+        match result {
+            Ok(handle) => handles.push(handle),
+            Err(err) => {
+                error.get_or_insert(err);
+            }
+        }
+    }
+    if error.is_none() && count != expected {
+        error = Some(SwapchainError::CreatedCountMismatch { expected, count });
+    }
+
+    match error {
+        Some(err) => {
+            handles.into_iter().for_each(destroy);
+            Err(err)
+        }
+        None => Ok(handles),
+    }
+}
+
 impl Drop for Swapchain {
     fn drop(&mut self) {
         for &image_view in self.image_views.iter() {
@@ -724,10 +1315,21 @@ impl Drop for Swapchain {
                 self.device
                     .vk_handle()
                     .destroy_image(*self.depth_images.get(i).unwrap(), None);
+            }
+            self.device
+                .free_memory(*self.depth_image_memories.get(i).unwrap());
+        }
+
+        for i in 0..self.color_images.len() {
+            unsafe {
                 self.device
                     .vk_handle()
-                    .free_memory(*self.depth_image_memories.get(i).unwrap(), None);
+                    .destroy_image_view(self.color_image_views[i], None);
+                self.device
+                    .vk_handle()
+                    .destroy_image(self.color_images[i], None);
             }
+            self.device.free_memory(self.color_image_memories[i]);
         }
 
         for &framebuffer in self.framebuffers.iter() {
@@ -739,10 +1341,310 @@ impl Drop for Swapchain {
         }
 
         unsafe {
-            self.device
-                .vk_handle()
-                .destroy_render_pass(self.render_pass, None);
             self.swapchain.destroy_swapchain(self.handle, None);
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that two durations are within a microsecond of each other
+    fn assert_close(actual: Duration, expected: Duration) {
+        let difference = (actual.as_secs_f64() - expected.as_secs_f64()).abs();
+        assert!(
+            difference < 1e-6,
+            "Expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn first_frame_time_is_used_as_is() {
+        assert_close(
+            smooth_frame_time(None, Duration::from_millis(16)),
+            Duration::from_millis(16),
+        );
+    }
+
+    #[test]
+    fn frame_times_are_smoothed_exponentially() {
+        let samples = [16, 16, 32, 16].map(Duration::from_millis);
+        let mut smoothed = None;
+        let mut history = Vec::new();
+        for sample in samples {
+            let frame_time = smooth_frame_time(smoothed, sample);
+            smoothed = Some(frame_time);
+            history.push(frame_time);
+        }
+
+        // A 16ms spike only moves the average by a tenth of the difference
+        let expected = [0.016, 0.016, 0.0176, 0.01744].map(Duration::from_secs_f64);
+        for (actual, expected) in history.into_iter().zip(expected) {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn frame_time_converges_to_a_steady_sample() {
+        let mut smoothed = Some(Duration::from_millis(100));
+        for _ in 0..200 {
+            smoothed = Some(smooth_frame_time(smoothed, Duration::from_millis(16)));
+        }
+
+        assert_close(smoothed.unwrap(), Duration::from_millis(16));
+    }
+
+    #[test]
+    fn handles_are_kept_when_all_are_created() {
+        let results = vec![Ok(1), Ok(2), Ok(3)];
+
+        let handles = all_created(results, 3, |_| panic!("No handle should be destroyed"));
+        assert_eq!(handles.unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn created_handles_are_destroyed_when_one_fails() {
+        let results = vec![
+            Ok(1),
+            Err(SwapchainError::CantCreateFramebuffer(
+                vk::Result::ERROR_OUT_OF_HOST_MEMORY.into(),
+            )),
+            Ok(3),
+        ];
+
+        let destroyed = std::cell::RefCell::new(Vec::new());
+        let handles = all_created(results, 3, |handle| destroyed.borrow_mut().push(handle));
+        assert!(matches!(
+            handles,
+            Err(SwapchainError::CantCreateFramebuffer(_))
+        ));
+        assert_eq!(destroyed.into_inner(), [1, 3]);
+    }
+
+    #[test]
+    fn handles_created_after_a_failure_are_destroyed() {
+        let results = vec![
+            Ok(1),
+            Ok(2),
+            Err(SwapchainError::CantCreateImageView(
+                vk::Result::ERROR_OUT_OF_DEVICE_MEMORY.into(),
+            )),
+            Ok(4),
+            Err(SwapchainError::CantCreateImageView(
+                vk::Result::ERROR_OUT_OF_HOST_MEMORY.into(),
+            )),
+        ];
+
+        let destroyed = std::cell::RefCell::new(Vec::new());
+        let handles = all_created(results, 5, |handle| destroyed.borrow_mut().push(handle));
+        assert!(matches!(
+            handles,
+            Err(SwapchainError::CantCreateImageView(
+                VulkanError::OutOfDeviceMemory
+            ))
+        ));
+        assert_eq!(destroyed.into_inner(), [1, 2, 4]);
+    }
+
+    #[test]
+    fn fewer_handles_than_images_are_destroyed() {
+        let destroyed = std::cell::RefCell::new(Vec::new());
+        let handles = all_created(vec![Ok(1), Ok(2)], 3, |handle| {
+            destroyed.borrow_mut().push(handle)
+        });
+
+        assert!(matches!(
+            handles,
+            Err(SwapchainError::CreatedCountMismatch {
+                expected: 3,
+                count: 2
+            })
+        ));
+        assert_eq!(destroyed.into_inner(), [1, 2]);
+    }
+
+    #[test]
+    fn empty_surface_formats_error() {
+        assert!(matches!(
+            SwapchainBuilder::new().choose_format(&[]),
+            Err(SwapchainError::NoSurfaceFormats)
+        ));
+    }
+
+    #[test]
+    fn empty_present_modes_error() {
+        assert!(matches!(
+            SwapchainBuilder::new().choose_present_mode(&[]),
+            Err(SwapchainError::NoPresentModes)
+        ));
+    }
+
+    /// Returns surface capabilities with `current_extent`, supporting extents between 100x100
+    /// and 1920x1080
+    fn capabilities(current_extent: vk::Extent2D) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            current_extent,
+            min_image_extent: vk::Extent2D {
+                width: 100,
+                height: 100,
+            },
+            max_image_extent: vk::Extent2D {
+                width: 1920,
+                height: 1080,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Extent a surface reports when it leaves the extent up to the swapchain
+    const UNDEFINED_EXTENT: vk::Extent2D = vk::Extent2D {
+        width: u32::MAX,
+        height: u32::MAX,
+    };
+
+    #[test]
+    fn current_extent_is_used_when_defined() {
+        let current_extent = vk::Extent2D {
+            width: 1280,
+            height: 720,
+        };
+
+        assert_eq!(
+            choose_extent(Some((640, 480)), &capabilities(current_extent)),
+            current_extent
+        );
+    }
+
+    #[test]
+    fn window_size_is_clamped_to_the_supported_extents() {
+        let capabilities = capabilities(UNDEFINED_EXTENT);
+
+        assert_eq!(
+            choose_extent(Some((3840, 50)), &capabilities),
+            vk::Extent2D {
+                width: 1920,
+                height: 100
+            }
+        );
+        assert_eq!(
+            choose_extent(Some((640, 480)), &capabilities),
+            vk::Extent2D {
+                width: 640,
+                height: 480
+            }
+        );
+    }
+
+    #[test]
+    fn missing_window_size_falls_back_to_800x600() {
+        assert_eq!(
+            choose_extent(None, &capabilities(UNDEFINED_EXTENT)),
+            vk::Extent2D {
+                width: 800,
+                height: 600
+            }
+        );
+    }
+
+    #[test]
+    fn supported_image_usage_is_allowed() {
+        let supported = ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC;
+
+        assert!(check_image_usage(supported, ImageUsageFlags::COLOR_ATTACHMENT).is_ok());
+        assert!(check_image_usage(supported, supported).is_ok());
+    }
+
+    #[test]
+    fn unsupported_image_usage_errors_with_the_unsupported_flags() {
+        let supported = ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC;
+        let requested = ImageUsageFlags::COLOR_ATTACHMENT
+            | ImageUsageFlags::TRANSFER_DST
+            | ImageUsageFlags::STORAGE;
+
+        match check_image_usage(supported, requested) {
+            Err(SwapchainError::UnsupportedImageUsage(unsupported)) => assert_eq!(
+                unsupported,
+                ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::STORAGE
+            ),
+            result => panic!("Expected unsupported image usage, got {:?}", result),
+        }
+    }
+
+    /// Describes the render pass of a swapchain with `samples` samples per pixel
+    fn swapchain_render_pass(samples: vk::SampleCountFlags) -> RenderPassDescription {
+        render_pass_description(vk::Format::B8G8R8A8_UNORM, vk::Format::D32_SFLOAT, samples)
+    }
+
+    #[test]
+    fn matching_attachment_count_is_allowed() {
+        let single_sampled = swapchain_render_pass(vk::SampleCountFlags::TYPE_1);
+        let multisampled = swapchain_render_pass(vk::SampleCountFlags::TYPE_4);
+
+        assert!(check_attachment_count(&single_sampled, 2).is_ok());
+        assert!(check_attachment_count(&multisampled, 3).is_ok());
+    }
+
+    #[test]
+    fn wrong_attachment_count_errors() {
+        assert!(matches!(
+            check_attachment_count(&swapchain_render_pass(vk::SampleCountFlags::TYPE_4), 2),
+            Err(SwapchainError::AttachmentMismatch {
+                expected: 3,
+                count: 2
+            })
+        ));
+        assert!(matches!(
+            check_attachment_count(&swapchain_render_pass(vk::SampleCountFlags::TYPE_1), 3),
+            Err(SwapchainError::AttachmentMismatch {
+                expected: 2,
+                count: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn optimal_present_doesnt_need_recreation() {
+        assert!(!present_needs_recreation(Ok(false)).unwrap());
+    }
+
+    #[test]
+    fn suboptimal_or_out_of_date_present_completes_and_needs_recreation() {
+        assert!(present_needs_recreation(Ok(true)).unwrap());
+        assert!(present_needs_recreation(Err(vk::Result::ERROR_OUT_OF_DATE_KHR)).unwrap());
+    }
+
+    #[test]
+    fn other_present_errors_are_returned() {
+        assert!(matches!(
+            present_needs_recreation(Err(vk::Result::ERROR_DEVICE_LOST)),
+            Err(SwapchainError::DeviceError(LogicalDeviceError::Other(
+                VulkanError::DeviceLost
+            )))
+        ));
+    }
+
+    #[test]
+    fn resized_swapchain_render_pass_stays_compatible() {
+        // Recreating the swapchain for a new window size keeps the formats and sample count
+        let before = swapchain_render_pass(vk::SampleCountFlags::TYPE_4);
+        let after = swapchain_render_pass(vk::SampleCountFlags::TYPE_4);
+
+        assert!(before.is_compatible_with(&after));
+    }
+
+    #[test]
+    fn swapchain_render_pass_with_other_formats_or_samples_is_incompatible() {
+        let before = swapchain_render_pass(vk::SampleCountFlags::TYPE_1);
+        let srgb = render_pass_description(
+            vk::Format::B8G8R8A8_SRGB,
+            vk::Format::D32_SFLOAT,
+            vk::SampleCountFlags::TYPE_1,
+        );
+
+        assert!(!before.is_compatible_with(&srgb));
+        assert!(!before.is_compatible_with(&swapchain_render_pass(vk::SampleCountFlags::TYPE_4)));
+    }
+}
@@ -2,7 +2,7 @@ use ash::vk;
 use std::rc::Rc;
 
 use crate::{
-    core::device::{LogicalDevice, LogicalDeviceError},
+    core::device::{DeviceExtension, LogicalDevice, LogicalDeviceError},
     pipeline::shader::ShaderStageFlags,
     VulkanError,
 };
@@ -13,6 +13,12 @@ pub enum DescriptorError {
     CantCreateLayout(VulkanError),
     #[error(transparent)]
     CantCreatePool(VulkanError),
+    #[error("Bindless textures require the '{}' device extension and its sampled image features", DeviceExtension::DescriptorIndexing)]
+    BindlessUnsupported,
+    #[error("Can't register another bindless texture, the array can only hold {0} textures")]
+    BindlessFull(u32),
+    #[error("The descriptor set's layout has no binding {0}")]
+    UnknownBinding(u32),
     #[error(transparent)]
     DeviceError(LogicalDeviceError),
 }
@@ -103,13 +109,34 @@ impl DescriptorSetLayout {
     pub fn new(
         device: Rc<LogicalDevice>,
         bindings: &[DescriptorSetLayoutBinding],
+    ) -> Result<DescriptorSetLayout, DescriptorError> {
+        DescriptorSetLayout::with_flags(
+            device,
+            bindings,
+            vk::DescriptorSetLayoutCreateFlags::empty(),
+            &[],
+        )
+    }
+
+    /// Creates a descriptor set layout with layout creation flags and per binding flags, if
+    /// `binding_flags` is non-empty it must have a flag for every binding
+    pub(crate) fn with_flags(
+        device: Rc<LogicalDevice>,
+        bindings: &[DescriptorSetLayoutBinding],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+        binding_flags: &[vk::DescriptorBindingFlags],
     ) -> Result<DescriptorSetLayout, DescriptorError> {
         let vk_bindings: Vec<vk::DescriptorSetLayoutBinding> =
             bindings.iter().map(|&binding| binding.into()).collect();
 
-        let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
-            .bindings(&vk_bindings)
-            .build();
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(binding_flags);
+        let mut create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(flags)
+            .bindings(&vk_bindings);
+        if !binding_flags.is_empty() {
+            create_info = create_info.push_next(&mut binding_flags_info);
+        }
 
         let handle = unsafe {
             device
@@ -130,6 +157,31 @@ impl DescriptorSetLayout {
     pub(crate) fn vk_handle(&self) -> vk::DescriptorSetLayout {
         self.handle
     }
+
+    /// Returns the bindings the layout was created with
+    pub fn bindings(&self) -> &[DescriptorSetLayoutBinding] {
+        &self.bindings
+    }
+
+    /// Returns the binding with the index `binding`, if the layout has one
+    pub fn binding(&self, binding: u32) -> Option<&DescriptorSetLayoutBinding> {
+        self.bindings.iter().find(|b| b.binding == binding)
+    }
+
+    /// Checks whether descriptor sets allocated with `other` can be bound where this layout is
+    /// expected, which is the case if both layouts define the same bindings with the same
+    /// descriptor type, count and shader stages.
+    pub fn is_compatible_with(&self, other: &DescriptorSetLayout) -> bool {
+        self.handle == other.handle
+            || (self.bindings.len() == other.bindings.len()
+                && self.bindings.iter().all(|binding| {
+                    other.binding(binding.binding).is_some_and(|other| {
+                        Into::<vk::DescriptorType>::into(binding.ty) == other.ty.into()
+                            && binding.count == other.count
+                            && binding.shader_stage_flags == other.shader_stage_flags
+                    })
+                }))
+    }
 }
 
 impl Drop for DescriptorSetLayout {
@@ -171,11 +223,18 @@ impl DescriptorPoolBuilder {
     }
 
     pub fn build(self, device: Rc<LogicalDevice>) -> Result<DescriptorPool, DescriptorError> {
-        DescriptorPool::new(device, self.max_sets, &self.pool_sizes)
+        DescriptorPool::new(
+            device,
+            self.max_sets,
+            &self.pool_sizes,
+            vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+        )
     }
 }
 
 pub struct DescriptorPool {
+    /// Flags the pool was created with
+    flags: vk::DescriptorPoolCreateFlags,
     handle: vk::DescriptorPool,
     device: Rc<LogicalDevice>,
 }
@@ -189,9 +248,10 @@ impl DescriptorPool {
         device: Rc<LogicalDevice>,
         max_sets: u32,
         pool_sizes: &[vk::DescriptorPoolSize],
+        flags: vk::DescriptorPoolCreateFlags,
     ) -> Result<DescriptorPool, DescriptorError> {
         let create_info = vk::DescriptorPoolCreateInfo::builder()
-            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .flags(flags)
             .pool_sizes(pool_sizes)
             .max_sets(max_sets);
 
@@ -202,7 +262,11 @@ impl DescriptorPool {
                 .map_err(|err| DescriptorError::CantCreatePool(err.into()))?
         };
 
-        Ok(DescriptorPool { handle, device })
+        Ok(DescriptorPool {
+            flags,
+            handle,
+            device,
+        })
     }
 }
 
@@ -216,14 +280,13 @@ impl DescriptorPool {
             .descriptor_pool(self.handle)
             .set_layouts(&set_layouts);
 
-        Ok(unsafe {
+        Ok(*unsafe {
             self.device
                 .vk_handle()
                 .allocate_descriptor_sets(&allocate_info)
                 .map_err(|err| DescriptorError::DeviceError(LogicalDeviceError::Other(err.into())))?
                 .first()
                 .expect("Something went very wrong: Created a descriptor set but failed to get it")
-                .clone()
         })
     }
 
@@ -254,10 +317,18 @@ impl Drop for DescriptorPool {
     }
 }
 
+/// Resource written into a descriptor binding
+#[derive(Clone, Copy)]
+enum DescriptorWrite {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
 pub struct DescriptorWriter {
     layout: Rc<DescriptorSetLayout>,
     pool: Rc<DescriptorPool>,
-    writes: Vec<vk::WriteDescriptorSet>,
+    /// Resources to write into the descriptor set, with the binding they are written to
+    writes: Vec<(u32, DescriptorWrite)>,
 }
 
 impl DescriptorWriter {
@@ -276,41 +347,356 @@ impl DescriptorWriter {
         binding: u32,
         buffer_info: vk::DescriptorBufferInfo,
     ) -> DescriptorWriter {
-        if !self.layout.bindings.iter().any(|b| b.binding == binding) {
+        if self.layout.binding(binding).is_none() {
             log::warn!("Tried to write a buffer to a descriptor binding that doesn't exist");
             return self;
         }
 
-        let binding = self
-            .layout
-            .bindings
-            .iter()
-            .find(|b| b.binding == binding)
-            .unwrap();
-        self.writes.push(
-            vk::WriteDescriptorSet::builder()
-                .descriptor_type(binding.ty.into())
-                .dst_binding(binding.binding)
-                .buffer_info(&[buffer_info])
-                .build(),
-        );
+        self.writes
+            .push((binding, DescriptorWrite::Buffer(buffer_info)));
         self
     }
 
-    pub fn write(mut self) -> Result<vk::DescriptorSet, DescriptorError> {
+    /// Writes an image, e.g. a [Texture::descriptor][crate::image::Texture::descriptor] into a
+    /// combined image sampler binding
+    pub fn write_image(
+        mut self,
+        binding: u32,
+        image_info: vk::DescriptorImageInfo,
+    ) -> DescriptorWriter {
+        if self.layout.binding(binding).is_none() {
+            log::warn!("Tried to write an image to a descriptor binding that doesn't exist");
+            return self;
+        }
+
+        self.writes
+            .push((binding, DescriptorWrite::Image(image_info)));
+        self
+    }
+
+    pub fn write(self) -> Result<vk::DescriptorSet, DescriptorError> {
         let set = self.pool.allocate_descriptor_set(self.layout.as_ref())?;
+        update_descriptors(&self.pool.device, &self.layout, set, &self.writes);
+
+        Ok(set)
+    }
+
+    /// Allocates a [DescriptorSet] and writes the resources into it, the set is freed back to
+    /// the pool when it is dropped
+    pub fn build(self) -> Result<DescriptorSet, DescriptorError> {
+        let handle = self.pool.allocate_descriptor_set(self.layout.as_ref())?;
+        update_descriptors(&self.pool.device, &self.layout, handle, &self.writes);
+
+        Ok(DescriptorSet {
+            handle,
+            layout: self.layout,
+            pool: self.pool,
+        })
+    }
+}
+
+/// Writes resources into their bindings of `set`, every binding must exist in `layout`
+fn update_descriptors(
+    device: &LogicalDevice,
+    layout: &DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    writes: &[(u32, DescriptorWrite)],
+) {
+    let writes: Vec<vk::WriteDescriptorSet> = writes
+        .iter()
+        .map(|(binding, write)| {
+            let binding = layout
+                .binding(*binding)
+                .expect("Descriptor written to a binding that isn't in the layout");
+            let builder = vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(binding.binding)
+                .descriptor_type(binding.ty.into());
+            match write {
+                DescriptorWrite::Buffer(buffer_info) => {
+                    builder.buffer_info(std::slice::from_ref(buffer_info))
+                }
+                DescriptorWrite::Image(image_info) => {
+                    builder.image_info(std::slice::from_ref(image_info))
+                }
+            }
+            .build()
+        })
+        .collect();
+
+    unsafe {
+        device.vk_handle().update_descriptor_sets(&writes, &[]);
+    };
+}
+
+/// A descriptor set allocated from a [DescriptorPool], e.g. to hold a per-frame uniform buffer
+/// with camera matrices or lights.
+///
+/// The set is freed back to its pool when dropped, if the pool was created with
+/// [DescriptorPool::builder].
+pub struct DescriptorSet {
+    /// Opaque handle to the Vulkan descriptor set
+    handle: vk::DescriptorSet,
+    /// Layout the descriptor set was allocated with
+    layout: Rc<DescriptorSetLayout>,
+    /// Pool the descriptor set was allocated from
+    pool: Rc<DescriptorPool>,
+}
+
+impl DescriptorSet {
+    /// Allocates a new [DescriptorSet] with `layout` from `pool`, none of its bindings are
+    /// written
+    pub fn new(
+        layout: Rc<DescriptorSetLayout>,
+        pool: Rc<DescriptorPool>,
+    ) -> Result<DescriptorSet, DescriptorError> {
+        let handle = pool.allocate_descriptor_set(layout.as_ref())?;
+
+        Ok(DescriptorSet {
+            handle,
+            layout,
+            pool,
+        })
+    }
+}
+
+impl DescriptorSet {
+    /// Returns the handle to the Vulkan descriptor set
+    pub(crate) fn vk_handle(&self) -> vk::DescriptorSet {
+        self.handle
+    }
 
-        for write in self.writes.iter_mut() {
-            write.dst_set = set;
+    /// Returns the layout the descriptor set was allocated with
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// Writes `buffer_info` into `binding` of the descriptor set
+    ///
+    /// The set must not be used by a command buffer that is still pending execution.
+    ///
+    /// # Errors
+    /// - [DescriptorError::UnknownBinding] - The set's layout has no binding `binding`
+    pub fn write_buffer(
+        &mut self,
+        binding: u32,
+        buffer_info: vk::DescriptorBufferInfo,
+    ) -> Result<(), DescriptorError> {
+        self.update(binding, DescriptorWrite::Buffer(buffer_info))
+    }
+
+    /// Writes `image_info` into `binding` of the descriptor set, e.g. to swap the
+    /// [Texture][crate::image::Texture] a material samples
+    ///
+    /// The set must not be used by a command buffer that is still pending execution.
+    ///
+    /// # Errors
+    /// - [DescriptorError::UnknownBinding] - The set's layout has no binding `binding`
+    pub fn write_image(
+        &mut self,
+        binding: u32,
+        image_info: vk::DescriptorImageInfo,
+    ) -> Result<(), DescriptorError> {
+        self.update(binding, DescriptorWrite::Image(image_info))
+    }
+
+    /// Writes a single resource into `binding` of the descriptor set
+    fn update(&mut self, binding: u32, write: DescriptorWrite) -> Result<(), DescriptorError> {
+        if self.layout.binding(binding).is_none() {
+            return Err(DescriptorError::UnknownBinding(binding));
         }
 
+        update_descriptors(
+            &self.pool.device,
+            &self.layout,
+            self.handle,
+            &[(binding, write)],
+        );
+        Ok(())
+    }
+}
+
+impl Drop for DescriptorSet {
+    fn drop(&mut self) {
+        if !self
+            .pool
+            .flags
+            .contains(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        {
+            return;
+        }
+
+        if let Err(err) = self.pool.free_descriptor_sets(&[self.handle]) {
+            log::warn!("Failed to free descriptor set: {}", err);
+        }
+    }
+}
+
+/// A large array of combined image samplers in a single descriptor set, that textures can be
+/// registered into and then indexed in shaders without rebinding descriptor sets.
+///
+/// The array is bound at binding 0 of its set, and should be declared in shaders as an unsized
+/// array e.g. `layout(set = 0, binding = 0) uniform sampler2D textures[];`. Requires
+/// [DeviceExtension::DescriptorIndexing].
+pub struct BindlessTextures {
+    /// Maximum number of textures that can be registered
+    capacity: u32,
+    /// Number of textures that have been registered
+    count: u32,
+    /// Opaque handle to the Vulkan descriptor set holding the texture array
+    set: vk::DescriptorSet,
+    /// Layout of the descriptor set holding the texture array
+    layout: DescriptorSetLayout,
+    /// Pool the descriptor set was allocated from
+    pool: DescriptorPool,
+}
+
+impl BindlessTextures {
+    /// Creates a new [BindlessTextures] array that can hold up to `capacity` textures, visible
+    /// to the shader stages in `stage`
+    ///
+    /// # Errors
+    /// - [DescriptorError::BindlessUnsupported] - The device wasn't created with
+    ///   [DeviceExtension::DescriptorIndexing], or doesn't support the features required to
+    ///   partially bind, update after bind, and non-uniformly index a variable sized sampled
+    ///   image array.
+    pub fn new(
+        device: Rc<LogicalDevice>,
+        capacity: u32,
+        stage: ShaderStageFlags,
+    ) -> Result<BindlessTextures, DescriptorError> {
+        let features = device.physical_device().descriptor_indexing_features();
+        if !device
+            .physical_device()
+            .enabled_extensions()
+            .contains(&DeviceExtension::DescriptorIndexing)
+            || features.shader_sampled_image_array_non_uniform_indexing != vk::TRUE
+            || features.descriptor_binding_sampled_image_update_after_bind != vk::TRUE
+            || features.descriptor_binding_partially_bound != vk::TRUE
+            || features.descriptor_binding_variable_descriptor_count != vk::TRUE
+            || features.runtime_descriptor_array != vk::TRUE
+        {
+            return Err(DescriptorError::BindlessUnsupported);
+        }
+
+        let layout = DescriptorSetLayout::with_flags(
+            device.clone(),
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::CombinedImageSampler,
+                count: capacity,
+                shader_stage_flags: stage,
+            }],
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            &[vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT],
+        )?;
+
+        let pool = DescriptorPool::new(
+            device,
+            1,
+            &[vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: capacity,
+            }],
+            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+        )?;
+
+        let set_layouts = [layout.vk_handle()];
+        let descriptor_counts = [capacity];
+        let mut variable_count_info =
+            vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+                .descriptor_counts(&descriptor_counts);
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool.handle)
+            .set_layouts(&set_layouts)
+            .push_next(&mut variable_count_info);
+
+        let set = *unsafe {
+            pool.device
+                .vk_handle()
+                .allocate_descriptor_sets(&allocate_info)
+                .map_err(|err| DescriptorError::DeviceError(LogicalDeviceError::Other(err.into())))?
+                .first()
+                .expect("Something went very wrong: Created a descriptor set but failed to get it")
+        };
+
+        Ok(BindlessTextures {
+            capacity,
+            count: 0,
+            set,
+            layout,
+            pool,
+        })
+    }
+}
+
+impl BindlessTextures {
+    /// Returns the maximum number of textures that can be registered
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Returns the number of textures that have been registered
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns whether no textures have been registered yet
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the layout of the descriptor set holding the texture array
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// Returns the Vulkan descriptor set holding the texture array
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+}
+
+impl BindlessTextures {
+    /// Writes a texture into the next free slot of the array, returning the index it can be
+    /// accessed at in shaders
+    ///
+    /// The `image_view` is expected to be in the `SHADER_READ_ONLY_OPTIMAL` layout when sampled.
+    /// Since the array is update-after-bind, textures can be registered while the descriptor set
+    /// is bound in a command buffer that hasn't been submitted yet.
+    pub fn register(
+        &mut self,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Result<u32, DescriptorError> {
+        if self.count >= self.capacity {
+            return Err(DescriptorError::BindlessFull(self.capacity));
+        }
+
+        let index = self.count;
+        let image_infos = [vk::DescriptorImageInfo {
+            sampler,
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(0)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos)
+            .build()];
+
         unsafe {
             self.pool
                 .device
                 .vk_handle()
-                .update_descriptor_sets(&self.writes, &[]);
+                .update_descriptor_sets(&writes, &[]);
         };
+        self.count += 1;
 
-        Ok(set)
+        Ok(index)
     }
 }
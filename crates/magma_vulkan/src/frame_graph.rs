@@ -0,0 +1,581 @@
+//! This module provides a lightweight frame graph, which orders passes by the images they read
+//! and write and inserts the image layout transitions and barriers needed between them
+//!
+//! Using a [FrameGraph] is optional, passes can still be recorded on a [CommandBuffer] by hand.
+
+use ash::vk;
+
+use crate::core::commands::buffer::{CommandBuffer, CommandBufferError};
+
+/// Records the commands of a [FramePass] on a command buffer
+type RecordPass<'a> = Box<dyn FnMut(&mut CommandBuffer) -> Result<(), CommandBufferError> + 'a>;
+
+/// Errors that can be returned by the [FrameGraph]
+#[derive(thiserror::Error, Debug)]
+pub enum FrameGraphError {
+    #[error("Pass '{pass}' uses image {image}, but the frame graph only has {count} images")]
+    UnknownImage {
+        pass: String,
+        image: usize,
+        count: usize,
+    },
+    #[error("The passes of the frame graph depend on each other in a cycle: {0:?}")]
+    Cycle(Vec<String>),
+    #[error(transparent)]
+    CommandBufferError(#[from] CommandBufferError),
+}
+
+/// Possible ways a pass can use an image, each determining the image layout the pass needs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageAccess {
+    /// Rendered to as a color attachment
+    ColorAttachment,
+    /// Rendered to as a depth stencil attachment
+    DepthStencilAttachment,
+    /// Sampled or read as an input attachment in fragment shaders
+    ShaderRead,
+    /// Copied from
+    TransferSrc,
+    /// Copied to
+    TransferDst,
+    /// Presented to a surface, for the last use of a swapchain image
+    Present,
+}
+
+impl ImageAccess {
+    /// Returns the layout an image must be in for this access
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            ImageAccess::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthStencilAttachment => {
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            }
+            ImageAccess::ShaderRead => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageAccess::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageAccess::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageAccess::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+        }
+    }
+
+    /// Returns the pipeline stages the access happens in
+    fn stage(self) -> vk::PipelineStageFlags {
+        match self {
+            ImageAccess::ColorAttachment => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ImageAccess::DepthStencilAttachment => {
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            ImageAccess::ShaderRead => vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ImageAccess::TransferSrc | ImageAccess::TransferDst => vk::PipelineStageFlags::TRANSFER,
+            ImageAccess::Present => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        }
+    }
+
+    /// Returns the memory accesses the access performs
+    fn access_mask(self) -> vk::AccessFlags {
+        match self {
+            ImageAccess::ColorAttachment => {
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            ImageAccess::DepthStencilAttachment => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            ImageAccess::ShaderRead => vk::AccessFlags::SHADER_READ,
+            ImageAccess::TransferSrc => vk::AccessFlags::TRANSFER_READ,
+            ImageAccess::TransferDst => vk::AccessFlags::TRANSFER_WRITE,
+            ImageAccess::Present => vk::AccessFlags::empty(),
+        }
+    }
+
+    /// Returns whether the access writes to the image
+    fn is_write(self) -> bool {
+        matches!(
+            self,
+            ImageAccess::ColorAttachment
+                | ImageAccess::DepthStencilAttachment
+                | ImageAccess::TransferDst
+        )
+    }
+}
+
+/// Handle to an image registered with a [FrameGraph]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+/// Image tracked by a [FrameGraph]
+struct GraphImage {
+    /// Opaque handle to the Vulkan image
+    handle: vk::Image,
+    /// Aspects of the image that are transitioned
+    aspect_mask: vk::ImageAspectFlags,
+    /// Layout the image is currently in
+    layout: vk::ImageLayout,
+    /// How the image was last used, if at all
+    last_access: Option<ImageAccess>,
+}
+
+/// A pass of a [FrameGraph], declaring the images it reads and writes and recording its commands
+pub struct FramePass<'a> {
+    /// Name of the pass, used in errors
+    name: String,
+    /// Images the pass reads from
+    reads: Vec<(ImageId, ImageAccess)>,
+    /// Images the pass reads the contents of from before the graph was executed
+    previous_reads: Vec<(ImageId, ImageAccess)>,
+    /// Images the pass writes to
+    writes: Vec<(ImageId, ImageAccess)>,
+    /// Records the commands of the pass
+    record: RecordPass<'a>,
+}
+
+impl<'a> FramePass<'a> {
+    /// Creates a new [FramePass] that records its commands with `record`
+    ///
+    /// Render passes begun in `record` should have their attachments' initial and final layouts
+    /// match the layouts of their accesses, e.g. [vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL] for
+    /// [ImageAccess::ColorAttachment], since the graph transitions attachments between passes.
+    pub fn new<F>(name: &str, record: F) -> FramePass<'a>
+    where
+        F: FnMut(&mut CommandBuffer) -> Result<(), CommandBufferError> + 'a,
+    {
+        FramePass {
+            name: name.to_string(),
+            reads: Vec::new(),
+            previous_reads: Vec::new(),
+            writes: Vec::new(),
+            record: Box::new(record),
+        }
+    }
+
+    /// Declares that the pass reads `image` with `access`, after the other passes writing it
+    pub fn read(mut self, image: ImageId, access: ImageAccess) -> FramePass<'a> {
+        self.reads.push((image, access));
+        self
+    }
+
+    /// Declares that the pass reads the contents `image` had before the graph was executed with
+    /// `access`, so it runs before the other passes writing it, e.g. to sample last frame's
+    /// history before it is overwritten
+    pub fn read_previous(mut self, image: ImageId, access: ImageAccess) -> FramePass<'a> {
+        self.previous_reads.push((image, access));
+        self
+    }
+
+    /// Declares that the pass writes `image` with `access`
+    pub fn write(mut self, image: ImageId, access: ImageAccess) -> FramePass<'a> {
+        self.writes.push((image, access));
+        self
+    }
+
+    /// Returns every image the pass uses and how
+    fn accesses(&self) -> impl Iterator<Item = &(ImageId, ImageAccess)> {
+        self.reads
+            .iter()
+            .chain(self.previous_reads.iter())
+            .chain(self.writes.iter())
+    }
+
+    /// Returns whether the pass writes `image`
+    fn writes(&self, image: ImageId) -> bool {
+        self.writes.iter().any(|&(written, _)| written == image)
+    }
+}
+
+/// Orders passes by their image dependencies and records them with the barriers needed between
+/// them
+///
+/// Passes writing the same image run in the order they were added, and passes reading an image run
+/// after the other passes writing it, regardless of the order they were added in. A pass that
+/// both reads and writes an image only runs after the passes writing it that were added before
+/// it. Passes declaring [FramePass::read_previous] instead run before the other passes writing the
+/// image. Otherwise passes keep the order they were added in.
+pub struct FrameGraph<'a> {
+    /// Images tracked by the graph
+    images: Vec<GraphImage>,
+    /// Passes of the graph, in the order they were added
+    passes: Vec<FramePass<'a>>,
+}
+
+impl<'a> FrameGraph<'a> {
+    /// Creates a new empty [FrameGraph]
+    pub fn new() -> FrameGraph<'a> {
+        FrameGraph {
+            images: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+}
+
+impl<'a> FrameGraph<'a> {
+    /// Registers an image that is currently in `layout` with the graph, transitioning `aspect_mask`
+    /// of its first mip level and array layer
+    pub fn add_image(
+        &mut self,
+        image: vk::Image,
+        aspect_mask: vk::ImageAspectFlags,
+        layout: vk::ImageLayout,
+    ) -> ImageId {
+        self.images.push(GraphImage {
+            handle: image,
+            aspect_mask,
+            layout,
+            last_access: None,
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// Adds a pass to the graph
+    pub fn add_pass(&mut self, pass: FramePass<'a>) {
+        self.passes.push(pass);
+    }
+
+    /// Returns the layout `image` will be left in once the graph has been executed, or is
+    /// currently in if it hasn't been
+    pub fn image_layout(&self, image: ImageId) -> Option<vk::ImageLayout> {
+        self.images.get(image.0).map(|image| image.layout)
+    }
+
+    /// Returns the names of the passes in the order they will be executed in
+    ///
+    /// # Errors
+    /// - [FrameGraphError::UnknownImage] - A pass uses an image that wasn't added to the graph
+    /// - [FrameGraphError::Cycle] - The passes' dependencies can't be satisfied by any order
+    pub fn execution_order(&self) -> Result<Vec<&str>, FrameGraphError> {
+        Ok(self
+            .sorted_passes()?
+            .into_iter()
+            .map(|index| self.passes[index].name.as_str())
+            .collect())
+    }
+
+    /// Records every pass on `command_buffer` in dependency order, transitioning the images each
+    /// pass uses into the layouts it needs beforehand.
+    ///
+    /// Must be recorded outside of a render pass.
+    ///
+    /// # Errors
+    /// See [FrameGraph::execution_order], along with any [CommandBufferError] returned by
+    /// recording the barriers or passes.
+    pub fn execute(&mut self, command_buffer: &mut CommandBuffer) -> Result<(), FrameGraphError> {
+        for index in self.sorted_passes()? {
+            let (src_stage, dst_stage, barriers) = self.pass_barriers(index);
+            if !barriers.is_empty() {
+                command_buffer.image_barriers(src_stage, dst_stage, &barriers)?;
+            }
+
+            (self.passes[index].record)(command_buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the source and destination stages and the barriers needed before the pass at
+    /// `index` runs, and records the pass's accesses as the images' last accesses
+    fn pass_barriers(
+        &mut self,
+        index: usize,
+    ) -> (
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        Vec<vk::ImageMemoryBarrier>,
+    ) {
+        let pass = &self.passes[index];
+
+        let mut barriers: Vec<vk::ImageMemoryBarrier> = Vec::new();
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut dst_stage = vk::PipelineStageFlags::empty();
+        for &(ImageId(id), access) in pass.accesses() {
+            let image = &mut self.images[id];
+            let needs_barrier = image.layout != access.layout()
+                || access.is_write()
+                || image.last_access.is_some_and(ImageAccess::is_write);
+            if needs_barrier {
+                let (src_access, src_access_stage) = match image.last_access {
+                    Some(last_access) => (last_access.access_mask(), last_access.stage()),
+                    None => (
+                        vk::AccessFlags::empty(),
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                    ),
+                };
+                src_stage |= src_access_stage;
+                dst_stage |= access.stage();
+
+                barriers.push(
+                    vk::ImageMemoryBarrier::builder()
+                        .src_access_mask(src_access)
+                        .dst_access_mask(access.access_mask())
+                        .old_layout(image.layout)
+                        .new_layout(access.layout())
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(image.handle)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: image.aspect_mask,
+                            base_mip_level: 0,
+                            level_count: 1,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        })
+                        .build(),
+                );
+            }
+
+            image.layout = access.layout();
+            image.last_access = Some(access);
+        }
+
+        (src_stage, dst_stage, barriers)
+    }
+
+    /// Sorts the passes topologically by their dependencies, breaking ties by the order they were
+    /// added in
+    fn sorted_passes(&self) -> Result<Vec<usize>, FrameGraphError> {
+        for pass in self.passes.iter() {
+            if let Some(&(ImageId(image), _)) = pass
+                .accesses()
+                .find(|(ImageId(image), _)| *image >= self.images.len())
+            {
+                return Err(FrameGraphError::UnknownImage {
+                    pass: pass.name.clone(),
+                    image,
+                    count: self.images.len(),
+                });
+            }
+        }
+
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (other_index, other) in self.passes.iter().enumerate() {
+                if other_index == index {
+                    continue;
+                }
+
+                let reads_others_writes = pass.reads.iter().any(|&(image, _)| {
+                    other.writes(image) && (other_index < index || !pass.writes(image))
+                });
+                let overwrites = pass.writes.iter().any(|&(image, _)| {
+                    (other_index < index && other.writes(image))
+                        || other.previous_reads.iter().any(|&(read, _)| read == image)
+                });
+                if reads_others_writes || overwrites {
+                    dependencies[index].push(other_index);
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = Vec::with_capacity(self.passes.len());
+        let mut scheduled = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len()).find(|&index| {
+                !scheduled[index]
+                    && dependencies[index]
+                        .iter()
+                        .all(|&dependency| scheduled[dependency])
+            });
+
+            match next {
+                Some(index) => {
+                    scheduled[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    return Err(FrameGraphError::Cycle(
+                        (0..self.passes.len())
+                            .filter(|&index| !scheduled[index])
+                            .map(|index| self.passes[index].name.clone())
+                            .collect(),
+                    ))
+                }
+            }
+        }
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a pass that records nothing
+    fn pass(name: &str) -> FramePass<'static> {
+        FramePass::new(name, |_| Ok(()))
+    }
+
+    #[test]
+    fn passes_reading_and_writing_the_same_image_keep_their_order() {
+        let mut graph = FrameGraph::new();
+        let history = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        let color = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+
+        // Samples last frame's history before it is overwritten
+        graph.add_pass(
+            pass("resolve")
+                .read_previous(history, ImageAccess::ShaderRead)
+                .write(color, ImageAccess::ColorAttachment),
+        );
+        graph.add_pass(
+            pass("accumulate")
+                .read(color, ImageAccess::ShaderRead)
+                .write(history, ImageAccess::ColorAttachment),
+        );
+
+        assert_eq!(
+            graph.execution_order().unwrap(),
+            vec!["resolve", "accumulate"]
+        );
+
+        let (src_stage, dst_stage, barriers) = graph.pass_barriers(0);
+        assert_eq!(src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        assert_eq!(
+            dst_stage,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        );
+        let layouts: Vec<(vk::ImageLayout, vk::ImageLayout)> = barriers
+            .iter()
+            .map(|barrier| (barrier.old_layout, barrier.new_layout))
+            .collect();
+        assert_eq!(
+            layouts,
+            vec![
+                (
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                ),
+                (
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                ),
+            ]
+        );
+
+        let (src_stage, dst_stage, barriers) = graph.pass_barriers(1);
+        assert_eq!(
+            src_stage,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+        assert_eq!(
+            dst_stage,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        );
+        let transitions: Vec<(vk::ImageLayout, vk::ImageLayout, vk::AccessFlags)> = barriers
+            .iter()
+            .map(|barrier| {
+                (
+                    barrier.old_layout,
+                    barrier.new_layout,
+                    barrier.src_access_mask,
+                )
+            })
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                ),
+                (
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::SHADER_READ
+                ),
+            ]
+        );
+        assert_eq!(
+            graph.image_layout(history),
+            Some(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        );
+    }
+
+    #[test]
+    fn readers_run_after_earlier_writers() {
+        let mut graph = FrameGraph::new();
+        let image = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+
+        graph.add_pass(pass("scene").write(image, ImageAccess::ColorAttachment));
+        graph.add_pass(pass("unrelated"));
+        graph.add_pass(pass("post").read(image, ImageAccess::ShaderRead));
+
+        assert_eq!(
+            graph.execution_order().unwrap(),
+            vec!["scene", "unrelated", "post"]
+        );
+    }
+
+    #[test]
+    fn readers_added_first_run_after_the_writer() {
+        let mut graph = FrameGraph::new();
+        let target = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        let swapchain_image = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+
+        graph.add_pass(
+            pass("sample")
+                .read(target, ImageAccess::ShaderRead)
+                .write(swapchain_image, ImageAccess::ColorAttachment),
+        );
+        graph.add_pass(pass("render to target").write(target, ImageAccess::ColorAttachment));
+
+        assert_eq!(
+            graph.execution_order().unwrap(),
+            vec!["render to target", "sample"]
+        );
+    }
+
+    #[test]
+    fn passes_reading_each_others_writes_are_a_cycle() {
+        let mut graph = FrameGraph::new();
+        let a = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        let b = graph.add_image(
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+
+        graph.add_pass(
+            pass("first")
+                .read(a, ImageAccess::ShaderRead)
+                .write(b, ImageAccess::ColorAttachment),
+        );
+        graph.add_pass(
+            pass("second")
+                .read(b, ImageAccess::ShaderRead)
+                .write(a, ImageAccess::ColorAttachment),
+        );
+
+        match graph.execution_order() {
+            Err(FrameGraphError::Cycle(passes)) => assert_eq!(passes, vec!["first", "second"]),
+            result => panic!("expected a cycle, got {:?}", result.map(|_| ())),
+        }
+    }
+}
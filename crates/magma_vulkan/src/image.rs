@@ -0,0 +1,603 @@
+//! This module wraps Vulkan image related types
+
+use ash::vk;
+use bitflags::bitflags;
+use std::rc::Rc;
+
+use self::container::ContainerImage;
+use crate::{
+    buffer::{ArenaAllocation, BufferError, BufferUsageFlags, FrameArena, MemoryPropertyFlags},
+    core::{
+        commands::{
+            buffer::{CommandBuffer, CommandBufferError},
+            pool::{CommandPool, CommandPoolError},
+        },
+        device::{LogicalDevice, LogicalDeviceError},
+    },
+    sampler::SamplerInfo,
+    VulkanError,
+};
+
+mod container;
+
+bitflags! {
+    /// Wraps VkImageUsageFlagBits
+    pub struct ImageUsageFlags: u32 {
+        /// Image can be used as the source of a transfer command, e.g. to read it back to the host
+        const TRANSFER_SRC = 0x1;
+        /// Image can be used as the destination of a transfer command
+        const TRANSFER_DST = 0x2;
+        /// Image can be sampled from in a shader
+        const SAMPLED = 0x4;
+        /// Image can be read from and written to in a shader as a storage image
+        const STORAGE = 0x8;
+        /// Image can be used as a color attachment in a framebuffer
+        const COLOR_ATTACHMENT = 0x10;
+        /// Image can be used as a depth stencil attachment in a framebuffer
+        const DEPTH_STENCIL_ATTACHMENT = 0x20;
+        /// Image can be used as an input attachment in a framebuffer
+        const INPUT_ATTACHMENT = 0x80;
+    }
+}
+
+impl Into<vk::ImageUsageFlags> for ImageUsageFlags {
+    fn into(self) -> vk::ImageUsageFlags {
+        vk::ImageUsageFlags::from_raw(self.bits())
+    }
+}
+
+impl std::fmt::Display for ImageUsageFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Errors that can be returned when creating a [Texture]
+#[derive(thiserror::Error, Debug)]
+pub enum TextureError {
+    #[error("A {width}x{height} RGBA texture needs {expected} bytes of pixels, but {len} were given")]
+    InvalidSize {
+        width: u32,
+        height: u32,
+        expected: usize,
+        len: usize,
+    },
+    #[error("Can't create a texture with a width or height of 0")]
+    EmptyExtent,
+    #[error("Can't generate mipmaps, {0:?} doesn't support linear blits on this device")]
+    MipmapsUnsupported(vk::Format),
+    #[error("Invalid texture file: {0}")]
+    InvalidContainer(&'static str),
+    #[error("The texture file's format {0} isn't supported")]
+    UnsupportedContainerFormat(String),
+    #[error("The device can't sample textures with the format {0:?}")]
+    UnsupportedFormat(vk::Format),
+    #[error("Failed to create image view: {0}")]
+    CantCreateImageView(VulkanError),
+    #[error(transparent)]
+    BufferError(#[from] BufferError),
+    #[error(transparent)]
+    CommandBufferError(#[from] CommandBufferError),
+    #[error(transparent)]
+    CommandPoolError(#[from] CommandPoolError),
+    #[error(transparent)]
+    DeviceError(#[from] LogicalDeviceError),
+}
+
+/// Allows you to create a [Texture]
+pub struct TextureBuilder {
+    /// Whether the pixels are sRGB encoded, rather than linear
+    srgb: bool,
+    /// Whether a full mipmap chain is generated from the uploaded pixels
+    mipmaps: bool,
+    /// Parameters of the sampler the texture is sampled with
+    sampler: SamplerInfo,
+}
+
+impl TextureBuilder {
+    /// Creates a new default [TextureBuilder], for an sRGB texture with mipmaps
+    pub fn new() -> TextureBuilder {
+        TextureBuilder {
+            srgb: true,
+            mipmaps: true,
+            sampler: SamplerInfo::default(),
+        }
+    }
+
+    /// Sets whether the pixels are sRGB encoded, which should be disabled for data textures such
+    /// as normal maps
+    pub fn srgb(mut self, srgb: bool) -> TextureBuilder {
+        self.srgb = srgb;
+        self
+    }
+
+    /// Sets whether a full mipmap chain is generated on the device from the uploaded pixels
+    pub fn mipmaps(mut self, mipmaps: bool) -> TextureBuilder {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    /// Sets the parameters of the sampler the texture is sampled with, the sampler is shared
+    /// through the device's [SamplerCache][crate::sampler::SamplerCache]
+    pub fn sampler(mut self, sampler: SamplerInfo) -> TextureBuilder {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Creates a [Texture] from tightly packed 8-bit RGBA `pixels`, uploading them through a
+    /// staging buffer with a one-off command buffer from `command_pool`.
+    ///
+    /// # Errors
+    /// - [TextureError::InvalidSize] - `pixels` isn't `width * height * 4` bytes long
+    /// - [TextureError::MipmapsUnsupported] - Mipmaps were requested but the device can't blit
+    ///   the texture's format with linear filtering
+    pub fn build(
+        self,
+        device: Rc<LogicalDevice>,
+        command_pool: &CommandPool,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Texture, TextureError> {
+        if width == 0 || height == 0 {
+            return Err(TextureError::EmptyExtent);
+        }
+        let expected = width as usize * height as usize * 4;
+        if pixels.len() != expected {
+            return Err(TextureError::InvalidSize {
+                width,
+                height,
+                expected,
+                len: pixels.len(),
+            });
+        }
+
+        let format = if self.srgb {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        };
+        let mip_levels = if self.mipmaps {
+            32 - width.max(height).leading_zeros()
+        } else {
+            1
+        };
+        if mip_levels > 1
+            && device
+                .find_supported_format(
+                    &[format],
+                    vk::ImageTiling::OPTIMAL,
+                    vk::FormatFeatureFlags::BLIT_SRC
+                        | vk::FormatFeatureFlags::BLIT_DST
+                        | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+                )
+                .is_err()
+        {
+            return Err(TextureError::MipmapsUnsupported(format));
+        }
+
+        self.create(
+            device,
+            command_pool,
+            format,
+            (width, height),
+            mip_levels,
+            &[pixels],
+        )
+    }
+
+    /// Creates a [Texture] from a KTX2 file's contents, uploading every mip level stored in the
+    /// file in a single staging pass.
+    ///
+    /// The texture keeps the file's format, which can be block-compressed, see
+    /// [Texture::supported_compressed_format] to pick which variant of an asset to load. Only
+    /// 2D textures without supercompression are supported.
+    ///
+    /// # Errors
+    /// - [TextureError::InvalidContainer] - `bytes` isn't a valid or supported KTX2 file
+    /// - [TextureError::UnsupportedFormat] - The device can't sample the file's format
+    pub fn build_ktx2(
+        self,
+        device: Rc<LogicalDevice>,
+        command_pool: &CommandPool,
+        bytes: &[u8],
+    ) -> Result<Texture, TextureError> {
+        let image = container::parse_ktx2(bytes)?;
+        self.build_container(device, command_pool, image)
+    }
+
+    /// Creates a [Texture] from a DDS file's contents, uploading every mip level stored in the
+    /// file in a single staging pass.
+    ///
+    /// Legacy `DXT1`, `DXT3`, `DXT5` and `ATI2` files are loaded as sRGB or linear depending on
+    /// [TextureBuilder::srgb], files with a DX10 header use the format they declare.
+    ///
+    /// # Errors
+    /// - [TextureError::InvalidContainer] - `bytes` isn't a valid or supported DDS file
+    /// - [TextureError::UnsupportedFormat] - The device can't sample the file's format
+    pub fn build_dds(
+        self,
+        device: Rc<LogicalDevice>,
+        command_pool: &CommandPool,
+        bytes: &[u8],
+    ) -> Result<Texture, TextureError> {
+        let image = container::parse_dds(bytes, self.srgb)?;
+        self.build_container(device, command_pool, image)
+    }
+
+    /// Creates a [Texture] from an image parsed from a container file
+    fn build_container(
+        self,
+        device: Rc<LogicalDevice>,
+        command_pool: &CommandPool,
+        image: ContainerImage,
+    ) -> Result<Texture, TextureError> {
+        if device
+            .find_supported_format(
+                &[image.format],
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            )
+            .is_err()
+        {
+            return Err(TextureError::UnsupportedFormat(image.format));
+        }
+
+        self.create(
+            device,
+            command_pool,
+            image.format,
+            image.extent,
+            image.levels.len() as u32,
+            &image.levels,
+        )
+    }
+
+    /// Creates the image for a [Texture] with `mip_levels` levels, uploads `levels` into the
+    /// first mip levels and generates the rest by blitting
+    fn create(
+        self,
+        device: Rc<LogicalDevice>,
+        command_pool: &CommandPool,
+        format: vk::Format,
+        (width, height): (u32, u32),
+        mip_levels: u32,
+        levels: &[&[u8]],
+    ) -> Result<Texture, TextureError> {
+        let mut usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        if mip_levels as usize > levels.len() {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(1)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (image, memory) =
+            device.create_image(&image_info, MemoryPropertyFlags::DEVICE_LOCAL)?;
+
+        // The texture owns the image from here, so it is cleaned up if the upload fails
+        let mut texture = Texture {
+            extent: (width, height),
+            mip_levels,
+            format,
+            sampler: vk::Sampler::null(),
+            image_view: vk::ImageView::null(),
+            image,
+            memory,
+            device: device.clone(),
+        };
+
+        // Every level is aligned to 16 bytes in the arena, which covers the texel block size of
+        // every format
+        let staging_size = levels
+            .iter()
+            .map(|level| (level.len() as u64).div_ceil(16) * 16)
+            .sum();
+        let mut staging =
+            FrameArena::new(device.clone(), staging_size, BufferUsageFlags::TRANSFER_SRC)?;
+        let allocations = levels
+            .iter()
+            .map(|level| staging.alloc(level))
+            .collect::<Result<Vec<ArenaAllocation>, BufferError>>()?;
+
+        let mut recorded = Ok(());
+        command_pool.immediate_submit(|command_buffer| {
+            recorded = texture.record_upload(command_buffer, &allocations);
+        })?;
+        recorded?;
+
+        let image_view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(texture.subresource_range(0, mip_levels));
+        texture.image_view = unsafe {
+            device
+                .vk_handle()
+                .create_image_view(&image_view_info, None)
+                .map_err(|err| TextureError::CantCreateImageView(err.into()))?
+        };
+        texture.sampler = device.sampler(&self.sampler)?;
+
+        Ok(texture)
+    }
+}
+
+/// A sampled 2D image along with its view and sampler, that can be bound as a combined image
+/// sampler so fragment shaders can sample it
+pub struct Texture {
+    /// Width and height, in pixels, of the largest mip level
+    extent: (u32, u32),
+    /// Number of mip levels in the image
+    mip_levels: u32,
+    /// Format of the image's texels
+    format: vk::Format,
+    /// Sampler the texture is sampled with, owned by the device's sampler cache
+    sampler: vk::Sampler,
+    /// Opaque handle to the Vulkan image view over every mip level
+    image_view: vk::ImageView,
+    /// Opaque handle to the Vulkan image
+    image: vk::Image,
+    /// Opaque handle to Vulkan device memory belonging to the image
+    memory: vk::DeviceMemory,
+    /// [LogicalDevice] the image and memory belong to
+    device: Rc<LogicalDevice>,
+}
+
+impl Texture {
+    /// Creates a new [TextureBuilder]
+    pub fn builder() -> TextureBuilder {
+        TextureBuilder::new()
+    }
+
+    /// Returns the best block-compressed format the device can sample, trying BC7, BC3 and BC1
+    /// before falling back to ETC2 for devices without BC support, e.g. mobile GPUs.
+    ///
+    /// Useful to pick which variant of a compressed asset to load with
+    /// [TextureBuilder::build_ktx2] or [TextureBuilder::build_dds].
+    pub fn supported_compressed_format(device: &LogicalDevice, srgb: bool) -> Option<vk::Format> {
+        let candidates = if srgb {
+            [
+                vk::Format::BC7_SRGB_BLOCK,
+                vk::Format::BC3_SRGB_BLOCK,
+                vk::Format::BC1_RGBA_SRGB_BLOCK,
+                vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+            ]
+        } else {
+            [
+                vk::Format::BC7_UNORM_BLOCK,
+                vk::Format::BC3_UNORM_BLOCK,
+                vk::Format::BC1_RGBA_UNORM_BLOCK,
+                vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            ]
+        };
+
+        device
+            .find_supported_format(
+                &candidates,
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::SAMPLED_IMAGE
+                    | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+            )
+            .ok()
+    }
+}
+
+impl Texture {
+    /// Returns the width and height, in pixels, of the texture
+    pub fn extent(&self) -> (u32, u32) {
+        self.extent
+    }
+
+    /// Returns the number of mip levels in the texture
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Returns the format of the texture's texels
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    /// Returns the descriptor image info to write the texture into a combined image sampler
+    /// binding
+    pub fn descriptor(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    /// Returns the subresource range covering `level_count` mip levels from `base_mip_level`
+    fn subresource_range(
+        &self,
+        base_mip_level: u32,
+        level_count: u32,
+    ) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level,
+            level_count,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    /// Returns a barrier transitioning `level_count` mip levels from `base_mip_level` between
+    /// layouts
+    fn layout_barrier(
+        &self,
+        base_mip_level: u32,
+        level_count: u32,
+        (old_layout, src_access_mask): (vk::ImageLayout, vk::AccessFlags),
+        (new_layout, dst_access_mask): (vk::ImageLayout, vk::AccessFlags),
+    ) -> vk::ImageMemoryBarrier {
+        vk::ImageMemoryBarrier::builder()
+            .image(self.image)
+            .subresource_range(self.subresource_range(base_mip_level, level_count))
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build()
+    }
+
+    /// Records copying `levels` into the first mip levels, blitting the last of them down into
+    /// the rest of the mip levels, and transitioning every level to `SHADER_READ_ONLY_OPTIMAL`
+    fn record_upload(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        levels: &[ArenaAllocation],
+    ) -> Result<(), CommandBufferError> {
+        let transfer_dst = (
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        let transfer_src = (
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        let shader_read = (
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::SHADER_READ,
+        );
+        let subresource = |mip_level| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let level_extent = |level: u32| {
+            (
+                (self.extent.0 >> level).max(1) as i32,
+                (self.extent.1 >> level).max(1) as i32,
+            )
+        };
+
+        command_buffer.image_barriers(
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            &[self.layout_barrier(
+                0,
+                self.mip_levels,
+                (vk::ImageLayout::UNDEFINED, vk::AccessFlags::empty()),
+                transfer_dst,
+            )],
+        )?;
+
+        let regions: Vec<vk::BufferImageCopy> = levels
+            .iter()
+            .enumerate()
+            .map(|(level, allocation)| {
+                let (width, height) = level_extent(level as u32);
+                vk::BufferImageCopy::builder()
+                    .buffer_offset(allocation.offset)
+                    .image_subresource(subresource(level as u32))
+                    .image_extent(vk::Extent3D {
+                        width: width as u32,
+                        height: height as u32,
+                        depth: 1,
+                    })
+                    .build()
+            })
+            .collect();
+        unsafe {
+            self.device.vk_handle().cmd_copy_buffer_to_image(
+                command_buffer.vk_handle(),
+                levels[0].buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+        };
+
+        // The last uploaded level is the source of the first generated one, so it stays behind
+        let uploaded = levels.len() as u32;
+        if uploaded > 1 {
+            command_buffer.image_barriers(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                &[self.layout_barrier(0, uploaded - 1, transfer_dst, shader_read)],
+            )?;
+        }
+
+        for level in uploaded..self.mip_levels {
+            command_buffer.image_barriers(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                &[self.layout_barrier(level - 1, 1, transfer_dst, transfer_src)],
+            )?;
+
+            let (width, height) = level_extent(level - 1);
+            let (next_width, next_height) = level_extent(level);
+            let blit = vk::ImageBlit::builder()
+                .src_subresource(subresource(level - 1))
+                .src_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: width,
+                        y: height,
+                        z: 1,
+                    },
+                ])
+                .dst_subresource(subresource(level))
+                .dst_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: next_width,
+                        y: next_height,
+                        z: 1,
+                    },
+                ])
+                .build();
+            unsafe {
+                self.device.vk_handle().cmd_blit_image(
+                    command_buffer.vk_handle(),
+                    self.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR,
+                );
+            };
+
+            command_buffer.image_barriers(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                &[self.layout_barrier(level - 1, 1, transfer_src, shader_read)],
+            )?;
+        }
+
+        command_buffer.image_barriers(
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            &[self.layout_barrier(self.mip_levels - 1, 1, transfer_dst, shader_read)],
+        )
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .vk_handle()
+                .destroy_image_view(self.image_view, None);
+            self.device.vk_handle().destroy_image(self.image, None);
+        };
+        self.device.free_memory(self.memory);
+    }
+}
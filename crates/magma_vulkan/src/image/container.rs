@@ -0,0 +1,425 @@
+//! Parses KTX2 and DDS texture containers into the mip levels to upload to a [Texture]
+//!
+//! [Texture]: super::Texture
+
+use ash::vk;
+
+use super::TextureError;
+
+/// Identifier every KTX2 file starts with
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// Size, in bytes, of the KTX2 identifier, header and index that come before the level index
+const KTX2_LEVEL_INDEX_OFFSET: usize = 80;
+
+/// Magic number every DDS file starts with
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+/// Size, in bytes, of the DDS magic number and header
+const DDS_HEADER_SIZE: usize = 128;
+/// Size, in bytes, of the DX10 header extension
+const DDS_DX10_HEADER_SIZE: usize = 20;
+/// `DDPF_FOURCC` pixel format flag, set when the pixel format is given by a four character code
+const DDPF_FOURCC: u32 = 0x4;
+/// `DDSCAPS2_CUBEMAP` flag, set for cube maps
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+/// `D3D10_RESOURCE_DIMENSION_TEXTURE2D` resource dimension of a DX10 header
+const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+
+/// A 2D image read from a texture container
+pub(crate) struct ContainerImage<'a> {
+    /// Format of the image's texels
+    pub format: vk::Format,
+    /// Width and height, in pixels, of the largest mip level
+    pub extent: (u32, u32),
+    /// Texel data of every mip level, starting with the largest
+    pub levels: Vec<&'a [u8]>,
+}
+
+/// Reads a little endian `u32` at `offset` in `bytes`
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(TextureError::InvalidContainer("unexpected end of file"))
+}
+
+/// Reads a little endian `u64` at `offset` in `bytes`
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+/// Returns `length` bytes at `offset` in `bytes`
+fn slice(bytes: &[u8], offset: u64, length: u64) -> Result<&[u8], TextureError> {
+    usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(length).ok())
+        .and_then(|(offset, length)| bytes.get(offset..offset.checked_add(length)?))
+        .ok_or(TextureError::InvalidContainer("mip level is out of bounds"))
+}
+
+/// Returns the width and height, in texels, of a block and its size in bytes for `format`
+fn block_layout(format: vk::Format) -> Option<(u32, u64)> {
+    match format {
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB => Some((1, 4)),
+        vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => Some((4, 8)),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Some((4, 16)),
+        _ => None,
+    }
+}
+
+/// Returns the size, in bytes, of mip level `level` of a `format` image with `extent`, or `None`
+/// if the block layout of `format` isn't known
+///
+/// # Errors
+/// - [TextureError::InvalidContainer] - The size doesn't fit in a `u64`, which can only happen
+///   for a corrupt extent
+fn level_size(
+    format: vk::Format,
+    (width, height): (u32, u32),
+    level: u32,
+) -> Result<Option<u64>, TextureError> {
+    let Some((block, block_size)) = block_layout(format) else {
+        return Ok(None);
+    };
+    let blocks_x = (width >> level).max(1).div_ceil(block) as u64;
+    let blocks_y = (height >> level).max(1).div_ceil(block) as u64;
+    blocks_x
+        .checked_mul(blocks_y)
+        .and_then(|blocks| blocks.checked_mul(block_size))
+        .map(Some)
+        .ok_or(TextureError::InvalidContainer("mip level is too large"))
+}
+
+/// Parses the contents of a KTX2 file
+pub(crate) fn parse_ktx2(bytes: &[u8]) -> Result<ContainerImage<'_>, TextureError> {
+    if !bytes.starts_with(&KTX2_IDENTIFIER) {
+        return Err(TextureError::InvalidContainer("missing KTX2 identifier"));
+    }
+
+    let format = vk::Format::from_raw(read_u32(bytes, 12)? as i32);
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+
+    if format == vk::Format::UNDEFINED {
+        return Err(TextureError::UnsupportedContainerFormat(
+            "VK_FORMAT_UNDEFINED, Basis Universal textures must be transcoded first".to_string(),
+        ));
+    }
+    if width == 0 || height == 0 || depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err(TextureError::InvalidContainer(
+            "only single layer 2D textures are supported",
+        ));
+    }
+    if supercompression != 0 {
+        return Err(TextureError::InvalidContainer(
+            "supercompressed textures aren't supported",
+        ));
+    }
+    if level_count > 32 - width.max(height).leading_zeros() {
+        return Err(TextureError::InvalidContainer("too many mip levels"));
+    }
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count as usize {
+        let entry = KTX2_LEVEL_INDEX_OFFSET + level * 24;
+        let data = slice(bytes, read_u64(bytes, entry)?, read_u64(bytes, entry + 8)?)?;
+        if level_size(format, (width, height), level as u32)?
+            .is_some_and(|size| size != data.len() as u64)
+        {
+            return Err(TextureError::InvalidContainer(
+                "mip level size doesn't match its extent",
+            ));
+        }
+        levels.push(data);
+    }
+
+    Ok(ContainerImage {
+        format,
+        extent: (width, height),
+        levels,
+    })
+}
+
+/// Returns the format of a DDS file's legacy four character code, in sRGB or linear encoding
+fn dds_four_cc_format(four_cc: &[u8], srgb: bool) -> Option<vk::Format> {
+    match (four_cc, srgb) {
+        (b"DXT1", false) => Some(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        (b"DXT1", true) => Some(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        (b"DXT3", false) => Some(vk::Format::BC2_UNORM_BLOCK),
+        (b"DXT3", true) => Some(vk::Format::BC2_SRGB_BLOCK),
+        (b"DXT5", false) => Some(vk::Format::BC3_UNORM_BLOCK),
+        (b"DXT5", true) => Some(vk::Format::BC3_SRGB_BLOCK),
+        (b"ATI1" | b"BC4U", _) => Some(vk::Format::BC4_UNORM_BLOCK),
+        (b"ATI2" | b"BC5U", _) => Some(vk::Format::BC5_UNORM_BLOCK),
+        _ => None,
+    }
+}
+
+/// Returns the format matching a `DXGI_FORMAT` of a DDS file's DX10 header
+fn dds_dxgi_format(dxgi_format: u32) -> Option<vk::Format> {
+    match dxgi_format {
+        28 => Some(vk::Format::R8G8B8A8_UNORM),
+        29 => Some(vk::Format::R8G8B8A8_SRGB),
+        71 => Some(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        72 => Some(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        74 => Some(vk::Format::BC2_UNORM_BLOCK),
+        75 => Some(vk::Format::BC2_SRGB_BLOCK),
+        77 => Some(vk::Format::BC3_UNORM_BLOCK),
+        78 => Some(vk::Format::BC3_SRGB_BLOCK),
+        80 => Some(vk::Format::BC4_UNORM_BLOCK),
+        83 => Some(vk::Format::BC5_UNORM_BLOCK),
+        87 => Some(vk::Format::B8G8R8A8_UNORM),
+        91 => Some(vk::Format::B8G8R8A8_SRGB),
+        98 => Some(vk::Format::BC7_UNORM_BLOCK),
+        99 => Some(vk::Format::BC7_SRGB_BLOCK),
+        _ => None,
+    }
+}
+
+/// Parses the contents of a DDS file, legacy block-compressed formats are read as sRGB if
+/// `srgb` is set
+pub(crate) fn parse_dds(bytes: &[u8], srgb: bool) -> Result<ContainerImage<'_>, TextureError> {
+    if !bytes.starts_with(DDS_MAGIC) {
+        return Err(TextureError::InvalidContainer("missing DDS magic number"));
+    }
+
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let level_count = read_u32(bytes, 28)?.max(1);
+    let pixel_format_flags = read_u32(bytes, 80)?;
+    let caps2 = read_u32(bytes, 112)?;
+    let four_cc = read_u32(bytes, 84)?.to_le_bytes();
+
+    if width == 0 || height == 0 || caps2 & DDSCAPS2_CUBEMAP != 0 {
+        return Err(TextureError::InvalidContainer(
+            "only 2D textures are supported",
+        ));
+    }
+    if pixel_format_flags & DDPF_FOURCC == 0 {
+        return Err(TextureError::UnsupportedContainerFormat(
+            "uncompressed DDS without a DX10 header".to_string(),
+        ));
+    }
+    if level_count > 32 - width.max(height).leading_zeros() {
+        return Err(TextureError::InvalidContainer("too many mip levels"));
+    }
+
+    let (format, mut offset) = if &four_cc == b"DX10" {
+        let dxgi_format = read_u32(bytes, DDS_HEADER_SIZE)?;
+        let dimension = read_u32(bytes, DDS_HEADER_SIZE + 4)?;
+        let array_size = read_u32(bytes, DDS_HEADER_SIZE + 12)?;
+        if dimension != DDS_DIMENSION_TEXTURE2D || array_size > 1 {
+            return Err(TextureError::InvalidContainer(
+                "only single layer 2D textures are supported",
+            ));
+        }
+
+        let format = dds_dxgi_format(dxgi_format).ok_or_else(|| {
+            TextureError::UnsupportedContainerFormat(format!("DXGI_FORMAT {}", dxgi_format))
+        })?;
+        (format, (DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE) as u64)
+    } else {
+        let format = dds_four_cc_format(&four_cc, srgb).ok_or_else(|| {
+            TextureError::UnsupportedContainerFormat(format!(
+                "four character code '{}'",
+                String::from_utf8_lossy(&four_cc)
+            ))
+        })?;
+        (format, DDS_HEADER_SIZE as u64)
+    };
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let size = level_size(format, (width, height), level)?.ok_or_else(|| {
+            TextureError::UnsupportedContainerFormat(format!("{:?} without a block layout", format))
+        })?;
+        levels.push(slice(bytes, offset, size)?);
+        offset = offset
+            .checked_add(size)
+            .ok_or(TextureError::InvalidContainer("mip level is out of bounds"))?;
+    }
+
+    Ok(ContainerImage {
+        format,
+        extent: (width, height),
+        levels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a KTX2 file of a single layer 2D `format` image, with a level of every size in
+    /// `level_sizes` filled with its index
+    fn ktx2(format: vk::Format, (width, height): (u32, u32), level_sizes: &[usize]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [format.as_raw() as u32, 1, width, height, 0, 0, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(level_sizes.len() as u32).to_le_bytes());
+        bytes.resize(KTX2_LEVEL_INDEX_OFFSET, 0);
+
+        let mut offset = KTX2_LEVEL_INDEX_OFFSET + level_sizes.len() * 24;
+        for &size in level_sizes {
+            for value in [offset, size, size] {
+                bytes.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            offset += size;
+        }
+        for (level, &size) in level_sizes.iter().enumerate() {
+            bytes.resize(bytes.len() + size, level as u8);
+        }
+
+        bytes
+    }
+
+    /// Builds a DDS file of a `four_cc` image, with a DX10 header if `dxgi_format` is given,
+    /// followed by `data`
+    fn dds(
+        four_cc: &[u8; 4],
+        dxgi_format: Option<u32>,
+        (width, height): (u32, u32),
+        level_count: u32,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = vec![0; DDS_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(DDS_MAGIC);
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[28..32].copy_from_slice(&level_count.to_le_bytes());
+        bytes[80..84].copy_from_slice(&DDPF_FOURCC.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+        if let Some(dxgi_format) = dxgi_format {
+            for value in [dxgi_format, DDS_DIMENSION_TEXTURE2D, 0, 1, 0] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(data);
+
+        bytes
+    }
+
+    #[test]
+    fn ktx2_bc1_levels_are_read() {
+        let bytes = ktx2(vk::Format::BC1_RGBA_UNORM_BLOCK, (8, 8), &[32, 8]);
+        let image = parse_ktx2(&bytes).unwrap();
+
+        assert_eq!(image.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!(image.extent, (8, 8));
+        assert_eq!(image.levels, vec![&[0; 32][..], &[1; 8][..]]);
+    }
+
+    #[test]
+    fn dds_bc7_is_read_from_the_dx10_header() {
+        let bytes = dds(b"DX10", Some(98), (4, 4), 1, &[7; 16]);
+        let image = parse_dds(&bytes, false).unwrap();
+
+        assert_eq!(image.format, vk::Format::BC7_UNORM_BLOCK);
+        assert_eq!(image.extent, (4, 4));
+        assert_eq!(image.levels, vec![&[7; 16][..]]);
+    }
+
+    #[test]
+    fn dds_legacy_four_cc_is_read_as_srgb() {
+        let bytes = dds(b"DXT1", None, (4, 4), 1, &[1; 8]);
+
+        assert_eq!(
+            parse_dds(&bytes, true).unwrap().format,
+            vk::Format::BC1_RGBA_SRGB_BLOCK
+        );
+    }
+
+    #[test]
+    fn truncated_headers_are_invalid() {
+        let ktx2_bytes = ktx2(vk::Format::BC1_RGBA_UNORM_BLOCK, (4, 4), &[8]);
+        let dds_bytes = dds(b"DXT1", None, (4, 4), 1, &[1; 8]);
+
+        assert!(matches!(
+            parse_ktx2(&ktx2_bytes[..30]),
+            Err(TextureError::InvalidContainer(_))
+        ));
+        assert!(matches!(
+            parse_dds(&dds_bytes[..86], false),
+            Err(TextureError::InvalidContainer(_))
+        ));
+    }
+
+    #[test]
+    fn mismatched_level_sizes_are_invalid() {
+        let bytes = ktx2(vk::Format::BC1_RGBA_UNORM_BLOCK, (4, 4), &[16]);
+
+        assert!(matches!(
+            parse_ktx2(&bytes),
+            Err(TextureError::InvalidContainer(
+                "mip level size doesn't match its extent"
+            ))
+        ));
+    }
+
+    #[test]
+    fn missing_level_data_is_invalid() {
+        let bytes = dds(b"DXT1", None, (8, 8), 2, &[1; 32]);
+
+        assert!(matches!(
+            parse_dds(&bytes, false),
+            Err(TextureError::InvalidContainer("mip level is out of bounds"))
+        ));
+    }
+
+    #[test]
+    fn unsupported_formats_are_reported() {
+        let ktx2_bytes = ktx2(vk::Format::UNDEFINED, (4, 4), &[8]);
+        let dds_bytes = dds(b"ABCD", None, (4, 4), 1, &[0; 8]);
+        let dxgi_bytes = dds(b"DX10", Some(2), (4, 4), 1, &[0; 16]);
+
+        assert!(matches!(
+            parse_ktx2(&ktx2_bytes),
+            Err(TextureError::UnsupportedContainerFormat(_))
+        ));
+        assert!(matches!(
+            parse_dds(&dds_bytes, false),
+            Err(TextureError::UnsupportedContainerFormat(_))
+        ));
+        assert!(matches!(
+            parse_dds(&dxgi_bytes, false),
+            Err(TextureError::UnsupportedContainerFormat(_))
+        ));
+    }
+
+    #[test]
+    fn huge_extents_are_invalid() {
+        let ktx2_bytes = ktx2(vk::Format::R8G8B8A8_UNORM, (u32::MAX, u32::MAX), &[4]);
+        let dds_bytes = dds(b"DX10", Some(28), (u32::MAX, u32::MAX), 1, &[0; 4]);
+
+        assert!(matches!(
+            parse_ktx2(&ktx2_bytes),
+            Err(TextureError::InvalidContainer("mip level is too large"))
+        ));
+        assert!(matches!(
+            parse_dds(&dds_bytes, false),
+            Err(TextureError::InvalidContainer("mip level is too large"))
+        ));
+    }
+}
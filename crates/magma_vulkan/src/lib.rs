@@ -1,11 +1,19 @@
 //! This crate provides a safe high-level abstraction around Vulkan
 
+#![allow(clippy::from_over_into, clippy::new_without_default)]
+
 extern crate log;
 
 pub mod buffer;
 pub mod core;
 pub mod descriptors;
+pub mod frame_graph;
+pub mod image;
 pub mod pipeline;
+pub mod post_process;
+pub mod render_pass;
+pub mod render_target;
+pub mod sampler;
 pub mod sync;
 pub mod utils;
 
@@ -129,33 +137,67 @@ impl From<VkResult> for VulkanError {
 }
 
 pub mod prelude {
-    pub use crate::buffer::{Buffer, BufferUsageFlags, MemoryPropertyFlags};
+    pub use crate::buffer::{
+        ArenaAllocation, Buffer, BufferUsageFlags, FrameArena, MemoryPropertyFlags,
+    };
 
-    pub use crate::core::commands::buffer::{CommandBuffer, CommandBufferLevel};
+    pub use crate::core::commands::buffer::{
+        ClearAttachment, ClearRect, ClearValue, CommandBuffer, CommandBufferError,
+        CommandBufferLevel, CommandBufferUsageFlags, IndexType,
+    };
     pub use crate::core::commands::pool::{CommandPool, CommandPoolError};
-    pub use crate::core::debugger::DebugLayer;
+    pub use crate::core::debugger::{
+        DebugLayer, DebugMessageFilter, DebugMessageSeverity, DebugMessageType, ValidationCallback,
+    };
     pub use crate::core::device::{
-        DeviceExtension, LogicalDevice, LogicalDeviceError, PhysicalDevice, PhysicalDeviceBuilder,
-        PhysicalDeviceError, PhysicalDeviceType, QueueFlags, QueueFamily,
+        CalibratedTimestamps, DeviceExtension, LogicalDevice, LogicalDeviceError, MemoryStats,
+        PhysicalDevice, PhysicalDeviceBuilder, PhysicalDeviceError, PhysicalDeviceInfo,
+        PhysicalDeviceType, QueueFlags, QueueFamily,
     };
-    pub use crate::core::instance::{Instance, InstanceError};
+    pub use crate::core::instance::{Instance, InstanceBuilder, InstanceError};
     pub use crate::core::surface::{Surface, SurfaceError};
-    pub use crate::core::swapchain::{ColorFormat, PresentMode, Swapchain, SwapchainError};
+    pub use crate::core::swapchain::{
+        ColorFormat, PresentMode, Swapchain, SwapchainDependent, SwapchainError,
+    };
 
     pub use crate::descriptors::{
-        DescriptorError, DescriptorPool, DescriptorSetLayoutBinding, DescriptorSetLayout, DescriptorType,
+        BindlessTextures, DescriptorError, DescriptorPool, DescriptorSet, DescriptorSetLayoutBinding, DescriptorSetLayout, DescriptorType,
         DescriptorWriter,
     };
 
-    pub use crate::pipeline::shader::{Shader, ShaderError, ShaderStageFlags};
-    pub use crate::pipeline::ubo::{EmptyPushConstant, UboFieldDescription, UniformBuffer};
+    pub use crate::frame_graph::{FrameGraph, FrameGraphError, FramePass, ImageAccess, ImageId};
+
+    pub use crate::image::{ImageUsageFlags, Texture, TextureBuilder, TextureError};
+
+    pub use crate::pipeline::config::{
+        CullMode, FrontFace, LineMode, PipelineConfigInfo, PrimitiveTopology,
+    };
+    pub use crate::pipeline::shader::{
+        Shader, ShaderDescriptorBinding, ShaderError, ShaderStageFlags, ValidationError,
+    };
+    pub use crate::pipeline::ubo::{
+        std140_offsets, std140_size, std140_write, std140_write_columns, std430_alignment,
+        std430_offsets, std430_size, EmptyPushConstant, UboFieldDescription, UniformBuffer,
+    };
     pub use crate::pipeline::vertex::{
         EmptyVertex, Vertex, VertexAttributeDescription, VertexBindingDescription, VertexInputRate,
         VkFormat,
     };
+    pub use crate::pipeline::watcher::{ShaderWatcher, ShaderWatcherError};
     pub use crate::pipeline::{Pipeline, PipelineBuilder, PipelineError};
 
-    pub use crate::sync::{Fence, Semaphore};
+    pub use crate::post_process::{PostProcessChain, PostProcessChainBuilder, PostProcessError};
+
+    pub use crate::render_pass::{
+        DepthResolve, RenderPass, RenderPassBuilder, RenderPassDescription, RenderPassError,
+        ResolveMode, Subpass,
+    };
+
+    pub use crate::render_target::{RenderTarget, RenderTargetBuilder, RenderTargetError};
+
+    pub use crate::sampler::{AddressMode, Filter, MipmapMode, SamplerCache, SamplerInfo};
+
+    pub use crate::sync::{Fence, Semaphore, SyncError};
 
     pub use memoffset::offset_of;
 }
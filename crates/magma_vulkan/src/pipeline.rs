@@ -2,18 +2,25 @@
 
 use ash::vk;
 use shader::ShaderStageFlags;
-use std::{any::TypeId, marker::PhantomData, rc::Rc};
+use std::{any::TypeId, collections::BTreeMap, marker::PhantomData, rc::Rc, time::Instant};
 
 use self::{
-    config::PipelineConfigInfo,
+    config::{CullMode, FrontFace, LineMode, PipelineConfigInfo, PrimitiveTopology},
     shader::{Shader, ShaderError, ShaderModule},
-    ubo::{EmptyPushConstant, UniformBuffer},
-    vertex::{EmptyVertex, Vertex},
+    ubo::{std430_size, EmptyPushConstant, UniformBuffer},
+    vertex::{EmptyVertex, Vertex, VertexInputRate},
 };
 use crate::{
     buffer::Buffer,
-    core::{commands::buffer::CommandBuffer, device::LogicalDevice},
-    descriptors::DescriptorSetLayout,
+    core::{
+        commands::buffer::{CommandBuffer, CommandBufferError, IndexType},
+        device::{DeviceExtension, LogicalDevice},
+        swapchain::{Swapchain, SwapchainDependent, SwapchainError},
+    },
+    descriptors::{
+        DescriptorError, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    },
+    render_pass::{RenderPass, RenderPassDescription},
     VulkanError,
 };
 
@@ -21,6 +28,7 @@ pub mod config;
 pub mod shader;
 pub mod ubo;
 pub mod vertex;
+pub mod watcher;
 
 /// Errors that can be thrown by the pipeline
 #[derive(thiserror::Error, Debug)]
@@ -31,12 +39,40 @@ pub enum PipelineError {
     CantCreateLayout(VulkanError),
     #[error("No render pass was set for the pipeline")]
     MissingRenderPass,
+    #[error("The pipeline targets subpass {subpass}, but the render pass only has {count} subpasses")]
+    InvalidSubpass { subpass: u32, count: usize },
+    #[error("Subpass {subpass} of the render pass references attachment {attachment}, but the render pass only has {count} attachments")]
+    InvalidAttachment {
+        subpass: u32,
+        attachment: u32,
+        count: usize,
+    },
+    #[error("The pipeline requires the '{0}' device feature, which the device doesn't support")]
+    MissingFeature(&'static str),
+    #[error("The pipeline requires the '{0}' device extension to be enabled")]
+    MissingExtension(DeviceExtension),
+    #[error("The pipeline rasterizes with {pipeline} samples, but attachment {attachment} of the subpass has {render_pass} samples")]
+    SampleCountMismatch {
+        pipeline: u32,
+        attachment: u32,
+        render_pass: u32,
+    },
+    #[error("{0} isn't a valid sample count, it must be a power of two up to 64")]
+    InvalidSampleCount(u32),
+    #[error("Primitive restart can only be enabled for strip and fan topologies")]
+    InvalidPrimitiveRestart,
     #[error("Failed to create Vulkan pipeline: {0}")]
     CantCreatePipeline(VulkanError),
     #[error("Missing shader with shader stage: {0}")]
     MissingShader(String),
     #[error("Building a shader failed: {0}")]
     ShaderError(#[from] ShaderError),
+    #[error("Failed to create a descriptor set layout: {0}")]
+    DescriptorError(#[from] DescriptorError),
+    #[error("Set {set}, binding {binding} declared in the shaders doesn't match the pipeline's descriptor set layouts")]
+    SetLayoutMismatch { set: u32, binding: u32 },
+    #[error("{} shader(s) don't match the pipeline's vertex or push constant type:\n{}", .0.len(), .0.iter().map(|err| err.to_string()).collect::<Vec<String>>().join("\n"))]
+    ShaderValidation(Vec<ShaderError>),
 }
 
 /// Allows you to create a graphics pipeline
@@ -47,10 +83,12 @@ where
 {
     /// Collection of shaders the pipeline will consist of
     shaders: Vec<Shader>,
-    /// Render pass to use for this pipeline
-    render_pass: Option<vk::RenderPass>,
+    /// Render pass to use for this pipeline, and the description it was created with
+    render_pass: Option<(vk::RenderPass, RenderPassDescription)>,
     /// Fixed function configuration
     config: PipelineConfigInfo,
+    /// Descriptor set layouts of the pipeline's layout, reflected from the shaders if empty
+    set_layouts: Vec<Rc<DescriptorSetLayout>>,
     v_phantom: PhantomData<V>,
     p_phantom: PhantomData<P>,
 }
@@ -66,6 +104,7 @@ where
             shaders: Vec::new(),
             render_pass: None,
             config: PipelineConfigInfo::default(),
+            set_layouts: Vec::new(),
             v_phantom: PhantomData,
             p_phantom: PhantomData,
         }
@@ -83,9 +122,113 @@ where
         self
     }
 
+    /// Sets the descriptor set layouts of the pipeline, ordered by set index, so descriptor sets
+    /// allocated with them can be shared between pipelines.
+    ///
+    /// Every descriptor declared in the attached shaders must be part of the layout for its set.
+    /// If no layouts are set, they are reflected from the attached shaders and can be retrieved
+    /// with [Pipeline::set_layout].
+    pub fn set_layouts(
+        mut self,
+        set_layouts: Vec<Rc<DescriptorSetLayout>>,
+    ) -> PipelineBuilder<V, P> {
+        self.set_layouts = set_layouts;
+        self
+    }
+
+    /// Sets which faces of triangles are culled
+    pub fn cull_mode(mut self, cull_mode: CullMode) -> PipelineBuilder<V, P> {
+        self.config.rasterization_info.cull_mode = cull_mode.into();
+        self
+    }
+
+    /// Sets the winding order of front facing triangles
+    pub fn front_face(mut self, front_face: FrontFace) -> PipelineBuilder<V, P> {
+        self.config.rasterization_info.front_face = front_face.into();
+        self
+    }
+
+    /// Sets how vertices are assembled into primitives, defaults to
+    /// [PrimitiveTopology::TriangleList]
+    pub fn topology(mut self, topology: PrimitiveTopology) -> PipelineBuilder<V, P> {
+        self.config.input_assembly_info.topology = topology.into();
+        self
+    }
+
+    /// Sets whether a special index value restarts the assembly of primitives when drawing
+    /// indexed strips or fans, allowing disconnected strips to be drawn in a single draw call.
+    ///
+    /// The restart index is the maximum value of the index type, see [IndexType::restart_index].
+    ///
+    /// [IndexType::restart_index]: crate::core::commands::buffer::IndexType::restart_index
+    pub fn primitive_restart(mut self, enable: bool) -> PipelineBuilder<V, P> {
+        self.config.input_assembly_info.primitive_restart_enable = enable as vk::Bool32;
+        self
+    }
+
+    /// Sets the number of samples used to rasterize each pixel for multisample anti-aliasing
+    ///
+    /// Must match the sample count of the attachments the pipeline's subpass renders to, the
+    /// highest count the device supports is given by
+    /// [PhysicalDevice::max_usable_sample_count].
+    ///
+    /// [PhysicalDevice::max_usable_sample_count]:
+    /// crate::core::device::PhysicalDevice::max_usable_sample_count
+    pub fn sample_count(mut self, samples: u32) -> PipelineBuilder<V, P> {
+        self.config.multisample_info.rasterization_samples =
+            vk::SampleCountFlags::from_raw(samples);
+        self
+    }
+
+    /// Sets the algorithm used to rasterize lines, e.g. [LineMode::Bresenham] for crisp debug
+    /// lines
+    ///
+    /// Requires [DeviceExtension::LineRasterization] and the device feature for the mode.
+    pub fn line_rasterization(mut self, line_mode: LineMode) -> PipelineBuilder<V, P> {
+        self.config.line_mode = Some(line_mode);
+        self
+    }
+
+    /// Sets whether fragment depths are clamped to the viewport's depth range instead of
+    /// primitives being clipped against the near and far planes, useful so shadow casters
+    /// behind the light's near plane still cast shadows
+    ///
+    /// Requires the `depth_clamp` device feature.
+    pub fn depth_clamp(mut self, enable: bool) -> PipelineBuilder<V, P> {
+        self.config.rasterization_info.depth_clamp_enable = enable as vk::Bool32;
+        self
+    }
+
+    /// Sets whether the depth bias is set per draw with [CommandBuffer::set_depth_bias] instead of
+    /// being baked into the pipeline, depth bias is enabled along with it
+    ///
+    /// [CommandBuffer::set_depth_bias]: crate::core::commands::buffer::CommandBuffer::set_depth_bias
+    pub fn dynamic_depth_bias(mut self, enable: bool) -> PipelineBuilder<V, P> {
+        self.config
+            .dynamic_state_enables
+            .retain(|&state| state != vk::DynamicState::DEPTH_BIAS);
+        if enable {
+            self.config
+                .dynamic_state_enables
+                .push(vk::DynamicState::DEPTH_BIAS);
+            self.config.rasterization_info.depth_bias_enable = vk::TRUE;
+        }
+        self
+    }
+
+    /// Sets the index of the subpass of the render pass the pipeline will be used in, defaults to
+    /// the first subpass
+    pub fn subpass(mut self, subpass: u32) -> PipelineBuilder<V, P> {
+        self.config.subpass = subpass;
+        self
+    }
+
     /// Sets the render pass to use for the pipeline
-    pub fn render_pass(mut self, render_pass: vk::RenderPass) -> PipelineBuilder<V, P> {
-        self.render_pass = Some(render_pass);
+    ///
+    /// The pipeline can be used with any render pass that is compatible with this one, see
+    /// [RenderPass::is_compatible_with].
+    pub fn render_pass(mut self, render_pass: &RenderPass) -> PipelineBuilder<V, P> {
+        self.render_pass = Some((render_pass.vk_handle(), render_pass.description().clone()));
         self
     }
 
@@ -93,26 +236,68 @@ where
     ///
     /// # Errors
     /// - [PipelineError::MissingShaderStage] - If a shader with [ShaderStage::Fragment] is provided then a shader with
-    /// [ShaderStage::Vertex] must also be provided.
+    ///   [ShaderStage::Vertex] must also be provided.
     /// - [PipelineError::MissingRenderPass] - You need to provide a render pass for the pipeiline
+    /// - [PipelineError::InvalidSubpass] - The subpass the pipeline targets doesn't exist in the
+    ///   render pass
+    /// - [PipelineError::MissingExtension] and [PipelineError::MissingFeature] - The
+    ///   configuration uses an extension that wasn't enabled or a feature the device doesn't
+    ///   support
+    /// - [PipelineError::InvalidPrimitiveRestart] - Primitive restart was enabled for a list
+    ///   topology
+    /// - [PipelineError::InvalidSampleCount] and [PipelineError::SampleCountMismatch] - The sample
+    ///   count isn't valid or doesn't match the attachments of the pipeline's subpass
+    /// - [PipelineError::InvalidAttachment] - The pipeline's subpass references an attachment the
+    ///   render pass doesn't have
+    /// - [PipelineError::ShaderValidation] - One or more shaders don't match the vertex or push
+    ///   constant type of the pipeline, contains every mismatch found in each shader
     /// - [PipelineError::CantCreateLayout] and [PipelineError::CantCreatePipeline] - Failed to
-    /// create required Vulkan objects, see the contained [VulkanError] for more information
+    ///   create required Vulkan objects, see the contained [VulkanError] for more information
     pub fn build(self, device: Rc<LogicalDevice>) -> Result<Pipeline<V, P>, PipelineError> {
-        if self.render_pass.is_none() {
-            return Err(PipelineError::MissingRenderPass);
+        let (render_pass, render_pass_description) = self
+            .render_pass
+            .ok_or(PipelineError::MissingRenderPass)?;
+        check_subpass(&render_pass_description, self.config.subpass)?;
+
+        check_depth_clamp(
+            &self.config.rasterization_info,
+            device.physical_device().features(),
+        )?;
+        check_primitive_restart(&self.config.input_assembly_info)?;
+
+        check_sample_count(
+            self.config.multisample_info.rasterization_samples.as_raw(),
+            &render_pass_description,
+            self.config.subpass,
+        )?;
+
+        if let Some(line_mode) = self.config.line_mode {
+            if !device
+                .physical_device()
+                .enabled_extensions()
+                .contains(&DeviceExtension::LineRasterization)
+            {
+                return Err(PipelineError::MissingExtension(
+                    DeviceExtension::LineRasterization,
+                ));
+            }
+
+            if !line_mode.is_supported(device.physical_device().line_rasterization_features()) {
+                return Err(PipelineError::MissingFeature(match line_mode {
+                    LineMode::Rectangular => "rectangular_lines",
+                    LineMode::Bresenham => "bresenham_lines",
+                    _ => "smooth_lines",
+                }));
+            }
         }
 
-        if TypeId::of::<V>() != TypeId::of::<EmptyVertex>() {
-            let vertex_shader = self
+        if TypeId::of::<V>() != TypeId::of::<EmptyVertex>()
+            && !self
                 .shaders
                 .iter()
-                .find(|&shader| shader.flags.contains(ShaderStageFlags::VERTEX));
-
-            if vertex_shader.is_none() {
-                return Err(PipelineError::MissingShader("Pipeline has a non-empty vertex type, yet no vertex shader was attached to the pipeline".to_string()));
-            } else {
-                vertex_shader.unwrap().check_vertex_input::<V>()?;
-            }
+                .any(|shader| shader.flags.contains(ShaderStageFlags::VERTEX))
+        {
+            return Err(PipelineError::MissingShader("Pipeline has a non-empty vertex type, yet no vertex shader was attached to the pipeline".to_string()));
         }
 
         if TypeId::of::<P>() != TypeId::of::<EmptyPushConstant>() {
@@ -137,18 +322,37 @@ where
                             required_shaders
                     )));
             }
+        }
 
-            for &shader in shaders.iter() {
-                shader.check_push_constant::<P>()?;
+        let mut validation_errors: Vec<ShaderError> = Vec::new();
+        for shader in self.shaders.iter() {
+            match shader.validate::<V, P>() {
+                Ok(()) => {}
+                Err(err @ ShaderError::Validation { .. }) => validation_errors.push(err),
+                Err(err) => return Err(err.into()),
             }
         }
+        if !validation_errors.is_empty() {
+            return Err(PipelineError::ShaderValidation(validation_errors));
+        }
 
         let mut shader_modules: Vec<ShaderModule> = Vec::new();
         let mut shader_stages: Vec<vk::PipelineShaderStageCreateInfo> = Vec::new();
-        let mut set_layouts: Vec<DescriptorSetLayout> = Vec::new();
+        let mut set_bindings: BTreeMap<u32, Vec<DescriptorSetLayoutBinding>> = BTreeMap::new();
         for shader in self.shaders.iter() {
             let shader_module = shader.build(device.clone())?;
-            set_layouts.append(&mut shader.get_descriptor_set_layouts(device.clone())?);
+            for descriptor in shader.descriptor_bindings()? {
+                let bindings = set_bindings.entry(descriptor.set).or_default();
+                match bindings
+                    .iter_mut()
+                    .find(|binding| binding.binding == descriptor.layout.binding)
+                {
+                    Some(binding) => {
+                        binding.shader_stage_flags |= descriptor.layout.shader_stage_flags
+                    }
+                    None => bindings.push(descriptor.layout),
+                }
+            }
 
             shader_stages.push(
                 vk::PipelineShaderStageCreateInfo::builder()
@@ -160,6 +364,7 @@ where
             shader_modules.push(shader_module);
         }
 
+        vertex::debug_assert_layout::<V>();
         let (vertex_attribute_descriptions, vertex_binding_descriptions) = {
             let vertex_attribute_descriptions: Vec<vk::VertexInputAttributeDescription> =
                 V::get_attribute_descriptions()
@@ -179,16 +384,56 @@ where
             .vertex_binding_descriptions(&vertex_binding_descriptions);
 
         let mut push_constant_ranges: Vec<vk::PushConstantRange> = Vec::new();
+        let mut push_constant_range = (ShaderStageFlags::empty(), 0);
         if TypeId::of::<P>() != TypeId::of::<EmptyPushConstant>() {
+            // The range covers the fields as they are uploaded, laid out using std430 rules like
+            // push constant blocks are by default
+            let size = std430_size(&P::get_std430_field_descriptions()) as u32;
+            push_constant_range = (P::stage(), size);
             push_constant_ranges.push(
                 vk::PushConstantRange::builder()
                     .stage_flags(P::stage().into())
                     .offset(0)
-                    .size(std::mem::size_of::<P>() as u32)
+                    .size(size)
                     .build(),
             );
         }
 
+        let set_layouts = if self.set_layouts.is_empty() {
+            // Sets the shaders don't use still need a layout, so that set indices line up
+            let set_count = set_bindings.keys().next_back().map_or(0, |&set| set + 1);
+            let mut set_layouts: Vec<Rc<DescriptorSetLayout>> =
+                Vec::with_capacity(set_count as usize);
+            for set in 0..set_count {
+                let bindings = set_bindings.get(&set).map_or(&[][..], |b| b.as_slice());
+                set_layouts.push(Rc::new(DescriptorSetLayout::new(device.clone(), bindings)?));
+            }
+            set_layouts
+        } else {
+            for (&set, bindings) in set_bindings.iter() {
+                for binding in bindings.iter() {
+                    let matches = self
+                        .set_layouts
+                        .get(set as usize)
+                        .and_then(|layout| layout.binding(binding.binding))
+                        .is_some_and(|layout_binding| {
+                            Into::<vk::DescriptorType>::into(layout_binding.ty) == binding.ty.into()
+                                && layout_binding.count >= binding.count
+                                && layout_binding
+                                    .shader_stage_flags
+                                    .contains(binding.shader_stage_flags)
+                        });
+                    if !matches {
+                        return Err(PipelineError::SetLayoutMismatch {
+                            set,
+                            binding: binding.binding,
+                        });
+                    }
+                }
+            }
+            self.set_layouts
+        };
+
         let vk_set_layouts: Vec<vk::DescriptorSetLayout> = set_layouts
             .iter()
             .map(|layout| layout.vk_handle())
@@ -205,18 +450,31 @@ where
                 .map_err(|err| PipelineError::CantCreateLayout(err.into()))?
         };
 
+        let line_state_info = line_state_info(self.config.line_mode);
+        let mut rasterization_info = self.config.rasterization_info;
+        if let Some(line_state_info) = line_state_info.as_ref() {
+            rasterization_info.p_next = line_state_info as *const _ as *const std::ffi::c_void;
+        }
+
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&self.config.dynamic_state_enables);
+        let dynamic_depth_bias = self
+            .config
+            .dynamic_state_enables
+            .contains(&vk::DynamicState::DEPTH_BIAS);
+
         let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stages)
             .vertex_input_state(&vertex_input_info)
             .input_assembly_state(&self.config.input_assembly_info)
             .viewport_state(&self.config.viewport_info)
-            .rasterization_state(&self.config.rasterization_info)
+            .rasterization_state(&rasterization_info)
             .multisample_state(&self.config.multisample_info)
             .color_blend_state(&self.config.color_blend_info)
             .depth_stencil_state(&self.config.depth_stencil_info)
-            .dynamic_state(&self.config.dynamic_state_info)
+            .dynamic_state(&dynamic_state_info)
             .layout(layout)
-            .render_pass(self.render_pass.unwrap())
+            .render_pass(render_pass)
             .subpass(self.config.subpass);
 
         let handle = unsafe {
@@ -232,6 +490,13 @@ where
 
         Ok(Pipeline {
             _shader_modules: shader_modules,
+            shader_paths: self.shaders.iter().map(|shader| shader.file_path).collect(),
+            config: self.config,
+            built_at: Instant::now(),
+            render_pass: render_pass_description,
+            push_constant_range,
+            dynamic_depth_bias,
+            set_layouts,
             layout,
             handle,
             device,
@@ -241,6 +506,96 @@ where
     }
 }
 
+/// Returns the line rasterization state to chain onto the rasterization state, if a `line_mode`
+/// is set
+fn line_state_info(
+    line_mode: Option<LineMode>,
+) -> Option<vk::PipelineRasterizationLineStateCreateInfoEXT> {
+    line_mode.map(|line_mode| {
+        vk::PipelineRasterizationLineStateCreateInfoEXT::builder()
+            .line_rasterization_mode(line_mode.into())
+            .build()
+    })
+}
+
+/// Checks that `subpass` exists in the render pass with `description`
+fn check_subpass(description: &RenderPassDescription, subpass: u32) -> Result<(), PipelineError> {
+    if subpass as usize >= description.subpasses.len() {
+        return Err(PipelineError::InvalidSubpass {
+            subpass,
+            count: description.subpasses.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that depth clamping is only enabled in `rasterization_info` if the device supports the
+/// `depth_clamp` feature
+fn check_depth_clamp(
+    rasterization_info: &vk::PipelineRasterizationStateCreateInfo,
+    features: &vk::PhysicalDeviceFeatures,
+) -> Result<(), PipelineError> {
+    if rasterization_info.depth_clamp_enable == vk::TRUE && features.depth_clamp != vk::TRUE {
+        return Err(PipelineError::MissingFeature("depth_clamp"));
+    }
+
+    Ok(())
+}
+
+/// Checks that primitive restart is only enabled in `input_assembly_info` for a topology that
+/// [supports it][PrimitiveTopology::supports_primitive_restart]
+fn check_primitive_restart(
+    input_assembly_info: &vk::PipelineInputAssemblyStateCreateInfo,
+) -> Result<(), PipelineError> {
+    if input_assembly_info.primitive_restart_enable == vk::TRUE
+        && !PrimitiveTopology::try_from(input_assembly_info.topology)
+            .is_ok_and(|topology| topology.supports_primitive_restart())
+    {
+        return Err(PipelineError::InvalidPrimitiveRestart);
+    }
+
+    Ok(())
+}
+
+/// Checks that `samples` is a valid sample count matching every attachment `subpass` of the
+/// render pass with `description` renders to, skipping unused attachment references
+fn check_sample_count(
+    samples: u32,
+    description: &RenderPassDescription,
+    subpass: u32,
+) -> Result<(), PipelineError> {
+    if !samples.is_power_of_two() || samples > 64 {
+        return Err(PipelineError::InvalidSampleCount(samples));
+    }
+
+    check_subpass(description, subpass)?;
+    let subpass_description = &description.subpasses[subpass as usize];
+    for &attachment in subpass_description
+        .color_attachments
+        .iter()
+        .chain(subpass_description.depth_stencil_attachment.iter())
+        .filter(|&&attachment| attachment != vk::ATTACHMENT_UNUSED)
+    {
+        let attachment_description = description.attachments.get(attachment as usize).ok_or(
+            PipelineError::InvalidAttachment {
+                subpass,
+                attachment,
+                count: description.attachments.len(),
+            },
+        )?;
+        if attachment_description.samples.as_raw() != samples {
+            return Err(PipelineError::SampleCountMismatch {
+                pipeline: samples,
+                attachment,
+                render_pass: attachment_description.samples.as_raw(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
 /// Represents a Graphics pipeline that can be used to draw to a surface
 pub struct Pipeline<V, P>
 where
@@ -249,6 +604,20 @@ where
 {
     /// List of the shader modules being used by the [Pipeline]
     _shader_modules: Vec<ShaderModule>,
+    /// File paths of the shaders the pipeline was created from
+    shader_paths: Vec<&'static str>,
+    /// Fixed function configuration the pipeline was created with
+    config: PipelineConfigInfo,
+    /// When the pipeline's shaders were loaded
+    built_at: Instant,
+    /// Description of the render pass the pipeline was created with
+    render_pass: RenderPassDescription,
+    /// Shader stages and size, in bytes, of the push constant range in the pipeline's layout
+    push_constant_range: (ShaderStageFlags, u32),
+    /// Whether the pipeline was created with dynamic depth bias
+    dynamic_depth_bias: bool,
+    /// Descriptor set layouts of the pipeline's layout, ordered by set index
+    set_layouts: Vec<Rc<DescriptorSetLayout>>,
     /// Opaque handle to Vulkan layout used to create the pipeline
     layout: vk::PipelineLayout,
     /// Opaque handle to Vulkan Pipeline
@@ -268,6 +637,30 @@ where
     pub fn builder() -> PipelineBuilder<V, P> {
         PipelineBuilder::new()
     }
+
+    /// Recreates the pipeline with the same configuration and descriptor set layouts, reloading
+    /// its shaders from disk, for use with `render_pass`.
+    ///
+    /// The pipeline is only replaced if every shader loads and the new pipeline is built
+    /// successfully, otherwise it is left untouched. It must not be in use by the device, see
+    /// [ShaderWatcher][watcher::ShaderWatcher] to rebuild pipelines when their shaders change.
+    ///
+    /// # Errors
+    /// Any error returned by [PipelineBuilder::build], including
+    /// [PipelineError::SetLayoutMismatch] if the reloaded shaders declare descriptors that aren't
+    /// part of the pipeline's descriptor set layouts
+    pub fn rebuild(&mut self, render_pass: &RenderPass) -> Result<(), PipelineError> {
+        let mut builder = PipelineBuilder::<V, P>::new()
+            .config(self.config.clone())
+            .set_layouts(self.set_layouts.clone())
+            .render_pass(render_pass);
+        for &file_path in self.shader_paths.iter() {
+            builder = builder.attach_shader(Shader::new(file_path)?);
+        }
+
+        *self = builder.build(self.device.clone())?;
+        Ok(())
+    }
 }
 
 impl<V, P> Pipeline<V, P>
@@ -279,6 +672,52 @@ where
     pub(crate) fn vk_handle(&self) -> vk::Pipeline {
         self.handle
     }
+
+    /// Returns the handle to the Vulkan layout the pipeline was created with
+    pub(crate) fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    /// Returns the file paths of the shaders the pipeline was created from
+    pub fn shader_paths(&self) -> &[&'static str] {
+        &self.shader_paths
+    }
+
+    /// Returns when the pipeline's shaders were loaded from disk
+    pub fn built_at(&self) -> Instant {
+        self.built_at
+    }
+
+    /// Returns the shader stages and size, in bytes, of the pipeline's push constant range
+    pub fn push_constant_range(&self) -> (ShaderStageFlags, u32) {
+        self.push_constant_range
+    }
+
+    /// Returns whether the depth bias is set per draw with
+    /// [CommandBuffer::set_depth_bias][crate::core::commands::buffer::CommandBuffer::set_depth_bias]
+    pub fn has_dynamic_depth_bias(&self) -> bool {
+        self.dynamic_depth_bias
+    }
+
+    /// Returns the layout of descriptor set `set` in the pipeline's layout, which descriptor
+    /// sets bound to the pipeline at `set` should be allocated with
+    pub fn set_layout(&self, set: u32) -> Option<Rc<DescriptorSetLayout>> {
+        self.set_layouts.get(set as usize).cloned()
+    }
+
+    /// Returns the descriptor set layouts of the pipeline's layout, ordered by set index
+    pub fn set_layouts(&self) -> &[Rc<DescriptorSetLayout>] {
+        &self.set_layouts
+    }
+
+    /// Checks whether the pipeline can be used with `render_pass`, which is the case if it is
+    /// compatible with the render pass the pipeline was created with.
+    ///
+    /// Useful to check a pipeline is still valid after the render pass it was created with was
+    /// recreated, e.g. when the [Swapchain][crate::core::swapchain::Swapchain] is resized.
+    pub fn is_compatible_with(&self, render_pass: &RenderPass) -> bool {
+        self.render_pass.is_compatible_with(render_pass.description())
+    }
 }
 
 impl<V, P> Pipeline<V, P>
@@ -286,56 +725,69 @@ where
     V: Vertex,
     P: UniformBuffer,
 {
-    pub fn bind_push_constant(&self, command_buffer: &CommandBuffer, data: P) {
-        match command_buffer.currently_bound_pipeline() {
-            Some(pipeline) => {
-                if pipeline != self.handle {
-                    log::warn!("A different pipeline is bound so can't bind this pipeline's push constants");
-                    return;
-                }
-            }
-            None => {
-                log::warn!("No pipeline is bound so this pipeline's push constants can't be bound");
-                return;
-            }
-        }
+    /// Uploads `data` as the pipeline's push constants, must be called after the pipeline has
+    /// been bound
+    ///
+    /// # Errors
+    /// See [CommandBuffer::push_constants]
+    pub fn bind_push_constant(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        data: P,
+    ) -> Result<(), CommandBufferError> {
+        command_buffer.push_constants(self, P::stage(), 0, &data)
+    }
 
-        unsafe {
-            self.device.vk_handle().cmd_push_constants(
-                command_buffer.vk_handle(),
-                self.layout,
-                P::stage().into(),
-                0,
-                data.as_bytes(),
-            );
-        };
+    /// Binds `sets` starting at set index 0, the layout of every descriptor set must be
+    /// compatible with the pipeline's layout for its set
+    ///
+    /// # Errors
+    /// - [CommandBufferError::IncompatibleDescriptorSet] - A descriptor set's layout isn't
+    ///   compatible with the pipeline's layout for its set
+    /// - [CommandBufferError::NoPipelineBound] and [CommandBufferError::PipelineMismatch] - The
+    ///   pipeline hasn't been bound
+    pub fn bind_descriptor_sets(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        sets: &[&DescriptorSet],
+    ) -> Result<(), CommandBufferError> {
+        self.check_descriptor_sets(0, sets)?;
+        unsafe { command_buffer.bind_descriptor_sets(self, 0, sets, &[]) }
     }
 
-    /// FIXME: check descriptor sets are defined by this pipeline
-    pub fn bind_descriptor_sets(&self, command_buffer: &CommandBuffer, sets: &[vk::DescriptorSet]) {
-        match command_buffer.currently_bound_pipeline() {
-            Some(pipeline) => {
-                if pipeline != self.handle {
-                    log::warn!("A different pipeline is bound so can't bind this pipeline's descriptor sets");
-                    return;
-                }
-            }
-            None => {
-                log::warn!("No pipeline is bound so this pipeline's descriptor sets can't be bound");
-                return;
+    /// Binds `descriptor_set` at set index `set`, the descriptor set's layout must be compatible
+    /// with the pipeline's layout for that set
+    ///
+    /// # Errors
+    /// See [Pipeline::bind_descriptor_sets]
+    pub fn bind_descriptor_set(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        set: u32,
+        descriptor_set: &DescriptorSet,
+    ) -> Result<(), CommandBufferError> {
+        self.check_descriptor_sets(set, &[descriptor_set])?;
+        unsafe { command_buffer.bind_descriptor_sets(self, set, &[descriptor_set], &[]) }
+    }
+
+    /// Checks that the layout of every descriptor set in `sets`, bound starting at set index
+    /// `first_set`, is compatible with the pipeline's layout for its set
+    fn check_descriptor_sets(
+        &self,
+        first_set: u32,
+        sets: &[&DescriptorSet],
+    ) -> Result<(), CommandBufferError> {
+        for (set, descriptor_set) in (first_set..).zip(sets.iter()) {
+            let compatible = self
+                .set_layouts
+                .get(set as usize)
+                .is_some_and(|layout| layout.is_compatible_with(descriptor_set.layout()));
+            if !compatible {
+                return Err(CommandBufferError::IncompatibleDescriptorSet(set));
             }
         }
 
-        unsafe {
-            self.device.vk_handle().cmd_bind_descriptor_sets(
-                command_buffer.vk_handle(),
-                vk::PipelineBindPoint::GRAPHICS,
-                self.layout,
-                0,
-                sets,
-                &[],
-            );
-        };
+        Ok(())
     }
 
     pub fn bind(&self, command_buffer: &mut CommandBuffer) {
@@ -346,11 +798,11 @@ where
         &self,
         command_buffer: &mut CommandBuffer,
         vertex_buffer: &Buffer<V, CAPACITY>,
-    ) {
+    ) -> Result<(), CommandBufferError> {
         unsafe {
-            command_buffer.bind_vertex_buffer(vertex_buffer);
-            command_buffer.draw(CAPACITY as u32, 1, 0, 0);
-        };
+            command_buffer.bind_vertex_buffer(vertex_buffer)?;
+            command_buffer.draw(CAPACITY as u32, 1, 0, 0)
+        }
     }
 
     pub fn draw_indexed<const V_CAPACITY: usize, const I_CAPACITY: usize>(
@@ -358,12 +810,143 @@ where
         command_buffer: &mut CommandBuffer,
         vertex_buffer: &Buffer<V, V_CAPACITY>,
         index_buffer: &Buffer<u32, I_CAPACITY>,
-    ) {
+    ) -> Result<(), CommandBufferError> {
         unsafe {
-            command_buffer.bind_vertex_buffer(vertex_buffer);
-            command_buffer.bind_index_buffer(index_buffer);
-            command_buffer.draw_indexed(I_CAPACITY as u32, 1, 0, 0, 0);
-        };
+            command_buffer.bind_vertex_buffer(vertex_buffer)?;
+            command_buffer.bind_index_buffer(index_buffer, IndexType::U32)?;
+            command_buffer.draw_indexed(I_CAPACITY as u32, 1, 0, 0, 0)
+        }
+    }
+}
+
+impl<V, P> Pipeline<V, P>
+where
+    V: Vertex,
+    P: UniformBuffer,
+{
+    /// Draws `instance_count` instances of the vertices in `vertex_buffer`, reading per instance
+    /// attributes from `instance_buffer`, e.g. to draw a forest of trees with a transform per
+    /// tree in a single draw call.
+    ///
+    /// The instance buffer is bound to the first binding of the pipeline's vertex type with an
+    /// instance input rate, e.g. fields declared with `#[binding(1, rate = "instance")]`, and the
+    /// vertex buffer to the first binding with a vertex input rate.
+    ///
+    /// # Errors
+    /// - [CommandBufferError::NoInstanceBinding] - The vertex type has no instance binding
+    /// - [CommandBufferError::VertexStrideMismatch] - The elements of a buffer don't match the
+    ///   stride of the binding it is bound to
+    /// - [CommandBufferError::InstanceCountOutOfRange] - `instance_count` is larger than the
+    ///   capacity of `instance_buffer`
+    pub fn draw_instanced<T, I, const V_CAPACITY: usize, const I_CAPACITY: usize>(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        vertex_buffer: &Buffer<T, V_CAPACITY>,
+        instance_buffer: &Buffer<I, I_CAPACITY>,
+        instance_count: u32,
+    ) -> Result<(), CommandBufferError> {
+        unsafe {
+            self.bind_instanced(
+                command_buffer,
+                vertex_buffer,
+                instance_buffer,
+                instance_count,
+            )?;
+            command_buffer.draw(V_CAPACITY as u32, instance_count, 0, 0)
+        }
+    }
+
+    /// Draws `instance_count` instances of the indexed vertices in `vertex_buffer`, reading per
+    /// instance attributes from `instance_buffer`, see [Pipeline::draw_instanced]
+    pub fn draw_indexed_instanced<
+        T,
+        I,
+        const V_CAPACITY: usize,
+        const I_CAPACITY: usize,
+        const INDEX_CAPACITY: usize,
+    >(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        vertex_buffer: &Buffer<T, V_CAPACITY>,
+        index_buffer: &Buffer<u32, INDEX_CAPACITY>,
+        instance_buffer: &Buffer<I, I_CAPACITY>,
+        instance_count: u32,
+    ) -> Result<(), CommandBufferError> {
+        unsafe {
+            self.bind_instanced(
+                command_buffer,
+                vertex_buffer,
+                instance_buffer,
+                instance_count,
+            )?;
+            command_buffer.bind_index_buffer(index_buffer, IndexType::U32)?;
+            command_buffer.draw_indexed(INDEX_CAPACITY as u32, instance_count, 0, 0, 0)
+        }
+    }
+
+    /// Checks the buffers match the pipeline's vertex bindings and binds them for an instanced
+    /// draw
+    ///
+    /// # Safety
+    /// The buffers must outlive the execution of the command buffer
+    unsafe fn bind_instanced<T, I, const V_CAPACITY: usize, const I_CAPACITY: usize>(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        vertex_buffer: &Buffer<T, V_CAPACITY>,
+        instance_buffer: &Buffer<I, I_CAPACITY>,
+        instance_count: u32,
+    ) -> Result<(), CommandBufferError> {
+        let bindings = V::get_binding_descriptions();
+        let instance_binding = bindings
+            .iter()
+            .find(|binding| binding.input_rate == VertexInputRate::Instance)
+            .ok_or(CommandBufferError::NoInstanceBinding)?;
+        if instance_count as usize > I_CAPACITY {
+            return Err(CommandBufferError::InstanceCountOutOfRange {
+                count: instance_count,
+                capacity: I_CAPACITY,
+            });
+        }
+
+        let vertex_binding = bindings
+            .iter()
+            .find(|binding| binding.input_rate == VertexInputRate::Vertex);
+        for (binding, size) in [
+            (Some(instance_binding), std::mem::size_of::<I>()),
+            (vertex_binding, std::mem::size_of::<T>()),
+        ] {
+            match binding {
+                Some(binding) if binding.stride as usize != size => {
+                    return Err(CommandBufferError::VertexStrideMismatch {
+                        binding: binding.binding,
+                        stride: binding.stride,
+                        size,
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(vertex_binding) = vertex_binding {
+            command_buffer.bind_vertex_buffer_at(vertex_binding.binding, vertex_buffer)?;
+        }
+        command_buffer.bind_vertex_buffer_at(instance_binding.binding, instance_buffer)
+    }
+}
+
+/// Rebuilds the pipeline for the swapchain's render pass if it is no longer compatible with the
+/// render pass the pipeline was created with
+impl<V: 'static, P: 'static> SwapchainDependent for Pipeline<V, P>
+where
+    V: Vertex,
+    P: UniformBuffer,
+{
+    fn swapchain_recreated(&mut self, swapchain: &Swapchain) -> Result<(), SwapchainError> {
+        if !self.is_compatible_with(swapchain.render_pass()) {
+            self.rebuild(swapchain.render_pass())?;
+        }
+
+        Ok(())
     }
 }
 
@@ -381,3 +964,178 @@ where
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_pass::Subpass;
+
+    /// Describes a render pass with a single subpass drawing to a color attachment with
+    /// `samples` samples
+    fn multisampled_pass(samples: vk::SampleCountFlags) -> RenderPassDescription {
+        RenderPassDescription {
+            attachments: vec![vk::AttachmentDescription::builder()
+                .format(vk::Format::B8G8R8A8_SRGB)
+                .samples(samples)
+                .build()],
+            subpasses: vec![Subpass {
+                color_attachments: vec![0],
+                ..Default::default()
+            }],
+            dependencies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn existing_subpass_is_allowed() {
+        let render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_1);
+
+        assert!(check_subpass(&render_pass, 0).is_ok());
+    }
+
+    #[test]
+    fn out_of_range_subpass_errors() {
+        let render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_1);
+
+        assert!(matches!(
+            check_subpass(&render_pass, 1),
+            Err(PipelineError::InvalidSubpass {
+                subpass: 1,
+                count: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn depth_clamp_with_the_feature_is_allowed() {
+        let config = PipelineBuilder::<EmptyVertex, EmptyPushConstant>::new()
+            .depth_clamp(true)
+            .config;
+        let features = vk::PhysicalDeviceFeatures {
+            depth_clamp: vk::TRUE,
+            ..Default::default()
+        };
+
+        assert!(check_depth_clamp(&config.rasterization_info, &features).is_ok());
+    }
+
+    #[test]
+    fn depth_clamp_without_the_feature_errors() {
+        let config = PipelineBuilder::<EmptyVertex, EmptyPushConstant>::new()
+            .depth_clamp(true)
+            .config;
+
+        assert!(matches!(
+            check_depth_clamp(&config.rasterization_info, &Default::default()),
+            Err(PipelineError::MissingFeature("depth_clamp"))
+        ));
+    }
+
+    #[test]
+    fn disabled_depth_clamp_without_the_feature_is_allowed() {
+        let config = PipelineBuilder::<EmptyVertex, EmptyPushConstant>::new().config;
+
+        assert!(check_depth_clamp(&config.rasterization_info, &Default::default()).is_ok());
+    }
+
+    #[test]
+    fn primitive_restart_with_a_strip_topology_is_allowed() {
+        let config = PipelineBuilder::<EmptyVertex, EmptyPushConstant>::new()
+            .topology(PrimitiveTopology::TriangleStrip)
+            .primitive_restart(true)
+            .config;
+
+        assert!(check_primitive_restart(&config.input_assembly_info).is_ok());
+    }
+
+    #[test]
+    fn primitive_restart_with_a_list_topology_errors() {
+        let config = PipelineBuilder::<EmptyVertex, EmptyPushConstant>::new()
+            .topology(PrimitiveTopology::TriangleList)
+            .primitive_restart(true)
+            .config;
+
+        assert!(matches!(
+            check_primitive_restart(&config.input_assembly_info),
+            Err(PipelineError::InvalidPrimitiveRestart)
+        ));
+    }
+
+    #[test]
+    fn bresenham_lines_produce_the_line_rasterization_state() {
+        let config = PipelineBuilder::<EmptyVertex, EmptyPushConstant>::new()
+            .line_rasterization(LineMode::Bresenham)
+            .config;
+        let line_state_info = line_state_info(config.line_mode).unwrap();
+
+        assert_eq!(
+            line_state_info.s_type,
+            vk::StructureType::PIPELINE_RASTERIZATION_LINE_STATE_CREATE_INFO_EXT
+        );
+        assert_eq!(
+            line_state_info.line_rasterization_mode,
+            vk::LineRasterizationModeEXT::BRESENHAM
+        );
+    }
+
+    #[test]
+    fn no_line_mode_leaves_out_the_line_rasterization_state() {
+        assert!(line_state_info(None).is_none());
+    }
+
+    #[test]
+    fn matching_sample_count_is_allowed() {
+        let render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_4);
+
+        assert!(check_sample_count(4, &render_pass, 0).is_ok());
+    }
+
+    #[test]
+    fn single_sample_pipeline_against_multisampled_render_pass_errors() {
+        let render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_4);
+
+        assert!(matches!(
+            check_sample_count(1, &render_pass, 0),
+            Err(PipelineError::SampleCountMismatch {
+                pipeline: 1,
+                attachment: 0,
+                render_pass: 4,
+            })
+        ));
+    }
+
+    #[test]
+    fn unused_attachment_references_are_skipped() {
+        let mut render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_4);
+        render_pass.subpasses[0]
+            .color_attachments
+            .push(vk::ATTACHMENT_UNUSED);
+
+        assert!(check_sample_count(4, &render_pass, 0).is_ok());
+    }
+
+    #[test]
+    fn out_of_range_attachment_reference_errors() {
+        let mut render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_4);
+        render_pass.subpasses[0].depth_stencil_attachment = Some(1);
+
+        assert!(matches!(
+            check_sample_count(4, &render_pass, 0),
+            Err(PipelineError::InvalidAttachment {
+                subpass: 0,
+                attachment: 1,
+                count: 1,
+            })
+        ));
+    }
+
+    #[test]
+    fn non_power_of_two_sample_count_errors() {
+        let render_pass = multisampled_pass(vk::SampleCountFlags::TYPE_4);
+
+        assert!(matches!(
+            check_sample_count(3, &render_pass, 0),
+            Err(PipelineError::InvalidSampleCount(3))
+        ));
+    }
+}
@@ -1,7 +1,139 @@
 use ash::vk;
 use std::rc::Rc;
 
+/// Possible faces of a triangle to cull
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl Into<vk::CullModeFlags> for CullMode {
+    fn into(self) -> vk::CullModeFlags {
+        match self {
+            CullMode::None => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK,
+            CullMode::FrontAndBack => vk::CullModeFlags::FRONT_AND_BACK,
+        }
+    }
+}
+
+/// Possible winding orders of a front facing triangle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Into<vk::FrontFace> for FrontFace {
+    fn into(self) -> vk::FrontFace {
+        match self {
+            FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+            FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+        }
+    }
+}
+
+/// Possible ways of assembling vertices into primitives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrimitiveTopology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl PrimitiveTopology {
+    /// Returns whether primitive restart can be enabled with this topology, which is only the
+    /// case for strips and fans
+    pub fn supports_primitive_restart(&self) -> bool {
+        matches!(
+            self,
+            PrimitiveTopology::LineStrip
+                | PrimitiveTopology::TriangleStrip
+                | PrimitiveTopology::TriangleFan
+        )
+    }
+}
+
+impl Into<vk::PrimitiveTopology> for PrimitiveTopology {
+    fn into(self) -> vk::PrimitiveTopology {
+        match self {
+            PrimitiveTopology::PointList => vk::PrimitiveTopology::POINT_LIST,
+            PrimitiveTopology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            PrimitiveTopology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            PrimitiveTopology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            PrimitiveTopology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            PrimitiveTopology::TriangleFan => vk::PrimitiveTopology::TRIANGLE_FAN,
+        }
+    }
+}
+
+impl TryFrom<vk::PrimitiveTopology> for PrimitiveTopology {
+    type Error = vk::PrimitiveTopology;
+
+    /// Fails with the Vulkan topology if it has no [PrimitiveTopology] equivalent, e.g. topologies
+    /// with adjacency
+    fn try_from(topology: vk::PrimitiveTopology) -> Result<PrimitiveTopology, Self::Error> {
+        match topology {
+            vk::PrimitiveTopology::POINT_LIST => Ok(PrimitiveTopology::PointList),
+            vk::PrimitiveTopology::LINE_LIST => Ok(PrimitiveTopology::LineList),
+            vk::PrimitiveTopology::LINE_STRIP => Ok(PrimitiveTopology::LineStrip),
+            vk::PrimitiveTopology::TRIANGLE_LIST => Ok(PrimitiveTopology::TriangleList),
+            vk::PrimitiveTopology::TRIANGLE_STRIP => Ok(PrimitiveTopology::TriangleStrip),
+            vk::PrimitiveTopology::TRIANGLE_FAN => Ok(PrimitiveTopology::TriangleFan),
+            topology => Err(topology),
+        }
+    }
+}
+
+/// Possible algorithms for rasterizing lines, requires
+/// [DeviceExtension::LineRasterization][crate::core::device::DeviceExtension::LineRasterization]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineMode {
+    /// Lines are rasterized as parallelograms, or rectangles if the device has strict lines
+    Default,
+    /// Lines are rasterized as rectangles
+    Rectangular,
+    /// Lines are rasterized with the Bresenham algorithm, giving crisp one pixel wide lines
+    Bresenham,
+    /// Lines are rasterized as antialiased rectangles
+    RectangularSmooth,
+}
+
+impl LineMode {
+    /// Returns whether the device supports rasterizing lines with this mode
+    pub(crate) fn is_supported(
+        &self,
+        features: &vk::PhysicalDeviceLineRasterizationFeaturesEXT,
+    ) -> bool {
+        match self {
+            LineMode::Default => true,
+            LineMode::Rectangular => features.rectangular_lines == vk::TRUE,
+            LineMode::Bresenham => features.bresenham_lines == vk::TRUE,
+            LineMode::RectangularSmooth => features.smooth_lines == vk::TRUE,
+        }
+    }
+}
+
+impl Into<vk::LineRasterizationModeEXT> for LineMode {
+    fn into(self) -> vk::LineRasterizationModeEXT {
+        match self {
+            LineMode::Default => vk::LineRasterizationModeEXT::DEFAULT,
+            LineMode::Rectangular => vk::LineRasterizationModeEXT::RECTANGULAR,
+            LineMode::Bresenham => vk::LineRasterizationModeEXT::BRESENHAM,
+            LineMode::RectangularSmooth => vk::LineRasterizationModeEXT::RECTANGULAR_SMOOTH,
+        }
+    }
+}
+
 /// Wraps fixed function configuration for creating a Vulkan graphics pipeline
+#[derive(Clone)]
 pub struct PipelineConfigInfo {
     pub viewport_info: vk::PipelineViewportStateCreateInfo,
     pub input_assembly_info: vk::PipelineInputAssemblyStateCreateInfo,
@@ -13,6 +145,8 @@ pub struct PipelineConfigInfo {
     pub dynamic_state_enables: Vec<vk::DynamicState>,
     pub dynamic_state_info: vk::PipelineDynamicStateCreateInfo,
     pub subpass: u32,
+    /// Algorithm to rasterize lines with, `None` leaves it up to the device
+    pub line_mode: Option<LineMode>,
 }
 
 impl Default for PipelineConfigInfo {
@@ -106,6 +240,95 @@ impl Default for PipelineConfigInfo {
             dynamic_state_enables,
             dynamic_state_info,
             subpass: 0,
+            line_mode: None,
+        }
+    }
+}
+
+impl PipelineConfigInfo {
+    /// Creates a configuration for rendering a shadow map into a depth-only render pass
+    ///
+    /// Front faces are culled and depth bias is enabled to reduce shadow acne and peter-panning,
+    /// and no color attachments are written to.
+    pub fn shadow() -> PipelineConfigInfo {
+        let mut config = PipelineConfigInfo::default();
+
+        config.rasterization_info.cull_mode = CullMode::Front.into();
+        config.rasterization_info.depth_bias_enable = vk::TRUE;
+        config.rasterization_info.depth_bias_constant_factor = 1.25;
+        config.rasterization_info.depth_bias_slope_factor = 1.75;
+
+        config.color_blend_info.attachment_count = 0;
+        config.color_blend_info.p_attachments = std::ptr::null();
+
+        config
+    }
+}
+
+impl PipelineConfigInfo {
+    /// Creates the recommended configuration for meshes with counter-clockwise winding, culling
+    /// back faces
+    ///
+    /// Meshes authored for OpenGL-style conventions wind their front faces counter-clockwise with
+    /// Y pointing up, whereas Vulkan's clip space has Y pointing down. Pair this preset with a
+    /// projection that flips Y, e.g. by negating `projection.y_axis.y` of a
+    /// `glam::Mat4::perspective_rh` matrix, so front faces keep their winding on screen instead
+    /// of being culled and leaving a blank screen.
+    pub fn vulkan_y_flip_preset() -> PipelineConfigInfo {
+        let mut config = PipelineConfigInfo::default();
+
+        config.rasterization_info.front_face = FrontFace::CounterClockwise.into();
+        config.rasterization_info.cull_mode = CullMode::Back.into();
+
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadow_preset_culls_front_faces_with_depth_bias() {
+        let config = PipelineConfigInfo::shadow();
+
+        assert_eq!(
+            config.rasterization_info.cull_mode,
+            vk::CullModeFlags::FRONT
+        );
+        assert_eq!(config.rasterization_info.depth_bias_enable, vk::TRUE);
+        assert!(config.rasterization_info.depth_bias_constant_factor > 0.0);
+        assert!(config.rasterization_info.depth_bias_slope_factor > 0.0);
+        assert_eq!(config.color_blend_info.attachment_count, 0);
+    }
+
+    #[test]
+    fn topologies_convert_back_from_vulkan() {
+        for topology in [
+            PrimitiveTopology::PointList,
+            PrimitiveTopology::LineList,
+            PrimitiveTopology::LineStrip,
+            PrimitiveTopology::TriangleList,
+            PrimitiveTopology::TriangleStrip,
+            PrimitiveTopology::TriangleFan,
+        ] {
+            let raw: vk::PrimitiveTopology = topology.into();
+            assert_eq!(PrimitiveTopology::try_from(raw), Ok(topology));
         }
+        assert_eq!(
+            PrimitiveTopology::try_from(vk::PrimitiveTopology::PATCH_LIST),
+            Err(vk::PrimitiveTopology::PATCH_LIST)
+        );
+    }
+
+    #[test]
+    fn vulkan_y_flip_preset_culls_clockwise_faces() {
+        let config = PipelineConfigInfo::vulkan_y_flip_preset();
+
+        assert_eq!(
+            config.rasterization_info.front_face,
+            vk::FrontFace::COUNTER_CLOCKWISE
+        );
+        assert_eq!(config.rasterization_info.cull_mode, vk::CullModeFlags::BACK);
     }
 }
@@ -2,8 +2,18 @@ extern crate spirv_reflect;
 
 use ash::vk;
 use bitflags::bitflags;
-use spirv_reflect::{types::ReflectFormat, ShaderModule as SpirvShader};
-use std::{ffi::CString, fmt::Debug, rc::Rc};
+use spirv_reflect::{
+    types::{ReflectBlockVariable, ReflectDescriptorType, ReflectFormat},
+    ShaderModule as SpirvShader,
+};
+use std::{
+    any::TypeId,
+    collections::BTreeMap,
+    ffi::CString,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
     core::device::LogicalDevice,
@@ -12,29 +22,267 @@ use crate::{
 };
 
 use super::{
-    ubo::{UboFieldDescription, UniformBuffer},
-    vertex::{Vertex, VertexAttributeDescription},
+    ubo::{std140_offsets, std430_offsets, EmptyPushConstant, UboFieldDescription, UniformBuffer},
+    vertex::{
+        attribute_size, EmptyVertex, Vertex, VertexAttributeDescription, VertexBindingDescription,
+    },
 };
 
 /// Possible errors that could be returned by a [Shader]
 #[derive(thiserror::Error, Debug)]
 pub enum ShaderError {
-    #[error("The shader file could not be found")]
-    FileNotFound,
-    #[error("Failed to read the contents of the file")]
-    CantRead,
-    #[error("Failed to parse the shader spirv")]
-    CantParseSpv(String),
+    #[error("The shader file `{0}` could not be found")]
+    FileNotFound(String),
+    #[error("Failed to read the contents of `{path}`: {message}")]
+    CantRead { path: String, message: String },
+    #[error("Failed to parse the shader spirv in `{path}`: {message}")]
+    Parse { path: String, message: String },
     #[error("Can't create a shader as its shader stage is not supported")]
     UnsupportedShaderStage,
     #[error("Failed to create a Vulkan shader module {0}")]
     BuildFail(VulkanError),
     #[error("Invalid shader definition: {0}")]
     InvalidDefinition(String),
+    #[error("Shader `{path}` doesn't match the types it is being used with:\n{}", display_validation_errors(.errors))]
+    Validation {
+        path: String,
+        errors: Vec<ValidationError>,
+    },
     #[error(transparent)]
     DescriptorError(#[from] DescriptorError),
 }
 
+/// A mismatch between a [Shader] and the vertex or push constant type it is being used with
+#[derive(thiserror::Error, Debug)]
+pub enum ValidationError {
+    #[error("Input variable `{name}` has an unsupported type `{format}`")]
+    UnsupportedInputFormat { name: String, format: String },
+    #[error("Shader contains {shader} input variables, but your Vertex struct has {vertex} fields")]
+    VertexFieldCount { shader: usize, vertex: usize },
+    #[error("Shader input variable at location {0} does not match the field in the Vertex struct you provided")]
+    VertexFieldMismatch(u32),
+    #[error("Shader contains an input variable at location {0}, but the Vertex struct you provided doesn't contain a matching field")]
+    MissingVertexField(u32),
+    #[error("Vertex field at location {location} is in binding {binding}, but your Vertex struct doesn't describe that binding")]
+    MissingVertexBinding { location: u32, binding: u32 },
+    #[error("Vertex field at location {location} ends at byte {end} of binding {binding}, past its stride of {stride} bytes")]
+    VertexFieldOutOfBounds {
+        location: u32,
+        binding: u32,
+        end: u32,
+        stride: u32,
+    },
+    #[error("Shader defines {0} push constants, but exactly one is required")]
+    PushConstantCount(usize),
+    #[error("Shader contains {shader} fields in push constant, but your UniformBuffer struct has {ubo} fields")]
+    PushConstantFieldCount { shader: usize, ubo: usize },
+    #[error("Push constant field `{name}` at index {index} is {shader} bytes in the shader, but {ubo} bytes in your struct")]
+    PushConstantFieldSize {
+        name: String,
+        index: usize,
+        shader: usize,
+        ubo: usize,
+    },
+    #[error("Push constant field `{name}` at index {index} is at offset {shader} in the shader, but at offset {ubo} in your struct when aligned using std430 rules")]
+    PushConstantFieldOffset {
+        name: String,
+        index: usize,
+        shader: usize,
+        ubo: usize,
+    },
+    #[error("Shader doesn't contain a uniform block at set {set}, binding {binding}")]
+    MissingUniformBlock { set: u32, binding: u32 },
+    #[error("Shader contains {shader} fields in the uniform block at set {set}, binding {binding}, but your UniformBuffer struct has {ubo} fields")]
+    UniformBlockFieldCount {
+        set: u32,
+        binding: u32,
+        shader: usize,
+        ubo: usize,
+    },
+    #[error("Uniform block field `{name}` at index {index} is {shader} bytes in the shader, but {ubo} bytes in your struct")]
+    UniformBlockFieldSize {
+        name: String,
+        index: usize,
+        shader: usize,
+        ubo: usize,
+    },
+    #[error("Uniform block field `{name}` at index {index} is at offset {shader} in the shader, but at offset {ubo} in your struct when aligned using std140 rules")]
+    UniformBlockFieldOffset {
+        name: String,
+        index: usize,
+        shader: usize,
+        ubo: usize,
+    },
+}
+
+/// Size or offset of a field that differs between a block in a shader and a [UniformBuffer]
+enum FieldMismatch {
+    Size,
+    Offset,
+}
+
+/// Returns the size or offset mismatches between the `members` of a block in a shader and the
+/// `fields` of a [UniformBuffer] at `offsets`, created with `mismatch`
+fn block_member_errors(
+    members: &[ReflectBlockVariable],
+    fields: &[UboFieldDescription],
+    offsets: &[usize],
+    mismatch: impl Fn(FieldMismatch, String, usize, usize, usize) -> ValidationError,
+) -> Vec<ValidationError> {
+    let mut errors: Vec<ValidationError> = Vec::new();
+    for (index, ((member, field), &offset)) in members
+        .iter()
+        .zip(fields.iter())
+        .zip(offsets.iter())
+        .enumerate()
+    {
+        if member.size as usize != field.size {
+            errors.push(mismatch(
+                FieldMismatch::Size,
+                member.name.clone(),
+                index,
+                member.size as usize,
+                field.size,
+            ));
+        }
+
+        if member.offset as usize != offset {
+            errors.push(mismatch(
+                FieldMismatch::Offset,
+                member.name.clone(),
+                index,
+                member.offset as usize,
+                offset,
+            ));
+        }
+    }
+
+    errors
+}
+
+/// Returns every mismatch between the `inputs` of a vertex shader, given as their location and
+/// format, and the `attributes` and `bindings` of a [Vertex]
+///
+/// Only the location and format of each input can be checked against the shader, where an
+/// attribute is read from is checked against the stride of its binding instead, so that
+/// attributes split across bindings have offsets relative to their own binding.
+fn vertex_attribute_errors(
+    inputs: &[(u32, vk::Format)],
+    attributes: &[VertexAttributeDescription],
+    bindings: &[VertexBindingDescription],
+) -> Vec<ValidationError> {
+    let mut errors: Vec<ValidationError> = Vec::new();
+    if inputs.len() != attributes.len() {
+        errors.push(ValidationError::VertexFieldCount {
+            shader: inputs.len(),
+            vertex: attributes.len(),
+        });
+    }
+
+    for &(location, format) in inputs.iter() {
+        match attributes
+            .iter()
+            .find(|attribute| attribute.location == location)
+        {
+            Some(attribute) if attribute.format != format => {
+                errors.push(ValidationError::VertexFieldMismatch(location))
+            }
+            Some(_) => {}
+            None => errors.push(ValidationError::MissingVertexField(location)),
+        }
+    }
+
+    for attribute in attributes.iter() {
+        let binding = match bindings
+            .iter()
+            .find(|binding| binding.binding == attribute.binding)
+        {
+            Some(binding) => binding,
+            None => {
+                errors.push(ValidationError::MissingVertexBinding {
+                    location: attribute.location,
+                    binding: attribute.binding,
+                });
+                continue;
+            }
+        };
+
+        if let Some(size) = attribute_size(attribute.format) {
+            let end = attribute.offset + size;
+            if end > binding.stride {
+                errors.push(ValidationError::VertexFieldOutOfBounds {
+                    location: attribute.location,
+                    binding: attribute.binding,
+                    end,
+                    stride: binding.stride,
+                });
+            }
+        }
+    }
+
+    errors
+}
+
+/// Formats a list of [ValidationErrors][ValidationError] with one error per line
+fn display_validation_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("- {}", error))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Number of words in the header of a SPIR-V module, before its first instruction
+const SPIRV_HEADER_WORDS: usize = 5;
+
+/// Checks that the instructions of the SPIR-V module `code` fit within it, returning a message
+/// with the word offset of the first malformed instruction otherwise
+///
+/// spirv-reflect doesn't say where it failed to parse a module, so this points at the offending
+/// instruction when a shader was compiled or copied incorrectly.
+fn check_spirv_words(code: &[u32]) -> Result<(), String> {
+    if code.len() < SPIRV_HEADER_WORDS {
+        return Err(format!(
+            "module is {} words long, which is shorter than the {} word header",
+            code.len(),
+            SPIRV_HEADER_WORDS
+        ));
+    }
+
+    let mut word = SPIRV_HEADER_WORDS;
+    while word < code.len() {
+        let word_count = (code[word] >> 16) as usize;
+        if word_count == 0 {
+            return Err(format!(
+                "instruction at word {} has a word count of 0",
+                word
+            ));
+        }
+        if word + word_count > code.len() {
+            return Err(format!(
+                "instruction at word {} is {} words long, but the module ends at word {}",
+                word,
+                word_count,
+                code.len()
+            ));
+        }
+        word += word_count;
+    }
+
+    Ok(())
+}
+
+/// Returns the path of the compiled SPIR-V a [Shader] created from `file_path` is loaded from,
+/// e.g. `shaders/cube.vert.spv` for `shaders/cube.vert`
+pub(crate) fn spirv_path(file_path: &str) -> PathBuf {
+    let mut path = Path::new(file_path).to_path_buf();
+    path.set_extension(format!(
+        "{}.spv",
+        path.extension().unwrap().to_str().unwrap()
+    ));
+    path
+}
+
 bitflags! {
     pub struct ShaderStageFlags: u32 {
         const VERTEX = 0b1;
@@ -72,19 +320,22 @@ impl Debug for Shader {
 impl Shader {
     pub fn new(file_path: &'static str) -> Result<Shader, ShaderError> {
         use std::fs::File;
-        use std::path::Path;
 
-        let mut path = Path::new(file_path).to_path_buf();
-        path.set_extension(format!(
-            "{}.spv",
-            path.extension().unwrap().to_str().unwrap()
-        ));
-
-        let code =
-            ash::util::read_spv(&mut File::open(path).map_err(|_| ShaderError::FileNotFound)?)
-                .map_err(|_| ShaderError::CantRead)?;
-        let reflect = SpirvShader::load_u32_data(&code)
-            .map_err(|err| ShaderError::CantParseSpv(err.to_string()))?;
+        let path = spirv_path(file_path);
+        let path_name = path.display().to_string();
+        let mut file = File::open(&path).map_err(|_| ShaderError::FileNotFound(path_name.clone()))?;
+        let code = ash::util::read_spv(&mut file).map_err(|err| ShaderError::CantRead {
+            path: path_name.clone(),
+            message: err.to_string(),
+        })?;
+        check_spirv_words(&code).map_err(|message| ShaderError::Parse {
+            path: path_name.clone(),
+            message,
+        })?;
+        let reflect = SpirvShader::load_u32_data(&code).map_err(|err| ShaderError::Parse {
+            path: path_name,
+            message: err.to_string(),
+        })?;
 
         let entry_point = reflect.get_entry_point_name();
         let shader_stage = ShaderStageFlags::from_bits(reflect.get_shader_stage().bits()).ok_or(
@@ -115,21 +366,99 @@ impl Shader {
 }
 
 impl Shader {
+    /// Creates a [ShaderError::Parse] for this shader from an error returned by spirv-reflect
+    fn parse_error(&self, message: &str) -> ShaderError {
+        ShaderError::Parse {
+            path: self.file_path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Validates the shader against the vertex type `V` and push constant type `P` a pipeline is
+    /// being created with.
+    ///
+    /// The vertex input is only checked if this is a vertex shader and `V` isn't [EmptyVertex],
+    /// likewise the push constant is only checked if `P` isn't [EmptyPushConstant] and is used in
+    /// this shader's stage.
+    ///
+    /// # Errors
+    /// Every mismatch that is found is collected into a [ShaderError::Validation] rather than
+    /// returning on the first one.
+    pub fn validate<V, P>(&self) -> Result<(), ShaderError>
+    where
+        V: Vertex + 'static,
+        P: UniformBuffer + 'static,
+    {
+        let mut errors: Vec<ValidationError> = Vec::new();
+        if TypeId::of::<V>() != TypeId::of::<EmptyVertex>()
+            && self.flags.contains(ShaderStageFlags::VERTEX)
+        {
+            errors.append(&mut self.vertex_input_errors::<V>()?);
+        }
+
+        if TypeId::of::<P>() != TypeId::of::<EmptyPushConstant>() && self.flags.intersects(P::stage())
+        {
+            errors.append(&mut self.push_constant_errors::<P>()?);
+        }
+
+        self.validation_result(errors)
+    }
+
+    /// Checks that the input variables of the shader match the attributes of the vertex type `V`
+    ///
+    /// # Errors
+    /// Every mismatch that is found is collected into a [ShaderError::Validation]
     pub fn check_vertex_input<V>(&self) -> Result<(), ShaderError>
+    where
+        V: Vertex,
+    {
+        let errors = self.vertex_input_errors::<V>()?;
+        self.validation_result(errors)
+    }
+
+    /// Checks that the push constant in the shader matches the fields of the push constant type
+    /// `P`
+    ///
+    /// # Errors
+    /// Every mismatch that is found is collected into a [ShaderError::Validation]
+    pub fn check_push_constant<P>(&self) -> Result<(), ShaderError>
+    where
+        P: UniformBuffer,
+    {
+        let errors = self.push_constant_errors::<P>()?;
+        self.validation_result(errors)
+    }
+
+    /// Wraps any validation errors found in this shader into a [ShaderError::Validation]
+    fn validation_result(&self, errors: Vec<ValidationError>) -> Result<(), ShaderError> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ShaderError::Validation {
+                path: self.file_path.to_string(),
+                errors,
+            })
+        }
+    }
+
+    /// Returns every mismatch between the input variables of the shader and the attributes of the
+    /// vertex type `V`
+    fn vertex_input_errors<V>(&self) -> Result<Vec<ValidationError>, ShaderError>
     where
         V: Vertex,
     {
         let input_variables = self
             .reflect
             .enumerate_input_variables(Some(
-                &self
+                self
                     .entry_point
                     .to_str()
                     .expect("Failed to cast CString to str"),
             ))
-            .map_err(|err| ShaderError::CantParseSpv(err.into()))?;
-        let mut vertex_attribute_descriptions: Vec<VertexAttributeDescription> = Vec::new();
-        let mut offset = 0;
+            .map_err(|err| self.parse_error(err))?;
+
+        let mut errors: Vec<ValidationError> = Vec::new();
+        let mut inputs: Vec<(u32, vk::Format)> = Vec::new();
         for input_variable in input_variables.iter() {
             if input_variable.storage_class == spirv_reflect::types::ReflectStorageClass::Input
                 && input_variable.decoration_flags.is_empty()
@@ -145,146 +474,242 @@ impl Shader {
                     ReflectFormat::R32G32B32_SFLOAT => vk::Format::R32G32B32_SFLOAT,
                     ReflectFormat::R32G32B32A32_SFLOAT => vk::Format::R32G32B32A32_SFLOAT,
                     _ => {
-                        return Err(ShaderError::InvalidDefinition(format!(
-                            "Input variable `{}` has an unsupported type `{:#?}`",
-                            input_variable.name, input_variable.format
-                        )))
+                        errors.push(ValidationError::UnsupportedInputFormat {
+                            name: input_variable.name.clone(),
+                            format: format!("{:?}", input_variable.format),
+                        });
+                        continue;
                     }
                 };
 
-                vertex_attribute_descriptions.push(VertexAttributeDescription {
-                    location: input_variable.location,
-                    binding: 0,
-                    format,
-                    offset,
-                });
-
-                offset += input_variable.numeric.vector.component_count * 4;
+                inputs.push((input_variable.location, format));
             }
         }
 
-        if vertex_attribute_descriptions.len() != V::get_attribute_descriptions().len() {
-            return Err(ShaderError::InvalidDefinition(format!(
-                "Shader contains {} input variable, but your Vertex struct only has {} fields",
-                vertex_attribute_descriptions.len(),
-                V::get_attribute_descriptions().len()
-            )));
-        }
-
-        for vertex_attribute in vertex_attribute_descriptions.iter() {
-            if let Some(user_vertex_attribute) = V::get_attribute_descriptions()
-                .iter()
-                .find(|attr| attr.location == vertex_attribute.location)
-            {
-                if vertex_attribute.ne(user_vertex_attribute) {
-                    return Err(
-                            ShaderError::InvalidDefinition(
-                                format!("Shader input variable at location {} does not match the field in the Vertex struct you provided", vertex_attribute.location)
-                        ));
-                }
-            } else {
-                return Err(
-                        ShaderError::InvalidDefinition(
-                            format!("Shader contains input variable with definition: {:#?}\nbut the Vertex struct you provided doesn't contain a matching field", vertex_attribute)
-                    ));
-            }
-        }
+        errors.append(&mut vertex_attribute_errors(
+            &inputs,
+            &V::get_attribute_descriptions(),
+            &V::get_binding_descriptions(),
+        ));
 
-        Ok(())
+        Ok(errors)
     }
 
-    pub fn check_push_constant<P>(&self) -> Result<(), ShaderError>
+    /// Returns every mismatch between the push constant in the shader and the fields of the push
+    /// constant type `P`
+    fn push_constant_errors<P>(&self) -> Result<Vec<ValidationError>, ShaderError>
     where
         P: UniformBuffer,
     {
         let push_constants = self
             .reflect
             .enumerate_push_constant_blocks(Some(
-                &self
+                self
                     .entry_point
                     .to_str()
                     .expect("Failed to cast CString to str"),
             ))
-            .map_err(|err| ShaderError::CantParseSpv(err.into()))?;
+            .map_err(|err| self.parse_error(err))?;
         if push_constants.len() != 1 {
-            return Err(ShaderError::InvalidDefinition(
-                "Shader doesn't define, or defines too many, push constant".to_string(),
-            ));
+            return Ok(vec![ValidationError::PushConstantCount(
+                push_constants.len(),
+            )]);
         }
         let push_constant = &push_constants[0];
 
-        let mut field_descriptions: Vec<UboFieldDescription> = Vec::new();
-        for member in push_constant.members.iter() {
-            field_descriptions.push(UboFieldDescription {
-                size: member.size as usize,
+        let mut errors: Vec<ValidationError> = Vec::new();
+        let user_field_descriptions = P::get_std430_field_descriptions();
+        if push_constant.members.len() != user_field_descriptions.len() {
+            errors.push(ValidationError::PushConstantFieldCount {
+                shader: push_constant.members.len(),
+                ubo: user_field_descriptions.len(),
             });
         }
 
-        if field_descriptions.len() != P::get_field_descriptions().len() {
-            return Err(ShaderError::InvalidDefinition(format!(
-                "Shader contains {} fields in push constant, but your UniformBuffer struct only has {} fields",
-                field_descriptions.len(),
-                P::get_field_descriptions().len()
-            )));
-        }
+        // Push constant blocks are laid out using std430 rules unless the shader says otherwise
+        let user_offsets = std430_offsets(&user_field_descriptions);
+        errors.append(&mut block_member_errors(
+            &push_constant.members,
+            &user_field_descriptions,
+            &user_offsets,
+            |mismatch, name, index, shader, ubo| match mismatch {
+                FieldMismatch::Size => ValidationError::PushConstantFieldSize {
+                    name,
+                    index,
+                    shader,
+                    ubo,
+                },
+                FieldMismatch::Offset => ValidationError::PushConstantFieldOffset {
+                    name,
+                    index,
+                    shader,
+                    ubo,
+                },
+            },
+        ));
+
+        Ok(errors)
+    }
 
-        for (index, (field_description, user_field_description)) in field_descriptions
+    /// Checks that the uniform block at `set` and `binding` in the shader matches the fields of
+    /// the uniform buffer type `U`, laid out using std140 rules like uniform blocks are by
+    /// default
+    ///
+    /// # Errors
+    /// Every mismatch that is found is collected into a [ShaderError::Validation]
+    pub fn check_uniform_block<U>(&self, set: u32, binding: u32) -> Result<(), ShaderError>
+    where
+        U: UniformBuffer,
+    {
+        let errors = self.uniform_block_errors::<U>(set, binding)?;
+        self.validation_result(errors)
+    }
+
+    /// Returns every mismatch between the uniform block at `set` and `binding` in the shader and
+    /// the fields of the uniform buffer type `U`
+    fn uniform_block_errors<U>(
+        &self,
+        set: u32,
+        binding: u32,
+    ) -> Result<Vec<ValidationError>, ShaderError>
+    where
+        U: UniformBuffer,
+    {
+        let descriptor_sets = self
+            .reflect
+            .enumerate_descriptor_sets(Some(
+                self
+                    .entry_point
+                    .to_str()
+                    .expect("Failed to cast CString to str"),
+            ))
+            .map_err(|err| self.parse_error(err))?;
+        let uniform_block = descriptor_sets
             .iter()
-            .zip(P::get_field_descriptions().iter())
-            .enumerate()
-        {
-            if field_description.size != user_field_description.size {
-                return Err(ShaderError::InvalidDefinition(format!(
-                    "Shader contains field with definition: {:#?} but your struct doesn't have a matching field, instead it has {:#?} at the same index {}",
-                    field_description,
-                    user_field_description,
-                    index
-                )));
-            }
+            .filter(|descriptor_set| descriptor_set.set == set)
+            .flat_map(|descriptor_set| descriptor_set.bindings.iter())
+            .find(|descriptor| {
+                descriptor.binding == binding
+                    && matches!(
+                        descriptor.descriptor_type,
+                        ReflectDescriptorType::UniformBuffer
+                            | ReflectDescriptorType::UniformBufferDynamic
+                    )
+            });
+        let uniform_block = match uniform_block {
+            Some(descriptor) => &descriptor.block,
+            None => return Ok(vec![ValidationError::MissingUniformBlock { set, binding }]),
+        };
+
+        let mut errors: Vec<ValidationError> = Vec::new();
+        let user_field_descriptions = U::get_field_descriptions();
+        if uniform_block.members.len() != user_field_descriptions.len() {
+            errors.push(ValidationError::UniformBlockFieldCount {
+                set,
+                binding,
+                shader: uniform_block.members.len(),
+                ubo: user_field_descriptions.len(),
+            });
         }
 
-        Ok(())
+        let user_offsets = std140_offsets(&user_field_descriptions);
+        errors.append(&mut block_member_errors(
+            &uniform_block.members,
+            &user_field_descriptions,
+            &user_offsets,
+            |mismatch, name, index, shader, ubo| match mismatch {
+                FieldMismatch::Size => ValidationError::UniformBlockFieldSize {
+                    name,
+                    index,
+                    shader,
+                    ubo,
+                },
+                FieldMismatch::Offset => ValidationError::UniformBlockFieldOffset {
+                    name,
+                    index,
+                    shader,
+                    ubo,
+                },
+            },
+        ));
+
+        Ok(errors)
     }
 
-    pub fn get_descriptor_set_layouts(
-        &self,
-        device: Rc<LogicalDevice>,
-    ) -> Result<Vec<DescriptorSetLayout>, ShaderError> {
+    /// Returns every descriptor binding declared in the shader along with the set it belongs
+    /// to, sorted by set and then binding.
+    ///
+    /// Opaque uniforms are included as well as uniform and storage blocks, so a `uniform
+    /// sampler2D` is reported as a
+    /// [DescriptorType::CombinedImageSampler](crate::descriptors::DescriptorType::CombinedImageSampler)
+    /// at its set and binding.
+    pub fn descriptor_bindings(&self) -> Result<Vec<ShaderDescriptorBinding>, ShaderError> {
         let shader_descriptors = self
             .reflect
             .enumerate_descriptor_sets(Some(
-                &self
+                self
                     .entry_point
                     .to_str()
                     .expect("Failed to cast CString to str"),
             ))
-            .map_err(|err| ShaderError::CantParseSpv(err.to_string()))?;
-        let mut descriptor_sets: Vec<DescriptorSetLayout> =
-            Vec::with_capacity(shader_descriptors.len());
+            .map_err(|err| self.parse_error(err))?;
 
+        let mut bindings: Vec<ShaderDescriptorBinding> = Vec::new();
         for descriptor_set in shader_descriptors.iter() {
-            let mut bindings: Vec<DescriptorSetLayoutBinding> =
-                Vec::with_capacity(descriptor_set.bindings.len());
             for binding in descriptor_set.bindings.iter() {
-                bindings.push(DescriptorSetLayoutBinding {
-                    binding: binding.binding,
-                    ty: binding.descriptor_type.try_into().map_err(|_| {
-                        ShaderError::InvalidDefinition(
-                            "Shader contains an invalid descriptor type".to_string(),
-                        )
-                    })?,
-                    count: binding.count,
-                    shader_stage_flags: self.flags,
+                bindings.push(ShaderDescriptorBinding {
+                    set: descriptor_set.set,
+                    layout: DescriptorSetLayoutBinding {
+                        binding: binding.binding,
+                        ty: binding.descriptor_type.try_into().map_err(|_| {
+                            ShaderError::InvalidDefinition(format!(
+                                "Descriptor `{}` at set {}, binding {} has an unsupported descriptor type `{:?}`",
+                                binding.name,
+                                descriptor_set.set,
+                                binding.binding,
+                                binding.descriptor_type
+                            ))
+                        })?,
+                        count: binding.count,
+                        shader_stage_flags: self.flags,
+                    },
                 });
             }
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.layout.binding));
+
+        Ok(bindings)
+    }
 
-            descriptor_sets.push(DescriptorSetLayout::new(device.clone(), &bindings)?);
+    /// Creates a [DescriptorSetLayout] for every descriptor set declared in the shader, ordered
+    /// by set index
+    pub fn get_descriptor_set_layouts(
+        &self,
+        device: Rc<LogicalDevice>,
+    ) -> Result<Vec<DescriptorSetLayout>, ShaderError> {
+        let mut sets: BTreeMap<u32, Vec<DescriptorSetLayoutBinding>> = BTreeMap::new();
+        for binding in self.descriptor_bindings()? {
+            sets.entry(binding.set).or_default().push(binding.layout);
+        }
+
+        let mut descriptor_sets: Vec<DescriptorSetLayout> = Vec::with_capacity(sets.len());
+        for bindings in sets.values() {
+            descriptor_sets.push(DescriptorSetLayout::new(device.clone(), bindings)?);
         }
 
         Ok(descriptor_sets)
     }
 }
 
+/// A descriptor binding declared in a [Shader]
+#[derive(Clone, Copy, Debug)]
+pub struct ShaderDescriptorBinding {
+    /// Index of the descriptor set the binding belongs to
+    pub set: u32,
+    /// Layout of the binding within its descriptor set
+    pub layout: DescriptorSetLayoutBinding,
+}
+
 /// Wraps a Vulkan shader module
 #[derive(Clone)]
 pub struct ShaderModule {
@@ -324,3 +749,354 @@ impl Drop for ShaderModule {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{descriptors::DescriptorType, pipeline::vertex::VertexInputRate};
+
+    /// Matches the `vec2 offset; vec3 color;` blocks of the descriptors and push constant shaders
+    struct OffsetColor {
+        _offset: [f32; 2],
+        _color: [f32; 3],
+    }
+
+    impl UniformBuffer for OffsetColor {
+        fn as_bytes(&self) -> &[u8] {
+            &[]
+        }
+
+        fn as_std140_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn as_std430_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn stage() -> ShaderStageFlags {
+            ShaderStageFlags::VERTEX
+        }
+
+        fn get_field_descriptions() -> Vec<UboFieldDescription> {
+            vec![
+                UboFieldDescription {
+                    size: 8,
+                    alignment: 8,
+                },
+                UboFieldDescription {
+                    size: 12,
+                    alignment: 16,
+                },
+            ]
+        }
+
+        fn get_std430_field_descriptions() -> Vec<UboFieldDescription> {
+            Self::get_field_descriptions()
+        }
+    }
+
+    /// Describes the fields of [OffsetColor] as if they were packed tightly
+    struct PackedOffsetColor {}
+
+    impl UniformBuffer for PackedOffsetColor {
+        fn as_bytes(&self) -> &[u8] {
+            &[]
+        }
+
+        fn as_std140_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn as_std430_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn stage() -> ShaderStageFlags {
+            ShaderStageFlags::VERTEX
+        }
+
+        fn get_field_descriptions() -> Vec<UboFieldDescription> {
+            vec![
+                UboFieldDescription {
+                    size: 8,
+                    alignment: 4,
+                },
+                UboFieldDescription {
+                    size: 12,
+                    alignment: 4,
+                },
+            ]
+        }
+
+        fn get_std430_field_descriptions() -> Vec<UboFieldDescription> {
+            Self::get_field_descriptions()
+        }
+    }
+
+    #[test]
+    fn uniform_block_matches_std140_layout() {
+        let shader = Shader::new("../../shaders/descriptors.vert").unwrap();
+
+        assert!(shader.check_uniform_block::<OffsetColor>(0, 0).is_ok());
+    }
+
+    #[test]
+    fn uniform_block_reports_packed_fields() {
+        let shader = Shader::new("../../shaders/descriptors.vert").unwrap();
+
+        match shader.check_uniform_block::<PackedOffsetColor>(0, 0) {
+            Err(ShaderError::Validation { errors, .. }) => assert!(matches!(
+                errors.as_slice(),
+                [ValidationError::UniformBlockFieldOffset {
+                    index: 1,
+                    shader: 16,
+                    ubo: 8,
+                    ..
+                }]
+            )),
+            result => panic!("Expected a validation error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn uniform_block_must_exist() {
+        let shader = Shader::new("../../shaders/descriptors.vert").unwrap();
+
+        match shader.check_uniform_block::<OffsetColor>(0, 1) {
+            Err(ShaderError::Validation { errors, .. }) => assert!(matches!(
+                errors.as_slice(),
+                [ValidationError::MissingUniformBlock { set: 0, binding: 1 }]
+            )),
+            result => panic!("Expected a validation error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn push_constant_matches_std430_layout() {
+        let shader = Shader::new("../../shaders/push_constant.vert").unwrap();
+
+        assert!(shader.check_push_constant::<OffsetColor>().is_ok());
+    }
+
+    /// Swaps the formats of the `vec2 position; vec3 color;` inputs of the vertex shader
+    struct SwappedVertex {}
+
+    impl Vertex for SwappedVertex {
+        fn get_attribute_descriptions() -> Vec<VertexAttributeDescription> {
+            vec![
+                VertexAttributeDescription {
+                    location: 0,
+                    binding: 0,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: 0,
+                },
+                VertexAttributeDescription {
+                    location: 1,
+                    binding: 0,
+                    format: vk::Format::R32G32_SFLOAT,
+                    offset: 12,
+                },
+            ]
+        }
+
+        fn get_binding_descriptions() -> Vec<VertexBindingDescription> {
+            vec![VertexBindingDescription {
+                binding: 0,
+                stride: 20,
+                input_rate: VertexInputRate::Vertex,
+            }]
+        }
+    }
+
+    /// Matches the `vec2 position; vec3 color;` inputs of the vertex shader, with each input in
+    /// its own binding like a vertex deriving `#[binding = N]` for each field
+    struct SplitVertex {}
+
+    impl Vertex for SplitVertex {
+        fn get_attribute_descriptions() -> Vec<VertexAttributeDescription> {
+            vec![
+                VertexAttributeDescription {
+                    location: 0,
+                    binding: 0,
+                    format: vk::Format::R32G32_SFLOAT,
+                    offset: 0,
+                },
+                VertexAttributeDescription {
+                    location: 1,
+                    binding: 1,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: 0,
+                },
+            ]
+        }
+
+        fn get_binding_descriptions() -> Vec<VertexBindingDescription> {
+            vec![
+                VertexBindingDescription {
+                    binding: 0,
+                    stride: 8,
+                    input_rate: VertexInputRate::Vertex,
+                },
+                VertexBindingDescription {
+                    binding: 1,
+                    stride: 12,
+                    input_rate: VertexInputRate::Vertex,
+                },
+            ]
+        }
+    }
+
+    /// Locations and formats of the `vec2 position; vec3 color;` inputs of the vertex shader
+    const VERTEX_INPUTS: [(u32, vk::Format); 2] = [
+        (0, vk::Format::R32G32_SFLOAT),
+        (1, vk::Format::R32G32B32_SFLOAT),
+    ];
+
+    #[test]
+    fn vertex_split_across_bindings_matches_the_shader() {
+        let shader = Shader::new("../../shaders/vertex.vert").unwrap();
+
+        assert!(shader.validate::<SplitVertex, EmptyPushConstant>().is_ok());
+    }
+
+    #[test]
+    fn attribute_past_the_stride_of_its_binding_errors() {
+        let mut attributes = SplitVertex::get_attribute_descriptions();
+        attributes[1].offset = 4;
+
+        assert!(matches!(
+            vertex_attribute_errors(
+                &VERTEX_INPUTS,
+                &attributes,
+                &SplitVertex::get_binding_descriptions(),
+            )
+            .as_slice(),
+            [ValidationError::VertexFieldOutOfBounds {
+                location: 1,
+                binding: 1,
+                end: 16,
+                stride: 12,
+            }]
+        ));
+    }
+
+    #[test]
+    fn attribute_in_an_undescribed_binding_errors() {
+        let bindings = SplitVertex::get_binding_descriptions();
+
+        assert!(matches!(
+            vertex_attribute_errors(
+                &VERTEX_INPUTS,
+                &SplitVertex::get_attribute_descriptions(),
+                &bindings[..1],
+            )
+            .as_slice(),
+            [ValidationError::MissingVertexBinding {
+                location: 1,
+                binding: 1
+            }]
+        ));
+    }
+
+    #[test]
+    fn every_vertex_mismatch_is_reported() {
+        let shader = Shader::new("../../shaders/vertex.vert").unwrap();
+
+        match shader.validate::<SwappedVertex, EmptyPushConstant>() {
+            Err(ShaderError::Validation { errors, .. }) => assert!(matches!(
+                errors.as_slice(),
+                [
+                    ValidationError::VertexFieldMismatch(0),
+                    ValidationError::VertexFieldMismatch(1)
+                ]
+            )),
+            result => panic!("Expected a validation error, got {:?}", result),
+        }
+    }
+
+    /// Header of a SPIR-V 1.0 module with an id bound of `bound`
+    fn spirv_header(bound: u32) -> Vec<u32> {
+        vec![0x0723_0203, 0x0001_0000, 0, bound, 0]
+    }
+
+    #[test]
+    fn well_formed_spirv_words_are_allowed() {
+        // OpCapability Shader, followed by OpMemoryModel Logical GLSL450
+        let mut code = spirv_header(1);
+        code.extend([(2 << 16) | 17, 1, (3 << 16) | 14, 0, 1]);
+
+        assert!(check_spirv_words(&code).is_ok());
+    }
+
+    #[test]
+    fn malformed_spirv_reports_the_word_of_the_instruction() {
+        let mut code = spirv_header(1);
+        code.extend([(2 << 16) | 17, 1, 14, 0, 1]);
+        assert_eq!(
+            check_spirv_words(&code).unwrap_err(),
+            "instruction at word 7 has a word count of 0"
+        );
+
+        let mut code = spirv_header(1);
+        code.extend([(2 << 16) | 17, 1, (4 << 16) | 14, 0, 1]);
+        assert_eq!(
+            check_spirv_words(&code).unwrap_err(),
+            "instruction at word 7 is 4 words long, but the module ends at word 10"
+        );
+    }
+
+    #[test]
+    fn malformed_spirv_file_returns_a_parse_error() {
+        let path = std::env::temp_dir().join("magma_malformed.frag");
+        let mut code = spirv_header(1);
+        code.push((2 << 16) | 17);
+        let bytes: Vec<u8> = code.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        std::fs::write(spirv_path(path.to_str().unwrap()), bytes).unwrap();
+
+        let file_path: &'static str =
+            Box::leak(path.to_str().unwrap().to_string().into_boxed_str());
+        match Shader::new(file_path) {
+            Err(ShaderError::Parse { path, message }) => {
+                assert!(path.ends_with("magma_malformed.frag.spv"));
+                assert_eq!(
+                    message,
+                    "instruction at word 5 is 2 words long, but the module ends at word 6"
+                );
+            }
+            result => panic!("Expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn sampler_reflects_as_a_combined_image_sampler() {
+        let shader = Shader::new("../../shaders/textured.frag").unwrap();
+
+        let bindings = shader.descriptor_bindings().unwrap();
+        assert_eq!(bindings.len(), 1);
+        let binding = bindings[0];
+        assert_eq!((binding.set, binding.layout.binding), (1, 0));
+        assert!(matches!(
+            binding.layout.ty,
+            DescriptorType::CombinedImageSampler
+        ));
+        assert_eq!(binding.layout.count, 1);
+        assert_eq!(
+            binding.layout.shader_stage_flags,
+            ShaderStageFlags::FRAGMENT
+        );
+    }
+
+    #[test]
+    fn buffer_block_reflects_as_a_storage_buffer() {
+        let shader = Shader::new("../../shaders/storage.frag").unwrap();
+
+        let bindings = shader.descriptor_bindings().unwrap();
+        assert_eq!(bindings.len(), 1);
+        let binding = bindings[0];
+        assert_eq!((binding.set, binding.layout.binding), (0, 0));
+        assert!(matches!(binding.layout.ty, DescriptorType::StorageBuffer));
+        let ty: vk::DescriptorType = binding.layout.ty.into();
+        assert_eq!(ty, vk::DescriptorType::STORAGE_BUFFER);
+    }
+}
@@ -2,13 +2,116 @@ use crate::pipeline::ShaderStageFlags;
 
 pub trait UniformBuffer {
     fn as_bytes(&self) -> &[u8];
+    /// Returns the fields laid out at their std140 offsets, with padding between them, ready to
+    /// be copied into a uniform buffer
+    fn as_std140_bytes(&self) -> Vec<u8>;
+    /// Returns the fields laid out at their std430 offsets, with padding between them, ready to
+    /// be uploaded as push constants
+    fn as_std430_bytes(&self) -> Vec<u8>;
     fn stage() -> ShaderStageFlags;
+    /// Returns the size and base alignment of every field following std140 layout rules
     fn get_field_descriptions() -> Vec<UboFieldDescription>;
+    /// Returns the size and base alignment of every field following std430 layout rules
+    fn get_std430_field_descriptions() -> Vec<UboFieldDescription>;
 }
 
 #[derive(Debug)]
 pub struct UboFieldDescription {
+    /// Size, in bytes, of the field
     pub size: usize,
+    /// Base alignment, in bytes, of the field following the layout rules it is described with
+    pub alignment: usize,
+}
+
+/// Calculates the offset, in bytes, of every field when laid out using std140 layout rules
+pub fn std140_offsets(fields: &[UboFieldDescription]) -> Vec<usize> {
+    let mut offsets: Vec<usize> = Vec::with_capacity(fields.len());
+    let mut offset = 0;
+    for field in fields.iter() {
+        offset = (offset + field.alignment - 1) & !(field.alignment - 1);
+        offsets.push(offset);
+        offset += field.size;
+    }
+
+    offsets
+}
+
+/// Calculates the size, in bytes, of a struct with `fields` laid out using std140 layout rules,
+/// which is rounded up to a multiple of 16 like the base alignment of a std140 struct
+pub fn std140_size(fields: &[UboFieldDescription]) -> usize {
+    let end = std140_offsets(fields)
+        .last()
+        .zip(fields.last())
+        .map_or(0, |(offset, field)| offset + field.size);
+
+    (end + 15) & !15
+}
+
+/// Calculates the offset, in bytes, of every field when laid out using std430 layout rules,
+/// which places fields the same way as std140 once they are described with std430 base
+/// alignments
+pub fn std430_offsets(fields: &[UboFieldDescription]) -> Vec<usize> {
+    std140_offsets(fields)
+}
+
+/// Returns the base alignment, in bytes, of a struct with `fields` laid out using std430 layout
+/// rules, which is the largest base alignment of its fields
+pub fn std430_alignment(fields: &[UboFieldDescription]) -> usize {
+    fields
+        .iter()
+        .map(|field| field.alignment)
+        .max()
+        .unwrap_or(4)
+}
+
+/// Calculates the size, in bytes, of a struct with `fields` laid out using std430 layout rules,
+/// which is rounded up to a multiple of the struct's base alignment
+pub fn std430_size(fields: &[UboFieldDescription]) -> usize {
+    let end = std430_offsets(fields)
+        .last()
+        .zip(fields.last())
+        .map_or(0, |(offset, field)| offset + field.size);
+    let alignment = std430_alignment(fields);
+
+    (end + alignment - 1) & !(alignment - 1)
+}
+
+/// Copies the bytes of `value` into `bytes` at `offset`
+///
+/// Used by the code generated by `#[derive(UniformBuffer)]` to write fields at their std140
+/// offsets.
+///
+/// # Safety
+/// `T` must be plain old data without any padding bytes, as every byte of `value` is read.
+#[doc(hidden)]
+pub unsafe fn std140_write<T>(bytes: &mut [u8], offset: usize, value: &T) {
+    let size = std::mem::size_of::<T>();
+    let value = std::slice::from_raw_parts(value as *const T as *const u8, size);
+    bytes[offset..offset + size].copy_from_slice(value);
+}
+
+/// Copies the `columns` columns of the matrix `value` into `bytes` at `offset`, with every
+/// column starting 16 bytes after the previous one as std140 lays out matrices as arrays of
+/// vec4s
+///
+/// Used by the code generated by `#[derive(UniformBuffer)]` to write matrix fields, and arrays of
+/// floats whose elements are likewise each padded to a vec4. Nothing is written if there are no
+/// `columns`.
+///
+/// # Safety
+/// `T` must be plain old data without any padding bytes, as every byte of `value` is read.
+#[doc(hidden)]
+pub unsafe fn std140_write_columns<T>(bytes: &mut [u8], offset: usize, value: &T, columns: usize) {
+    if columns == 0 {
+        return;
+    }
+
+    let size = std::mem::size_of::<T>();
+    let value = std::slice::from_raw_parts(value as *const T as *const u8, size);
+    for (index, column) in value.chunks_exact(size / columns).enumerate() {
+        let column_offset = offset + index * 16;
+        bytes[column_offset..column_offset + column.len()].copy_from_slice(column);
+    }
 }
 
 pub struct EmptyPushConstant {}
@@ -18,6 +121,14 @@ impl UniformBuffer for EmptyPushConstant {
         &[0]
     }
 
+    fn as_std140_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_std430_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
     fn stage() -> ShaderStageFlags {
         ShaderStageFlags::empty()
     }
@@ -25,5 +136,70 @@ impl UniformBuffer for EmptyPushConstant {
     fn get_field_descriptions() -> Vec<UboFieldDescription> {
         vec![]
     }
+
+    fn get_std430_field_descriptions() -> Vec<UboFieldDescription> {
+        vec![]
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(size: usize, alignment: usize) -> UboFieldDescription {
+        UboFieldDescription { size, alignment }
+    }
+
+    #[test]
+    fn float_after_vec3_is_packed_into_its_padding() {
+        let fields = [field(12, 16), field(4, 4)];
+
+        assert_eq!(std140_offsets(&fields), vec![0, 12]);
+        assert_eq!(std140_size(&fields), 16);
+    }
+
+    #[test]
+    fn vec3_after_float_is_aligned_to_16_bytes() {
+        let fields = [field(4, 4), field(12, 16), field(4, 4)];
+
+        assert_eq!(std140_offsets(&fields), vec![0, 16, 28]);
+        assert_eq!(std140_size(&fields), 32);
+    }
+
+    #[test]
+    fn std430_size_is_rounded_to_the_largest_alignment() {
+        // A float followed by a mat2, whose columns are 8 bytes apart in std430
+        let fields = [field(4, 4), field(16, 8)];
+
+        assert_eq!(std430_offsets(&fields), vec![0, 8]);
+        assert_eq!(std430_size(&fields), 24);
+        assert_eq!(std140_size(&fields), 32);
+    }
+
+    #[test]
+    fn columns_are_written_16_bytes_apart() {
+        let value: [f32; 4] = [1.0, 2.0, 3.0, 4.0];
+        let mut bytes = vec![0_u8; 32];
+        unsafe { std140_write_columns(&mut bytes, 0, &value, 2) };
+
+        assert_eq!(&bytes[0..8], f32_bytes(&[1.0, 2.0]).as_slice());
+        assert_eq!(&bytes[8..16], &[0; 8]);
+        assert_eq!(&bytes[16..24], f32_bytes(&[3.0, 4.0]).as_slice());
+    }
+
+    #[test]
+    fn empty_arrays_write_nothing() {
+        let value: [f32; 0] = [];
+        let mut bytes = vec![0_u8; 16];
+        unsafe { std140_write_columns(&mut bytes, 0, &value, 0) };
+
+        assert_eq!(bytes, vec![0; 16]);
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+}
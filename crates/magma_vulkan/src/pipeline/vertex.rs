@@ -2,7 +2,7 @@ use ash::vk;
 
 pub type VkFormat = ash::vk::Format;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum VertexInputRate {
     Vertex,
     Instance,
@@ -70,3 +70,94 @@ impl Vertex for EmptyVertex {
     }
 }
 
+
+/// Returns the size, in bytes, of a vertex attribute with `format`, or `None` if the format isn't
+/// one a [Vertex] attribute is expected to use
+pub(crate) fn attribute_size(format: VkFormat) -> Option<u32> {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32_SINT | vk::Format::R32_UINT => Some(4),
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_SINT | vk::Format::R32G32_UINT => Some(8),
+        vk::Format::R32G32B32_SFLOAT
+        | vk::Format::R32G32B32_SINT
+        | vk::Format::R32G32B32_UINT => Some(12),
+        vk::Format::R32G32B32A32_SFLOAT
+        | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R32G32B32A32_UINT => Some(16),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_UINT => Some(4),
+        _ => None,
+    }
+}
+
+/// Asserts, in debug builds, that the attributes `V` describes cover the whole of `V` apart from
+/// trailing padding, catching a Rust vertex struct that has drifted from its attribute
+/// descriptions and would otherwise be read as garbage by the shader
+pub(crate) fn debug_assert_layout<V: Vertex>() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let sizes: Option<Vec<u32>> = V::get_attribute_descriptions()
+        .iter()
+        .map(|description| attribute_size(description.format))
+        .collect();
+    let Some(sizes) = sizes else {
+        return;
+    };
+
+    let alignment = std::mem::align_of::<V>() as u32;
+    let attributes_size = sizes.iter().sum::<u32>().div_ceil(alignment) * alignment;
+    debug_assert_eq!(
+        std::mem::size_of::<V>() as u32,
+        attributes_size,
+        "`{}` is {} bytes but its vertex attributes only describe {} bytes",
+        std::any::type_name::<V>(),
+        std::mem::size_of::<V>(),
+        attributes_size
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vertex whose attributes describe the first `N` floats of its `[f32; 4]` position
+    #[allow(dead_code)]
+    struct PartialVertex<const N: usize> {
+        position: [f32; 4],
+    }
+
+    impl<const N: usize> Vertex for PartialVertex<N> {
+        fn get_attribute_descriptions() -> Vec<VertexAttributeDescription> {
+            let format = match N {
+                2 => vk::Format::R32G32_SFLOAT,
+                _ => vk::Format::R32G32B32A32_SFLOAT,
+            };
+            vec![VertexAttributeDescription {
+                location: 0,
+                binding: 0,
+                format,
+                offset: 0,
+            }]
+        }
+
+        fn get_binding_descriptions() -> Vec<VertexBindingDescription> {
+            vec![VertexBindingDescription {
+                binding: 0,
+                stride: std::mem::size_of::<Self>() as u32,
+                input_rate: VertexInputRate::Vertex,
+            }]
+        }
+    }
+
+    #[test]
+    fn attributes_covering_the_vertex_pass() {
+        debug_assert_layout::<PartialVertex<4>>();
+    }
+
+    #[test]
+    #[cfg_attr(not(debug_assertions), ignore)]
+    #[should_panic(expected = "vertex attributes only describe 8 bytes")]
+    fn attributes_not_covering_the_vertex_panic() {
+        debug_assert_layout::<PartialVertex<2>>();
+    }
+}
@@ -0,0 +1,347 @@
+//! Watches the shaders [Pipelines][Pipeline] are created from, so they can be rebuilt while the
+//! application is running when their shaders are edited
+
+use ash::vk;
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::Command,
+    time::{Instant, SystemTime},
+};
+
+use crate::{core::device::LogicalDeviceError, render_pass::RenderPass};
+
+use super::{shader::spirv_path, ubo::UniformBuffer, vertex::Vertex, Pipeline, PipelineError};
+
+/// Errors that can be returned by a [ShaderWatcher]
+#[derive(thiserror::Error, Debug)]
+pub enum ShaderWatcherError {
+    #[error("Failed to run the shader compiler `{compiler}`: {message}")]
+    CantRunCompiler { compiler: String, message: String },
+    #[error("Failed to compile `{path}`:\n{message}")]
+    CompileFailed { path: String, message: String },
+    #[error("Failed to compile {} changed shaders:\n{}", .0.len(), display_compile_errors(.0))]
+    CompileErrors(Vec<ShaderWatcherError>),
+    #[error("Failed to rebuild a pipeline: {0}")]
+    PipelineError(#[from] PipelineError),
+    #[error(transparent)]
+    DeviceError(#[from] LogicalDeviceError),
+}
+
+/// A shader file being watched for changes
+struct WatchedShader {
+    /// Last modification time of the watched file
+    modified: Option<SystemTime>,
+    /// When a change to the shader was last picked up
+    changed_at: Option<Instant>,
+}
+
+/// Watches the shader files used to create [Pipelines][Pipeline] and rebuilds the pipelines when
+/// they change, so shaders can be iterated on without restarting the application.
+///
+/// Call [ShaderWatcher::poll] once per frame to pick up changed shaders, then
+/// [ShaderWatcher::reload] for each watched pipeline before recording commands with it.
+///
+/// By default the GLSL sources are watched and compiled with `glslc` into the `.spv` files
+/// [Shader::new][super::shader::Shader::new] loads, use [ShaderWatcher::precompiled] to only
+/// watch the `.spv` files when shaders are compiled by another tool, e.g. `glslc --watch`.
+pub struct ShaderWatcher {
+    /// Command used to compile GLSL sources into SPIR-V, `None` to watch the SPIR-V files
+    compiler: Option<String>,
+    /// Watched shaders, keyed by the path they are loaded with
+    shaders: HashMap<&'static str, WatchedShader>,
+    /// When rebuilding a pipeline last failed, keyed by its handle, so it is only rebuilt again
+    /// once one of its shaders changes
+    failed_builds: HashMap<vk::Pipeline, Instant>,
+}
+
+impl ShaderWatcher {
+    /// Creates a [ShaderWatcher] that recompiles changed GLSL sources with `glslc`
+    pub fn new() -> ShaderWatcher {
+        ShaderWatcher::with_compiler("glslc")
+    }
+
+    /// Creates a [ShaderWatcher] that recompiles changed GLSL sources with `compiler`, which is
+    /// run as `<compiler> <source> -o <source>.spv`
+    pub fn with_compiler(compiler: &str) -> ShaderWatcher {
+        ShaderWatcher {
+            compiler: Some(compiler.to_string()),
+            shaders: HashMap::new(),
+            failed_builds: HashMap::new(),
+        }
+    }
+
+    /// Creates a [ShaderWatcher] that watches the compiled `.spv` files instead of the GLSL
+    /// sources, without compiling anything itself
+    pub fn precompiled() -> ShaderWatcher {
+        ShaderWatcher {
+            compiler: None,
+            shaders: HashMap::new(),
+            failed_builds: HashMap::new(),
+        }
+    }
+}
+
+impl ShaderWatcher {
+    /// Starts watching the shaders `pipeline` was created from
+    pub fn watch<V, P>(&mut self, pipeline: &Pipeline<V, P>)
+    where
+        V: Vertex,
+        P: UniformBuffer,
+    {
+        for &file_path in pipeline.shader_paths() {
+            if !self.shaders.contains_key(file_path) {
+                let modified = self.modified(file_path);
+                self.shaders.insert(
+                    file_path,
+                    WatchedShader {
+                        modified,
+                        changed_at: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Checks the watched shaders for changes, compiling the changed GLSL sources, and returns
+    /// whether any shader changed
+    ///
+    /// Every watched shader is checked even if some fail to compile, so the shaders that did
+    /// compile can still be picked up by [ShaderWatcher::reload].
+    ///
+    /// # Errors
+    /// - [ShaderWatcherError::CompileErrors] - Changed shaders failed to compile, holding a
+    ///   [ShaderWatcherError::CantRunCompiler] or [ShaderWatcherError::CompileFailed] for each.
+    ///   Pipelines keep using the previous version of those shaders until they are changed again.
+    pub fn poll(&mut self) -> Result<bool, ShaderWatcherError> {
+        let mut changed = false;
+        let mut errors: Vec<ShaderWatcherError> = Vec::new();
+        let paths: Vec<&'static str> = self.shaders.keys().copied().collect();
+        for file_path in paths {
+            let modified = self.modified(file_path);
+            let shader = self
+                .shaders
+                .get_mut(file_path)
+                .expect("Shader is being watched");
+            if modified.is_none() || modified == shader.modified {
+                continue;
+            }
+            shader.modified = modified;
+
+            if let Some(compiler) = &self.compiler {
+                if let Err(err) = compile(compiler, file_path) {
+                    errors.push(err);
+                    continue;
+                }
+            }
+            shader.changed_at = Some(Instant::now());
+            changed = true;
+        }
+
+        if !errors.is_empty() {
+            return Err(ShaderWatcherError::CompileErrors(errors));
+        }
+
+        Ok(changed)
+    }
+
+    /// Returns whether any of the shaders `pipeline` was created from changed after it was built,
+    /// or after rebuilding it last failed
+    pub fn needs_rebuild<V, P>(&self, pipeline: &Pipeline<V, P>) -> bool
+    where
+        V: Vertex,
+        P: UniformBuffer,
+    {
+        let last_attempt = self
+            .failed_builds
+            .get(&pipeline.vk_handle())
+            .map_or(pipeline.built_at(), |&failed_at| {
+                failed_at.max(pipeline.built_at())
+            });
+        pipeline.shader_paths().iter().any(|file_path| {
+            self.shaders
+                .get(file_path)
+                .and_then(|shader| shader.changed_at)
+                .is_some_and(|changed_at| changed_at > last_attempt)
+        })
+    }
+
+    /// Rebuilds `pipeline` for use with `render_pass` if any of its shaders changed, and returns
+    /// whether it was rebuilt.
+    ///
+    /// Waits for the device to be idle before rebuilding, as the previous pipeline may still be
+    /// in use. If the rebuild fails the previous pipeline is kept, and is only rebuilt again
+    /// once one of its shaders changes.
+    ///
+    /// # Errors
+    /// - [ShaderWatcherError::PipelineError] - The changed shaders couldn't be loaded or don't
+    ///   match the pipeline, see [Pipeline::rebuild]
+    /// - [ShaderWatcherError::DeviceError] - Failed to wait for the device to be idle
+    pub fn reload<V, P>(
+        &mut self,
+        pipeline: &mut Pipeline<V, P>,
+        render_pass: &RenderPass,
+    ) -> Result<bool, ShaderWatcherError>
+    where
+        V: Vertex + 'static,
+        P: UniformBuffer + 'static,
+    {
+        if !self.needs_rebuild(pipeline) {
+            return Ok(false);
+        }
+
+        pipeline.device.wait_for_idle()?;
+        let previous_handle = pipeline.vk_handle();
+        if let Err(err) = pipeline.rebuild(render_pass) {
+            self.failed_builds.insert(previous_handle, Instant::now());
+            return Err(err.into());
+        }
+        self.failed_builds.remove(&previous_handle);
+
+        log::info!("Rebuilt pipeline from {:?}", pipeline.shader_paths());
+        Ok(true)
+    }
+
+    /// Returns the last modification time of the file watched for the shader at `file_path`
+    fn modified(&self, file_path: &str) -> Option<SystemTime> {
+        let path = match self.compiler {
+            Some(_) => Path::new(file_path).to_path_buf(),
+            None => spirv_path(file_path),
+        };
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+/// Formats a list of [ShaderWatcherErrors][ShaderWatcherError] with one error per line
+fn display_compile_errors(errors: &[ShaderWatcherError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("- {}", error))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Compiles the GLSL source at `file_path` into the SPIR-V file it is loaded from with
+/// `compiler`
+fn compile(compiler: &str, file_path: &str) -> Result<(), ShaderWatcherError> {
+    let output = Command::new(compiler)
+        .arg(file_path)
+        .arg("-o")
+        .arg(spirv_path(file_path))
+        .output()
+        .map_err(|err| ShaderWatcherError::CantRunCompiler {
+            compiler: compiler.to_string(),
+            message: err.to_string(),
+        })?;
+
+    if !output.status.success() {
+        return Err(ShaderWatcherError::CompileFailed {
+            path: file_path.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{fs::File, path::PathBuf, time::Duration};
+
+    /// Removes the directory of a test's shader files when dropped, even if the test panics
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Creates an empty file at `path` in a fresh directory, which is removed once the returned
+    /// guard is dropped
+    fn temp_file(path: &str) -> TempDir {
+        let dir = Path::new(path).parent().unwrap();
+        std::fs::create_dir_all(dir).unwrap();
+        File::create(path).unwrap();
+        TempDir(dir.to_path_buf())
+    }
+
+    /// Moves the modification time of the file at `path` forward by a second
+    fn touch(path: &Path) {
+        let modified = std::fs::metadata(path).unwrap().modified().unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    /// Starts watching the shader at `file_path` without a pipeline
+    fn watch(watcher: &mut ShaderWatcher, file_path: &'static str) {
+        let modified = watcher.modified(file_path);
+        watcher.shaders.insert(
+            file_path,
+            WatchedShader {
+                modified,
+                changed_at: None,
+            },
+        );
+    }
+
+    #[test]
+    fn spirv_path_appends_spv_to_the_extension() {
+        assert_eq!(
+            spirv_path("shaders/cube.vert"),
+            Path::new("shaders/cube.vert.spv")
+        );
+    }
+
+    #[test]
+    fn precompiled_watcher_picks_up_spirv_changes_once() {
+        let file_path = "../../target/magma-watcher-precompiled/precompiled.frag";
+        let spirv_file = "../../target/magma-watcher-precompiled/precompiled.frag.spv";
+        let _dir = temp_file(spirv_file);
+        let mut watcher = ShaderWatcher::precompiled();
+        watch(&mut watcher, file_path);
+
+        assert!(!watcher.poll().unwrap());
+        touch(Path::new(spirv_file));
+        assert!(watcher.poll().unwrap());
+        assert!(!watcher.poll().unwrap());
+        assert!(watcher.shaders[file_path].changed_at.is_some());
+    }
+
+    #[test]
+    fn missing_files_are_not_changes() {
+        let mut watcher = ShaderWatcher::precompiled();
+        watch(&mut watcher, "shaders/missing.frag");
+
+        assert!(!watcher.poll().unwrap());
+    }
+
+    #[test]
+    fn compile_errors_are_collected_without_picking_up_the_change() {
+        let file_path = "../../target/magma-watcher-broken/broken.frag";
+        let _dir = temp_file(file_path);
+        let mut watcher = ShaderWatcher::with_compiler("magma-missing-compiler");
+        watch(&mut watcher, file_path);
+
+        touch(Path::new(file_path));
+        match watcher.poll() {
+            Err(ShaderWatcherError::CompileErrors(errors)) => {
+                assert!(matches!(
+                    errors.as_slice(),
+                    [ShaderWatcherError::CantRunCompiler { .. }]
+                ));
+            }
+            result => panic!("Expected compile errors, got {:?}", result),
+        }
+        assert!(watcher.shaders[file_path].changed_at.is_none());
+        assert!(!watcher.poll().unwrap());
+    }
+}
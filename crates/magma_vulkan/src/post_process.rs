@@ -0,0 +1,310 @@
+//! This module chains full-screen post-processing passes, such as tonemapping, FXAA or bloom,
+//! that each sample the output of the previous pass, with the last pass drawing to the swapchain
+
+use ash::vk;
+use std::rc::Rc;
+
+use crate::{
+    core::{
+        commands::buffer::{ClearValue, CommandBuffer, CommandBufferError},
+        device::LogicalDevice,
+        swapchain::Swapchain,
+    },
+    descriptors::{
+        DescriptorError, DescriptorPool, DescriptorSet, DescriptorSetLayout,
+        DescriptorSetLayoutBinding, DescriptorType,
+    },
+    pipeline::{
+        shader::{Shader, ShaderStageFlags},
+        ubo::EmptyPushConstant,
+        vertex::EmptyVertex,
+        Pipeline, PipelineError,
+    },
+    render_pass::input_aspect_mask,
+    render_target::{RenderTarget, RenderTargetError},
+};
+
+/// Errors that can be returned by a [PostProcessChain]
+#[derive(thiserror::Error, Debug)]
+pub enum PostProcessError {
+    #[error("A post-processing chain needs at least one pass")]
+    NoPasses,
+    #[error("Failed to create the pipeline of a pass: {0}")]
+    PipelineError(#[from] PipelineError),
+    #[error("Failed to create the render target of a pass: {0}")]
+    RenderTargetError(#[from] RenderTargetError),
+    #[error(transparent)]
+    DescriptorError(#[from] DescriptorError),
+    #[error(transparent)]
+    CommandBufferError(#[from] CommandBufferError),
+}
+
+/// Allows you to create a [PostProcessChain]
+pub struct PostProcessChainBuilder {
+    /// Vertex and fragment shader of every pass, in the order they are applied
+    passes: Vec<(Shader, Shader)>,
+    /// Format of the scene and of the outputs of every pass but the last
+    format: vk::Format,
+}
+
+impl PostProcessChainBuilder {
+    /// Creates a new [PostProcessChainBuilder] with no passes, rendering the scene and
+    /// intermediate outputs in [vk::Format::R16G16B16A16_SFLOAT] so HDR colors are kept until
+    /// they are tonemapped
+    pub fn new() -> PostProcessChainBuilder {
+        PostProcessChainBuilder {
+            passes: Vec::new(),
+            format: vk::Format::R16G16B16A16_SFLOAT,
+        }
+    }
+
+    /// Adds a full-screen pass, applied after the passes that were added before it.
+    ///
+    /// The pass is drawn with
+    /// [CommandBuffer::draw_fullscreen_triangle][crate::core::commands::buffer::CommandBuffer::draw_fullscreen_triangle],
+    /// whose documentation shows a matching vertex shader, and the fragment shader samples the
+    /// previous pass's output from a `sampler2D` at set 0, binding 0.
+    pub fn add_pass(
+        mut self,
+        vertex_shader: Shader,
+        fragment_shader: Shader,
+    ) -> PostProcessChainBuilder {
+        self.passes.push((vertex_shader, fragment_shader));
+        self
+    }
+
+    /// Sets the format the scene and the outputs of every pass but the last are rendered in
+    pub fn format(mut self, format: vk::Format) -> PostProcessChainBuilder {
+        self.format = format;
+        self
+    }
+
+    /// Creates a [PostProcessChain] that draws to `swapchain`, with render targets the size of
+    /// the swapchain
+    ///
+    /// # Errors
+    /// - [PostProcessError::NoPasses] - No passes were added
+    /// - [PostProcessError::PipelineError] - A pass's shaders don't match the chain, e.g. the
+    ///   fragment shader declares descriptors besides the input at set 0, binding 0
+    /// - [PostProcessError::RenderTargetError] and [PostProcessError::DescriptorError] - Failed
+    ///   to create the render targets or descriptor sets of the passes
+    pub fn build(
+        self,
+        device: Rc<LogicalDevice>,
+        swapchain: &Swapchain,
+    ) -> Result<PostProcessChain, PostProcessError> {
+        if self.passes.is_empty() {
+            return Err(PostProcessError::NoPasses);
+        }
+
+        let (width, height) = swapchain.extent();
+        let mut targets: Vec<RenderTarget> = Vec::with_capacity(self.passes.len());
+        targets.push(RenderTarget::builder().color_format(self.format).build(
+            device.clone(),
+            width,
+            height,
+        )?);
+        for _ in 1..self.passes.len() {
+            targets.push(
+                RenderTarget::builder()
+                    .color_format(self.format)
+                    .depth(false)
+                    .build(device.clone(), width, height)?,
+            );
+        }
+
+        let set_layout = Rc::new(DescriptorSetLayout::new(
+            device.clone(),
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                shader_stage_flags: ShaderStageFlags::FRAGMENT,
+            }],
+        )?);
+        let descriptor_pool = Rc::new(
+            DescriptorPool::builder()
+                .add_pool_size(
+                    DescriptorType::CombinedImageSampler,
+                    self.passes.len() as u32,
+                )
+                .max_sets(self.passes.len() as u32)
+                .build(device.clone())?,
+        );
+
+        let pass_count = self.passes.len();
+        let mut passes: Vec<PostProcessPass> = Vec::with_capacity(pass_count);
+        for (index, (vertex_shader, fragment_shader)) in self.passes.into_iter().enumerate() {
+            let mut builder = Pipeline::<EmptyVertex, EmptyPushConstant>::builder()
+                .attach_shader(vertex_shader)
+                .attach_shader(fragment_shader)
+                .set_layouts(vec![set_layout.clone()]);
+            builder = match targets.get(index + 1) {
+                Some(output) => builder.render_pass(output.render_pass()),
+                None => builder
+                    .render_pass(swapchain.render_pass())
+                    .sample_count(swapchain.sample_count()),
+            };
+
+            let mut input = DescriptorSet::new(set_layout.clone(), descriptor_pool.clone())?;
+            input.write_image(0, targets[index].descriptor())?;
+
+            passes.push(PostProcessPass {
+                pipeline: builder.build(device.clone())?,
+                input,
+            });
+        }
+
+        Ok(PostProcessChain { passes, targets })
+    }
+}
+
+/// A full-screen pass of a [PostProcessChain]
+struct PostProcessPass {
+    /// Pipeline drawing the pass
+    pipeline: Pipeline<EmptyVertex, EmptyPushConstant>,
+    /// Descriptor set sampling the output of the previous pass
+    input: DescriptorSet,
+}
+
+/// A chain of full-screen passes that each sample the output of the previous pass, the first
+/// pass samples the scene drawn into [PostProcessChain::input] and the last pass draws to the
+/// swapchain image.
+///
+/// Pipelines drawing the scene are created with the render pass of
+/// [PostProcessChain::input], the scene is drawn into it at the start of every frame and then
+/// [PostProcessChain::record] applies the passes.
+pub struct PostProcessChain {
+    /// Passes in the order they are applied
+    passes: Vec<PostProcessPass>,
+    /// Input of every pass, the scene followed by the outputs of every pass but the last
+    targets: Vec<RenderTarget>,
+}
+
+impl PostProcessChain {
+    /// Creates a new [PostProcessChainBuilder]
+    pub fn builder() -> PostProcessChainBuilder {
+        PostProcessChainBuilder::new()
+    }
+}
+
+impl PostProcessChain {
+    /// Returns the render target the scene is drawn into before it is post-processed, which has
+    /// a depth attachment
+    pub fn input(&self) -> &RenderTarget {
+        &self.targets[0]
+    }
+
+    /// Records every pass into `command_buffer`, the last one drawing into the framebuffer of
+    /// `swapchain` at `image_index`.
+    ///
+    /// The scene must have been drawn into [PostProcessChain::input] earlier in the command
+    /// buffer. The swapchain's render pass is begun and ended by the last pass.
+    pub fn record(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        swapchain: &Swapchain,
+        image_index: usize,
+    ) -> Result<(), PostProcessError> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            match self.targets.get(index + 1) {
+                Some(output) => output.begin(command_buffer, [0.0, 0.0, 0.0, 0.0])?,
+                None => {
+                    let clear_values =
+                        clear_values(&swapchain.render_pass().description().attachments);
+                    let framebuffer = *swapchain
+                        .framebuffers()
+                        .get(image_index)
+                        .expect("Image index is within the swapchain's framebuffers");
+
+                    command_buffer.begin_render_pass(
+                        swapchain.render_pass(),
+                        framebuffer,
+                        swapchain.extent(),
+                        &clear_values,
+                    )?;
+                    let extent = swapchain.extent();
+                    command_buffer.set_viewport(extent.0 as f32, extent.1 as f32)?;
+                    command_buffer.set_scissor(extent)?;
+                }
+            }
+
+            pass.pipeline.bind(command_buffer);
+            pass.pipeline
+                .bind_descriptor_set(command_buffer, 0, &pass.input)?;
+            command_buffer.draw_fullscreen_triangle()?;
+            command_buffer.end_render_pass();
+        }
+
+        Ok(())
+    }
+
+    /// Resizes the render targets to the size of `swapchain` after it was recreated, and
+    /// rebuilds the last pass if the swapchain's render pass changed.
+    ///
+    /// The chain must not be in use by the device.
+    pub fn resize(&mut self, swapchain: &Swapchain) -> Result<(), PostProcessError> {
+        let (width, height) = swapchain.extent();
+        for target in self.targets.iter_mut() {
+            target.resize(width, height)?;
+        }
+        for (pass, target) in self.passes.iter_mut().zip(self.targets.iter()) {
+            pass.input.write_image(0, target.descriptor())?;
+        }
+
+        let last = self.passes.last_mut().expect("Chain has at least one pass");
+        if !last.pipeline.is_compatible_with(swapchain.render_pass()) {
+            last.pipeline.rebuild(swapchain.render_pass())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the values the swapchain's `attachments` are cleared to when the last pass begins,
+/// black for color attachments and the far plane for depth stencil attachments
+fn clear_values(attachments: &[vk::AttachmentDescription]) -> Vec<ClearValue> {
+    attachments
+        .iter()
+        .map(|attachment| {
+            if input_aspect_mask(attachment.format) == vk::ImageAspectFlags::COLOR {
+                ClearValue::Color(0.0, 0.0, 0.0, 1.0)
+            } else {
+                ClearValue::DepthStencil {
+                    depth: 1.0,
+                    stencil: 0,
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(format: vk::Format) -> vk::AttachmentDescription {
+        vk::AttachmentDescription {
+            format,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn depth_attachments_are_cleared_to_the_far_plane() {
+        let clear_values = clear_values(&[
+            attachment(vk::Format::B8G8R8A8_SRGB),
+            attachment(vk::Format::D32_SFLOAT),
+            attachment(vk::Format::D24_UNORM_S8_UINT),
+        ]);
+
+        assert!(matches!(
+            clear_values.as_slice(),
+            [
+                ClearValue::Color(r, g, b, a),
+                ClearValue::DepthStencil { depth: d0, stencil: 0 },
+                ClearValue::DepthStencil { depth: d1, stencil: 0 },
+            ] if [*r, *g, *b, *a] == [0.0, 0.0, 0.0, 1.0] && *d0 == 1.0 && *d1 == 1.0
+        ));
+    }
+}
@@ -0,0 +1,964 @@
+//! This module wraps the creation of a Vulkan render pass and checking whether render passes are
+//! compatible with each other
+
+use ash::vk;
+use std::rc::Rc;
+
+use crate::{
+    core::device::{DeviceExtension, LogicalDevice},
+    VulkanError,
+};
+
+/// Errors that can be returned by the [RenderPass]
+#[derive(thiserror::Error, Debug)]
+pub enum RenderPassError {
+    #[error("Failed to create a Vulkan render pass: {0}")]
+    CantCreate(VulkanError),
+    #[error("A render pass needs at least one subpass")]
+    NoSubpasses,
+    #[error("Subpass {subpass} references attachment {attachment}, but the render pass only has {count} attachments")]
+    InvalidAttachment {
+        subpass: usize,
+        attachment: u32,
+        count: usize,
+    },
+    #[error("Subpass {subpass} has {resolve} resolve attachments but {color} color attachments, it must resolve either none or all of them")]
+    ResolveCountMismatch {
+        subpass: usize,
+        resolve: usize,
+        color: usize,
+    },
+    #[error("Subpass {0} resolves its depth but has no depth stencil attachment")]
+    DepthResolveWithoutDepth(usize),
+    #[error("The render pass requires the '{0}' device extension to be enabled")]
+    MissingExtension(DeviceExtension),
+    #[error("The device doesn't support resolving depth with {0:?}")]
+    UnsupportedResolveMode(ResolveMode),
+    #[error("The device can neither resolve stencil with {0:?} nor leave it unresolved while resolving depth")]
+    UnsupportedStencilResolveMode(ResolveMode),
+}
+
+/// Possible ways of resolving the samples of a multisampled depth attachment into a single
+/// sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolveMode {
+    /// Uses the value of the first sample, always supported
+    SampleZero,
+    /// Uses the average of all samples
+    Average,
+    /// Uses the smallest of all samples
+    Min,
+    /// Uses the largest of all samples
+    Max,
+}
+
+impl Into<vk::ResolveModeFlags> for ResolveMode {
+    fn into(self) -> vk::ResolveModeFlags {
+        match self {
+            ResolveMode::SampleZero => vk::ResolveModeFlags::SAMPLE_ZERO,
+            ResolveMode::Average => vk::ResolveModeFlags::AVERAGE,
+            ResolveMode::Min => vk::ResolveModeFlags::MIN,
+            ResolveMode::Max => vk::ResolveModeFlags::MAX,
+        }
+    }
+}
+
+/// Describes how a subpass resolves its multisampled depth stencil attachment into a single
+/// sample attachment, so that the depth can be sampled afterwards, e.g. for SSAO
+///
+/// Requires [DeviceExtension::CreateRenderPass2] and [DeviceExtension::DepthStencilResolve].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthResolve {
+    /// Attachment the depth is resolved into
+    pub attachment: u32,
+    /// How the samples of each pixel are combined
+    pub mode: ResolveMode,
+}
+
+/// Describes which attachments of a [RenderPass] a subpass uses, by their index in the render
+/// pass
+///
+/// The layouts of the attachments during the subpass are chosen from how they are used.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Subpass {
+    /// Attachments that are written to as color attachments
+    pub color_attachments: Vec<u32>,
+    /// Attachment that is used as the depth stencil attachment
+    pub depth_stencil_attachment: Option<u32>,
+    /// Attachments that are read from in shaders as input attachments
+    pub input_attachments: Vec<u32>,
+    /// Attachments that each color attachment is resolved into, must either be empty or have the
+    /// same length as the color attachments
+    pub resolve_attachments: Vec<u32>,
+    /// Attachments that aren't used by this subpass but whose contents must be preserved
+    pub preserve_attachments: Vec<u32>,
+    /// How the depth stencil attachment is resolved, if at all
+    pub depth_resolve: Option<DepthResolve>,
+}
+
+impl Subpass {
+    /// Returns every attachment index this subpass references
+    fn attachment_indices(&self) -> impl Iterator<Item = &u32> {
+        self.color_attachments
+            .iter()
+            .chain(self.depth_stencil_attachment.iter())
+            .chain(self.input_attachments.iter())
+            .chain(self.resolve_attachments.iter())
+            .chain(self.preserve_attachments.iter())
+            .chain(self.depth_resolve.iter().map(|resolve| &resolve.attachment))
+    }
+}
+
+/// Describes the attachments, subpasses, and dependencies a [RenderPass] was created with
+///
+/// Kept separately from the render pass so that objects created against a render pass, such as a
+/// [Pipeline][crate::pipeline::Pipeline], can check compatibility after the render pass has been
+/// destroyed.
+#[derive(Clone, Debug)]
+pub struct RenderPassDescription {
+    /// Attachments used by the render pass
+    pub attachments: Vec<vk::AttachmentDescription>,
+    /// Subpasses of the render pass
+    pub subpasses: Vec<Subpass>,
+    /// Execution and memory dependencies between subpasses
+    pub dependencies: Vec<vk::SubpassDependency>,
+}
+
+impl RenderPassDescription {
+    /// Checks whether a render pass with this description is compatible with one described by
+    /// `other`, following the Vulkan render pass compatibility rules.
+    ///
+    /// Two render passes are compatible if their corresponding attachment references have
+    /// matching formats and sample counts, and everything else is identical except for the
+    /// load/store operations and image layouts. If both render passes only have a single subpass,
+    /// resolve attachments are ignored.
+    ///
+    /// Framebuffers and pipelines created with one render pass can be used with any other
+    /// compatible render pass.
+    pub fn is_compatible_with(&self, other: &RenderPassDescription) -> bool {
+        if self.subpasses.len() != other.subpasses.len()
+            || self.dependencies.len() != other.dependencies.len()
+        {
+            return false;
+        }
+
+        let dependencies_match =
+            self.dependencies
+                .iter()
+                .zip(other.dependencies.iter())
+                .all(|(a, b)| {
+                    a.src_subpass == b.src_subpass
+                        && a.dst_subpass == b.dst_subpass
+                        && a.src_stage_mask == b.src_stage_mask
+                        && a.dst_stage_mask == b.dst_stage_mask
+                        && a.src_access_mask == b.src_access_mask
+                        && a.dst_access_mask == b.dst_access_mask
+                        && a.dependency_flags == b.dependency_flags
+                });
+        if !dependencies_match {
+            return false;
+        }
+
+        let ignore_resolve = self.subpasses.len() == 1;
+        self.subpasses
+            .iter()
+            .zip(other.subpasses.iter())
+            .all(|(a, b)| {
+                self.references_compatible(&a.color_attachments, other, &b.color_attachments)
+                    && self.references_compatible(
+                        &a.input_attachments,
+                        other,
+                        &b.input_attachments,
+                    )
+                    && self.reference_compatible(
+                        a.depth_stencil_attachment,
+                        other,
+                        b.depth_stencil_attachment,
+                    )
+                    && (ignore_resolve
+                        || (self.references_compatible(
+                            &a.resolve_attachments,
+                            other,
+                            &b.resolve_attachments,
+                        ) && self.reference_compatible(
+                            a.depth_resolve.map(|resolve| resolve.attachment),
+                            other,
+                            b.depth_resolve.map(|resolve| resolve.attachment),
+                        ) && a.depth_resolve.map(|resolve| resolve.mode)
+                            == b.depth_resolve.map(|resolve| resolve.mode)))
+                    && a.preserve_attachments == b.preserve_attachments
+            })
+    }
+
+    /// Checks whether two arrays of attachment references are compatible, treating the shorter
+    /// array as if it was padded with unused references
+    fn references_compatible(
+        &self,
+        references: &[u32],
+        other: &RenderPassDescription,
+        other_references: &[u32],
+    ) -> bool {
+        let count = references.len().max(other_references.len());
+        (0..count).all(|i| {
+            self.reference_compatible(
+                references.get(i).copied(),
+                other,
+                other_references.get(i).copied(),
+            )
+        })
+    }
+
+    /// Checks whether two attachment references are compatible, they are if they are both unused
+    /// or reference attachments with the same format and sample count
+    fn reference_compatible(
+        &self,
+        reference: Option<u32>,
+        other: &RenderPassDescription,
+        other_reference: Option<u32>,
+    ) -> bool {
+        let attachment = reference.and_then(|index| self.attachments.get(index as usize));
+        let other_attachment =
+            other_reference.and_then(|index| other.attachments.get(index as usize));
+
+        match (attachment, other_attachment) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a.format == b.format && a.samples == b.samples,
+            _ => false,
+        }
+    }
+}
+
+/// Allows you to create a [RenderPass]
+pub struct RenderPassBuilder {
+    /// Description of the render pass being built
+    description: RenderPassDescription,
+}
+
+impl RenderPassBuilder {
+    /// Creates a new [RenderPassBuilder] with no attachments or subpasses
+    pub fn new() -> RenderPassBuilder {
+        RenderPassBuilder {
+            description: RenderPassDescription {
+                attachments: Vec::new(),
+                subpasses: Vec::new(),
+                dependencies: Vec::new(),
+            },
+        }
+    }
+
+    /// Adds an attachment to the render pass, it can be referenced in subpasses by the order it
+    /// was added in
+    pub fn add_attachment(mut self, attachment: vk::AttachmentDescription) -> RenderPassBuilder {
+        self.description.attachments.push(attachment);
+        self
+    }
+
+    /// Adds a subpass to the render pass
+    pub fn add_subpass(mut self, subpass: Subpass) -> RenderPassBuilder {
+        self.description.subpasses.push(subpass);
+        self
+    }
+
+    /// Adds a dependency between two subpasses of the render pass
+    pub fn add_dependency(mut self, dependency: vk::SubpassDependency) -> RenderPassBuilder {
+        self.description.dependencies.push(dependency);
+        self
+    }
+
+    /// Builds a [RenderPass] from the attachments, subpasses, and dependencies that were added
+    ///
+    /// # Errors
+    /// - [RenderPassError::NoSubpasses] - No subpasses were added
+    /// - [RenderPassError::InvalidAttachment] - A subpass references an attachment that wasn't
+    ///   added
+    /// - [RenderPassError::ResolveCountMismatch] - A subpass resolves a different number of
+    ///   attachments than it has color attachments
+    /// - [RenderPassError::DepthResolveWithoutDepth], [RenderPassError::MissingExtension],
+    ///   [RenderPassError::UnsupportedResolveMode], and
+    ///   [RenderPassError::UnsupportedStencilResolveMode] - A subpass resolves depth without a
+    ///   depth attachment, without the required extensions, or with a mode the device doesn't
+    ///   support
+    /// - [RenderPassError::CantCreate] - Failed to create the Vulkan render pass, see the
+    ///   contained [VulkanError] for more information
+    pub fn build(self, device: Rc<LogicalDevice>) -> Result<RenderPass, RenderPassError> {
+        let description = self.description;
+        validate_subpasses(&description)?;
+
+        let resolves_depth = description
+            .subpasses
+            .iter()
+            .any(|subpass| subpass.depth_resolve.is_some());
+        let handle = if resolves_depth {
+            create_render_pass2(&device, &description)?
+        } else {
+            create_render_pass(&device, &description)?
+        };
+
+        Ok(RenderPass {
+            description,
+            handle,
+            device,
+        })
+    }
+}
+
+/// Checks that `description` has at least one subpass, and that its subpasses only reference
+/// attachments that exist and resolve either none or all of their color attachments
+///
+/// # Errors
+/// - [RenderPassError::NoSubpasses] - The description has no subpasses
+/// - [RenderPassError::InvalidAttachment] - A subpass references an attachment out of range
+/// - [RenderPassError::ResolveCountMismatch] - A subpass has resolve attachments, but not as
+///   many as it has color attachments
+fn validate_subpasses(description: &RenderPassDescription) -> Result<(), RenderPassError> {
+    if description.subpasses.is_empty() {
+        return Err(RenderPassError::NoSubpasses);
+    }
+
+    for (subpass_index, subpass) in description.subpasses.iter().enumerate() {
+        if let Some(&attachment) = subpass.attachment_indices().find(|&&attachment| {
+            attachment != vk::ATTACHMENT_UNUSED
+                && attachment as usize >= description.attachments.len()
+        }) {
+            return Err(RenderPassError::InvalidAttachment {
+                subpass: subpass_index,
+                attachment,
+                count: description.attachments.len(),
+            });
+        }
+
+        if !subpass.resolve_attachments.is_empty()
+            && subpass.resolve_attachments.len() != subpass.color_attachments.len()
+        {
+            return Err(RenderPassError::ResolveCountMismatch {
+                subpass: subpass_index,
+                resolve: subpass.resolve_attachments.len(),
+                color: subpass.color_attachments.len(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a Vulkan render pass from `description`
+fn create_render_pass(
+    device: &LogicalDevice,
+    description: &RenderPassDescription,
+) -> Result<vk::RenderPass, RenderPassError> {
+    let references = |attachments: &[u32], layout: vk::ImageLayout| {
+        attachments
+            .iter()
+            .map(|&attachment| vk::AttachmentReference { attachment, layout })
+            .collect::<Vec<vk::AttachmentReference>>()
+    };
+    let color_references: Vec<Vec<vk::AttachmentReference>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            references(
+                &subpass.color_attachments,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+        })
+        .collect();
+    let input_references: Vec<Vec<vk::AttachmentReference>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            references(
+                &subpass.input_attachments,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+        })
+        .collect();
+    let resolve_references: Vec<Vec<vk::AttachmentReference>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            references(
+                &subpass.resolve_attachments,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+        })
+        .collect();
+    let depth_references: Vec<Option<vk::AttachmentReference>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            subpass
+                .depth_stencil_attachment
+                .map(|attachment| vk::AttachmentReference {
+                    attachment,
+                    layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                })
+        })
+        .collect();
+
+    let subpasses: Vec<vk::SubpassDescription> = description
+        .subpasses
+        .iter()
+        .enumerate()
+        .map(|(i, subpass)| {
+            let mut builder = vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&color_references[i])
+                .input_attachments(&input_references[i])
+                .preserve_attachments(&subpass.preserve_attachments);
+            if !resolve_references[i].is_empty() {
+                builder = builder.resolve_attachments(&resolve_references[i]);
+            }
+            if let Some(depth_reference) = depth_references[i].as_ref() {
+                builder = builder.depth_stencil_attachment(depth_reference);
+            }
+
+            builder.build()
+        })
+        .collect();
+
+    let create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&description.attachments)
+        .subpasses(&subpasses)
+        .dependencies(&description.dependencies);
+
+    unsafe {
+        device
+            .vk_handle()
+            .create_render_pass(&create_info, None)
+            .map_err(|err| RenderPassError::CantCreate(err.into()))
+    }
+}
+
+/// Returns the aspects of an attachment with `format` that are read from as an input attachment
+pub(crate) fn input_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+/// Returns how the stencil of a depth stencil attachment with `format` is resolved while its
+/// depth is resolved with `mode`, given the device's resolve `properties`
+///
+/// Stencil is resolved with the same mode as depth when the device supports it, as leaving it
+/// unresolved while resolving depth requires `independentResolveNone` for formats with stencil.
+fn stencil_resolve_mode(
+    mode: ResolveMode,
+    format: vk::Format,
+    properties: &vk::PhysicalDeviceDepthStencilResolveProperties,
+) -> Result<vk::ResolveModeFlags, RenderPassError> {
+    if !input_aspect_mask(format).contains(vk::ImageAspectFlags::STENCIL) {
+        return Ok(vk::ResolveModeFlags::NONE);
+    }
+
+    if properties
+        .supported_stencil_resolve_modes
+        .contains(mode.into())
+    {
+        Ok(mode.into())
+    } else if properties.independent_resolve_none == vk::TRUE {
+        Ok(vk::ResolveModeFlags::NONE)
+    } else {
+        Err(RenderPassError::UnsupportedStencilResolveMode(mode))
+    }
+}
+
+/// Creates a Vulkan render pass from `description` through `VK_KHR_create_renderpass2`, which
+/// is needed to resolve depth stencil attachments
+///
+/// # Errors
+/// - [RenderPassError::DepthResolveWithoutDepth] - A subpass resolves depth without having a
+///   depth stencil attachment
+/// - [RenderPassError::MissingExtension] - [DeviceExtension::CreateRenderPass2] or
+///   [DeviceExtension::DepthStencilResolve] isn't enabled
+/// - [RenderPassError::UnsupportedResolveMode] - The device can't resolve depth with the mode
+/// - [RenderPassError::UnsupportedStencilResolveMode] - The device can't resolve the stencil of
+///   a depth stencil format with the mode, or leave it unresolved
+fn create_render_pass2(
+    device: &LogicalDevice,
+    description: &RenderPassDescription,
+) -> Result<vk::RenderPass, RenderPassError> {
+    let physical_device = device.physical_device();
+    for extension in [
+        DeviceExtension::CreateRenderPass2,
+        DeviceExtension::DepthStencilResolve,
+    ] {
+        if !physical_device.enabled_extensions().contains(&extension) {
+            return Err(RenderPassError::MissingExtension(extension));
+        }
+    }
+
+    let resolve_properties = physical_device.depth_stencil_resolve_properties();
+    let mut stencil_resolve_modes: Vec<vk::ResolveModeFlags> =
+        Vec::with_capacity(description.subpasses.len());
+    for (subpass_index, subpass) in description.subpasses.iter().enumerate() {
+        let stencil_mode = match subpass.depth_resolve {
+            Some(resolve) => {
+                let depth_attachment = subpass
+                    .depth_stencil_attachment
+                    .filter(|&attachment| attachment != vk::ATTACHMENT_UNUSED)
+                    .ok_or(RenderPassError::DepthResolveWithoutDepth(subpass_index))?;
+                if !resolve_properties
+                    .supported_depth_resolve_modes
+                    .contains(resolve.mode.into())
+                {
+                    return Err(RenderPassError::UnsupportedResolveMode(resolve.mode));
+                }
+
+                stencil_resolve_mode(
+                    resolve.mode,
+                    description.attachments[depth_attachment as usize].format,
+                    resolve_properties,
+                )?
+            }
+            None => vk::ResolveModeFlags::NONE,
+        };
+        stencil_resolve_modes.push(stencil_mode);
+    }
+
+    let attachments: Vec<vk::AttachmentDescription2> = description
+        .attachments
+        .iter()
+        .map(|attachment| {
+            vk::AttachmentDescription2::builder()
+                .flags(attachment.flags)
+                .format(attachment.format)
+                .samples(attachment.samples)
+                .load_op(attachment.load_op)
+                .store_op(attachment.store_op)
+                .stencil_load_op(attachment.stencil_load_op)
+                .stencil_store_op(attachment.stencil_store_op)
+                .initial_layout(attachment.initial_layout)
+                .final_layout(attachment.final_layout)
+                .build()
+        })
+        .collect();
+
+    let reference = |attachment: u32, layout: vk::ImageLayout, aspect_mask: vk::ImageAspectFlags| {
+        vk::AttachmentReference2::builder()
+            .attachment(attachment)
+            .layout(layout)
+            .aspect_mask(aspect_mask)
+            .build()
+    };
+    let references = |attachments: &[u32], layout: vk::ImageLayout| {
+        attachments
+            .iter()
+            .map(|&attachment| reference(attachment, layout, vk::ImageAspectFlags::COLOR))
+            .collect::<Vec<vk::AttachmentReference2>>()
+    };
+    let color_references: Vec<Vec<vk::AttachmentReference2>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            references(
+                &subpass.color_attachments,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+        })
+        .collect();
+    let input_references: Vec<Vec<vk::AttachmentReference2>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            subpass
+                .input_attachments
+                .iter()
+                .map(|&attachment| {
+                    reference(
+                        attachment,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        description
+                            .attachments
+                            .get(attachment as usize)
+                            .map_or(vk::ImageAspectFlags::empty(), |attachment| {
+                                input_aspect_mask(attachment.format)
+                            }),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let resolve_references: Vec<Vec<vk::AttachmentReference2>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            references(
+                &subpass.resolve_attachments,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+        })
+        .collect();
+    let depth_reference = |attachment: u32| {
+        reference(
+            attachment,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::ImageAspectFlags::empty(),
+        )
+    };
+    let depth_references: Vec<Option<vk::AttachmentReference2>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| subpass.depth_stencil_attachment.map(depth_reference))
+        .collect();
+    let depth_resolve_references: Vec<Option<vk::AttachmentReference2>> = description
+        .subpasses
+        .iter()
+        .map(|subpass| {
+            subpass
+                .depth_resolve
+                .map(|resolve| depth_reference(resolve.attachment))
+        })
+        .collect();
+    let mut depth_resolve_infos: Vec<vk::SubpassDescriptionDepthStencilResolve> = description
+        .subpasses
+        .iter()
+        .enumerate()
+        .map(|(i, subpass)| {
+            let mut builder = vk::SubpassDescriptionDepthStencilResolve::builder()
+                .stencil_resolve_mode(stencil_resolve_modes[i]);
+            if let (Some(resolve), Some(resolve_reference)) =
+                (subpass.depth_resolve, depth_resolve_references[i].as_ref())
+            {
+                builder = builder
+                    .depth_resolve_mode(resolve.mode.into())
+                    .depth_stencil_resolve_attachment(resolve_reference);
+            }
+
+            builder.build()
+        })
+        .collect();
+
+    let subpasses: Vec<vk::SubpassDescription2> = description
+        .subpasses
+        .iter()
+        .zip(depth_resolve_infos.iter_mut())
+        .enumerate()
+        .map(|(i, (subpass, depth_resolve_info))| {
+            let mut builder = vk::SubpassDescription2::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&color_references[i])
+                .input_attachments(&input_references[i])
+                .preserve_attachments(&subpass.preserve_attachments);
+            if !resolve_references[i].is_empty() {
+                builder = builder.resolve_attachments(&resolve_references[i]);
+            }
+            if let Some(depth_reference) = depth_references[i].as_ref() {
+                builder = builder.depth_stencil_attachment(depth_reference);
+            }
+            if subpass.depth_resolve.is_some() {
+                builder = builder.push_next(depth_resolve_info);
+            }
+
+            builder.build()
+        })
+        .collect();
+
+    let dependencies: Vec<vk::SubpassDependency2> = description
+        .dependencies
+        .iter()
+        .map(|dependency| {
+            vk::SubpassDependency2::builder()
+                .src_subpass(dependency.src_subpass)
+                .dst_subpass(dependency.dst_subpass)
+                .src_stage_mask(dependency.src_stage_mask)
+                .dst_stage_mask(dependency.dst_stage_mask)
+                .src_access_mask(dependency.src_access_mask)
+                .dst_access_mask(dependency.dst_access_mask)
+                .dependency_flags(dependency.dependency_flags)
+                .build()
+        })
+        .collect();
+
+    let create_info = vk::RenderPassCreateInfo2::builder()
+        .attachments(&attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+
+    let create_render_pass2 = ash::extensions::khr::CreateRenderPass2::new(
+        device.instance().vk_handle(),
+        device.vk_handle(),
+    );
+    unsafe {
+        create_render_pass2
+            .create_render_pass2(&create_info, None)
+            .map_err(|err| RenderPassError::CantCreate(err.into()))
+    }
+}
+
+/// Wraps a Vulkan render pass
+pub struct RenderPass {
+    /// Description the render pass was created with
+    description: RenderPassDescription,
+    /// Opaque handle to Vulkan render pass
+    handle: vk::RenderPass,
+    /// [LogicalDevice] the render pass belongs to
+    device: Rc<LogicalDevice>,
+}
+
+impl RenderPass {
+    /// Creates a new [RenderPassBuilder]
+    pub fn builder() -> RenderPassBuilder {
+        RenderPassBuilder::new()
+    }
+}
+
+impl RenderPass {
+    /// Returns the Vulkan handle to the render pass
+    pub(crate) fn vk_handle(&self) -> vk::RenderPass {
+        self.handle
+    }
+
+    /// Returns the attachments, subpasses, and dependencies the render pass was created with
+    pub fn description(&self) -> &RenderPassDescription {
+        &self.description
+    }
+
+    /// Checks whether this render pass is compatible with `other`
+    ///
+    /// See [RenderPassDescription::is_compatible_with]
+    pub fn is_compatible_with(&self, other: &RenderPass) -> bool {
+        self.description.is_compatible_with(&other.description)
+    }
+}
+
+impl Drop for RenderPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .vk_handle()
+                .destroy_render_pass(self.handle, None);
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Describes a render pass with a single subpass drawing to a color attachment of `format`
+    /// that is loaded with `load_op`
+    fn color_pass(format: vk::Format, load_op: vk::AttachmentLoadOp) -> RenderPassDescription {
+        RenderPassDescription {
+            attachments: vec![vk::AttachmentDescription::builder()
+                .format(format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .build()],
+            subpasses: vec![Subpass {
+                color_attachments: vec![0],
+                ..Default::default()
+            }],
+            dependencies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn load_op_difference_is_compatible() {
+        let clear = color_pass(vk::Format::B8G8R8A8_SRGB, vk::AttachmentLoadOp::CLEAR);
+        let load = color_pass(vk::Format::B8G8R8A8_SRGB, vk::AttachmentLoadOp::LOAD);
+
+        assert!(clear.is_compatible_with(&load));
+        assert!(load.is_compatible_with(&clear));
+    }
+
+    #[test]
+    fn format_difference_is_incompatible() {
+        let srgb = color_pass(vk::Format::B8G8R8A8_SRGB, vk::AttachmentLoadOp::CLEAR);
+        let unorm = color_pass(vk::Format::B8G8R8A8_UNORM, vk::AttachmentLoadOp::CLEAR);
+
+        assert!(!srgb.is_compatible_with(&unorm));
+        assert!(!unorm.is_compatible_with(&srgb));
+    }
+
+    /// Returns resolve properties supporting `stencil_modes`, and leaving stencil unresolved if
+    /// `independent_resolve_none` is set
+    fn resolve_properties(
+        stencil_modes: vk::ResolveModeFlags,
+        independent_resolve_none: bool,
+    ) -> vk::PhysicalDeviceDepthStencilResolveProperties {
+        vk::PhysicalDeviceDepthStencilResolveProperties {
+            supported_depth_resolve_modes: vk::ResolveModeFlags::SAMPLE_ZERO
+                | vk::ResolveModeFlags::MIN,
+            supported_stencil_resolve_modes: stencil_modes,
+            independent_resolve_none: independent_resolve_none as vk::Bool32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stencil_is_resolved_with_the_depth_mode() {
+        let properties = resolve_properties(vk::ResolveModeFlags::SAMPLE_ZERO, false);
+
+        assert_eq!(
+            stencil_resolve_mode(
+                ResolveMode::SampleZero,
+                vk::Format::D24_UNORM_S8_UINT,
+                &properties
+            )
+            .unwrap(),
+            vk::ResolveModeFlags::SAMPLE_ZERO
+        );
+    }
+
+    #[test]
+    fn depth_only_formats_leave_stencil_unresolved() {
+        let properties = resolve_properties(vk::ResolveModeFlags::empty(), false);
+
+        assert_eq!(
+            stencil_resolve_mode(ResolveMode::Min, vk::Format::D32_SFLOAT, &properties).unwrap(),
+            vk::ResolveModeFlags::NONE
+        );
+    }
+
+    #[test]
+    fn unresolved_stencil_requires_independent_resolve_none() {
+        let properties = resolve_properties(vk::ResolveModeFlags::SAMPLE_ZERO, true);
+        assert_eq!(
+            stencil_resolve_mode(
+                ResolveMode::Min,
+                vk::Format::D32_SFLOAT_S8_UINT,
+                &properties
+            )
+            .unwrap(),
+            vk::ResolveModeFlags::NONE
+        );
+
+        let properties = resolve_properties(vk::ResolveModeFlags::SAMPLE_ZERO, false);
+        assert!(matches!(
+            stencil_resolve_mode(
+                ResolveMode::Min,
+                vk::Format::D32_SFLOAT_S8_UINT,
+                &properties
+            ),
+            Err(RenderPassError::UnsupportedStencilResolveMode(
+                ResolveMode::Min
+            ))
+        ));
+    }
+
+    #[test]
+    fn partial_resolve_attachments_error() {
+        let mut description = color_pass(vk::Format::B8G8R8A8_SRGB, vk::AttachmentLoadOp::CLEAR);
+        description.attachments = vec![description.attachments[0]; 3];
+        description.subpasses[0] = Subpass {
+            color_attachments: vec![0, 1],
+            resolve_attachments: vec![2],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            validate_subpasses(&description),
+            Err(RenderPassError::ResolveCountMismatch {
+                subpass: 0,
+                resolve: 1,
+                color: 2,
+            })
+        ));
+
+        description.subpasses[0].resolve_attachments.clear();
+        assert!(validate_subpasses(&description).is_ok());
+    }
+
+    #[test]
+    fn unused_attachment_references_are_valid() {
+        let mut description = color_pass(vk::Format::B8G8R8A8_SRGB, vk::AttachmentLoadOp::CLEAR);
+        description.subpasses[0]
+            .color_attachments
+            .push(vk::ATTACHMENT_UNUSED);
+
+        assert!(validate_subpasses(&description).is_ok());
+    }
+
+    /// Describes a render pass whose first subpass renders to a multisampled depth attachment
+    /// that is resolved into a single sample attachment with `mode`, which the second subpass
+    /// reads as an input attachment
+    fn depth_resolve_pass(mode: ResolveMode) -> RenderPassDescription {
+        let depth_attachment = |samples: vk::SampleCountFlags| {
+            vk::AttachmentDescription::builder()
+                .format(vk::Format::D32_SFLOAT)
+                .samples(samples)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .build()
+        };
+
+        let builder = RenderPassBuilder::new()
+            .add_attachment(depth_attachment(vk::SampleCountFlags::TYPE_4))
+            .add_attachment(depth_attachment(vk::SampleCountFlags::TYPE_1))
+            .add_subpass(Subpass {
+                depth_stencil_attachment: Some(0),
+                depth_resolve: Some(DepthResolve {
+                    attachment: 1,
+                    mode,
+                }),
+                ..Default::default()
+            })
+            .add_subpass(Subpass {
+                input_attachments: vec![1],
+                ..Default::default()
+            });
+        builder.description
+    }
+
+    #[test]
+    fn depth_resolve_is_kept_in_the_description() {
+        let description = depth_resolve_pass(ResolveMode::Min);
+
+        assert!(validate_subpasses(&description).is_ok());
+        assert_eq!(
+            description.subpasses[0].depth_resolve,
+            Some(DepthResolve {
+                attachment: 1,
+                mode: ResolveMode::Min,
+            })
+        );
+        assert!(description.is_compatible_with(&depth_resolve_pass(ResolveMode::Min)));
+    }
+
+    #[test]
+    fn depth_resolve_mode_difference_is_incompatible() {
+        let min = depth_resolve_pass(ResolveMode::Min);
+        let max = depth_resolve_pass(ResolveMode::Max);
+
+        assert!(!min.is_compatible_with(&max));
+        assert!(!max.is_compatible_with(&min));
+    }
+
+    #[test]
+    fn depth_resolve_attachment_difference_is_incompatible() {
+        let resolved = depth_resolve_pass(ResolveMode::SampleZero);
+        let mut unresolved = resolved.clone();
+        unresolved.subpasses[0].depth_resolve = None;
+
+        assert!(!resolved.is_compatible_with(&unresolved));
+        assert!(!unresolved.is_compatible_with(&resolved));
+    }
+
+    #[test]
+    fn depth_formats_have_depth_aspects() {
+        assert_eq!(
+            input_aspect_mask(vk::Format::D32_SFLOAT),
+            vk::ImageAspectFlags::DEPTH
+        );
+        assert_eq!(
+            input_aspect_mask(vk::Format::D24_UNORM_S8_UINT),
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        );
+        assert_eq!(
+            input_aspect_mask(vk::Format::B8G8R8A8_SRGB),
+            vk::ImageAspectFlags::COLOR
+        );
+    }
+}
@@ -0,0 +1,544 @@
+//! This module wraps offscreen rendering into images that can then be sampled as textures, e.g.
+//! for post-processing, shadow maps or minimaps
+
+use ash::vk;
+use std::rc::Rc;
+
+use crate::{
+    buffer::MemoryPropertyFlags,
+    core::{
+        commands::buffer::{ClearValue, CommandBuffer, CommandBufferError},
+        device::{LogicalDevice, LogicalDeviceError},
+    },
+    render_pass::{RenderPass, RenderPassDescription, RenderPassError, Subpass},
+    sampler::SamplerInfo,
+    VulkanError,
+};
+
+/// Errors that can be returned by a [RenderTarget]
+#[derive(thiserror::Error, Debug)]
+pub enum RenderTargetError {
+    #[error("Can't create a render target with a width or height of 0")]
+    EmptyExtent,
+    #[error("{0:?} can't be rendered to and sampled from on this device")]
+    UnsupportedFormat(vk::Format),
+    #[error("Failed to create a Vulkan image view: {0}")]
+    CantCreateImageView(VulkanError),
+    #[error("Failed to create a Vulkan framebuffer: {0}")]
+    CantCreateFramebuffer(VulkanError),
+    #[error("Failed to create the render target's render pass: {0}")]
+    RenderPassError(#[from] RenderPassError),
+    #[error(transparent)]
+    DeviceError(#[from] LogicalDeviceError),
+}
+
+/// Allows you to create a [RenderTarget]
+pub struct RenderTargetBuilder {
+    /// Format of the color attachment
+    color_format: vk::Format,
+    /// Whether the render target has a depth attachment
+    depth: bool,
+    /// How the color attachment is sampled
+    sampler: SamplerInfo,
+}
+
+impl RenderTargetBuilder {
+    /// Creates a new [RenderTargetBuilder] for an RGBA render target with a depth attachment,
+    /// sampled with linear filtering
+    pub fn new() -> RenderTargetBuilder {
+        RenderTargetBuilder {
+            color_format: vk::Format::R8G8B8A8_UNORM,
+            depth: true,
+            sampler: SamplerInfo::default(),
+        }
+    }
+
+    /// Sets the format of the color attachment, e.g. [vk::Format::R16G16B16A16_SFLOAT] to render
+    /// HDR colors that are tonemapped later
+    pub fn color_format(mut self, format: vk::Format) -> RenderTargetBuilder {
+        self.color_format = format;
+        self
+    }
+
+    /// Sets whether the render target has a depth attachment, defaults to `true`
+    pub fn depth(mut self, depth: bool) -> RenderTargetBuilder {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets how the color attachment is sampled, see [SamplerInfo]
+    pub fn sampler(mut self, sampler: SamplerInfo) -> RenderTargetBuilder {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Creates a [RenderTarget] of `width` by `height` pixels
+    ///
+    /// # Errors
+    /// - [RenderTargetError::EmptyExtent] - `width` or `height` is 0
+    /// - [RenderTargetError::UnsupportedFormat] - The color format can't be used as both a color
+    ///   attachment and a sampled image
+    /// - [RenderTargetError::RenderPassError], [RenderTargetError::CantCreateImageView] and
+    ///   [RenderTargetError::CantCreateFramebuffer] - Failed to create required Vulkan objects
+    pub fn build(
+        self,
+        device: Rc<LogicalDevice>,
+        width: u32,
+        height: u32,
+    ) -> Result<RenderTarget, RenderTargetError> {
+        device
+            .find_supported_format(
+                &[self.color_format],
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            )
+            .map_err(|_| RenderTargetError::UnsupportedFormat(self.color_format))?;
+        let depth_format = if self.depth {
+            Some(device.find_supported_format(
+                &[
+                    vk::Format::D32_SFLOAT,
+                    vk::Format::D32_SFLOAT_S8_UINT,
+                    vk::Format::D24_UNORM_S8_UINT,
+                ],
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+            )?)
+        } else {
+            None
+        };
+
+        let render_pass = create_render_pass(device.clone(), self.color_format, depth_format)?;
+        let sampler = device.sampler(&self.sampler)?;
+
+        let mut render_target = RenderTarget {
+            extent: (0, 0),
+            color_format: self.color_format,
+            depth_format,
+            sampler,
+            color: None,
+            depth: None,
+            framebuffer: vk::Framebuffer::null(),
+            render_pass,
+            device,
+        };
+        render_target.resize(width, height)?;
+
+        Ok(render_target)
+    }
+}
+
+/// Creates a render pass with a color attachment that is left ready to be sampled, and an
+/// optional depth attachment
+fn create_render_pass(
+    device: Rc<LogicalDevice>,
+    color_format: vk::Format,
+    depth_format: Option<vk::Format>,
+) -> Result<RenderPass, RenderPassError> {
+    let description = render_pass_description(color_format, depth_format);
+
+    let mut builder = RenderPass::builder();
+    for attachment in description.attachments {
+        builder = builder.add_attachment(attachment);
+    }
+    for subpass in description.subpasses {
+        builder = builder.add_subpass(subpass);
+    }
+    for dependency in description.dependencies {
+        builder = builder.add_dependency(dependency);
+    }
+
+    builder.build(device)
+}
+
+/// An image, its memory and a view over it, used as an attachment of a [RenderTarget]
+struct Attachment {
+    /// Opaque handle to the Vulkan image
+    image: vk::Image,
+    /// Opaque handle to Vulkan device memory belonging to the image
+    memory: vk::DeviceMemory,
+    /// Opaque handle to the Vulkan image view over the image
+    view: vk::ImageView,
+}
+
+impl Attachment {
+    /// Creates a 2D attachment image of `extent` with a view over `aspect_mask`
+    fn new(
+        device: &LogicalDevice,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        extent: (u32, u32),
+    ) -> Result<Attachment, RenderTargetError> {
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width: extent.0,
+                height: extent.1,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (image, memory) =
+            device.create_image(&image_info, MemoryPropertyFlags::DEVICE_LOCAL)?;
+
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        let view = unsafe { device.vk_handle().create_image_view(&view_info, None) };
+        let view = match view {
+            Ok(view) => view,
+            Err(err) => {
+                unsafe { device.vk_handle().destroy_image(image, None) };
+                device.free_memory(memory);
+                return Err(RenderTargetError::CantCreateImageView(err.into()));
+            }
+        };
+
+        Ok(Attachment {
+            image,
+            memory,
+            view,
+        })
+    }
+
+    /// Destroys the image view and image, and frees the memory
+    fn destroy(&self, device: &LogicalDevice) {
+        unsafe {
+            device.vk_handle().destroy_image_view(self.view, None);
+            device.vk_handle().destroy_image(self.image, None);
+        };
+        device.free_memory(self.memory);
+    }
+}
+
+/// An offscreen color and optional depth attachment, along with the render pass and framebuffer
+/// to draw into them, whose color attachment can be sampled as a texture afterwards
+///
+/// Pipelines drawing into the render target must be created with its
+/// [render pass][RenderTarget::render_pass].
+pub struct RenderTarget {
+    /// Width and height, in pixels, of the attachments
+    extent: (u32, u32),
+    /// Format of the color attachment
+    color_format: vk::Format,
+    /// Format of the depth attachment, if there is one
+    depth_format: Option<vk::Format>,
+    /// Sampler the color attachment is sampled with, owned by the device's sampler cache
+    sampler: vk::Sampler,
+    /// Color attachment that is sampled after rendering
+    color: Option<Attachment>,
+    /// Depth attachment, if the render target has one
+    depth: Option<Attachment>,
+    /// Opaque handle to the Vulkan framebuffer over the attachments
+    framebuffer: vk::Framebuffer,
+    /// Render pass drawing into the attachments
+    render_pass: RenderPass,
+    /// [LogicalDevice] the render target belongs to
+    device: Rc<LogicalDevice>,
+}
+
+impl RenderTarget {
+    /// Creates a new [RenderTargetBuilder]
+    pub fn builder() -> RenderTargetBuilder {
+        RenderTargetBuilder::new()
+    }
+}
+
+impl RenderTarget {
+    /// Returns the width and height, in pixels, of the render target
+    pub fn extent(&self) -> (u32, u32) {
+        self.extent
+    }
+
+    /// Returns the format of the color attachment
+    pub fn color_format(&self) -> vk::Format {
+        self.color_format
+    }
+
+    /// Returns the render pass drawing into the render target, which consists of the color
+    /// attachment followed by the depth attachment if there is one
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    /// Returns the descriptor image info to write the color attachment into a combined image
+    /// sampler binding, the render target must have been drawn into and ended before it is
+    /// sampled
+    pub fn descriptor(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self
+                .color
+                .as_ref()
+                .expect("Render target has a color attachment")
+                .view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+}
+
+impl RenderTarget {
+    /// Begins the render target's render pass on `command_buffer`, clearing the color attachment
+    /// to `clear_color` and the depth to 1.0, and sets the viewport and scissor to cover the
+    /// render target
+    pub fn begin(
+        &self,
+        command_buffer: &mut CommandBuffer,
+        clear_color: [f32; 4],
+    ) -> Result<(), CommandBufferError> {
+        let mut clear_values = vec![ClearValue::Color(
+            clear_color[0],
+            clear_color[1],
+            clear_color[2],
+            clear_color[3],
+        )];
+        if self.depth_format.is_some() {
+            clear_values.push(ClearValue::DepthStencil {
+                depth: 1.0,
+                stencil: 0,
+            });
+        }
+
+        command_buffer.begin_render_pass(
+            &self.render_pass,
+            self.framebuffer,
+            self.extent,
+            &clear_values,
+        )?;
+        command_buffer.set_viewport(self.extent.0 as f32, self.extent.1 as f32)?;
+        command_buffer.set_scissor(self.extent)
+    }
+
+    /// Ends the render target's render pass on `command_buffer`, after which the color
+    /// attachment can be sampled by later commands
+    pub fn end(&self, command_buffer: &mut CommandBuffer) {
+        command_buffer.end_render_pass();
+    }
+
+    /// Recreates the attachments and framebuffer with a size of `width` by `height` pixels, e.g.
+    /// to follow the size of the window.
+    ///
+    /// The render pass is kept, so pipelines created with it stay valid, but descriptor sets the
+    /// color attachment was written to must be written again. The render target must not be in
+    /// use by the device.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), RenderTargetError> {
+        if width == 0 || height == 0 {
+            return Err(RenderTargetError::EmptyExtent);
+        }
+
+        self.destroy_attachments();
+        self.extent = (width, height);
+
+        self.color = Some(Attachment::new(
+            &self.device,
+            self.color_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::COLOR,
+            self.extent,
+        )?);
+        if let Some(depth_format) = self.depth_format {
+            self.depth = Some(Attachment::new(
+                &self.device,
+                depth_format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                vk::ImageAspectFlags::DEPTH,
+                self.extent,
+            )?);
+        }
+
+        let attachments: Vec<vk::ImageView> = self
+            .color
+            .iter()
+            .chain(self.depth.iter())
+            .map(|attachment| attachment.view)
+            .collect();
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(self.render_pass.vk_handle())
+            .attachments(&attachments)
+            .width(width)
+            .height(height)
+            .layers(1);
+        self.framebuffer = unsafe {
+            self.device
+                .vk_handle()
+                .create_framebuffer(&framebuffer_info, None)
+                .map_err(|err| RenderTargetError::CantCreateFramebuffer(err.into()))?
+        };
+
+        Ok(())
+    }
+
+    /// Destroys the framebuffer and attachments, if they were created
+    fn destroy_attachments(&mut self) {
+        if self.framebuffer != vk::Framebuffer::null() {
+            unsafe {
+                self.device
+                    .vk_handle()
+                    .destroy_framebuffer(self.framebuffer, None);
+            }
+            self.framebuffer = vk::Framebuffer::null();
+        }
+
+        for attachment in self.color.take().iter().chain(self.depth.take().iter()) {
+            attachment.destroy(&self.device);
+        }
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        self.destroy_attachments();
+    }
+}
+
+/// Describes the render pass of a render target, whose color attachment is left ready to be
+/// sampled and is followed by a depth attachment if there is a `depth_format`
+fn render_pass_description(
+    color_format: vk::Format,
+    depth_format: Option<vk::Format>,
+) -> RenderPassDescription {
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .build();
+
+    // Previous reads of the color attachment must finish before it is written to again, and
+    // the writes must finish before the attachment is sampled after the render pass
+    let dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::SHADER_READ,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::BY_REGION,
+        },
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            dependency_flags: vk::DependencyFlags::BY_REGION,
+        },
+    ];
+
+    let mut attachments = vec![color_attachment];
+    let mut subpass = Subpass {
+        color_attachments: vec![0],
+        ..Default::default()
+    };
+
+    if let Some(depth_format) = depth_format {
+        let depth_attachment = vk::AttachmentDescription::builder()
+            .format(depth_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .build();
+        attachments.push(depth_attachment);
+        subpass.depth_stencil_attachment = Some(1);
+    }
+
+    RenderPassDescription {
+        attachments,
+        subpasses: vec![subpass],
+        dependencies: dependencies.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_attachment_is_left_ready_to_be_sampled() {
+        let description = render_pass_description(vk::Format::R8G8B8A8_UNORM, None);
+
+        assert_eq!(description.attachments.len(), 1);
+        assert_eq!(
+            description.attachments[0].format,
+            vk::Format::R8G8B8A8_UNORM
+        );
+        assert_eq!(
+            description.attachments[0].final_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+        assert_eq!(description.subpasses[0].color_attachments, vec![0]);
+        assert_eq!(description.subpasses[0].depth_stencil_attachment, None);
+    }
+
+    #[test]
+    fn depth_attachment_follows_the_color_attachment() {
+        let description =
+            render_pass_description(vk::Format::R8G8B8A8_UNORM, Some(vk::Format::D32_SFLOAT));
+
+        assert_eq!(description.attachments.len(), 2);
+        assert_eq!(description.attachments[1].format, vk::Format::D32_SFLOAT);
+        assert_eq!(
+            description.attachments[1].final_layout,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        );
+        assert_eq!(description.subpasses[0].depth_stencil_attachment, Some(1));
+    }
+
+    #[test]
+    fn dependencies_order_sampling_around_the_pass() {
+        let description = render_pass_description(vk::Format::R8G8B8A8_UNORM, None);
+        let [before, after] = description.dependencies.as_slice() else {
+            panic!("Expected two dependencies");
+        };
+
+        assert_eq!(before.src_subpass, vk::SUBPASS_EXTERNAL);
+        assert_eq!(
+            before.src_stage_mask,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+        assert_eq!(before.src_access_mask, vk::AccessFlags::SHADER_READ);
+        assert!(before
+            .dst_access_mask
+            .contains(vk::AccessFlags::COLOR_ATTACHMENT_WRITE));
+
+        assert_eq!(after.dst_subpass, vk::SUBPASS_EXTERNAL);
+        assert_eq!(
+            after.src_stage_mask,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        );
+        assert_eq!(
+            after.src_access_mask,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+        );
+        assert_eq!(
+            after.dst_stage_mask,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+        assert_eq!(after.dst_access_mask, vk::AccessFlags::SHADER_READ);
+    }
+}
@@ -0,0 +1,264 @@
+//! This module wraps Vulkan samplers and caches them so that textures can share them
+
+use ash::vk;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::core::device::LogicalDeviceError;
+
+/// Possible filters to apply when sampling a texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+impl Into<vk::Filter> for Filter {
+    fn into(self) -> vk::Filter {
+        match self {
+            Filter::Nearest => vk::Filter::NEAREST,
+            Filter::Linear => vk::Filter::LINEAR,
+        }
+    }
+}
+
+/// Possible filters to apply between mipmap levels when sampling a texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MipmapMode {
+    Nearest,
+    Linear,
+}
+
+impl Into<vk::SamplerMipmapMode> for MipmapMode {
+    fn into(self) -> vk::SamplerMipmapMode {
+        match self {
+            MipmapMode::Nearest => vk::SamplerMipmapMode::NEAREST,
+            MipmapMode::Linear => vk::SamplerMipmapMode::LINEAR,
+        }
+    }
+}
+
+/// Possible ways of handling texture coordinates outside of the texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl Into<vk::SamplerAddressMode> for AddressMode {
+    fn into(self) -> vk::SamplerAddressMode {
+        match self {
+            AddressMode::Repeat => vk::SamplerAddressMode::REPEAT,
+            AddressMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+            AddressMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            AddressMode::ClampToBorder => vk::SamplerAddressMode::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+/// Describes the parameters a sampler is created with
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerInfo {
+    /// Filter to use when the texture is magnified
+    pub mag_filter: Filter,
+    /// Filter to use when the texture is minified
+    pub min_filter: Filter,
+    /// Filter to use between mipmap levels
+    pub mipmap_mode: MipmapMode,
+    /// How to handle texture coordinates outside of the texture, on all axes
+    pub address_mode: AddressMode,
+    /// Maximum anisotropy to use, `None` disables anisotropic filtering.
+    ///
+    /// Will be clamped to the maximum supported by the [PhysicalDevice]
+    ///
+    /// [PhysicalDevice]: crate::core::device::PhysicalDevice
+    pub max_anisotropy: Option<f32>,
+    /// Minimum level of detail to clamp to
+    pub min_lod: f32,
+    /// Maximum level of detail to clamp to, use [vk::LOD_CLAMP_NONE] to not clamp
+    pub max_lod: f32,
+}
+
+impl Default for SamplerInfo {
+    fn default() -> SamplerInfo {
+        SamplerInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: MipmapMode::Linear,
+            address_mode: AddressMode::Repeat,
+            max_anisotropy: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+        }
+    }
+}
+
+impl PartialEq for SamplerInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.mag_filter == other.mag_filter
+            && self.min_filter == other.min_filter
+            && self.mipmap_mode == other.mipmap_mode
+            && self.address_mode == other.address_mode
+            && self.max_anisotropy.map(f32::to_bits) == other.max_anisotropy.map(f32::to_bits)
+            && self.min_lod.to_bits() == other.min_lod.to_bits()
+            && self.max_lod.to_bits() == other.max_lod.to_bits()
+    }
+}
+
+impl Eq for SamplerInfo {}
+
+impl Hash for SamplerInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode.hash(state);
+        self.max_anisotropy.map(f32::to_bits).hash(state);
+        self.min_lod.to_bits().hash(state);
+        self.max_lod.to_bits().hash(state);
+    }
+}
+
+/// Caches Vulkan samplers by the parameters they were created with, so that textures that are
+/// sampled the same way share a single sampler.
+///
+/// The samplers are destroyed along with the [LogicalDevice] that owns the cache.
+///
+/// [LogicalDevice]: crate::core::device::LogicalDevice
+pub struct SamplerCache {
+    /// Vulkan samplers that have been created, keyed by the parameters they were created with
+    samplers: RefCell<HashMap<SamplerInfo, vk::Sampler>>,
+}
+
+impl SamplerCache {
+    /// Creates a new empty [SamplerCache]
+    pub(crate) fn new() -> SamplerCache {
+        SamplerCache {
+            samplers: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl SamplerCache {
+    /// Returns the number of unique samplers in the cache
+    pub fn len(&self) -> usize {
+        self.samplers.borrow().len()
+    }
+
+    /// Returns whether no samplers have been created yet
+    pub fn is_empty(&self) -> bool {
+        self.samplers.borrow().is_empty()
+    }
+
+    /// Returns a sampler created with `info`, creating it if one doesn't exist yet
+    pub(crate) fn get(
+        &self,
+        device: &ash::Device,
+        limits: &vk::PhysicalDeviceLimits,
+        anisotropy_supported: bool,
+        info: &SamplerInfo,
+    ) -> Result<vk::Sampler, LogicalDeviceError> {
+        if let Some(&sampler) = self.samplers.borrow().get(info) {
+            return Ok(sampler);
+        }
+
+        let max_anisotropy = match info.max_anisotropy {
+            Some(_) if !anisotropy_supported => {
+                log::warn!("Anisotropic filtering was requested but is not supported by the device, it will be disabled");
+                None
+            }
+            Some(max_anisotropy) => Some(max_anisotropy.clamp(1.0, limits.max_sampler_anisotropy)),
+            None => None,
+        };
+
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(info.mag_filter.into())
+            .min_filter(info.min_filter.into())
+            .mipmap_mode(info.mipmap_mode.into())
+            .address_mode_u(info.address_mode.into())
+            .address_mode_v(info.address_mode.into())
+            .address_mode_w(info.address_mode.into())
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .min_lod(info.min_lod)
+            .max_lod(info.max_lod.max(info.min_lod))
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS);
+
+        let sampler = unsafe {
+            device
+                .create_sampler(&create_info, None)
+                .map_err(|err| LogicalDeviceError::Other(err.into()))?
+        };
+        self.samplers.borrow_mut().insert(*info, sampler);
+
+        Ok(sampler)
+    }
+
+    /// Destroys every sampler in the cache
+    pub(crate) fn destroy(&self, device: &ash::Device) {
+        for (_, sampler) in self.samplers.borrow_mut().drain() {
+            unsafe {
+                device.destroy_sampler(sampler, None);
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    /// Returns the hash of `info`
+    fn hash(info: &SamplerInfo) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        info.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_infos_share_a_key() {
+        let info = SamplerInfo {
+            max_anisotropy: Some(16.0),
+            ..Default::default()
+        };
+        let same = info;
+
+        assert_eq!(info, same);
+        assert_eq!(hash(&info), hash(&same));
+
+        let mut samplers: HashMap<SamplerInfo, u32> = HashMap::new();
+        samplers.insert(info, 1);
+        samplers.insert(same, 2);
+        assert_eq!(samplers.len(), 1);
+        assert_eq!(samplers[&info], 2);
+    }
+
+    #[test]
+    fn differing_infos_have_separate_keys() {
+        let info = SamplerInfo::default();
+        let clamped = SamplerInfo {
+            max_lod: 4.0,
+            ..Default::default()
+        };
+        let anisotropic = SamplerInfo {
+            max_anisotropy: Some(1.0),
+            ..Default::default()
+        };
+
+        assert_ne!(info, clamped);
+        assert_ne!(info, anisotropic);
+
+        let samplers: HashMap<SamplerInfo, u32> =
+            HashMap::from([(info, 0), (clamped, 1), (anisotropic, 2)]);
+        assert_eq!(samplers.len(), 3);
+    }
+}
@@ -3,11 +3,43 @@
 use std::rc::Rc;
 use ash::vk;
 
-use crate::core::device::{LogicalDevice, LogicalDeviceError};
+use crate::{
+    core::device::{DeviceExtension, LogicalDevice, LogicalDeviceError},
+    VulkanError,
+};
+
+/// Errors that can be returned when sharing synchronization objects with other APIs
+#[derive(thiserror::Error, Debug)]
+pub enum SyncError {
+    #[error("Sharing semaphores requires the '{0}' device extension to be enabled")]
+    MissingExtension(DeviceExtension),
+    #[error("The semaphore wasn't created as exportable")]
+    NotExportable,
+    #[error("Failed to export the semaphore: {0}")]
+    CantExport(VulkanError),
+    #[error(transparent)]
+    DeviceError(#[from] LogicalDeviceError),
+}
+
+/// Extension required to export semaphores as handles on the current platform
+#[cfg(windows)]
+const EXTERNAL_SEMAPHORE_EXTENSION: DeviceExtension = DeviceExtension::ExternalSemaphoreWin32;
+#[cfg(not(windows))]
+const EXTERNAL_SEMAPHORE_EXTENSION: DeviceExtension = DeviceExtension::ExternalSemaphoreFd;
+
+/// Handle type semaphores are exported as on the current platform
+#[cfg(windows)]
+const EXTERNAL_SEMAPHORE_HANDLE_TYPE: vk::ExternalSemaphoreHandleTypeFlags =
+    vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_WIN32;
+#[cfg(not(windows))]
+const EXTERNAL_SEMAPHORE_HANDLE_TYPE: vk::ExternalSemaphoreHandleTypeFlags =
+    vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD;
 
 /// Wraps a Vulkan Semaphore
 #[derive(Clone)]
 pub struct Semaphore {
+    /// Whether the semaphore can be exported to be shared with other APIs
+    exportable: bool,
     /// Opaque handle to Vulkan semaphore
     handle: vk::Semaphore,
     /// Logical device this semaphore belongs to
@@ -31,7 +63,44 @@ impl Semaphore {
                 .map_err(|err| LogicalDeviceError::Other(err.into()))?
         };
 
-        Ok(Semaphore { handle, device })
+        Ok(Semaphore {
+            exportable: false,
+            handle,
+            device,
+        })
+    }
+
+    /// Creates a new Semaphore that can be exported, with `Semaphore::export_fd` or
+    /// `Semaphore::export_win32_handle` on Windows, and shared with other APIs e.g. OpenXR
+    ///
+    /// # Errors
+    /// - [SyncError::MissingExtension] - Requires [DeviceExtension::ExternalSemaphoreFd], or
+    ///   [DeviceExtension::ExternalSemaphoreWin32] on Windows
+    /// - [SyncError::DeviceError] - Failed to create the semaphore
+    pub fn new_exportable(device: Rc<LogicalDevice>) -> Result<Semaphore, SyncError> {
+        if !device
+            .physical_device()
+            .enabled_extensions()
+            .contains(&EXTERNAL_SEMAPHORE_EXTENSION)
+        {
+            return Err(SyncError::MissingExtension(EXTERNAL_SEMAPHORE_EXTENSION));
+        }
+
+        let mut export_info =
+            vk::ExportSemaphoreCreateInfo::builder().handle_types(EXTERNAL_SEMAPHORE_HANDLE_TYPE);
+        let create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut export_info);
+        let handle = unsafe {
+            device
+                .vk_handle()
+                .create_semaphore(&create_info, None)
+                .map_err(|err| LogicalDeviceError::Other(err.into()))?
+        };
+
+        Ok(Semaphore {
+            exportable: true,
+            handle,
+            device,
+        })
     }
 }
 
@@ -40,6 +109,59 @@ impl Semaphore {
     pub(crate) fn vk_handle(&self) -> vk::Semaphore {
         self.handle
     }
+
+    /// Returns whether the semaphore can be exported
+    pub fn is_exportable(&self) -> bool {
+        self.exportable
+    }
+}
+
+impl Semaphore {
+    /// Exports the semaphore as an opaque POSIX file descriptor, ownership of which is
+    /// transferred to the caller
+    #[cfg(not(windows))]
+    pub fn export_fd(&self) -> Result<i32, SyncError> {
+        if !self.exportable {
+            return Err(SyncError::NotExportable);
+        }
+
+        let external_semaphore = ash::extensions::khr::ExternalSemaphoreFd::new(
+            self.device.instance().vk_handle(),
+            self.device.vk_handle(),
+        );
+        let get_info = vk::SemaphoreGetFdInfoKHR::builder()
+            .semaphore(self.handle)
+            .handle_type(EXTERNAL_SEMAPHORE_HANDLE_TYPE);
+
+        unsafe {
+            external_semaphore
+                .get_semaphore_fd(&get_info)
+                .map_err(|err| SyncError::CantExport(err.into()))
+        }
+    }
+
+    /// Exports the semaphore as an opaque Win32 handle, which the caller must close once it is no
+    /// longer needed
+    #[cfg(windows)]
+    pub fn export_win32_handle(&self) -> Result<vk::HANDLE, SyncError> {
+        if !self.exportable {
+            return Err(SyncError::NotExportable);
+        }
+
+        let external_semaphore = ash::extensions::khr::ExternalSemaphoreWin32::new(
+            self.device.instance().vk_handle(),
+            self.device.vk_handle(),
+        );
+        let get_info = vk::SemaphoreGetWin32HandleInfoKHR::builder()
+            .semaphore(self.handle)
+            .handle_type(EXTERNAL_SEMAPHORE_HANDLE_TYPE);
+
+        unsafe {
+            external_semaphore
+                .get_semaphore_win32_handle(&get_info)
+                .map_err(|err| SyncError::CantExport(err.into()))
+        }
+    }
 }
 
 impl Drop for Semaphore {
@@ -87,6 +209,28 @@ impl Fence {
     }
 }
 
+impl Fence {
+    /// Returns whether the fence is signaled, without waiting for it
+    pub fn is_signaled(&self) -> Result<bool, VulkanError> {
+        unsafe {
+            self.device
+                .vk_handle()
+                .get_fence_status(self.handle)
+                .map_err(|err| err.into())
+        }
+    }
+
+    /// Resets the fence to the unsignaled state
+    pub fn reset(&self) -> Result<(), VulkanError> {
+        unsafe {
+            self.device
+                .vk_handle()
+                .reset_fences(&[self.handle])
+                .map_err(|err| err.into())
+        }
+    }
+}
+
 impl Drop for Fence {
     fn drop(&mut self) {
         unsafe {
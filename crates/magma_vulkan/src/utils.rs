@@ -13,8 +13,11 @@ pub fn char_array_to_string(raw_string_array: &[c_char]) -> String {
 }
 
 /// Converts a char pointer to a String
-pub fn char_ptr_to_string(string_ptr: *const i8) -> String {
-    let raw_string = unsafe { CStr::from_ptr(string_ptr) };
+///
+/// # Safety
+/// `string_ptr` must point to a valid, nul-terminated C string
+pub unsafe fn char_ptr_to_string(string_ptr: *const i8) -> String {
+    let raw_string = CStr::from_ptr(string_ptr);
 
     raw_string
         .to_str()
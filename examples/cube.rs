@@ -95,17 +95,23 @@ fn main() -> Result<()> {
     let mut swapchain = Swapchain::builder()
         .preferred_color_format(ColorFormat::Srgb)
         .preferred_present_mode(PresentMode::Mailbox)
+        .window_size(window.inner_size().into())
         .build(logical_device.clone(), &surface)?;
 
     let vertex_shader = Shader::new("shaders/cube.vert")?;
     let fragment_shader = Shader::new("shaders/cube.frag")?;
 
-    let pipeline = Pipeline::<CubeVertex, PushConstant>::builder()
+    let mut pipeline = Pipeline::<CubeVertex, PushConstant>::builder()
         .attach_shader(vertex_shader)
         .attach_shader(fragment_shader)
         .render_pass(swapchain.render_pass())
         .build(logical_device.clone())?;
 
+    // Rebuild the pipeline whenever the cube shaders are edited, so they can be tweaked while
+    // the example is running
+    let mut shader_watcher = ShaderWatcher::new();
+    shader_watcher.watch(&pipeline);
+
     let mut command_pool = CommandPool::new(
         logical_device.clone(),
         logical_device
@@ -211,11 +217,7 @@ fn main() -> Result<()> {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => should_close = true,
                 WindowEvent::Resized(size) => {
-                    if size.width == 0 && size.height == 0 {
-                        is_minimized = true
-                    } else {
-                        is_minimized = false
-                    }
+                    is_minimized = size.width == 0 && size.height == 0
                 }
                 _ => {}
             },
@@ -232,30 +234,42 @@ fn main() -> Result<()> {
             continue;
         }
 
+        if let Err(err) = shader_watcher.poll() {
+            log::error!("{}", err);
+        }
+        if let Err(err) = shader_watcher.reload(&mut pipeline, swapchain.render_pass()) {
+            log::error!("{}", err);
+        }
+
         let result = swapchain.acquire_next_image();
         if result.is_err() {
-            swapchain = recreate_swapchain(
-                swapchain,
-                logical_device.clone(),
+            swapchain.recreate_with(
                 &mut surface,
-                &mut command_pool,
+                window.inner_size().into(),
+                &mut [&mut command_pool, &mut pipeline],
             )?;
             continue;
         }
         let image_index = result.unwrap();
 
         let command_buffer = command_pool.buffers_mut().get_mut(image_index).unwrap();
-        command_buffer.begin()?;
-        command_buffer.set_clear_color((0.01, 0.01, 0.01));
+        command_buffer.begin(CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
         command_buffer.begin_render_pass(
             swapchain.render_pass(),
             *swapchain.framebuffers().get(image_index).unwrap(),
             swapchain.extent(),
+            &[
+                ClearValue::Color(0.01, 0.01, 0.01, 1.0),
+                ClearValue::DepthStencil {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            ],
         )?;
 
         let extent = swapchain.extent();
         command_buffer.set_viewport(extent.0 as f32, extent.1 as f32)?;
-        command_buffer.set_scissor(extent.clone())?;
+        command_buffer.set_scissor(extent)?;
 
         pipeline.bind(command_buffer);
         pipeline.bind_push_constant(
@@ -265,19 +279,18 @@ fn main() -> Result<()> {
                     * camera.view_matrix
                     * cube_transform.as_matrix(),
             },
-        );
-        pipeline.draw_indexed(command_buffer, &vertex_buffer, &index_buffer);
+        )?;
+        pipeline.draw_indexed(command_buffer, &vertex_buffer, &index_buffer)?;
 
         command_buffer.end_render_pass();
         command_buffer.end()?;
 
-        let result = swapchain.submit_command_buffer(command_buffer, image_index);
-        if result.is_err() {
-            swapchain = recreate_swapchain(
-                swapchain,
-                logical_device.clone(),
+        swapchain.submit_command_buffer(command_buffer, image_index)?;
+        if swapchain.needs_recreation() {
+            swapchain.recreate_with(
                 &mut surface,
-                &mut command_pool,
+                window.inner_size().into(),
+                &mut [&mut command_pool, &mut pipeline],
             )?;
             continue;
         }
@@ -287,29 +300,3 @@ fn main() -> Result<()> {
 
     Ok(())
 }
-
-fn recreate_swapchain(
-    old_swapchain: Swapchain,
-    logical_device: Rc<LogicalDevice>,
-    surface: &mut Surface,
-    command_pool: &mut CommandPool,
-) -> Result<Swapchain> {
-    logical_device.wait_for_idle()?;
-    surface.update(logical_device.physical_device())?;
-
-    let swapchain = Swapchain::builder()
-        .old_swapchain(&old_swapchain)
-        .preferred_color_format(ColorFormat::Srgb)
-        .preferred_present_mode(PresentMode::Mailbox)
-        .build(logical_device.clone(), surface)?;
-
-    if swapchain.framebuffers().len() != command_pool.buffers().len() {
-        command_pool.free_buffers();
-        command_pool.allocate_buffers(
-            swapchain.framebuffers().len() as u32,
-            CommandBufferLevel::Primary,
-        )?;
-    }
-
-    Ok(swapchain)
-}
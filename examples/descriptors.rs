@@ -59,6 +59,7 @@ fn main() -> Result<()> {
     let mut swapchain = Swapchain::builder()
         .preferred_color_format(ColorFormat::Srgb)
         .preferred_present_mode(PresentMode::Mailbox)
+        .window_size(window.inner_size().into())
         .build(logical_device.clone(), &surface)?;
 
     let vertex_shader = Shader::new("shaders/descriptors.vert")?;
@@ -95,8 +96,9 @@ fn main() -> Result<()> {
         // manually
         //
         // Lastly we need the min offset alignment of the buffer to match that of the physical
-        // device
-        ubo_buffers.push(Buffer::new(
+        // device, and room for the fields of the ubo laid out the way the shader expects them,
+        // which `new_uniform` takes care of
+        ubo_buffers.push(Buffer::new_uniform(
             logical_device.clone(),
             BufferUsageFlags::UNIFORM_BUFFER,
             MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
@@ -104,21 +106,23 @@ fn main() -> Result<()> {
     }
 
     let mut descriptor_sets = Vec::with_capacity(ubo_buffers.len());
-    for i in 0..ubo_buffers.len() {
+    for ubo_buffer in ubo_buffers.iter() {
         // In order for a buffer to be able to write to a desriptor set in needs an appropriate
         // usage flag (UNIFORM_BUFFER in this case) set, we can get the buffer's descriptor buffer
         // info using `.descriptor()` but it will only return descriptor buffer info if the buffer
         // usage flags support it
         descriptor_sets.push(
             DescriptorWriter::new(descriptor_set_layout.clone(), descriptor_pool.clone())
-                .write_buffer(0, ubo_buffers[i].descriptor().unwrap())
-                .write()?
+                .write_buffer(0, ubo_buffer.descriptor().unwrap())
+                .build()?
         );
     }
 
+    // The pipeline is created with the same set layout, so the descriptor sets can be bound to it
     let pipeline = Pipeline::<SimpleVertex, EmptyPushConstant>::builder()
         .attach_shader(vertex_shader)
         .attach_shader(fragment_shader)
+        .set_layouts(vec![descriptor_set_layout.clone()])
         .render_pass(swapchain.render_pass())
         .build(logical_device.clone())?;
 
@@ -188,11 +192,7 @@ fn main() -> Result<()> {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => should_close = true,
                 WindowEvent::Resized(size) => {
-                    if size.width == 0 && size.height == 0 {
-                        is_minimized = true
-                    } else {
-                        is_minimized = false
-                    }
+                    is_minimized = size.width == 0 && size.height == 0
                 }
                 _ => {}
             },
@@ -206,55 +206,59 @@ fn main() -> Result<()> {
 
         let result = swapchain.acquire_next_image();
         if result.is_err() {
-            swapchain = recreate_swapchain(
-                swapchain,
-                logical_device.clone(),
+            swapchain.recreate_with(
                 &mut surface,
-                &mut command_pool,
+                window.inner_size().into(),
+                &mut [&mut command_pool],
             )?;
             continue;
         }
         let image_index = result.unwrap();
 
         let command_buffer = command_pool.buffers_mut().get_mut(image_index).unwrap();
-        command_buffer.begin()?;
-        command_buffer.set_clear_color((0.01, 0.01, 0.01));
+        command_buffer.begin(CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
         command_buffer.begin_render_pass(
             swapchain.render_pass(),
             *swapchain.framebuffers().get(image_index).unwrap(),
             swapchain.extent(),
+            &[
+                ClearValue::Color(0.01, 0.01, 0.01, 1.0),
+                ClearValue::DepthStencil {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            ],
         )?;
 
         let extent = swapchain.extent();
         command_buffer.set_viewport(extent.0 as f32, extent.1 as f32)?;
-        command_buffer.set_scissor(extent.clone())?;
+        command_buffer.set_scissor(extent)?;
 
         pipeline.bind(command_buffer);
 
         // Before we bind the descriptor set we need to write some data to it
+        // `write_uniform` pads the fields to the offsets the shader expects them at
         let ubo = ubo_buffers.get_mut(image_index).unwrap();
-        ubo.map(u64::MAX, 0)?;
-        ubo.write(&[Ubo {
+        ubo.write_uniform(0, &[Ubo {
             _offset: [0.25, -0.25],
             _color: [0.5, 0.5, 0.5],
-        }]);
+        }])?;
 
         // Lastly, we need to set the descriptor set on the pipeline so that the shader recieves
         // the ubo
-        pipeline.bind_descriptor_sets(command_buffer, &[descriptor_sets[image_index]]);
+        pipeline.bind_descriptor_set(command_buffer, 0, &descriptor_sets[image_index])?;
         
-        pipeline.draw_indexed(command_buffer, &vertex_buffer, &index_buffer);
+        pipeline.draw_indexed(command_buffer, &vertex_buffer, &index_buffer)?;
 
         command_buffer.end_render_pass();
         command_buffer.end()?;
 
-        let result = swapchain.submit_command_buffer(command_buffer, image_index);
-        if result.is_err() {
-            swapchain = recreate_swapchain(
-                swapchain,
-                logical_device.clone(),
+        swapchain.submit_command_buffer(command_buffer, image_index)?;
+        if swapchain.needs_recreation() {
+            swapchain.recreate_with(
                 &mut surface,
-                &mut command_pool,
+                window.inner_size().into(),
+                &mut [&mut command_pool],
             )?;
             continue;
         }
@@ -264,29 +268,3 @@ fn main() -> Result<()> {
 
     Ok(())
 }
-
-fn recreate_swapchain(
-    old_swapchain: Swapchain,
-    logical_device: Rc<LogicalDevice>,
-    surface: &mut Surface,
-    command_pool: &mut CommandPool,
-) -> Result<Swapchain> {
-    logical_device.wait_for_idle()?;
-    surface.update(logical_device.physical_device())?;
-
-    let swapchain = Swapchain::builder()
-        .old_swapchain(&old_swapchain)
-        .preferred_color_format(ColorFormat::Srgb)
-        .preferred_present_mode(PresentMode::Mailbox)
-        .build(logical_device.clone(), surface)?;
-
-    if swapchain.framebuffers().len() != command_pool.buffers().len() {
-        command_pool.free_buffers();
-        command_pool.allocate_buffers(
-            swapchain.framebuffers().len() as u32,
-            CommandBufferLevel::Primary,
-        )?;
-    }
-
-    Ok(swapchain)
-}
@@ -49,6 +49,7 @@ fn main() -> Result<()> {
     let mut swapchain = Swapchain::builder()
         .preferred_color_format(ColorFormat::Srgb)
         .preferred_present_mode(PresentMode::Mailbox)
+        .window_size(window.inner_size().into())
         .build(logical_device.clone(), &surface)?;
 
     let vertex_shader = Shader::new("shaders/vertex.vert")?;
@@ -124,11 +125,7 @@ fn main() -> Result<()> {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => should_close = true,
                 WindowEvent::Resized(size) => {
-                    if size.width == 0 && size.height == 0 {
-                        is_minimized = true
-                    } else {
-                        is_minimized = false
-                    }
+                    is_minimized = size.width == 0 && size.height == 0
                 }
                 _ => {}
             },
@@ -142,42 +139,46 @@ fn main() -> Result<()> {
 
         let result = swapchain.acquire_next_image();
         if result.is_err() {
-            swapchain = recreate_swapchain(
-                swapchain,
-                logical_device.clone(),
+            swapchain.recreate_with(
                 &mut surface,
-                &mut command_pool,
+                window.inner_size().into(),
+                &mut [&mut command_pool],
             )?;
             continue;
         }
         let image_index = result.unwrap();
 
         let command_buffer = command_pool.buffers_mut().get_mut(image_index).unwrap();
-        command_buffer.begin()?;
-        command_buffer.set_clear_color((0.01, 0.01, 0.01));
+        command_buffer.begin(CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
         command_buffer.begin_render_pass(
             swapchain.render_pass(),
             *swapchain.framebuffers().get(image_index).unwrap(),
             swapchain.extent(),
+            &[
+                ClearValue::Color(0.01, 0.01, 0.01, 1.0),
+                ClearValue::DepthStencil {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            ],
         )?;
 
         let extent = swapchain.extent();
         command_buffer.set_viewport(extent.0 as f32, extent.1 as f32)?;
-        command_buffer.set_scissor(extent.clone())?;
+        command_buffer.set_scissor(extent)?;
 
         pipeline.bind(command_buffer);
-        pipeline.draw(command_buffer, &vertex_buffer);
+        pipeline.draw(command_buffer, &vertex_buffer)?;
 
         command_buffer.end_render_pass();
         command_buffer.end()?;
 
-        let result = swapchain.submit_command_buffer(command_buffer, image_index);
-        if result.is_err() {
-            swapchain = recreate_swapchain(
-                swapchain,
-                logical_device.clone(),
+        swapchain.submit_command_buffer(command_buffer, image_index)?;
+        if swapchain.needs_recreation() {
+            swapchain.recreate_with(
                 &mut surface,
-                &mut command_pool,
+                window.inner_size().into(),
+                &mut [&mut command_pool],
             )?;
             continue;
         }
@@ -187,29 +188,3 @@ fn main() -> Result<()> {
 
     Ok(())
 }
-
-fn recreate_swapchain(
-    old_swapchain: Swapchain,
-    logical_device: Rc<LogicalDevice>,
-    surface: &mut Surface,
-    command_pool: &mut CommandPool,
-) -> Result<Swapchain> {
-    logical_device.wait_for_idle()?;
-    surface.update(logical_device.physical_device())?;
-
-    let swapchain = Swapchain::builder()
-        .old_swapchain(&old_swapchain)
-        .preferred_color_format(ColorFormat::Srgb)
-        .preferred_present_mode(PresentMode::Mailbox)
-        .build(logical_device.clone(), surface)?;
-
-    if swapchain.framebuffers().len() != command_pool.buffers().len() {
-        command_pool.free_buffers();
-        command_pool.allocate_buffers(
-            swapchain.framebuffers().len() as u32,
-            CommandBufferLevel::Primary,
-        )?;
-    }
-
-    Ok(swapchain)
-}
@@ -0,0 +1,11 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) buffer Colors {
+	vec4 colors[];
+};
+
+void main() {
+	outColor = colors[0];
+}
@@ -0,0 +1,401 @@
+//! Tests that need a Vulkan device, run them with `cargo test -- --ignored` on a machine with a
+//! Vulkan driver
+
+use std::{rc::Rc, time::Duration};
+
+use winit::{event_loop::EventLoop, window::WindowBuilder};
+
+use magma::prelude::*;
+
+/// Creates a device with a graphics queue and `extensions` enabled, without a surface
+fn device(extensions: &[DeviceExtension]) -> Rc<LogicalDevice> {
+    let instance = Instance::new_headless(&[]).expect("Failed to create an instance");
+    let physical_device = PhysicalDevice::builder()
+        .add_queue_family(QueueFamily::new(QueueFlags::GRAPHICS))
+        .device_extensions(extensions)
+        .build(&instance)
+        .expect("No device supports the extensions");
+    Rc::new(
+        LogicalDevice::new(instance, physical_device).expect("Failed to create a logical device"),
+    )
+}
+
+/// Creates an event loop on the test's thread, which isn't the main thread
+#[cfg(target_os = "linux")]
+fn event_loop() -> EventLoop<()> {
+    winit::platform::unix::EventLoopExtUnix::new_any_thread()
+}
+
+/// Creates an event loop on the test's thread, which isn't the main thread
+#[cfg(target_os = "windows")]
+fn event_loop() -> EventLoop<()> {
+    winit::platform::windows::EventLoopExtWindows::new_any_thread()
+}
+
+/// Creates a command pool for the graphics queue family of `device`
+fn command_pool(device: &Rc<LogicalDevice>) -> CommandPool {
+    CommandPool::new(
+        device.clone(),
+        device
+            .physical_device()
+            .queue_family(QueueFlags::GRAPHICS)
+            .unwrap(),
+    )
+    .expect("Failed to create a command pool")
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn immediate_submit_completes_before_returning() {
+    let device = device(&[]);
+    let command_pool = command_pool(&device);
+
+    let mut recorded = false;
+    command_pool
+        .immediate_submit(|_| recorded = true)
+        .expect("Failed to submit");
+    assert!(recorded);
+
+    let mut source = Buffer::<u32, 4>::new(
+        device.clone(),
+        BufferUsageFlags::TRANSFER_SRC,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+    )
+    .unwrap();
+    source.map(u64::MAX, 0).unwrap();
+    source.write(&[1, 2, 3, 4]);
+    let mut destination = Buffer::<u32, 4>::new(
+        device.clone(),
+        BufferUsageFlags::TRANSFER_DST,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+    )
+    .unwrap();
+
+    destination.copy_from(&source, &command_pool).unwrap();
+    destination.map(u64::MAX, 0).unwrap();
+    assert_eq!(destination.mapped_slice(), Some(&[1, 2, 3, 4][..]));
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn compute_only_device_copies_buffers() {
+    let instance = Instance::new_headless(&[]).expect("Failed to create an instance");
+    let physical_device = PhysicalDevice::builder()
+        .add_queue_family(QueueFamily::new(QueueFlags::COMPUTE))
+        .build(&instance)
+        .expect("No device has a compute queue");
+    let device = Rc::new(
+        LogicalDevice::new_compute_only(instance, physical_device)
+            .expect("Failed to create a compute only device"),
+    );
+    assert!(device.queue(QueueFlags::COMPUTE).is_some());
+    let command_pool = CommandPool::new(
+        device.clone(),
+        device
+            .physical_device()
+            .queue_family(QueueFlags::COMPUTE)
+            .unwrap(),
+    )
+    .unwrap();
+
+    let mut source = Buffer::<u32, 4>::new(
+        device.clone(),
+        BufferUsageFlags::TRANSFER_SRC,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+    )
+    .unwrap();
+    source.map(u64::MAX, 0).unwrap();
+    source.write(&[5, 6, 7, 8]);
+    let mut destination = Buffer::<u32, 4>::new(
+        device.clone(),
+        BufferUsageFlags::TRANSFER_DST,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+    )
+    .unwrap();
+
+    destination.copy_from(&source, &command_pool).unwrap();
+    destination.map(u64::MAX, 0).unwrap();
+    assert_eq!(destination.mapped_slice(), Some(&[5, 6, 7, 8][..]));
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn buffer_with_device_address_usage_has_an_address() {
+    let device = device(&[DeviceExtension::BufferDeviceAddress]);
+
+    let buffer = Buffer::<u32, 4>::new(
+        device,
+        BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+    )
+    .unwrap();
+
+    assert_ne!(buffer.device_address().unwrap(), 0);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn validation_errors_are_reported_when_recorded() {
+    let instance = Instance::builder()
+        .debug_layers(&[DebugLayer::KhronosValidation])
+        .record_validation_errors(true)
+        .headless(true)
+        .build()
+        .expect("Failed to create an instance");
+    let physical_device = PhysicalDevice::builder()
+        .add_queue_family(QueueFamily::new(QueueFlags::GRAPHICS))
+        .build(&instance)
+        .expect("No device has a graphics queue");
+    let device = Rc::new(LogicalDevice::new(instance, physical_device).unwrap());
+    assert!(device.instance().check_validation_errors().is_ok());
+
+    // A buffer needs at least one usage flag
+    let _buffer = Buffer::<u32, 4>::new(
+        device.clone(),
+        BufferUsageFlags::empty(),
+        MemoryPropertyFlags::HOST_VISIBLE,
+    );
+
+    assert!(matches!(
+        device.instance().check_validation_errors(),
+        Err(InstanceError::ValidationErrors(errors)) if !errors.is_empty()
+    ));
+    assert!(device.instance().check_validation_errors().is_ok());
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn new_fence_is_signaled_until_reset() {
+    let fence = Fence::new(device(&[])).unwrap();
+
+    assert!(fence.is_signaled().unwrap());
+    fence.reset().unwrap();
+    assert!(!fence.is_signaled().unwrap());
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn calibrated_timestamps_increase_monotonically() {
+    let device = device(&[DeviceExtension::CalibratedTimestamps]);
+
+    let first = device.calibrated_timestamps().unwrap();
+    std::thread::sleep(Duration::from_millis(1));
+    let second = device.calibrated_timestamps().unwrap();
+
+    assert!(second.device > first.device);
+    assert!(second.host > first.host);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device and a display"]
+fn waiting_for_a_presented_id_succeeds() {
+    let event_loop = event_loop();
+    let window = WindowBuilder::new()
+        .with_title("wait_for_present")
+        .build(&event_loop)
+        .unwrap();
+
+    let instance = Instance::new(&[]).unwrap();
+    let physical_device = PhysicalDevice::builder()
+        .add_queue_family(QueueFamily::new(QueueFlags::GRAPHICS))
+        .device_extensions(&[
+            DeviceExtension::Swapchain,
+            DeviceExtension::PresentId,
+            DeviceExtension::PresentWait,
+        ])
+        .build(&instance)
+        .expect("No device supports waiting for presents");
+    let device = Rc::new(LogicalDevice::new(instance, physical_device).unwrap());
+    let surface = Surface::new(device.instance(), device.physical_device(), &window).unwrap();
+    let mut swapchain = Swapchain::builder()
+        .preferred_present_mode(PresentMode::Fifo)
+        .window_size(window.inner_size().into())
+        .build(device.clone(), &surface)
+        .unwrap();
+    let mut command_pool = command_pool(&device);
+    command_pool
+        .allocate_buffers(
+            swapchain.framebuffers().len() as u32,
+            CommandBufferLevel::Primary,
+        )
+        .unwrap();
+
+    let image_index = swapchain.acquire_next_image().unwrap();
+    let command_buffer = &mut command_pool.buffers_mut()[image_index];
+    command_buffer
+        .begin(CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+        .unwrap();
+    command_buffer
+        .begin_render_pass(
+            swapchain.render_pass(),
+            swapchain.framebuffers()[image_index],
+            swapchain.extent(),
+            &[
+                ClearValue::Color(0.0, 0.0, 0.0, 1.0),
+                ClearValue::DepthStencil {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            ],
+        )
+        .unwrap();
+    command_buffer.end_render_pass();
+    command_buffer.end().unwrap();
+    swapchain
+        .submit_command_buffer(command_buffer, image_index)
+        .unwrap();
+
+    let present_id = swapchain
+        .last_present_id()
+        .expect("Present wasn't given an id");
+    assert!(swapchain
+        .wait_for_present(present_id, Duration::from_secs(1))
+        .unwrap());
+    device.wait_for_idle().unwrap();
+}
+
+#[test]
+#[ignore = "needs a Vulkan device with two graphics queues"]
+fn queues_of_the_same_family_are_distinct() {
+    let instance = Instance::new_headless(&[]).unwrap();
+    let physical_device = PhysicalDevice::builder()
+        .add_queue_family(QueueFamily::new(QueueFlags::GRAPHICS).with_count(2))
+        .build(&instance)
+        .unwrap();
+    let queue_family = physical_device.queue_families()[0];
+    assert_eq!(queue_family.count, 2);
+    assert_eq!(queue_family.queue_count, 2, "The device has a single queue");
+    let device = LogicalDevice::new(instance, physical_device).unwrap();
+
+    let first = device.queue_at(QueueFlags::GRAPHICS, 0).unwrap();
+    let second = device.queue_at(QueueFlags::GRAPHICS, 1).unwrap();
+    assert_ne!(first.handle, second.handle);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device and a display"]
+fn swapchain_has_an_image_for_every_framebuffer() {
+    let event_loop = event_loop();
+    let window = WindowBuilder::new()
+        .with_title("swapchain images")
+        .build(&event_loop)
+        .unwrap();
+
+    let instance = Instance::new(&[]).unwrap();
+    let physical_device = PhysicalDevice::builder()
+        .add_queue_family(QueueFamily::new(QueueFlags::GRAPHICS))
+        .device_extensions(&[DeviceExtension::Swapchain])
+        .build(&instance)
+        .unwrap();
+    let device = Rc::new(LogicalDevice::new(instance, physical_device).unwrap());
+    let surface = Surface::new(device.instance(), device.physical_device(), &window).unwrap();
+    let swapchain = Swapchain::builder()
+        .window_size(window.inner_size().into())
+        .build(device, &surface)
+        .unwrap();
+
+    // The swapchain asks for one more image than the minimum, which the driver may exceed
+    let capabilities = surface.capabilities();
+    let mut requested = capabilities.min_image_count + 1;
+    if capabilities.max_image_count > 0 {
+        requested = requested.min(capabilities.max_image_count);
+    }
+    let images = swapchain.images();
+    assert!(images.len() >= requested as usize);
+    assert_eq!(images.len(), swapchain.framebuffers().len());
+    assert!((0..images.len()).all(|index| swapchain.image_layout(index).is_some()));
+    assert!(swapchain.image_layout(images.len()).is_none());
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn samplers_with_the_same_info_are_shared() {
+    let device = device(&[]);
+    let info = SamplerInfo {
+        max_lod: 4.0,
+        ..Default::default()
+    };
+
+    let sampler = device.sampler(&info).unwrap();
+    assert_eq!(device.sampler(&info).unwrap(), sampler);
+    assert_eq!(device.sampler_cache().len(), 1);
+
+    let nearest = device
+        .sampler(&SamplerInfo {
+            mag_filter: Filter::Nearest,
+            ..info
+        })
+        .unwrap();
+    assert_ne!(nearest, sampler);
+    assert_eq!(device.sampler_cache().len(), 2);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn bindless_textures_are_registered_until_full() {
+    let device = device(&[DeviceExtension::DescriptorIndexing]);
+    let command_pool = command_pool(&device);
+    let texture = Texture::builder()
+        .build(device.clone(), &command_pool, 1, 1, &[255, 0, 255, 255])
+        .unwrap();
+    let descriptor = texture.descriptor();
+
+    let mut textures = BindlessTextures::new(device, 2, ShaderStageFlags::FRAGMENT).unwrap();
+    assert_eq!(
+        textures
+            .register(descriptor.image_view, descriptor.sampler)
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        textures
+            .register(descriptor.image_view, descriptor.sampler)
+            .unwrap(),
+        1
+    );
+    assert_eq!(textures.len(), 2);
+    assert!(matches!(
+        textures.register(descriptor.image_view, descriptor.sampler),
+        Err(DescriptorError::BindlessFull(2))
+    ));
+}
+
+#[test]
+#[cfg(not(windows))]
+#[ignore = "needs a Vulkan device"]
+fn exportable_semaphore_yields_a_file_descriptor() {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let device = device(&[DeviceExtension::ExternalSemaphoreFd]);
+
+    let semaphore = Semaphore::new_exportable(device.clone()).unwrap();
+    let fd = semaphore.export_fd().unwrap();
+    assert!(fd >= 0);
+    // Ownership of the file descriptor was transferred, so it has to be closed
+    drop(unsafe { OwnedFd::from_raw_fd(fd) });
+
+    assert!(matches!(
+        Semaphore::new(device).unwrap().export_fd(),
+        Err(SyncError::NotExportable)
+    ));
+}
+
+#[test]
+#[cfg(windows)]
+#[ignore = "needs a Vulkan device"]
+fn exportable_semaphore_yields_a_win32_handle() {
+    use std::os::windows::io::{FromRawHandle, OwnedHandle};
+
+    let device = device(&[DeviceExtension::ExternalSemaphoreWin32]);
+
+    let semaphore = Semaphore::new_exportable(device.clone()).unwrap();
+    let handle = semaphore.export_win32_handle().unwrap();
+    assert!(!handle.is_null());
+    // The handle has to be closed once it is no longer needed
+    drop(unsafe { OwnedHandle::from_raw_handle(handle) });
+
+    assert!(matches!(
+        Semaphore::new(device).unwrap().export_win32_handle(),
+        Err(SyncError::NotExportable)
+    ));
+}
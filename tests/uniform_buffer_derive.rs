@@ -0,0 +1,58 @@
+use magma::prelude::*;
+
+#[derive(UniformBuffer)]
+#[ubo(stage = "fragment")]
+struct Light {
+    color: glam::Vec3,
+    intensity: f32,
+}
+
+#[derive(UniformBuffer)]
+#[ubo(stage = "fragment")]
+struct Material {
+    enabled: bool,
+    layer: i32,
+    rotation: glam::Quat,
+    #[ubo(nested)]
+    light: Light,
+}
+
+/// Returns the material written by every test, with a distinct value in every field
+fn material() -> Material {
+    Material {
+        enabled: true,
+        layer: -3,
+        rotation: glam::Quat::from_xyzw(0.0, 0.6, 0.0, 0.8),
+        light: Light {
+            color: glam::vec3(1.0, 0.5, 0.25),
+            intensity: 2.0,
+        },
+    }
+}
+
+/// Reads the `f32`s in `bytes`
+fn floats(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn bool_int_quat_and_nested_fields_are_laid_out_using_std140() {
+    let offsets = std140_offsets(&Material::get_field_descriptions());
+
+    assert_eq!(offsets, [0, 4, 16, 32]);
+    assert_eq!(material().as_std140_bytes().len(), 48);
+}
+
+#[test]
+fn bool_int_quat_and_nested_fields_are_written_at_their_offsets() {
+    let bytes = material().as_std140_bytes();
+
+    assert_eq!(u32::from_ne_bytes(bytes[0..4].try_into().unwrap()), 1);
+    assert_eq!(i32::from_ne_bytes(bytes[4..8].try_into().unwrap()), -3);
+    assert!(bytes[8..16].iter().all(|&byte| byte == 0));
+    assert_eq!(floats(&bytes[16..32]), [0.0, 0.6, 0.0, 0.8]);
+    assert_eq!(floats(&bytes[32..48]), [1.0, 0.5, 0.25, 2.0]);
+}
@@ -0,0 +1,100 @@
+use magma::prelude::*;
+
+#[repr(C)]
+#[derive(Vertex)]
+struct SplitVertex {
+    #[location = 0]
+    #[binding = 0]
+    position: [f32; 3],
+    #[location = 1]
+    #[binding = 1]
+    normal: [f32; 3],
+    #[location = 2]
+    #[binding = 1]
+    uv: [f32; 2],
+}
+
+#[test]
+fn fields_in_two_bindings_get_a_binding_description_each() {
+    let bindings = SplitVertex::get_binding_descriptions();
+
+    assert_eq!(bindings.len(), 2);
+    assert_eq!((bindings[0].binding, bindings[0].stride), (0, 12));
+    assert_eq!((bindings[1].binding, bindings[1].stride), (1, 20));
+    assert!(bindings
+        .iter()
+        .all(|binding| binding.input_rate == VertexInputRate::Vertex));
+}
+
+#[test]
+fn fields_in_two_bindings_are_packed_per_binding() {
+    let attributes = SplitVertex::get_attribute_descriptions();
+
+    let layout: Vec<(u32, u32, u32)> = attributes
+        .iter()
+        .map(|attribute| (attribute.location, attribute.binding, attribute.offset))
+        .collect();
+    assert_eq!(layout, [(0, 0, 0), (1, 1, 0), (2, 1, 12)]);
+}
+
+#[repr(C)]
+#[derive(Vertex)]
+#[vertex(stride = 32, rate = "instance")]
+struct PaddedInstance {
+    #[location = 0]
+    offset: [f32; 3],
+    #[location = 1]
+    scale: [f32; 1],
+}
+
+#[test]
+fn stride_and_rate_override_the_binding_description() {
+    let bindings = PaddedInstance::get_binding_descriptions();
+
+    assert_eq!(bindings.len(), 1);
+    assert_eq!(bindings[0].stride, 32);
+    assert_eq!(bindings[0].input_rate, VertexInputRate::Instance);
+}
+
+#[repr(C)]
+#[derive(Vertex)]
+struct TreeVertex {
+    #[location = 0]
+    position: [f32; 3],
+    #[location = 1]
+    #[binding(1, rate = "instance")]
+    translation: [f32; 3],
+}
+
+#[test]
+fn instance_fields_get_an_instance_rate_binding() {
+    let bindings = TreeVertex::get_binding_descriptions();
+
+    assert_eq!(bindings.len(), 2);
+    assert_eq!(bindings[0].binding, 0);
+    assert_eq!(bindings[0].input_rate, VertexInputRate::Vertex);
+    assert_eq!(bindings[1].binding, 1);
+    assert_eq!(bindings[1].input_rate, VertexInputRate::Instance);
+    assert_eq!(bindings[1].stride, 12);
+}
+
+/// Matches the `vec2 position; vec3 color;` inputs of shaders/vertex.vert, with the color read
+/// per instance
+#[repr(C)]
+#[derive(Vertex)]
+struct InstancedVertex {
+    #[location = 0]
+    position: [f32; 2],
+    #[location = 1]
+    #[binding(1, rate = "instance")]
+    color: [f32; 3],
+}
+
+#[test]
+fn instance_rate_vertex_passes_shader_validation() {
+    let shader = Shader::new("shaders/vertex.vert").unwrap();
+
+    assert!(shader
+        .validate::<InstancedVertex, EmptyPushConstant>()
+        .is_ok());
+}